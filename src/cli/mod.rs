@@ -9,7 +9,10 @@ use crate::{
     },
     extracts::process_watcher::ProcessWatcher,
     run, start_daemon,
-    types::cli::TracerCliInitArgs,
+    types::{
+        cli::{TracerCliInitArgs, TracerCliRunArgs, TracerCliTargetsCommand},
+        event::attributes::log::LogSeverity,
+    },
     SOCKET_PATH,
 };
 use anyhow::Result;
@@ -17,10 +20,14 @@ use clap::{Parser, Subcommand};
 use nondaemon_commands::{
     clean_up_after_daemon, print_config_info_sync, setup_config, update_tracer,
 };
+use run_command::run_wrapped_command;
+use targets_command::run_targets_command;
 
 use std::{env, fs::canonicalize};
 use sysinfo::System;
 pub mod nondaemon_commands;
+pub mod run_command;
+pub mod targets_command;
 
 #[derive(Parser)]
 #[clap(
@@ -49,14 +56,23 @@ pub enum Commands {
     },
 
     /// Log a message to the service
-    Log { message: String },
+    Log {
+        message: String,
+        /// Severity of the message
+        #[clap(long, short, value_enum, default_value_t = LogSeverity::Info)]
+        severity: LogSeverity,
+    },
 
-    /// Send an alert to the service, sending an e-mail
+    /// Send an alert to the service, routed to the configured notification channel
     Alert { message: String },
 
     /// Start the daemon
     Init(TracerCliInitArgs),
 
+    /// Trace a command as its own pipeline run, e.g. `tracer run -p my-pipeline -- nextflow run main.nf`.
+    /// Starts the daemon if needed and ends the run when the command exits
+    Run(TracerCliRunArgs),
+
     /// Stop the daemon
     Terminate,
 
@@ -95,6 +111,12 @@ pub enum Commands {
 
     /// Shows the current version of the daemon
     Version,
+
+    /// List the targets and explain which one a process matches, e.g. `tracer targets test --name bwa --cmd "bwa mem ref.fa"`
+    Targets {
+        #[clap(subcommand)]
+        command: TracerCliTargetsCommand,
+    },
 }
 
 pub fn process_cli() -> Result<()> {
@@ -136,8 +158,13 @@ pub fn process_cli() -> Result<()> {
             }
             result
         }
+        Commands::Run(args) => {
+            let exit_code = run_wrapped_command(args)?;
+            std::process::exit(exit_code)
+        }
         Commands::ApplyBashrc => ConfigManager::setup_aliases(),
         Commands::Info => print_config_info_sync(),
+        Commands::Targets { command } => run_targets_command(command),
         _ => run_async_command(cli.command),
     }
 }
@@ -145,10 +172,12 @@ pub fn process_cli() -> Result<()> {
 #[tokio::main]
 pub async fn run_async_command(commands: Commands) -> Result<()> {
     let result = match commands {
-        Commands::Log { message } => send_log_request(SOCKET_PATH, message).await,
+        Commands::Log { message, severity } => {
+            send_log_request(SOCKET_PATH, message, severity).await
+        }
         Commands::Alert { message } => send_alert_request(SOCKET_PATH, message).await,
         Commands::Terminate => send_terminate_request(SOCKET_PATH).await,
-        Commands::Start => send_start_run_request(SOCKET_PATH).await,
+        Commands::Start => send_start_run_request(SOCKET_PATH, false).await.map(|_| ()),
         Commands::End => send_end_run_request(SOCKET_PATH).await,
         Commands::Update => update_tracer().await,
         Commands::Tag { tags } => send_update_tags_request(SOCKET_PATH, &tags).await,
@@ -186,8 +215,8 @@ pub async fn run_async_command(commands: Commands) -> Result<()> {
         }
     };
 
-    if result.is_err() {
-        println!("Failed to send command to the daemon. Maybe the daemon is not running? If it's not, run `tracer init` to start the daemon.");
+    if let Err(err) = result {
+        println!("Failed to send command to the daemon: {:#}", err);
     } else {
         println!("Command sent successfully.")
     }
@@ -55,6 +55,14 @@ pub async fn print_config_info() -> Result<()> {
         config.grafana_workspace_url.cyan().underline()
     );
 
+    if config.offline {
+        let _ = writeln!(
+            &mut output,
+            "Offline mode: {} (events are written to local files only)",
+            "enabled".yellow()
+        );
+    }
+
     let _ = writeln!(
         &mut output,
         "Process polling interval: {} ms",
@@ -0,0 +1,213 @@
+// src/cli/run_command.rs
+use std::os::unix::process::ExitStatusExt;
+use std::process::{ExitStatus, Stdio};
+use std::time::{Duration, Instant};
+
+use anyhow::{bail, Context, Result};
+use sysinfo::{Pid, Signal, System};
+use tokio::process::{Child, Command};
+use tokio::signal::unix::{signal, SignalKind};
+use tokio::time::sleep;
+
+use crate::daemon_communication::client::{
+    send_finish_run_request, send_info_request, send_set_run_root_pid_request,
+    send_start_run_request,
+};
+use crate::daemon_communication::structs::StartRunResponse;
+use crate::types::cli::{TracerCliInitArgs, TracerCliRunArgs};
+use crate::types::event::attributes::run::RunCompletion;
+use crate::SOCKET_PATH;
+
+const DAEMON_START_TIMEOUT: Duration = Duration::from_secs(60);
+const DAEMON_POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Exit code of a shell when the command can't be found or executed
+const COMMAND_NOT_FOUND_EXIT_CODE: i32 = 127;
+
+/// Runs the command given to `tracer run` as its own pipeline run:
+/// starts or attaches to the daemon, begins a run, spawns the command and ends the run when it exits.
+/// Returns the exit code `tracer run` should exit with
+#[tokio::main]
+pub async fn run_wrapped_command(args: TracerCliRunArgs) -> Result<i32> {
+    let run = begin_run(&args.init, args.end_active_run).await?;
+    println!("Tracing run {} ({})", run.run_name, run.run_id);
+
+    let command_line = args.command.join(" ");
+
+    let mut child = match Command::new(&args.command[0])
+        .args(&args.command[1..])
+        .env("TRACER_RUN_ID", &run.run_id)
+        .env("TRACER_RUN_NAME", &run.run_name)
+        .spawn()
+    {
+        Ok(child) => child,
+        Err(err) => {
+            eprintln!("Failed to start {}: {err}", args.command[0]);
+            end_run(RunCompletion {
+                command: command_line,
+                exit_code: Some(COMMAND_NOT_FOUND_EXIT_CODE),
+                signal: None,
+            })
+            .await;
+            return Ok(COMMAND_NOT_FOUND_EXIT_CODE);
+        }
+    };
+
+    if let Some(pid) = child.id() {
+        if let Err(err) = send_set_run_root_pid_request(SOCKET_PATH, pid).await {
+            eprintln!("Failed to set the root process of the run: {err}");
+        }
+    }
+
+    let status = wait_forwarding_signals(&mut child).await?;
+
+    end_run(RunCompletion {
+        command: command_line,
+        exit_code: status.code(),
+        signal: status.signal(),
+    })
+    .await;
+
+    Ok(exit_code(&status))
+}
+
+/// Starts a new run on the running daemon, or starts the daemon which begins a run by itself.
+/// A run already in progress is only ended with `end_active_run`
+async fn begin_run(init: &TracerCliInitArgs, end_active_run: bool) -> Result<StartRunResponse> {
+    if let Ok(info) = send_info_request(SOCKET_PATH).await {
+        if let Some(active) = info.inner.filter(|_| !end_active_run) {
+            bail!(
+                "Run {} ({}) is already in progress, pass --end-active-run to end it and start a new one",
+                active.run_name,
+                active.run_id
+            );
+        }
+        // the daemon checks again, another run may have begun since
+        let run = send_start_run_request(SOCKET_PATH, !end_active_run).await?;
+        if run.pipeline_name != init.pipeline_name {
+            println!(
+                "Warning: the daemon is already running for pipeline {}, the run is recorded under it",
+                run.pipeline_name
+            );
+        }
+        return Ok(run);
+    }
+
+    println!("Starting daemon...");
+    start_daemon_process(init).await?;
+    wait_for_daemon_run().await
+}
+
+/// Runs `tracer init` with the same arguments, it returns once the daemon has forked
+async fn start_daemon_process(init: &TracerCliInitArgs) -> Result<()> {
+    let mut command = Command::new(std::env::current_exe()?);
+    command
+        .arg("init")
+        .arg("--pipeline-name")
+        .arg(&init.pipeline_name)
+        .stdout(Stdio::null());
+
+    if let Some(run_id) = &init.run_id {
+        command.arg("--run-id").arg(run_id);
+    }
+    if !init.tags.is_empty() {
+        command.arg("--tags").arg(init.tags.join(","));
+    }
+    if init.offline {
+        command.arg("--offline");
+    }
+
+    let status = command
+        .status()
+        .await
+        .context("Failed to run tracer init")?;
+    if !status.success() {
+        bail!("tracer init exited with {status}");
+    }
+
+    Ok(())
+}
+
+async fn wait_for_daemon_run() -> Result<StartRunResponse> {
+    let deadline = Instant::now() + DAEMON_START_TIMEOUT;
+
+    while Instant::now() < deadline {
+        if let Ok(info) = send_info_request(SOCKET_PATH).await {
+            if let Some(run) = info.inner {
+                return Ok(StartRunResponse {
+                    run_name: run.run_name,
+                    run_id: run.run_id,
+                    pipeline_name: run.pipeline_name,
+                });
+            }
+        }
+        sleep(DAEMON_POLL_INTERVAL).await;
+    }
+
+    bail!("The daemon didn't start a run in time. If a previous daemon was killed, run `tracer cleanup` and try again.")
+}
+
+async fn wait_forwarding_signals(child: &mut Child) -> Result<ExitStatus> {
+    let mut interrupt = signal(SignalKind::interrupt())?;
+    let mut terminate = signal(SignalKind::terminate())?;
+    let mut hangup = signal(SignalKind::hangup())?;
+
+    loop {
+        tokio::select! {
+            status = child.wait() => return Ok(status?),
+            // ctrl-c already reaches the command through the terminal's process group,
+            // keep waiting so its exit still gets recorded
+            _ = interrupt.recv() => {}
+            _ = terminate.recv() => forward_signal(child, Signal::Term),
+            _ = hangup.recv() => forward_signal(child, Signal::Hangup),
+        }
+    }
+}
+
+fn forward_signal(child: &Child, signal: Signal) {
+    let Some(pid) = child.id() else {
+        return;
+    };
+    let pid = Pid::from_u32(pid);
+
+    let mut system = System::new();
+    system.refresh_process(pid);
+    if let Some(process) = system.process(pid) {
+        process.kill_with(signal);
+    }
+}
+
+async fn end_run(completion: RunCompletion) {
+    if let Err(err) = send_finish_run_request(SOCKET_PATH, &completion).await {
+        eprintln!("Failed to end the run: {err}");
+    }
+}
+
+/// Mirrors the shell convention of 128 + signal number for commands killed by a signal
+fn exit_code(status: &ExitStatus) -> i32 {
+    status
+        .code()
+        .unwrap_or_else(|| 128 + status.signal().unwrap_or_default())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_exit_code_of_signaled_command() {
+        let status = std::process::Command::new("sh")
+            .args(["-c", "kill -TERM $$"])
+            .status()
+            .unwrap();
+
+        assert_eq!(status.signal(), Some(15));
+        assert_eq!(exit_code(&status), 143);
+
+        let status = std::process::Command::new("sh")
+            .args(["-c", "exit 3"])
+            .status()
+            .unwrap();
+        assert_eq!(exit_code(&status), 3);
+    }
+}
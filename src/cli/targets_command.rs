@@ -0,0 +1,353 @@
+// src/cli/targets_command.rs
+use std::fmt::Write;
+
+use std::collections::HashSet;
+
+use anyhow::Result;
+use colored::Colorize;
+use sysinfo::{Pid, ProcessRefreshKind, System, UpdateKind};
+
+use crate::config_manager::target_process::rules::{ProcessInfo, ProcessLookup};
+use crate::config_manager::target_process::target_packs::{
+    load_target_packs, merge_target_packs, TargetPack,
+};
+use crate::config_manager::target_process::targets_list::TARGETS;
+use crate::config_manager::target_process::{MatchVerdict, Target};
+use crate::config_manager::ConfigManager;
+use crate::extracts::process_watcher::{merged_ancestor, process_info};
+use crate::types::cli::TracerCliTargetsCommand;
+
+/// `filter_out` entries shown per target by `list`, the built-in conda exceptions run to hundreds
+const LISTED_FILTERS: usize = 3;
+
+/// `tracer targets`, evaluated against the targets the daemon would load, without talking to it
+pub fn run_targets_command(command: TracerCliTargetsCommand) -> Result<()> {
+    let packs = ConfigManager::get_target_packs_path()
+        .map(|dir| load_target_packs(&dir))
+        .unwrap_or_default();
+    let config_targets = ConfigManager::load_config().targets;
+    let targets = merge_target_packs(&TARGETS, &config_targets, &packs);
+    let output = match command {
+        TracerCliTargetsCommand::List => list_targets(&targets, &config_targets, &packs),
+        TracerCliTargetsCommand::Test { name, cmd, exe } => {
+            explain_process(&targets, &ProcessInfo::new(&name, &cmd, &exe), &(), |_| {
+                None
+            })
+        }
+        TracerCliTargetsCommand::Scan => scan_processes(&targets),
+    };
+    print!("{output}");
+    Ok(())
+}
+
+fn describe_target(target: &Target) -> String {
+    let mut description = format!("{}, named after {}", target.match_type, target.display_name);
+    if let Some(filter_out) = target
+        .filter_out
+        .as_ref()
+        .filter(|filters| !filters.is_empty())
+    {
+        let filters: Vec<String> = filter_out
+            .iter()
+            .take(LISTED_FILTERS)
+            .map(ToString::to_string)
+            .collect();
+        let _ = write!(description, ", unless {}", filters.join(" or "));
+        if filter_out.len() > LISTED_FILTERS {
+            let _ = write!(
+                description,
+                " or {} more",
+                filter_out.len() - LISTED_FILTERS
+            );
+        }
+    }
+    if let Some(rule) = &target.rule {
+        let _ = write!(description, ", when {rule}");
+    }
+    description
+}
+
+fn list_targets(targets: &[Target], config_targets: &[Target], packs: &[TargetPack]) -> String {
+    let mut output = String::new();
+    for (index, target) in targets.iter().enumerate() {
+        let origin = match packs.iter().find(|pack| pack.targets.contains(target)) {
+            Some(pack) => pack.name.as_str(),
+            None if config_targets.contains(target) => "tracer.toml",
+            None => "built-in",
+        };
+        let _ = writeln!(
+            output,
+            "#{index:<4} {:<12} {:<24} {}",
+            origin,
+            target.id(),
+            describe_target(target)
+        );
+    }
+    for pack in packs {
+        let version = pack.version.as_deref().unwrap_or("unversioned");
+        let _ = write!(
+            output,
+            "\npack {} {version}, priority {}",
+            pack.name, pack.priority
+        );
+        if !pack.disable.is_empty() {
+            let _ = write!(output, ", disables {}", pack.disable.join(", "));
+        }
+        output.push('\n');
+    }
+    let _ = writeln!(
+        output,
+        "\n{} targets, the first one a process matches names it",
+        targets.len()
+    );
+    output
+}
+
+/// Every target that got past its `match_type`, and the one the daemon would use.
+/// Ancestors are looked up in `processes`. `merged_into` is the ancestor a `merge_with_parents` target,
+/// by index, tracks the process as
+fn explain_process(
+    targets: &[Target],
+    process: &ProcessInfo,
+    processes: &dyn ProcessLookup,
+    merged_into: impl Fn(usize) -> Option<u32>,
+) -> String {
+    let mut output = String::new();
+    let pid = process.pid.map(|pid| format!("{pid} ")).unwrap_or_default();
+    let _ = writeln!(
+        output,
+        "{pid}{} {:?} {}",
+        process.name.bold(),
+        process.command,
+        process.bin_path
+    );
+
+    let mut tracked_by = None;
+    for (index, target) in targets.iter().enumerate() {
+        let verdict = match target.explain(process, processes) {
+            MatchVerdict::NotMatched => continue,
+            MatchVerdict::Matched => {
+                tracked_by.get_or_insert((index, target));
+                "matched".green().to_string()
+            }
+            MatchVerdict::FilteredOut(filter) => {
+                format!("{} by {filter}", "filtered out".yellow())
+            }
+            MatchVerdict::RuleNotSatisfied
+                if process.pid.is_none()
+                    && target.rule.as_ref().is_some_and(|rule| rule.needs_pid()) =>
+            {
+                format!(
+                    "{}: {}",
+                    "rule needs a live pid, skipped".yellow(),
+                    target.rule.as_ref().unwrap()
+                )
+            }
+            MatchVerdict::RuleNotSatisfied => format!(
+                "{}: {}",
+                "rule not satisfied".yellow(),
+                target.rule.as_ref().unwrap()
+            ),
+        };
+        let _ = writeln!(output, "  #{index} {}: {verdict}", target.match_type);
+    }
+
+    match tracked_by {
+        Some((index, target)) => {
+            let display_name = target
+                .get_display_name_object()
+                .get_display_name(&process.name, &process.args);
+            let _ = write!(
+                output,
+                "  tracked as {} by target #{index}",
+                display_name.bold()
+            );
+            if target.should_be_merged_with_parents() {
+                match merged_into(index) {
+                    Some(pid) if Some(pid) != process.pid => {
+                        let _ = write!(output, ", merged into ancestor {pid}");
+                    }
+                    Some(_) => {}
+                    None => output.push_str(", merged with its parents"),
+                }
+            }
+            output.push('\n');
+        }
+        None => {
+            let _ = writeln!(output, "  {}", "not tracked".red());
+        }
+    }
+    output
+}
+
+fn scan_processes(targets: &[Target]) -> String {
+    let mut system = System::new();
+    system.refresh_processes_specifics(
+        ProcessRefreshKind::new()
+            .with_cmd(UpdateKind::Always)
+            .with_exe(UpdateKind::Always),
+    );
+
+    let mut processes: Vec<_> = system
+        .processes()
+        .values()
+        .filter(|proc| proc.thread_kind().is_none())
+        .collect();
+    processes.sort_by_key(|proc| proc.pid());
+
+    let explained: Vec<(ProcessInfo, Vec<MatchVerdict>)> = processes
+        .iter()
+        .map(|proc| {
+            let process = process_info(proc);
+            let verdicts = targets
+                .iter()
+                .map(|t| t.explain(&process, system.processes()))
+                .collect();
+            (process, verdicts)
+        })
+        .collect();
+    // the processes each target matches, the daemon merges them into their ancestors the same way
+    let matched_by: Vec<HashSet<Pid>> = (0..targets.len())
+        .map(|index| {
+            explained
+                .iter()
+                .filter(|(_, verdicts)| verdicts[index] == MatchVerdict::Matched)
+                .filter_map(|(process, _)| process.pid.map(Pid::from_u32))
+                .collect()
+        })
+        .collect();
+
+    let mut output = String::new();
+    let mut tracked = 0;
+    for (process, verdicts) in &explained {
+        if verdicts
+            .iter()
+            .all(|verdict| *verdict == MatchVerdict::NotMatched)
+        {
+            continue;
+        }
+        if verdicts.contains(&MatchVerdict::Matched) {
+            tracked += 1;
+        }
+        let merged_into = |index: usize| {
+            let pid = Pid::from_u32(process.pid?);
+            let ancestor = merged_ancestor(
+                pid,
+                |pid| system.process(pid)?.parent(),
+                |pid| matched_by[index].contains(&pid),
+                targets[index].should_force_ancestor_to_match(),
+            );
+            Some(ancestor.as_u32())
+        };
+        let _ = writeln!(
+            output,
+            "{}",
+            explain_process(targets, process, system.processes(), merged_into)
+        );
+    }
+    let _ = writeln!(
+        output,
+        "{tracked} of {} processes are tracked",
+        processes.len()
+    );
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config_manager::target_process::rules::{EnvVarStruct, TargetRule};
+    use crate::config_manager::target_process::target_matching::{
+        CommandContainsStruct, TargetMatch,
+    };
+    use crate::config_manager::target_process::DisplayName;
+
+    #[test]
+    fn test_explain_process() {
+        colored::control::set_override(false);
+        let targets = vec![
+            Target::new(TargetMatch::ProcessName("java".to_string())).set_filter_out(Some(vec![
+                TargetMatch::CommandContains(CommandContainsStruct {
+                    process_name: None,
+                    command_content: "--version".to_string(),
+                }),
+            ])),
+            Target::new(TargetMatch::CommandContains(CommandContainsStruct {
+                process_name: Some("java".to_string()),
+                command_content: "picard.jar".to_string(),
+            }))
+            .set_display_name(DisplayName::Name("picard".to_string())),
+        ];
+        let explain = |command: &str| {
+            explain_process(
+                &targets,
+                &ProcessInfo::new("java", command, "/usr/bin/java"),
+                &(),
+                |_| None,
+            )
+        };
+
+        let output = explain("java -jar picard.jar --version");
+        assert!(output.contains(
+            r#"#0 process name is "java": filtered out by command contains "--version""#
+        ));
+        assert!(output
+            .contains(r#"#1 command contains "picard.jar" and process name is "java": matched"#));
+        assert!(output.contains("tracked as picard by target #1"));
+
+        let output = explain("java -jar picard.jar MarkDuplicates");
+        assert!(output.contains("tracked as java by target #0"));
+
+        let output = explain_process(
+            &targets,
+            &ProcessInfo::new("bwa", "bwa mem", ""),
+            &(),
+            |_| None,
+        );
+        assert!(output.contains("not tracked"));
+
+        let pack = TargetPack {
+            name: "lab.toml".to_string(),
+            version: Some("2025.03".to_string()),
+            targets: targets[1..].to_vec(),
+            ..Default::default()
+        };
+        let output = list_targets(&targets, &targets[..1], &[pack]);
+        assert!(output.contains(
+            r#"tracer.toml  java                     process name is "java", named after the process name, unless command contains "--version""#
+        ));
+        assert!(output.contains("#1    lab.toml     java:picard.jar"));
+        assert!(output.contains("pack lab.toml 2025.03, priority 0"));
+        assert!(list_targets(&TARGETS[..1], &[], &[]).contains("built-in"));
+    }
+
+    #[test]
+    fn test_explain_merged_and_pid_rules() {
+        colored::control::set_override(false);
+        let targets = vec![
+            Target::new(TargetMatch::ProcessName("nextflow".to_string())).set_rule(Some(
+                TargetRule::EnvVar(EnvVarStruct {
+                    name: "NXF_HOME".to_string(),
+                    value: None,
+                }),
+            )),
+            Target::new(TargetMatch::ProcessName("java".to_string())).set_merge_with_parents(true),
+        ];
+
+        // `tracer targets test` has no pid to read the environment of
+        let output = explain_process(
+            &targets,
+            &ProcessInfo::new("nextflow", "nextflow run", ""),
+            &(),
+            |_| None,
+        );
+        assert!(output.contains("rule needs a live pid, skipped: $NXF_HOME is set"));
+
+        let java =
+            ProcessInfo::new("java", "java -jar app.jar", "/usr/bin/java").set_pid(42, Some(7));
+        let output = explain_process(&targets, &java, &(), |_| Some(7));
+        assert!(output.contains("tracked as java by target #1, merged into ancestor 7"));
+        let output = explain_process(&targets, &java, &(), |_| Some(42));
+        assert!(output.contains("tracked as java by target #1\n"));
+    }
+}
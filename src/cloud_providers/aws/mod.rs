@@ -2,6 +2,7 @@ mod pricing;
 mod s3;
 mod secrets;
 
+use anyhow::{Context, Result};
 use aws_config::{BehaviorVersion, SdkConfig};
 use aws_credential_types::provider::ProvideCredentials;
 pub use pricing::PricingClient;
@@ -16,7 +17,7 @@ use crate::types::config::AwsConfig;
 async fn get_initialized_aws_conf(
     initialization_conf: AwsConfig,
     region: &'static str,
-) -> SdkConfig {
+) -> Result<SdkConfig> {
     let config_loader = aws_config::defaults(BehaviorVersion::latest());
     let config = match initialization_conf {
         AwsConfig::Profile(profile) => config_loader.profile_name(profile),
@@ -29,7 +30,7 @@ async fn get_initialized_aws_conf(
             let assumed_credentials_provider = assumed_role_provider
                 .provide_credentials()
                 .await
-                .expect("Failed to get assumed session role");
+                .context("Failed to get assumed session role")?;
 
             config_loader.credentials_provider(assumed_credentials_provider)
         }
@@ -41,11 +42,11 @@ async fn get_initialized_aws_conf(
 
     let credentials_provider = config
         .credentials_provider()
-        .expect("Failed to get credentials_provider");
+        .context("Failed to get credentials_provider")?;
     let _ = credentials_provider
         .provide_credentials()
         .await
-        .expect("No Credentials Loaded");
+        .context("No Credentials Loaded")?;
 
-    config
+    Ok(config)
 }
@@ -19,13 +19,16 @@ pub struct PricingClient {
 impl PricingClient {
     /// Creates a new PricingClient instance
     /// Note: Currently only us-east-1 region is supported for the pricing API
-    pub async fn new(initialization_conf: AwsConfig, _region: &'static str) -> Self {
+    pub async fn new(
+        initialization_conf: AwsConfig,
+        _region: &'static str,
+    ) -> Result<Self, anyhow::Error> {
         let region = "us-east-1";
-        let config = get_initialized_aws_conf(initialization_conf, region).await;
+        let config = get_initialized_aws_conf(initialization_conf, region).await?;
 
-        Self {
+        Ok(Self {
             client: pricing::client::Client::new(&config),
-        }
+        })
     }
 
     /// Fetches EC2 instance pricing based on provided filters
@@ -171,7 +174,9 @@ mod tests {
     async fn setup_client() -> PricingClient {
         dotenv().ok();
         let config = AwsConfig::Env;
-        PricingClient::new(config, "us-east-1").await
+        PricingClient::new(config, "us-east-1")
+            .await
+            .expect("Failed to create pricing client")
     }
 
     // Basic functionality test
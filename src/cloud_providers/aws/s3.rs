@@ -14,13 +14,16 @@ pub struct S3Client {
 
 #[allow(dead_code)]
 impl S3Client {
-    pub async fn new(initialization_conf: AwsConfig, region: &'static str) -> Self {
-        let config = get_initialized_aws_conf(initialization_conf, region).await;
+    pub async fn new(
+        initialization_conf: AwsConfig,
+        region: &'static str,
+    ) -> Result<Self, anyhow::Error> {
+        let config = get_initialized_aws_conf(initialization_conf, region).await?;
 
-        Self {
+        Ok(Self {
             client: aws_sdk_s3::Client::new(&config),
             region: region.to_string(),
-        }
+        })
     }
 
     pub async fn new_with_config(config: SdkConfig, region: &str) -> Self {
@@ -259,7 +262,9 @@ pub mod tests {
         let region = "us-east-2";
         setup_env_vars(region);
         let config = AwsConfig::Env;
-        S3Client::new(config, region).await
+        S3Client::new(config, region)
+            .await
+            .expect("Failed to create s3 client")
     }
 
     #[tokio::test]
@@ -7,13 +7,15 @@ pub struct SecretsClient {
 }
 
 impl SecretsClient {
-    pub async fn new(initialization_conf: crate::types::config::AwsConfig) -> Self {
+    pub async fn new(
+        initialization_conf: crate::types::config::AwsConfig,
+    ) -> Result<Self, anyhow::Error> {
         let region = "us-east-1";
-        let config = get_initialized_aws_conf(initialization_conf, region).await;
+        let config = get_initialized_aws_conf(initialization_conf, region).await?;
 
-        Self {
+        Ok(Self {
             client: Client::new(&config),
-        }
+        })
     }
 
     pub async fn get_secrets<T>(&self, secret_arn: &str) -> Result<T, anyhow::Error>
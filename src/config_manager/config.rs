@@ -12,12 +12,15 @@ use crate::{
         bashrc_intercept::{modify_bashrc_file, rewrite_interceptor_bashrc_file},
         target_process::target_matching::TargetMatch,
     },
-    types::{aws::aws_region::AwsRegion, config::AwsConfig},
+    types::{
+        aws::aws_region::AwsRegion,
+        config::{AwsConfig, DiscoveryConfig, ExportersConfig, NotificationsConfig, SpoolConfig},
+    },
 };
 
 use crate::config_manager::target_process::Target;
 
-use super::target_process::targets_list;
+use super::target_process::{target_packs, targets_list};
 
 const DEFAULT_API_KEY: &str = "EAjg7eHtsGnP3fTURcPz1";
 const DEFAULT_CONFIG_FILE_LOCATION_FROM_HOME: &str = ".config/tracer/tracer.toml";
@@ -37,6 +40,7 @@ pub struct ConfigFile {
     pub new_run_pause_ms: Option<u64>,
     pub file_size_not_changing_period_ms: Option<u64>,
     pub process_metrics_send_interval_ms: Option<u64>,
+    pub aggregate_process_tree_metrics: Option<bool>,
     pub targets: Option<Vec<Target>>,
     pub aws_region: Option<String>,
     pub aws_role_arn: Option<String>,
@@ -46,6 +50,12 @@ pub struct ConfigFile {
     pub database_name: String,
 
     pub grafana_workspace_url: String,
+
+    pub offline: Option<bool>,
+    pub exporters: Option<ExportersConfig>,
+    pub spool: Option<SpoolConfig>,
+    pub notifications: Option<NotificationsConfig>,
+    pub discovery: Option<DiscoveryConfig>,
 }
 
 #[derive(Clone, Debug)]
@@ -56,6 +66,8 @@ pub struct Config {
     pub process_metrics_send_interval_ms: u64,
     pub file_size_not_changing_period_ms: u64,
     pub new_run_pause_ms: u64,
+    /// Tool metrics sum up the tool's descendant processes, only the tool's own process is measured when false
+    pub aggregate_process_tree_metrics: bool,
     pub targets: Vec<Target>,
     pub aws_init_type: AwsConfig,
     pub aws_region: AwsRegion,
@@ -65,6 +77,13 @@ pub struct Config {
     pub database_name: String,
 
     pub grafana_workspace_url: String,
+
+    /// Skips every AWS and database lookup, events are only written to local sinks
+    pub offline: bool,
+    pub exporters: ExportersConfig,
+    pub spool: SpoolConfig,
+    pub notifications: NotificationsConfig,
+    pub discovery: DiscoveryConfig,
 }
 
 pub struct ConfigManager;
@@ -82,9 +101,29 @@ impl ConfigManager {
         }
     }
 
+    pub fn get_target_packs_path() -> Option<PathBuf> {
+        match homedir::get_my_home() {
+            Ok(Some(path)) => Some(path.join(target_packs::TARGET_PACKS_DIR_FROM_HOME)),
+            _ => None,
+        }
+    }
+
+    /// The built-in targets with the config's targets and the target packs merged in,
+    /// what the daemon matches processes against
+    pub fn load_targets(config: &Config) -> Vec<Target> {
+        let packs = ConfigManager::get_target_packs_path()
+            .map(|dir| target_packs::load_target_packs(&dir))
+            .unwrap_or_default();
+        target_packs::merge_target_packs(&targets_list::TARGETS, &config.targets, &packs)
+    }
+
     fn load_config_from_file(path: &PathBuf) -> Result<Config> {
         let config = std::fs::read_to_string(path)?;
-        let config: ConfigFile = toml::from_str(&config)?;
+        Self::load_config_from_str(&config)
+    }
+
+    fn load_config_from_str(config: &str) -> Result<Config> {
+        let config: ConfigFile = toml::from_str(config)?;
 
         let aws_init_type = match (config.aws_role_arn, config.aws_profile) {
             (Some(role), None) => AwsConfig::RoleArn(role),
@@ -108,9 +147,8 @@ impl ConfigManager {
             file_size_not_changing_period_ms: config
                 .file_size_not_changing_period_ms
                 .unwrap_or(FILE_SIZE_NOT_CHANGING_PERIOD_MS),
-            targets: config
-                .targets
-                .unwrap_or_else(|| targets_list::TARGETS.to_vec()),
+            aggregate_process_tree_metrics: config.aggregate_process_tree_metrics.unwrap_or(true),
+            targets: config.targets.unwrap_or_default(),
             aws_init_type,
             aws_region: AwsRegion::UsEast2,
 
@@ -119,6 +157,12 @@ impl ConfigManager {
             database_host: config.database_host,
 
             grafana_workspace_url: config.grafana_workspace_url,
+
+            offline: config.offline.unwrap_or(false),
+            exporters: config.exporters.unwrap_or_default(),
+            spool: config.spool.unwrap_or_default(),
+            notifications: config.notifications.unwrap_or_default(),
+            discovery: config.discovery.unwrap_or_default(),
         })
     }
 
@@ -129,7 +173,8 @@ impl ConfigManager {
             batch_submission_interval_ms: BATCH_SUBMISSION_INTERVAL_MS,
             new_run_pause_ms: NEW_RUN_PAUSE_MS,
             file_size_not_changing_period_ms: FILE_SIZE_NOT_CHANGING_PERIOD_MS,
-            targets: targets_list::TARGETS.to_vec(),
+            aggregate_process_tree_metrics: true,
+            targets: vec![],
             process_metrics_send_interval_ms: PROCESS_METRICS_SEND_INTERVAL_MS,
             // aws_init_type: AwsConfig::Profile("me".to_string()),
             aws_init_type: AwsConfig::Profile(
@@ -150,7 +195,13 @@ impl ConfigManager {
             database_host:
                 "tracer-cluster-v2-instance-1.cdgizpzxtdp6.us-east-1.rds.amazonaws.com:5432".into(),
 
-            grafana_workspace_url: DEFAULT_GRAFANA_WORKSPACE_URL.to_string()
+            grafana_workspace_url: DEFAULT_GRAFANA_WORKSPACE_URL.to_string(),
+
+            offline: false,
+            exporters: ExportersConfig::default(),
+            spool: SpoolConfig::default(),
+            notifications: NotificationsConfig::default(),
+            discovery: DiscoveryConfig::default(),
         }
     }
 
@@ -175,6 +226,10 @@ impl ConfigManager {
             config.api_key = api_key;
         }
 
+        if let Ok(offline) = std::env::var("TRACER_OFFLINE") {
+            config.offline = matches!(offline.to_lowercase().as_str(), "1" | "true" | "yes");
+        }
+
         config
     }
 
@@ -182,8 +237,7 @@ impl ConfigManager {
         let config = ConfigManager::load_config();
         rewrite_interceptor_bashrc_file(
             env::current_exe()?,
-            config
-                .targets
+            ConfigManager::load_targets(&config)
                 .iter()
                 .filter(|target| {
                     matches!(
@@ -218,8 +272,9 @@ impl ConfigManager {
             file_size_not_changing_period_ms: Some(config.file_size_not_changing_period_ms),
             process_polling_interval_ms: Some(config.process_polling_interval_ms),
             batch_submission_interval_ms: Some(config.batch_submission_interval_ms),
-            targets: Some(config.targets.clone()),
+            targets: (!config.targets.is_empty()).then(|| config.targets.clone()),
             process_metrics_send_interval_ms: Some(config.process_metrics_send_interval_ms),
+            aggregate_process_tree_metrics: Some(config.aggregate_process_tree_metrics),
             aws_role_arn,
             aws_profile,
             aws_region: Some(config.aws_region.as_str().to_string()),
@@ -228,6 +283,11 @@ impl ConfigManager {
             database_name: config.database_name.clone(),
             database_host: config.database_host.clone(),
             grafana_workspace_url: config.grafana_workspace_url.clone(),
+            offline: Some(config.offline),
+            exporters: Some(config.exporters.clone()),
+            spool: Some(config.spool.clone()),
+            notifications: Some(config.notifications.clone()),
+            discovery: Some(config.discovery.clone()),
         };
         let config = toml::to_string(&config_out)?;
         std::fs::write(config_file_location, config)?;
@@ -313,7 +373,8 @@ mod tests {
             config.process_metrics_send_interval_ms,
             PROCESS_METRICS_SEND_INTERVAL_MS
         );
-        assert!(!config.targets.is_empty());
+        assert!(config.targets.is_empty());
+        assert!(!ConfigManager::load_targets(&config).is_empty());
     }
 
     #[test]
@@ -357,4 +418,63 @@ mod tests {
 
         assert!(ConfigManager::validate_path(&file_path).is_err());
     }
+
+    #[test]
+    fn test_exporters_config_from_file() {
+        let config = r#"
+            api_key = "key"
+            database_secrets_arn = "arn"
+            database_host = "db:5432"
+            database_name = "tracer_db"
+            grafana_workspace_url = "https://grafana"
+
+            [exporters.aurora]
+            enabled = false
+
+            [exporters.local_file]
+            enabled = true
+            directory = "/tmp/tracer-exports"
+        "#;
+
+        let config = ConfigManager::load_config_from_str(config).unwrap();
+        assert!(!config.exporters.aurora.enabled);
+        assert!(config.exporters.local_file.enabled);
+        assert_eq!(
+            config.exporters.local_file.directory.as_deref(),
+            Some("/tmp/tracer-exports")
+        );
+    }
+
+    #[test]
+    fn test_exporters_config_defaults_to_aurora_only() {
+        let config = r#"
+            api_key = "key"
+            database_secrets_arn = "arn"
+            database_host = "db:5432"
+            database_name = "tracer_db"
+            grafana_workspace_url = "https://grafana"
+        "#;
+
+        let config = ConfigManager::load_config_from_str(config).unwrap();
+        assert!(config.exporters.aurora.enabled);
+        assert!(!config.exporters.local_file.enabled);
+        assert!(!config.exporters.prometheus.enabled);
+        assert!(!config.offline);
+        assert!(config.aggregate_process_tree_metrics);
+    }
+
+    #[test]
+    fn test_offline_config_from_file() {
+        let config = r#"
+            api_key = "key"
+            database_secrets_arn = "arn"
+            database_host = "db:5432"
+            database_name = "tracer_db"
+            grafana_workspace_url = "https://grafana"
+            offline = true
+        "#;
+
+        let config = ConfigManager::load_config_from_str(config).unwrap();
+        assert!(config.offline);
+    }
 }
@@ -1,7 +1,11 @@
 // File: src/target/mod.rs
+pub mod rules;
 pub mod target_matching;
+pub mod target_packs;
 pub mod targets_list;
+use rules::{ProcessInfo, ProcessLookup, TargetRule};
 use serde::{Deserialize, Serialize};
+use std::fmt;
 use target_matching::{matches_target, TargetMatch};
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
@@ -48,27 +52,66 @@ impl DisplayName {
     }
 }
 
+impl fmt::Display for DisplayName {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DisplayName::Name(name) => write!(f, "{name:?}"),
+            DisplayName::Default() => write!(f, "the process name"),
+            DisplayName::UseFirstArgument() => write!(f, "the first argument"),
+            DisplayName::UseFirstArgumentBaseName() => {
+                write!(f, "the base name of the first argument")
+            }
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
 pub struct Target {
+    /// Names the target for target packs to replace or disable, see `Target::id`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
     pub match_type: TargetMatch,
     pub display_name: DisplayName,
     pub merge_with_parents: bool,
     pub force_ancestor_to_match: bool,
     pub filter_out: Option<Vec<TargetMatch>>,
+    /// Has to hold on top of `match_type`, for conditions on the process' ancestors and environment
+    #[serde(default)]
+    pub rule: Option<TargetRule>,
 }
 
 pub trait TargetMatchable {
     fn matches(&self, process_name: &str, command: &str, bin_path: &str) -> bool;
 }
 
+/// Why a target does or doesn't match a process, see `Target::explain`
+#[derive(Debug, Clone, PartialEq)]
+pub enum MatchVerdict {
+    Matched,
+    /// `match_type` matched, this `filter_out` entry excluded the process
+    FilteredOut(TargetMatch),
+    /// `match_type` matched, `rule` didn't hold
+    RuleNotSatisfied,
+    NotMatched,
+}
+
 impl Target {
     pub fn new(match_type: TargetMatch) -> Target {
         Target {
+            id: None,
             match_type,
             display_name: DisplayName::Default(),
             merge_with_parents: true,
             force_ancestor_to_match: true,
             filter_out: None,
+            rule: None,
+        }
+    }
+
+    pub fn set_id(self, id: &str) -> Target {
+        Target {
+            id: Some(id.to_string()),
+            ..self
         }
     }
 
@@ -97,6 +140,34 @@ impl Target {
         Target { filter_out, ..self }
     }
 
+    pub fn set_rule(self, rule: Option<TargetRule>) -> Target {
+        Target { rule, ..self }
+    }
+
+    /// `id`, or what the target matches on when it has none, e.g. `bwa` or `python3.12:plotPCA`
+    pub fn id(&self) -> String {
+        if let Some(id) = &self.id {
+            return id.clone();
+        }
+        match &self.match_type {
+            TargetMatch::ProcessName(name)
+            | TargetMatch::ShortLivedProcessExecutable(name)
+            | TargetMatch::BinPathStartsWith(name)
+            | TargetMatch::BinPathLastComponent(name) => name.clone(),
+            TargetMatch::CommandContains(inner) => match &inner.process_name {
+                Some(process_name) => format!("{process_name}:{}", inner.command_content),
+                None => inner.command_content.clone(),
+            },
+            TargetMatch::ProcessNameRegex(pattern) | TargetMatch::CommandRegex(pattern) => {
+                pattern.as_str().to_string()
+            }
+            TargetMatch::BinPathGlob(pattern) => pattern.as_str().to_string(),
+            TargetMatch::ArgumentMatches(inner) => {
+                format!("{}:{}", inner.position, inner.pattern.as_str())
+            }
+        }
+    }
+
     pub fn should_be_merged_with_parents(&self) -> bool {
         self.merge_with_parents
     }
@@ -108,23 +179,59 @@ impl Target {
     pub fn get_display_name_object(&self) -> DisplayName {
         self.display_name.clone()
     }
+
+    /// Like `matches`, with the rule's conditions on the process' surroundings read from `/proc`
+    /// and its ancestors from `processes`
+    pub fn matches_process(&self, process: &ProcessInfo, processes: &dyn ProcessLookup) -> bool {
+        self.matches_command(process)
+            && self
+                .rule
+                .as_ref()
+                .map_or(true, |rule| rule.matches(process, processes))
+    }
+
+    /// `matches_process` step by step, for `tracer targets` to tell which part decided
+    pub fn explain(&self, process: &ProcessInfo, processes: &dyn ProcessLookup) -> MatchVerdict {
+        if !matches_target(&self.match_type, process) {
+            return MatchVerdict::NotMatched;
+        }
+        let filtered_by = self
+            .filter_out
+            .iter()
+            .flatten()
+            .find(|filter| matches_target(filter, process));
+        if let Some(filter) = filtered_by {
+            return MatchVerdict::FilteredOut(filter.clone());
+        }
+        if self
+            .rule
+            .as_ref()
+            .is_some_and(|rule| !rule.matches(process, processes))
+        {
+            return MatchVerdict::RuleNotSatisfied;
+        }
+        MatchVerdict::Matched
+    }
+
+    fn matches_command(&self, process: &ProcessInfo) -> bool {
+        matches_target(&self.match_type, process)
+            && !self
+                .filter_out
+                .iter()
+                .flatten()
+                .any(|filter| matches_target(filter, process))
+    }
 }
 
 impl TargetMatchable for Target {
     fn matches(&self, process_name: &str, command: &str, bin_path: &str) -> bool {
-        matches_target(&self.match_type, process_name, command, bin_path)
-            && (self.filter_out.is_none()
-                || !self
-                    .filter_out
-                    .as_ref()
-                    .unwrap()
-                    .matches(process_name, command, bin_path))
+        self.matches_process(&ProcessInfo::new(process_name, command, bin_path), &())
     }
 }
 
 impl TargetMatchable for Vec<TargetMatch> {
     fn matches(&self, process_name: &str, command: &str, bin_path: &str) -> bool {
-        self.iter()
-            .any(|target| matches_target(target, process_name, command, bin_path))
+        let process = ProcessInfo::new(process_name, command, bin_path);
+        self.iter().any(|target| matches_target(target, &process))
     }
 }
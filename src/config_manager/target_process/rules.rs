@@ -0,0 +1,422 @@
+//! Rules composing match types with conditions on a process' ancestors, environment and working directory
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+use super::target_matching::{matches_target, GlobPattern, RegexPattern, TargetMatch};
+
+/// How far up the tree `AncestorMatches` looks, guards against pid reuse making a cycle
+const MAX_ANCESTORS: usize = 64;
+
+/// A variable of the environment the process was started with, `value` matches any value when missing
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct EnvVarStruct {
+    pub name: String,
+    pub value: Option<RegexPattern>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum TargetRule {
+    Match(TargetMatch),
+    All(Vec<TargetRule>),
+    Any(Vec<TargetRule>),
+    Not(Box<TargetRule>),
+    /// The rule holds for the direct parent
+    ParentMatches(Box<TargetRule>),
+    /// The rule holds for the parent or any process above it
+    AncestorMatches(Box<TargetRule>),
+    EnvVar(EnvVarStruct),
+    WorkingDirectory(GlobPattern),
+}
+
+impl fmt::Display for TargetRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let list = |f: &mut fmt::Formatter<'_>, name: &str, rules: &[TargetRule]| {
+            let rules: Vec<String> = rules.iter().map(ToString::to_string).collect();
+            write!(f, "{name}({})", rules.join(", "))
+        };
+        match self {
+            TargetRule::Match(target) => write!(f, "{target}"),
+            TargetRule::All(rules) => list(f, "all", rules),
+            TargetRule::Any(rules) => list(f, "any", rules),
+            TargetRule::Not(rule) => write!(f, "not({rule})"),
+            TargetRule::ParentMatches(rule) => write!(f, "parent({rule})"),
+            TargetRule::AncestorMatches(rule) => write!(f, "ancestor({rule})"),
+            TargetRule::EnvVar(inner) => match &inner.value {
+                Some(pattern) => write!(f, "${} matches /{}/", inner.name, pattern.as_str()),
+                None => write!(f, "${} is set", inner.name),
+            },
+            TargetRule::WorkingDirectory(pattern) => {
+                write!(f, "working directory matches glob {:?}", pattern.as_str())
+            }
+        }
+    }
+}
+
+/// A process rules are evaluated against. Its ancestors, environment and working directory
+/// are read from `/proc` only when a rule asks for them
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ProcessInfo {
+    /// `None` when only the command is known, conditions on the process' surroundings don't hold then
+    pub pid: Option<u32>,
+    pub parent_pid: Option<u32>,
+    pub name: String,
+    /// The space joined `args`
+    pub command: String,
+    pub args: Vec<String>,
+    pub bin_path: String,
+}
+
+impl ProcessInfo {
+    /// When only the joined command is known, its arguments are split back on whitespace
+    pub fn new(name: &str, command: &str, bin_path: &str) -> Self {
+        ProcessInfo {
+            name: name.to_string(),
+            command: command.to_string(),
+            args: command.split_whitespace().map(str::to_string).collect(),
+            bin_path: bin_path.to_string(),
+            ..Default::default()
+        }
+    }
+
+    pub fn from_argv(name: &str, args: &[String], bin_path: &str) -> Self {
+        ProcessInfo {
+            name: name.to_string(),
+            command: args.join(" "),
+            args: args.to_vec(),
+            bin_path: bin_path.to_string(),
+            ..Default::default()
+        }
+    }
+
+    pub fn set_pid(self, pid: u32, parent_pid: Option<u32>) -> Self {
+        ProcessInfo {
+            pid: Some(pid),
+            parent_pid,
+            ..self
+        }
+    }
+
+    /// A running process read from `/proc`, `None` once it's gone
+    pub fn read(pid: u32) -> Option<Self> {
+        let cmd: Vec<String> = std::fs::read(format!("/proc/{pid}/cmdline"))
+            .ok()?
+            .split(|byte| *byte == 0)
+            .filter(|arg| !arg.is_empty())
+            .map(|arg| String::from_utf8_lossy(arg).into_owned())
+            .collect();
+        let (name, parent_pid) = read_stat(pid)?;
+        let exe = std::fs::read_link(format!("/proc/{pid}/exe"))
+            .map(|exe| exe.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        Some(ProcessInfo::from_argv(&name, &cmd, &exe).set_pid(pid, parent_pid))
+    }
+
+    /// Parent first, only the ones a rule gets to are looked up
+    fn ancestors<'a>(
+        &self,
+        processes: &'a dyn ProcessLookup,
+    ) -> impl Iterator<Item = ProcessInfo> + 'a {
+        let parent_pid = self.parent_pid.or_else(|| processes.parent_pid(self.pid?));
+        std::iter::successors(parent_pid, |pid| processes.parent_pid(*pid))
+            // the kernel's own tasks and init have no parent
+            .take_while(|pid| *pid != 0)
+            .take(MAX_ANCESTORS)
+            .map_while(|pid| processes.process(pid))
+    }
+
+    fn env_var(&self, name: &str) -> Option<String> {
+        let environ = std::fs::read(format!("/proc/{}/environ", self.pid?)).ok()?;
+        environ.split(|byte| *byte == 0).find_map(|entry| {
+            let value = entry.strip_prefix(name.as_bytes())?.strip_prefix(b"=")?;
+            Some(String::from_utf8_lossy(value).into_owned())
+        })
+    }
+
+    fn working_directory(&self) -> Option<String> {
+        let cwd = std::fs::read_link(format!("/proc/{}/cwd", self.pid?)).ok()?;
+        Some(cwd.to_string_lossy().into_owned())
+    }
+}
+
+/// Name and parent pid from `/proc/<pid>/stat`
+fn read_stat(pid: u32) -> Option<(String, Option<u32>)> {
+    let stat = std::fs::read_to_string(format!("/proc/{pid}/stat")).ok()?;
+    // the name can contain spaces and parens, it's enclosed by the first '(' and the last ')'
+    let name = stat.get(stat.find('(')? + 1..stat.rfind(')')?)?.to_string();
+    let parent_pid = stat[stat.rfind(')')? + 1..]
+        .split_whitespace()
+        .nth(1)
+        .and_then(|ppid| ppid.parse().ok());
+    Some((name, parent_pid))
+}
+
+/// Where rules find the ancestors of a process, the daemon passes the processes it polled
+pub trait ProcessLookup {
+    fn parent_pid(&self, pid: u32) -> Option<u32>;
+    /// A running process, `None` once it's gone
+    fn process(&self, pid: u32) -> Option<ProcessInfo>;
+}
+
+/// Knows no processes, for processes only known by their command
+impl ProcessLookup for () {
+    fn parent_pid(&self, _pid: u32) -> Option<u32> {
+        None
+    }
+
+    fn process(&self, _pid: u32) -> Option<ProcessInfo> {
+        None
+    }
+}
+
+/// Reads processes from `/proc`, for when no processes were polled
+pub struct ProcFs;
+
+impl ProcessLookup for ProcFs {
+    fn parent_pid(&self, pid: u32) -> Option<u32> {
+        read_stat(pid)?.1
+    }
+
+    fn process(&self, pid: u32) -> Option<ProcessInfo> {
+        ProcessInfo::read(pid)
+    }
+}
+
+impl TargetRule {
+    /// Whether the rule looks at the process' surroundings, which only a running process has
+    pub fn needs_pid(&self) -> bool {
+        match self {
+            TargetRule::Match(_) => false,
+            TargetRule::All(rules) | TargetRule::Any(rules) => rules.iter().any(Self::needs_pid),
+            TargetRule::Not(rule) => rule.needs_pid(),
+            TargetRule::ParentMatches(_)
+            | TargetRule::AncestorMatches(_)
+            | TargetRule::EnvVar(_)
+            | TargetRule::WorkingDirectory(_) => true,
+        }
+    }
+
+    /// Conditions on what can't be read, a process that's gone or another user's environment, don't hold.
+    /// Ancestors come from `processes`
+    pub fn matches(&self, process: &ProcessInfo, processes: &dyn ProcessLookup) -> bool {
+        match self {
+            TargetRule::Match(target) => matches_target(target, process),
+            TargetRule::All(rules) => rules.iter().all(|rule| rule.matches(process, processes)),
+            TargetRule::Any(rules) => rules.iter().any(|rule| rule.matches(process, processes)),
+            TargetRule::Not(rule) => !rule.matches(process, processes),
+            TargetRule::ParentMatches(rule) => process
+                .ancestors(processes)
+                .next()
+                .is_some_and(|parent| rule.matches(&parent, processes)),
+            TargetRule::AncestorMatches(rule) => process
+                .ancestors(processes)
+                .any(|ancestor| rule.matches(&ancestor, processes)),
+            TargetRule::EnvVar(inner) => process.env_var(&inner.name).is_some_and(|value| {
+                inner
+                    .value
+                    .as_ref()
+                    .map_or(true, |pattern| pattern.is_match(&value))
+            }),
+            TargetRule::WorkingDirectory(pattern) => process
+                .working_directory()
+                .is_some_and(|cwd| pattern.is_match(&cwd)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config_manager::target_process::target_matching::CommandContainsStruct;
+    use crate::config_manager::target_process::{Target, TargetMatchable};
+    use std::collections::HashMap;
+    use std::process::Command;
+
+    #[test]
+    fn test_combinators() {
+        let picard = TargetRule::All(vec![
+            TargetRule::Match(TargetMatch::ProcessName("java".to_string())),
+            TargetRule::Match(TargetMatch::CommandContains(CommandContainsStruct {
+                process_name: None,
+                command_content: "picard.jar".to_string(),
+            })),
+            TargetRule::Not(Box::new(TargetRule::Match(TargetMatch::CommandContains(
+                CommandContainsStruct {
+                    process_name: None,
+                    command_content: "--version".to_string(),
+                },
+            )))),
+        ]);
+        let java = |command: &str| ProcessInfo::new("java", command, "/usr/bin/java");
+
+        assert!(picard.matches(&java("java -jar picard.jar MarkDuplicates I=in.bam"), &()));
+        assert!(!picard.matches(&java("java -jar picard.jar --version"), &()));
+        assert!(!picard.matches(&java("java -jar gatk.jar HaplotypeCaller"), &()));
+
+        let any = TargetRule::Any(vec![
+            TargetRule::Match(TargetMatch::ProcessName("bwa".to_string())),
+            TargetRule::Match(TargetMatch::ProcessName("bwa-mem2".to_string())),
+        ]);
+        assert!(any.matches(
+            &ProcessInfo::new("bwa-mem2", "bwa-mem2 mem", "/usr/bin/bwa-mem2"),
+            &()
+        ));
+        assert!(!any.matches(
+            &ProcessInfo::new("bowtie2", "bowtie2", "/usr/bin/bowtie2"),
+            &()
+        ));
+        // a process known by its command only has no parent, even to a lookup that reads `/proc`
+        let parent = TargetRule::ParentMatches(Box::new(any));
+        assert!(!parent.matches(
+            &ProcessInfo::new("samtools", "samtools", "/usr/bin/samtools"),
+            &ProcFs
+        ));
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_ancestor_environment_and_working_directory() {
+        let dir = tempfile::tempdir().unwrap();
+        let work_dir = dir.path().join("work").join("a1");
+        std::fs::create_dir_all(&work_dir).unwrap();
+        let script = work_dir.join(".command.run");
+        std::fs::write(&script, "#!/bin/sh\nsleep 5 &\nwait\n").unwrap();
+
+        let mut wrapper = Command::new("sh")
+            .arg(&script)
+            .current_dir(&work_dir)
+            .env("NXF_TASK_WORKDIR", &work_dir)
+            .spawn()
+            .unwrap();
+        std::thread::sleep(std::time::Duration::from_millis(500));
+        let sleep = std::fs::read_to_string(format!(
+            "/proc/{}/task/{}/children",
+            wrapper.id(),
+            wrapper.id()
+        ))
+        .unwrap()
+        .split_whitespace()
+        .next()
+        .unwrap()
+        .parse()
+        .unwrap();
+        let sleep = ProcessInfo::read(sleep).unwrap();
+
+        let command_run = TargetRule::Match(TargetMatch::CommandContains(CommandContainsStruct {
+            process_name: None,
+            command_content: ".command.run".to_string(),
+        }));
+        assert!(TargetRule::ParentMatches(Box::new(command_run.clone())).matches(&sleep, &ProcFs));
+        assert!(TargetRule::AncestorMatches(Box::new(command_run.clone())).matches(&sleep, &ProcFs));
+        // the test runner is an ancestor, the sleep's own command isn't
+        let ancestor = |name: &str| {
+            TargetRule::AncestorMatches(Box::new(TargetRule::Match(TargetMatch::ProcessName(
+                name.to_string(),
+            ))))
+        };
+        assert!(!ancestor("sleep").matches(&sleep, &ProcFs));
+        let runner = ProcessInfo::read(std::process::id()).unwrap();
+        assert!(ancestor(&runner.name).matches(&sleep, &ProcFs));
+
+        let env = |value: Option<&str>| {
+            TargetRule::EnvVar(EnvVarStruct {
+                name: "NXF_TASK_WORKDIR".to_string(),
+                value: value.map(|value| RegexPattern::new(value).unwrap()),
+            })
+        };
+        assert!(env(None).matches(&sleep, &ProcFs));
+        assert!(env(Some("/work/a1$")).matches(&sleep, &ProcFs));
+        assert!(!env(Some("/work/b2$")).matches(&sleep, &ProcFs));
+        assert!(!env(None).matches(&runner, &ProcFs));
+
+        let cwd = |pattern: &str| TargetRule::WorkingDirectory(GlobPattern::new(pattern).unwrap());
+        assert!(cwd("**/work/*").matches(&sleep, &ProcFs));
+        assert!(!cwd("**/work/*").matches(&runner, &ProcFs));
+
+        // pipeline tools and interactive sessions run the same command
+        let target = Target::new(TargetMatch::ProcessName("sleep".to_string()))
+            .set_rule(Some(TargetRule::AncestorMatches(Box::new(command_run))));
+        assert!(target.matches_process(&sleep, &ProcFs));
+        assert!(!target.matches(&sleep.name, &sleep.command, &sleep.bin_path));
+
+        wrapper.kill().unwrap();
+        wrapper.wait().unwrap();
+        let _ = Command::new("kill")
+            .arg(sleep.pid.unwrap().to_string())
+            .status();
+    }
+
+    /// Polled processes, counting the ones looked up
+    struct Polled {
+        processes: HashMap<u32, ProcessInfo>,
+        looked_up: std::cell::RefCell<Vec<u32>>,
+    }
+
+    impl ProcessLookup for Polled {
+        fn parent_pid(&self, pid: u32) -> Option<u32> {
+            self.processes.get(&pid)?.parent_pid
+        }
+
+        fn process(&self, pid: u32) -> Option<ProcessInfo> {
+            self.looked_up.borrow_mut().push(pid);
+            self.processes.get(&pid).cloned()
+        }
+    }
+
+    #[test]
+    fn test_ancestors_come_from_the_lookup() {
+        let process = |pid: u32, parent_pid: u32, name: &str| {
+            (
+                pid,
+                ProcessInfo::new(name, name, "").set_pid(pid, Some(parent_pid)),
+            )
+        };
+        let polled = Polled {
+            processes: HashMap::from([
+                process(1, 0, "init"),
+                process(10, 1, "nextflow"),
+                process(20, 10, "bash"),
+                process(30, 20, "samtools"),
+            ]),
+            looked_up: Default::default(),
+        };
+        let samtools = polled.processes[&30].clone();
+        let named = |name: &str| {
+            Box::new(TargetRule::Match(TargetMatch::ProcessName(
+                name.to_string(),
+            )))
+        };
+
+        assert!(TargetRule::ParentMatches(named("bash")).matches(&samtools, &polled));
+        assert!(!TargetRule::ParentMatches(named("nextflow")).matches(&samtools, &polled));
+        // only the parent is looked up
+        assert_eq!(polled.looked_up.take(), vec![20, 20]);
+
+        assert!(TargetRule::AncestorMatches(named("nextflow")).matches(&samtools, &polled));
+        assert_eq!(polled.looked_up.take(), vec![20, 10]);
+        assert!(!TargetRule::AncestorMatches(named("samtools")).matches(&samtools, &polled));
+        assert_eq!(polled.looked_up.take(), vec![20, 10, 1]);
+    }
+
+    #[test]
+    fn test_rules_load_from_toml() {
+        let target: Target = toml::from_str(
+            r#"
+            match_type = { CommandContains = { process_name = "java", command_content = "picard.jar" } }
+            display_name = { Name = "picard" }
+            merge_with_parents = true
+            force_ancestor_to_match = false
+            rule = { AncestorMatches = { Match = { CommandContains = { command_content = ".command.run" } } } }
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            target.rule,
+            Some(TargetRule::AncestorMatches(Box::new(TargetRule::Match(
+                TargetMatch::CommandContains(CommandContainsStruct {
+                    process_name: None,
+                    command_content: ".command.run".to_string(),
+                })
+            ))))
+        );
+    }
+}
@@ -1,6 +1,11 @@
-use serde::{Deserialize, Serialize};
+use anyhow::{Context, Result};
+use globset::{GlobBuilder, GlobMatcher};
+use regex::{Regex, RegexBuilder};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 
-use std::{borrow::Cow, path::Path};
+use std::{borrow::Cow, fmt, path::Path};
+
+use super::rules::ProcessInfo;
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
 pub struct CommandContainsStruct {
@@ -8,6 +13,91 @@ pub struct CommandContainsStruct {
     pub command_content: String,
 }
 
+/// A case-insensitive regex, compiled when the targets load and written back as its source
+#[derive(Clone, Debug)]
+pub struct RegexPattern(Regex);
+
+impl RegexPattern {
+    pub fn new(pattern: &str) -> Result<Self> {
+        RegexBuilder::new(pattern)
+            .case_insensitive(true)
+            .build()
+            .map(RegexPattern)
+            .with_context(|| format!("Invalid target regex {pattern:?}"))
+    }
+
+    pub fn is_match(&self, text: &str) -> bool {
+        self.0.is_match(text)
+    }
+
+    pub fn as_str(&self) -> &str {
+        self.0.as_str()
+    }
+}
+
+/// A case-insensitive glob where `*` stays within a path component and `**` spans components
+#[derive(Clone, Debug)]
+pub struct GlobPattern {
+    pattern: String,
+    matcher: GlobMatcher,
+}
+
+impl GlobPattern {
+    pub fn new(pattern: &str) -> Result<Self> {
+        let glob = GlobBuilder::new(pattern)
+            .case_insensitive(true)
+            .literal_separator(true)
+            .build()
+            .with_context(|| format!("Invalid target glob {pattern:?}"))?;
+        Ok(GlobPattern {
+            pattern: pattern.to_string(),
+            matcher: glob.compile_matcher(),
+        })
+    }
+
+    pub fn is_match(&self, path: &str) -> bool {
+        self.matcher.is_match(path)
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.pattern
+    }
+}
+
+macro_rules! impl_pattern_serde {
+    ($pattern:ty) => {
+        impl PartialEq for $pattern {
+            fn eq(&self, other: &Self) -> bool {
+                self.as_str() == other.as_str()
+            }
+        }
+
+        impl Serialize for $pattern {
+            fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                serializer.serialize_str(self.as_str())
+            }
+        }
+
+        impl<'de> Deserialize<'de> for $pattern {
+            fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                let pattern = String::deserialize(deserializer)?;
+                <$pattern>::new(&pattern)
+                    .map_err(|err| serde::de::Error::custom(format!("{err:#}")))
+            }
+        }
+    };
+}
+
+impl_pattern_serde!(RegexPattern);
+impl_pattern_serde!(GlobPattern);
+
+/// An argument at a position of the command line, 0 being the program
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ArgumentMatchesStruct {
+    pub position: usize,
+    pub pattern: RegexPattern,
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
 pub enum TargetMatch {
     ProcessName(String),
@@ -15,6 +105,47 @@ pub enum TargetMatch {
     CommandContains(CommandContainsStruct),
     BinPathStartsWith(String),
     BinPathLastComponent(String),
+    ProcessNameRegex(RegexPattern),
+    CommandRegex(RegexPattern),
+    BinPathGlob(GlobPattern),
+    ArgumentMatches(ArgumentMatchesStruct),
+}
+
+impl fmt::Display for TargetMatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TargetMatch::ProcessName(name) => write!(f, "process name is {name:?}"),
+            TargetMatch::ShortLivedProcessExecutable(name) => {
+                write!(f, "short-lived executable {name:?}")
+            }
+            TargetMatch::CommandContains(inner) => {
+                write!(f, "command contains {:?}", inner.command_content)?;
+                match &inner.process_name {
+                    Some(name) => write!(f, " and process name is {name:?}"),
+                    None => Ok(()),
+                }
+            }
+            TargetMatch::BinPathStartsWith(prefix) => {
+                write!(f, "binary path starts with {prefix:?}")
+            }
+            TargetMatch::BinPathLastComponent(name) => write!(f, "binary name is {name:?}"),
+            TargetMatch::ProcessNameRegex(pattern) => {
+                write!(f, "process name matches /{}/", pattern.as_str())
+            }
+            TargetMatch::CommandRegex(pattern) => {
+                write!(f, "command matches /{}/", pattern.as_str())
+            }
+            TargetMatch::BinPathGlob(pattern) => {
+                write!(f, "binary path matches glob {:?}", pattern.as_str())
+            }
+            TargetMatch::ArgumentMatches(inner) => write!(
+                f,
+                "argument {} matches /{}/",
+                inner.position,
+                inner.pattern.as_str()
+            ),
+        }
+    }
 }
 
 pub fn to_lowercase(s: &str) -> Cow<str> {
@@ -44,18 +175,19 @@ pub fn bin_path_starts_with(expected_prefix: &str, bin_path: &str) -> bool {
 }
 
 pub fn bin_path_last_component_matches(expected_name: &str, bin_path: &str) -> bool {
-    let last_component_lower =
-        to_lowercase(Path::new(bin_path).file_name().unwrap().to_str().unwrap());
+    // kernel threads and processes that are gone have no binary path
+    let Some(last_component) = Path::new(bin_path)
+        .file_name()
+        .and_then(|name| name.to_str())
+    else {
+        return false;
+    };
     let name_lower = to_lowercase(expected_name);
-    last_component_lower == name_lower
+    to_lowercase(last_component) == name_lower
 }
 
-pub fn matches_target(
-    target: &TargetMatch,
-    process_name: &str,
-    command: &str,
-    bin_path: &str,
-) -> bool {
+pub fn matches_target(target: &TargetMatch, process: &ProcessInfo) -> bool {
+    let (process_name, command, bin_path) = (&process.name, &process.command, &process.bin_path);
     match target {
         TargetMatch::ProcessName(name) => process_name_matches(name, process_name),
         TargetMatch::BinPathStartsWith(prefix) => bin_path_starts_with(prefix, bin_path),
@@ -68,6 +200,13 @@ pub fn matches_target(
         TargetMatch::BinPathLastComponent(expected_name) => {
             bin_path_last_component_matches(expected_name, bin_path)
         }
+        TargetMatch::ProcessNameRegex(pattern) => pattern.is_match(process_name),
+        TargetMatch::CommandRegex(pattern) => pattern.is_match(command),
+        TargetMatch::BinPathGlob(pattern) => pattern.is_match(bin_path),
+        TargetMatch::ArgumentMatches(inner) => process
+            .args
+            .get(inner.position)
+            .is_some_and(|argument| inner.pattern.is_match(argument)),
     }
 }
 
@@ -242,6 +381,81 @@ mod tests {
         ));
     }
 
+    #[test]
+    fn test_regex_and_glob_targets() -> Result<()> {
+        let command = "/usr/bin/Rscript /opt/pipelines/bin/run_deseq2.R --counts counts.tsv";
+        let rscript = |target: &Target| target.matches("Rscript", command, "/usr/bin/Rscript");
+
+        let target = Target::new(TargetMatch::CommandRegex(RegexPattern::new(
+            r"rscript .*/bin/run_deseq2\.r\b",
+        )?));
+        assert!(rscript(&target));
+        assert!(!target.matches("Rscript", "/usr/bin/Rscript other.R", "/usr/bin/Rscript"));
+
+        let target = Target::new(TargetMatch::ArgumentMatches(ArgumentMatchesStruct {
+            position: 1,
+            pattern: RegexPattern::new(r"/run_deseq2\.R$")?,
+        }));
+        assert!(rscript(&target));
+        assert!(!target.matches("Rscript", "Rscript", "/usr/bin/Rscript"));
+
+        // arguments are taken from argv, so one with a space in it stays whole
+        let argv = ["Rscript", "/data/my scripts/run_deseq2.R"].map(str::to_string);
+        let process = ProcessInfo::from_argv("Rscript", &argv, "/usr/bin/Rscript");
+        assert!(target.matches_process(&process, &()));
+        assert!(!target.matches_process(
+            &ProcessInfo::new("Rscript", &process.command, "/usr/bin/Rscript"),
+            &()
+        ));
+
+        let target = Target::new(TargetMatch::ProcessNameRegex(RegexPattern::new(
+            "^(samtools|bcftools)$",
+        )?));
+        assert!(target.matches("BCFtools", "bcftools view", "/usr/bin/bcftools"));
+        assert!(!target.matches("samtools-wrapper", "", "/usr/bin/samtools-wrapper"));
+
+        let target = Target::new(TargetMatch::BinPathGlob(GlobPattern::new(
+            "/opt/*/bin/samtools",
+        )?));
+        assert!(target.matches("samtools", "", "/opt/conda/bin/samtools"));
+        assert!(!target.matches("samtools", "", "/opt/conda/envs/qc/bin/samtools"));
+        let target = Target::new(TargetMatch::BinPathGlob(GlobPattern::new(
+            "/opt/**/bin/samtools",
+        )?));
+        assert!(target.matches("samtools", "", "/opt/conda/envs/qc/bin/samtools"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_patterns_compile_when_targets_load() {
+        #[derive(Deserialize)]
+        struct Targets {
+            targets: Vec<TargetMatch>,
+        }
+
+        let targets: Targets = toml::from_str(
+            r#"targets = [
+                { CommandRegex = "python .*/bin/run_deseq2\\.R" },
+                { BinPathGlob = "/opt/**/bin/*" },
+                { ArgumentMatches = { position = 1, pattern = "^index$" } },
+            ]"#,
+        )
+        .unwrap();
+        assert_eq!(
+            targets.targets[2],
+            TargetMatch::ArgumentMatches(ArgumentMatchesStruct {
+                position: 1,
+                pattern: RegexPattern::new("^index$").unwrap(),
+            })
+        );
+
+        let error = toml::from_str::<Targets>(r#"targets = [{ CommandRegex = "run_(deseq2" }]"#)
+            .err()
+            .unwrap();
+        assert!(error.to_string().contains("Invalid target regex"));
+    }
+
     #[test]
     fn test_process_name_case_insensitive() {
         let target = Target::new(TargetMatch::ProcessName("specific_process".to_string()));
@@ -0,0 +1,312 @@
+//! Target packs, TOML or YAML files in `~/.config/tracer/targets.d` that extend the built-in targets
+//! without recompiling. Each pack adds targets, replaces targets of the same id and disables others by id.
+//! The `targets` of tracer.toml are one more source, between the packs and the built-ins
+use std::cmp::Reverse;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+
+use anyhow::{bail, Context, Result};
+use serde::Deserialize;
+
+use super::Target;
+
+pub const TARGET_PACKS_DIR_FROM_HOME: &str = ".config/tracer/targets.d";
+/// How often the daemon looks for edited packs
+const CHECK_INTERVAL: Duration = Duration::from_secs(2);
+
+#[derive(Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct TargetPack {
+    /// File name, set when the pack loads
+    #[serde(skip)]
+    pub name: String,
+    /// Version of the pack's catalog, to tell which one a host runs
+    pub version: Option<String>,
+    /// Higher priorities are tried first. tracer.toml and the built-ins have priority 0,
+    /// in that order after packs of the same priority
+    #[serde(default)]
+    pub priority: i32,
+    /// Ids of built-in or tracer.toml targets, or targets of packs with a lower priority, to leave out
+    #[serde(default)]
+    pub disable: Vec<String>,
+    #[serde(default)]
+    pub targets: Vec<Target>,
+}
+
+impl TargetPack {
+    pub fn load(path: &Path) -> Result<TargetPack> {
+        let contents = std::fs::read_to_string(path)?;
+        let mut pack: TargetPack = match path.extension().and_then(|ext| ext.to_str()) {
+            Some("toml") => toml::from_str(&contents)?,
+            Some("yaml" | "yml") => serde_yaml::from_str(&contents)?,
+            _ => bail!("Target packs are .toml, .yaml or .yml files"),
+        };
+        pack.name = path
+            .file_name()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .into_owned();
+        Ok(pack)
+    }
+}
+
+/// Pack files of the directory by name, other files like editor backups are ignored
+fn pack_files(dir: &Path) -> Vec<PathBuf> {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return vec![];
+    };
+    let mut files: Vec<PathBuf> = entries
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| {
+            matches!(
+                path.extension().and_then(|ext| ext.to_str()),
+                Some("toml" | "yaml" | "yml")
+            )
+        })
+        .collect();
+    files.sort();
+    files
+}
+
+/// Packs of the directory, one that fails to load is left out with a warning
+pub fn load_target_packs(dir: &Path) -> Vec<TargetPack> {
+    pack_files(dir)
+        .iter()
+        .filter_map(|path| {
+            TargetPack::load(path)
+                .with_context(|| format!("Failed to load target pack {path:?}"))
+                .map_err(|err| tracing::warn!("{:#}, leaving it out", err))
+                .ok()
+        })
+        .collect()
+}
+
+/// Merges the packs, the `targets` of tracer.toml and the built-ins into the targets the daemon tries in order.
+/// Sources are taken by priority, an id a source defines or disables is dropped from every source after it
+pub fn merge_target_packs(
+    builtins: &[Target],
+    config_targets: &[Target],
+    packs: &[TargetPack],
+) -> Vec<Target> {
+    // packs, then tracer.toml, then the built-ins among sources of the same priority.
+    // The sort is stable, packs of the same priority keep their file name order
+    let mut sources: Vec<(i32, u8, &[Target], &[String])> = packs
+        .iter()
+        .map(|pack| {
+            (
+                pack.priority,
+                0,
+                pack.targets.as_slice(),
+                pack.disable.as_slice(),
+            )
+        })
+        .collect();
+    sources.push((0, 1, config_targets, &[]));
+    sources.push((0, 2, builtins, &[]));
+    sources.sort_by_key(|(priority, layer, _, _)| (Reverse(*priority), *layer));
+
+    let mut claimed: HashSet<String> = HashSet::new();
+    let mut targets = vec![];
+    for (_, _, source_targets, disable) in sources {
+        let added: Vec<&Target> = source_targets
+            .iter()
+            .filter(|target| !claimed.contains(&target.id()))
+            .collect();
+        claimed.extend(added.iter().map(|target| target.id()));
+        claimed.extend(disable.iter().cloned());
+        targets.extend(added.into_iter().cloned());
+    }
+    targets
+}
+
+type PackStamp = (PathBuf, Option<SystemTime>, u64);
+
+/// Notices packs that were added, edited or removed by their modification times and sizes
+#[derive(Debug)]
+pub struct TargetPacksWatcher {
+    dir: Option<PathBuf>,
+    stamps: Vec<PackStamp>,
+    last_check: Instant,
+}
+
+impl TargetPacksWatcher {
+    pub fn new(dir: Option<PathBuf>) -> Self {
+        let stamps = dir.as_deref().map(Self::read_stamps).unwrap_or_default();
+        TargetPacksWatcher {
+            dir,
+            stamps,
+            last_check: Instant::now(),
+        }
+    }
+
+    fn read_stamps(dir: &Path) -> Vec<PackStamp> {
+        pack_files(dir)
+            .into_iter()
+            .map(|path| {
+                let metadata = std::fs::metadata(&path).ok();
+                let modified = metadata.as_ref().and_then(|meta| meta.modified().ok());
+                let len = metadata.map(|meta| meta.len()).unwrap_or_default();
+                (path, modified, len)
+            })
+            .collect()
+    }
+
+    /// Whether the packs changed since the last call, checked at most every `CHECK_INTERVAL`
+    pub fn changed(&mut self) -> bool {
+        let Some(dir) = &self.dir else {
+            return false;
+        };
+        if self.last_check.elapsed() < CHECK_INTERVAL {
+            return false;
+        }
+        self.last_check = Instant::now();
+
+        let stamps = Self::read_stamps(dir);
+        if stamps == self.stamps {
+            return false;
+        }
+        self.stamps = stamps;
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config_manager::target_process::target_matching::TargetMatch;
+    use crate::config_manager::target_process::targets_list::TARGETS;
+    use crate::config_manager::target_process::DisplayName;
+
+    fn process_name(name: &str) -> Target {
+        Target::new(TargetMatch::ProcessName(name.to_string()))
+    }
+
+    fn ids(targets: &[Target]) -> Vec<String> {
+        targets.iter().map(Target::id).collect()
+    }
+
+    #[test]
+    fn test_packs_load_from_toml_and_yaml() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(
+            dir.path().join("lab.toml"),
+            r#"
+            version = "2025.03"
+            priority = 10
+            disable = ["STAR"]
+
+            [[targets]]
+            id = "deseq2"
+            match_type = { ArgumentMatches = { position = 1, pattern = '/bin/run_deseq2\.R$' } }
+            display_name = { Name = "deseq2" }
+            merge_with_parents = true
+            force_ancestor_to_match = true
+            "#,
+        )
+        .unwrap();
+        std::fs::write(
+            dir.path().join("core.yaml"),
+            r#"
+version: "1.2.0"
+targets:
+  - match_type: !ProcessName bwa-mem2
+    display_name: !Default []
+    merge_with_parents: true
+    force_ancestor_to_match: false
+"#,
+        )
+        .unwrap();
+        std::fs::write(dir.path().join("broken.yml"), "targets: [").unwrap();
+        // a misspelled field would otherwise be ignored silently
+        std::fs::write(dir.path().join("typo.toml"), "prioirty = 10").unwrap();
+        std::fs::write(dir.path().join("lab.toml~"), "").unwrap();
+
+        let packs = load_target_packs(dir.path());
+        assert_eq!(packs.len(), 2);
+        assert_eq!(packs[0].name, "core.yaml");
+        assert_eq!(
+            packs[0].targets,
+            vec![process_name("bwa-mem2").set_force_ancestor_to_match(false)]
+        );
+        assert_eq!(packs[1].version.as_deref(), Some("2025.03"));
+        assert_eq!(packs[1].disable, vec!["STAR".to_string()]);
+        assert_eq!(
+            packs[1].targets[0].display_name,
+            DisplayName::Name("deseq2".to_string())
+        );
+    }
+
+    #[test]
+    fn test_merge_by_priority() {
+        let builtins = vec![
+            process_name("STAR"),
+            process_name("bwa"),
+            process_name("java"),
+            process_name("samtools"),
+        ];
+        let config_targets = vec![process_name("samtools").set_merge_with_parents(false)];
+        let pack = |name: &str, priority, targets, disable: &[&str]| TargetPack {
+            name: name.to_string(),
+            priority,
+            targets,
+            disable: disable.iter().map(|id| id.to_string()).collect(),
+            ..Default::default()
+        };
+        let picard = process_name("java")
+            .set_id("java")
+            .set_display_name(DisplayName::Name("picard".to_string()));
+        let packs = vec![
+            pack("a.toml", 0, vec![process_name("fastp")], &["STAR"]),
+            pack("b.toml", 10, vec![picard.clone()], &[]),
+            // loses to the built-ins, and to b.toml for java
+            pack(
+                "c.toml",
+                -1,
+                vec![
+                    process_name("bwa").set_merge_with_parents(false),
+                    process_name("java"),
+                ],
+                &[],
+            ),
+        ];
+
+        let targets = merge_target_packs(&builtins, &config_targets, &packs);
+        assert_eq!(ids(&targets), vec!["java", "fastp", "samtools", "bwa"]);
+        assert_eq!(targets[0], picard);
+        // tracer.toml replaces the built-in of the same id
+        assert!(!targets[2].should_be_merged_with_parents());
+        assert!(targets[3].should_be_merged_with_parents());
+    }
+
+    #[test]
+    fn test_builtin_target_ids_are_unique() {
+        let mut seen = HashSet::new();
+        for id in ids(&TARGETS) {
+            assert!(seen.insert(id.clone()), "duplicate built-in target id {id}");
+        }
+    }
+
+    #[test]
+    fn test_watcher_notices_edits() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut watcher = TargetPacksWatcher::new(Some(dir.path().to_path_buf()));
+        watcher.last_check -= CHECK_INTERVAL;
+        assert!(!watcher.changed());
+
+        std::fs::write(dir.path().join("lab.toml"), "priority = 1\n").unwrap();
+        // checked again only after the interval
+        assert!(!watcher.changed());
+        watcher.last_check -= CHECK_INTERVAL;
+        assert!(watcher.changed());
+        watcher.last_check -= CHECK_INTERVAL;
+        assert!(!watcher.changed());
+
+        std::fs::remove_file(dir.path().join("lab.toml")).unwrap();
+        watcher.last_check -= CHECK_INTERVAL;
+        assert!(watcher.changed());
+
+        assert!(!TargetPacksWatcher::new(None).changed());
+    }
+}
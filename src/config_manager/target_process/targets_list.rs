@@ -689,7 +689,8 @@ pub static ref TARGETS: Vec<Target> = [
     Target::new(TargetMatch::ProcessName("fastqc".to_string())),
     Target::new(TargetMatch::ShortLivedProcessExecutable(
         "fastqc".to_string()
-    )),
+    ))
+    .set_id("fastqc-short-lived"),
     Target::new(TargetMatch::ProcessName("multiqc".to_string())),
     Target::new(TargetMatch::ProcessName("fastp".to_string())),
     Target::new(TargetMatch::ProcessName("PEAR".to_string())),
@@ -1,7 +1,6 @@
 // src/cli.rs
-use anyhow::Result;
-use serde::Deserialize;
-use serde_json::{from_str, json};
+use anyhow::{bail, Context, Result};
+use serde_json::json;
 use std::path::PathBuf;
 use tokio::{
     io::{AsyncReadExt, AsyncWriteExt},
@@ -9,166 +8,133 @@ use tokio::{
 };
 
 use crate::extracts::process_watcher::ShortLivedProcessLog;
+use crate::types::event::attributes::log::LogSeverity;
+use crate::types::event::attributes::run::RunCompletion;
 use crate::utils::debug_log::Logger;
 
-use super::structs::InfoResponse;
-
-pub async fn send_log_request(socket_path: &str, message: String) -> Result<()> {
-    let mut socket = UnixStream::connect(socket_path).await?;
-
-    let log_request = json!({
-            "command": "log",
-            "message": message
-    });
-    let log_request_json =
-        serde_json::to_string(&log_request).expect("Failed to serialize log request");
-    socket.write_all(log_request_json.as_bytes()).await?;
-
-    Ok(())
-}
+use super::structs::{
+    version_mismatch_error, InfoResponse, Request, Response, StartRunResponse, VersionedRequest,
+    VersionedResponse, PROTOCOL_VERSION,
+};
 
-pub async fn send_alert_request(socket_path: &str, message: String) -> Result<()> {
-    let mut socket = UnixStream::connect(socket_path).await?;
-    let alert_request: serde_json::Value = json!({
-            "command": "alert",
-            "message": message
-    });
-    let alert_request_json =
-        serde_json::to_string(&alert_request).expect("Failed to serialize alrt request");
-    socket.write_all(alert_request_json.as_bytes()).await?;
+/// Sends a request to the daemon and waits for its reply, daemon side errors are returned as `Err`
+pub async fn send_request(socket_path: &str, request: Request) -> Result<Response> {
+    let mut socket = UnixStream::connect(socket_path).await.with_context(|| {
+        format!("Failed to connect to the daemon at {socket_path}. Maybe the daemon is not running? If it's not, run `tracer init` to start the daemon.")
+    })?;
 
-    Ok(())
-}
+    let request_json = serde_json::to_string(&VersionedRequest::new(request))?;
+    socket.write_all(request_json.as_bytes()).await?;
+    // the daemon reads until the end of the stream
+    socket.shutdown().await?;
 
-pub async fn send_terminate_request(socket_path: &str) -> Result<()> {
-    let mut socket = UnixStream::connect(socket_path).await?;
+    let mut reply = Vec::new();
+    socket.read_to_end(&mut reply).await?;
 
-    let terminate_request = json!({
-            "command": "terminate"
-    });
+    if reply.is_empty() {
+        bail!(
+            "The daemon closed the connection without replying. {}",
+            version_mismatch_error(Some(PROTOCOL_VERSION as u64), None)
+        );
+    }
 
-    let terminate_request_json =
-        serde_json::to_string(&terminate_request).expect("Failed to serialize terminate request");
+    let reply: serde_json::Value =
+        serde_json::from_slice(&reply).context("Failed to parse the daemon's reply")?;
+    let daemon_version = reply.get("version").and_then(|version| version.as_u64());
+    if daemon_version != Some(PROTOCOL_VERSION as u64) {
+        bail!(version_mismatch_error(
+            Some(PROTOCOL_VERSION as u64),
+            daemon_version
+        ));
+    }
 
-    socket.write_all(terminate_request_json.as_bytes()).await?;
+    let reply: VersionedResponse =
+        serde_json::from_value(reply).context("Failed to parse the daemon's reply")?;
 
-    Ok(())
+    match reply.response {
+        Response::Error(message) => bail!("The daemon rejected the command: {message}"),
+        response => Ok(response),
+    }
 }
 
-pub async fn send_start_run_request(socket_path: &str) -> Result<()> {
-    let mut socket = UnixStream::connect(socket_path).await?;
-
-    let start_request = json!({
-            "command": "start"
-    });
-
-    let start_request_json =
-        serde_json::to_string(&start_request).expect("Failed to serialize start request");
+async fn send_request_expecting_ok(socket_path: &str, request: Request) -> Result<()> {
+    match send_request(socket_path, request).await? {
+        Response::Ok => Ok(()),
+        response => bail!("Unexpected reply from the daemon: {response:?}"),
+    }
+}
 
-    socket.write_all(start_request_json.as_bytes()).await?;
+pub async fn send_log_request(
+    socket_path: &str,
+    message: String,
+    severity: LogSeverity,
+) -> Result<()> {
+    send_request_expecting_ok(socket_path, Request::Log { message, severity }).await
+}
 
-    socket.shutdown().await?;
+pub async fn send_alert_request(socket_path: &str, message: String) -> Result<()> {
+    send_request_expecting_ok(socket_path, Request::Alert { message }).await
+}
 
-    #[derive(Deserialize)]
-    struct StartRunResponse {
-        run_name: String,
-    }
+pub async fn send_terminate_request(socket_path: &str) -> Result<()> {
+    send_request_expecting_ok(socket_path, Request::Terminate).await
+}
 
-    let mut buffer = [0; 1024];
-    let n = socket.read(&mut buffer).await?;
-    let response = std::str::from_utf8(&buffer[..n])?;
-    let response: StartRunResponse = from_str(response)?;
+/// Starts a new run, `if_idle` makes the daemon refuse rather than end the run in progress
+pub async fn send_start_run_request(socket_path: &str, if_idle: bool) -> Result<StartRunResponse> {
+    let response = match send_request(socket_path, Request::Start { if_idle }).await? {
+        Response::RunStarted(response) => response,
+        response => bail!("Unexpected reply from the daemon: {response:?}"),
+    };
 
     println!("Started a new run with name: {}", response.run_name);
 
-    Ok(())
+    Ok(response)
 }
 
 pub async fn send_end_run_request(socket_path: &str) -> Result<()> {
-    let mut socket = UnixStream::connect(socket_path).await?;
-
-    let end_request = json!({
-            "command": "end"
-    });
-
-    let end_request_json =
-        serde_json::to_string(&end_request).expect("Failed to serialize start request");
+    send_request_expecting_ok(socket_path, Request::End { completion: None }).await
+}
 
-    socket.write_all(end_request_json.as_bytes()).await?;
+/// Ends the current run, recording how the command wrapped by `tracer run` exited
+pub async fn send_finish_run_request(socket_path: &str, completion: &RunCompletion) -> Result<()> {
+    let request = Request::End {
+        completion: Some(completion.clone()),
+    };
+    send_request_expecting_ok(socket_path, request).await
+}
 
-    Ok(())
+pub async fn send_set_run_root_pid_request(socket_path: &str, pid: u32) -> Result<()> {
+    send_request_expecting_ok(socket_path, Request::SetRunRootPid { pid }).await
 }
 
 pub async fn send_info_request(socket_path: &str) -> Result<InfoResponse> {
-    let mut socket = UnixStream::connect(socket_path).await?;
-
-    let ping_request = json!({
-            "command": "info"
-    });
-
-    let info_request_json =
-        serde_json::to_string(&ping_request).expect("Failed to serialize info request");
-
-    socket.write_all(info_request_json.as_bytes()).await?;
-
-    socket.shutdown().await?;
-
-    let mut buffer = [0; 1024];
-    let n = socket.read(&mut buffer).await?;
-    let response = std::str::from_utf8(&buffer[..n])?;
-    let response: InfoResponse = from_str(response)?;
-
-    Ok(response)
+    match send_request(socket_path, Request::Info).await? {
+        Response::Info(response) => Ok(response),
+        response => bail!("Unexpected reply from the daemon: {response:?}"),
+    }
 }
 
 pub async fn send_refresh_config_request(socket_path: &str) -> Result<()> {
-    let mut socket = UnixStream::connect(socket_path).await?;
-
-    let setup_request = json!({
-            "command": "refresh_config"
-    });
-
-    let setup_request_json =
-        serde_json::to_string(&setup_request).expect("Failed to serialize setup request");
-
-    socket.write_all(setup_request_json.as_bytes()).await?;
-
-    Ok(())
+    send_request_expecting_ok(socket_path, Request::RefreshConfig).await
 }
 
-pub async fn send_update_tags_request(socket_path: &str, tags: &Vec<String>) -> Result<()> {
-    let mut socket = UnixStream::connect(socket_path).await?;
-
-    let tag_request = json!({
-            "command": "tag",
-            "tags": tags
-    });
-
-    let tag_request_json =
-        serde_json::to_string(&tag_request).expect("Failed to serialize tag request");
-
-    socket.write_all(tag_request_json.as_bytes()).await?;
-
-    Ok(())
+pub async fn send_update_tags_request(socket_path: &str, tags: &[String]) -> Result<()> {
+    let request = Request::Tag {
+        tags: tags.to_vec(),
+    };
+    send_request_expecting_ok(socket_path, request).await
 }
 
 pub async fn send_log_short_lived_process_request(
     socket_path: &str,
     log: ShortLivedProcessLog,
 ) -> Result<()> {
-    let mut socket = UnixStream::connect(socket_path).await?;
-
-    let log_request = json!({
-            "command": "log_short_lived_process",
-            "log": log
-    });
-
-    let log_request_json =
-        serde_json::to_string(&log_request).expect("Failed to serialize log request");
-
-    socket.write_all(log_request_json.as_bytes()).await?;
-
-    Ok(())
+    send_request_expecting_ok(
+        socket_path,
+        Request::LogShortLivedProcess { log: Box::new(log) },
+    )
+    .await
 }
 
 pub async fn send_upload_file_request(socket_path: &str, file_path: &PathBuf) -> Result<()> {
@@ -184,22 +150,15 @@ pub async fn send_upload_file_request(socket_path: &str, file_path: &PathBuf) ->
         )
         .await;
 
-    let mut socket = UnixStream::connect(socket_path).await?;
-
-    let upload_request = json!({
-        "command": "upload",
-        "file_path": file_path
-    });
-
-    let upload_request_json =
-        serde_json::to_string(&upload_request).expect("Failed to serialize upload request");
-
-    socket.write_all(upload_request_json.as_bytes()).await?;
+    let request = Request::Upload {
+        file_path: file_path.clone(),
+    };
+    send_request_expecting_ok(socket_path, request).await?;
 
     logger
         .log(
-            "send_upload_file_request//socket.write_all",
-            Some(&upload_request),
+            "send_upload_file_request//send_request",
+            Some(&json!({ "file_path": file_path })),
         )
         .await;
 
@@ -210,8 +169,9 @@ pub async fn send_upload_file_request(socket_path: &str, file_path: &PathBuf) ->
 mod tests {
     use super::*;
     use crate::SOCKET_PATH;
+    use serde_json::Value;
     use serial_test::serial;
-    use tokio::{io::AsyncReadExt, net::UnixListener};
+    use tokio::{net::UnixListener, task::JoinHandle};
 
     fn setup_test_unix_listener() -> UnixListener {
         let _ = env_logger::builder().is_test(true).try_init();
@@ -222,30 +182,47 @@ mod tests {
         UnixListener::bind(SOCKET_PATH).expect("Failed to bind to unix socket")
     }
 
-    async fn check_listener_value(listener: &UnixListener, expected_value: &str) {
-        let (mut stream, _) = listener.accept().await.unwrap();
-        let mut buffer = [0; 1024];
-        let n = stream.read(&mut buffer).await.unwrap();
-        let received = std::str::from_utf8(&buffer[..n]).unwrap();
-        assert_eq!(received, expected_value);
+    /// Accepts one request, answers it with `reply` and returns what was received
+    fn reply_once(listener: UnixListener, reply: Value) -> JoinHandle<Value> {
+        tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut received = String::new();
+            stream.read_to_string(&mut received).await.unwrap();
+            stream
+                .write_all(reply.to_string().as_bytes())
+                .await
+                .unwrap();
+            serde_json::from_str(&received).unwrap()
+        })
+    }
+
+    fn respond_ok(listener: UnixListener) -> JoinHandle<Value> {
+        reply_once(
+            listener,
+            json!({ "version": PROTOCOL_VERSION, "status": "ok" }),
+        )
+    }
+
+    async fn check_listener_value(server: JoinHandle<Value>, expected_value: Value) {
+        assert_eq!(server.await.unwrap(), expected_value);
     }
 
     #[tokio::test]
     #[serial]
     async fn test_send_log_request() -> Result<()> {
-        let listener = setup_test_unix_listener();
+        let server = respond_ok(setup_test_unix_listener());
         let message = "Test Message".to_string();
 
-        send_log_request(SOCKET_PATH, message.clone()).await?;
+        send_log_request(SOCKET_PATH, message.clone(), LogSeverity::Warning).await?;
 
         check_listener_value(
-            &listener,
+            server,
             json!({
+                "version": PROTOCOL_VERSION,
                 "command": "log",
-                "message": message
-            })
-            .to_string()
-            .as_str(),
+                "message": message,
+                "severity": "warning"
+            }),
         )
         .await;
 
@@ -255,19 +232,18 @@ mod tests {
     #[tokio::test]
     #[serial]
     async fn test_send_alert_request() -> Result<()> {
-        let listener = setup_test_unix_listener();
+        let server = respond_ok(setup_test_unix_listener());
         let message = "Test Message".to_string();
 
         send_alert_request(SOCKET_PATH, message.clone()).await?;
 
         check_listener_value(
-            &listener,
+            server,
             json!({
+                "version": PROTOCOL_VERSION,
                 "command": "alert",
                 "message": message
-            })
-            .to_string()
-            .as_str(),
+            }),
         )
         .await;
 
@@ -277,17 +253,47 @@ mod tests {
     #[tokio::test]
     #[serial]
     async fn test_send_terminate_request() -> Result<()> {
-        let listener = setup_test_unix_listener();
+        let server = respond_ok(setup_test_unix_listener());
 
         send_terminate_request(SOCKET_PATH).await?;
 
         check_listener_value(
-            &listener,
+            server,
             json!({
+                "version": PROTOCOL_VERSION,
                 "command": "terminate"
-            })
-            .to_string()
-            .as_str(),
+            }),
+        )
+        .await;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn test_send_start_run_request() -> Result<()> {
+        let server = reply_once(
+            setup_test_unix_listener(),
+            json!({
+                "version": PROTOCOL_VERSION,
+                "status": "run_started",
+                "data": {
+                    "run_name": "mystic-leopard-21",
+                    "run_id": "run-id",
+                    "pipeline_name": "pipeline"
+                }
+            }),
+        );
+
+        let response = send_start_run_request(SOCKET_PATH, false).await?;
+        assert_eq!(response.run_name, "mystic-leopard-21");
+
+        check_listener_value(
+            server,
+            json!({
+                "version": PROTOCOL_VERSION,
+                "command": "start"
+            }),
         )
         .await;
 
@@ -297,17 +303,45 @@ mod tests {
     #[tokio::test]
     #[serial]
     async fn test_send_end_run_request() -> Result<()> {
-        let listener = setup_test_unix_listener();
+        let server = respond_ok(setup_test_unix_listener());
 
         send_end_run_request(SOCKET_PATH).await?;
 
         check_listener_value(
-            &listener,
+            server,
             json!({
+                "version": PROTOCOL_VERSION,
                 "command": "end"
-            })
-            .to_string()
-            .as_str(),
+            }),
+        )
+        .await;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn test_send_finish_run_request() -> Result<()> {
+        let server = respond_ok(setup_test_unix_listener());
+        let completion = RunCompletion {
+            command: "nextflow run main.nf".to_string(),
+            exit_code: Some(1),
+            signal: None,
+        };
+
+        send_finish_run_request(SOCKET_PATH, &completion).await?;
+
+        check_listener_value(
+            server,
+            json!({
+                "version": PROTOCOL_VERSION,
+                "command": "end",
+                "completion": {
+                    "command": "nextflow run main.nf",
+                    "exit_code": 1,
+                    "signal": null
+                }
+            }),
         )
         .await;
 
@@ -317,17 +351,16 @@ mod tests {
     #[tokio::test]
     #[serial]
     async fn test_send_refresh_config_request() -> Result<()> {
-        let listener = setup_test_unix_listener();
+        let server = respond_ok(setup_test_unix_listener());
 
         send_refresh_config_request(SOCKET_PATH).await?;
 
         check_listener_value(
-            &listener,
+            server,
             json!({
+                "version": PROTOCOL_VERSION,
                 "command": "refresh_config"
-            })
-            .to_string()
-            .as_str(),
+            }),
         )
         .await;
 
@@ -337,19 +370,18 @@ mod tests {
     #[tokio::test]
     #[serial]
     async fn test_send_update_tags_request() -> Result<()> {
-        let listener = setup_test_unix_listener();
+        let server = respond_ok(setup_test_unix_listener());
         let tags = vec!["tag1".to_string(), "tag2".to_string(), "tag3".to_string()];
 
         send_update_tags_request(SOCKET_PATH, &tags).await?;
 
         check_listener_value(
-            &listener,
+            server,
             json!({
+                "version": PROTOCOL_VERSION,
                 "command": "tag",
                 "tags": tags
-            })
-            .to_string()
-            .as_str(),
+            }),
         )
         .await;
 
@@ -359,22 +391,49 @@ mod tests {
     #[tokio::test]
     #[serial]
     async fn test_send_upload_file_request() -> Result<()> {
-        let listener = setup_test_unix_listener();
+        let server = respond_ok(setup_test_unix_listener());
         let file_path = PathBuf::from("log_outgoing_http_calls.txt".to_string());
 
         send_upload_file_request(SOCKET_PATH, &file_path).await?;
 
         check_listener_value(
-            &listener,
+            server,
             json!({
+                "version": PROTOCOL_VERSION,
                 "command": "upload",
                 "file_path": file_path.clone()
-            })
-            .to_string()
-            .as_str(),
+            }),
         )
         .await;
 
         Ok(())
     }
+
+    #[tokio::test]
+    #[serial]
+    async fn test_daemon_error_reply_is_returned() {
+        let server = reply_once(
+            setup_test_unix_listener(),
+            json!({ "version": PROTOCOL_VERSION, "status": "error", "data": "no run in progress" }),
+        );
+
+        let err = send_end_run_request(SOCKET_PATH).await.unwrap_err();
+        server.await.unwrap();
+
+        assert!(err.to_string().contains("no run in progress"));
+    }
+
+    #[tokio::test]
+    #[serial]
+    async fn test_daemon_version_mismatch_is_reported() {
+        let server = reply_once(
+            setup_test_unix_listener(),
+            json!({ "version": PROTOCOL_VERSION + 1, "status": "ok" }),
+        );
+
+        let err = send_end_run_request(SOCKET_PATH).await.unwrap_err();
+        server.await.unwrap();
+
+        assert!(err.to_string().contains("Protocol version mismatch"));
+    }
 }
@@ -1,248 +1,299 @@
-use anyhow::Result;
-use chrono::Utc;
-use core::panic;
-use serde_json::{json, Value};
-use std::{future::Future, pin::Pin, sync::Arc};
+use anyhow::{anyhow, bail, Context, Result};
+use serde_json::Value;
+use std::{path::Path, sync::Arc, time::Duration};
 use tokio::{
     io::{AsyncReadExt, AsyncWriteExt},
     net::{UnixListener, UnixStream},
     sync::{Mutex, RwLock},
+    time::{sleep, timeout},
 };
 use tokio_util::sync::CancellationToken;
 
 use crate::{
     config_manager::{Config, ConfigManager},
-    daemon_communication::structs::{InfoResponse, InnerInfoResponse},
-    events::{recorder::EventType, send_alert_event, send_log_event, send_update_tags_event},
+    daemon_communication::structs::{
+        version_mismatch_error, InfoResponse, InnerInfoResponse, Request, Response,
+        StartRunResponse, VersionedRequest, VersionedResponse, PROTOCOL_VERSION,
+    },
+    events::{send_alert_event, send_log_event, send_update_tags_event},
     extracts::process_watcher::ShortLivedProcessLog,
     tracer_client::TracerClient,
-    utils::{debug_log::Logger, upload::upload_from_file_path},
+    types::event::attributes::{log::LogSeverity, run::RunCompletion},
+    utils::{
+        debug_log::Logger, notifications::send_alert_notification, upload::upload_from_file_path,
+    },
 };
 
-type ProcessOutput<'a> =
-    Option<Pin<Box<dyn Future<Output = Result<String, anyhow::Error>> + 'a + Send>>>;
-
-pub fn process_log_command<'a>(
-    service_url: &'a str,
-    api_key: &'a str,
-    object: &serde_json::Map<String, serde_json::Value>,
-    tracer_client: &'a Arc<Mutex<TracerClient>>,
-) -> ProcessOutput<'a> {
-    if !object.contains_key("message") {
-        return None;
-    };
-    let message = object.get("message").unwrap().as_str().unwrap().to_string();
-
-    async fn fun<'a>(
-        tracer_client: &'a Arc<Mutex<TracerClient>>,
-        _service_url: &'a str,
-        api_key: &'a str,
-        message: String,
-    ) -> Result<String, anyhow::Error> {
-        let event_recorder = &mut tracer_client.lock().await.logs;
-
-        event_recorder.record_event(
-            EventType::RunStatusMessage,
-            message.clone(),
-            None,
-            Some(Utc::now()),
-        );
-
-        // TODO: remove
-        send_log_event(api_key, message).await
-    }
-
-    Some(Box::pin(fun(tracer_client, api_key, service_url, message)))
+/// Clients write their whole request and shut down their write half right away
+const READ_TIMEOUT: Duration = Duration::from_secs(5);
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+const UPLOAD_REQUEST_TIMEOUT: Duration = Duration::from_secs(300);
+const ACCEPT_ERROR_BACKOFF: Duration = Duration::from_millis(100);
+const MAX_REQUEST_BYTES: u64 = 1024 * 1024;
+
+pub async fn process_log_command(
+    tracer_client: &Arc<Mutex<TracerClient>>,
+    message: String,
+    severity: LogSeverity,
+) -> Result<Response> {
+    send_log_event(&mut tracer_client.lock().await.logs, severity, message);
+    Ok(Response::Ok)
 }
 
-pub fn process_alert_command<'a>(
-    service_url: &'a str,
-    api_key: &'a str,
-    object: &serde_json::Map<String, serde_json::Value>,
-    tracer_client: &'a Arc<Mutex<TracerClient>>,
-) -> ProcessOutput<'a> {
-    if !object.contains_key("message") {
-        return None;
-    };
-
-    let message = object.get("message").unwrap().as_str().unwrap().to_string();
-
-    async fn fun<'a>(
-        tracer_client: &'a Arc<Mutex<TracerClient>>,
-        _service_url: &'a str,
-        _api_key: &'a str,
-        message: String,
-    ) -> Result<String, anyhow::Error> {
-        let event_recorder = &mut tracer_client.lock().await.logs;
-
-        event_recorder.record_event(EventType::Alert, message.clone(), None, Some(Utc::now()));
-        // TODO: remove
-        send_alert_event(message).await
+pub async fn process_alert_command(
+    tracer_client: &Arc<Mutex<TracerClient>>,
+    config: &Arc<RwLock<Config>>,
+    message: String,
+) -> Result<Response> {
+    let alert = send_alert_event(&mut tracer_client.lock().await.logs, message);
+    let notifications = config.read().await.notifications.clone();
+
+    match send_alert_notification(&notifications, &alert).await {
+        Ok(true) => {}
+        Ok(false) => {
+            tracing::warn!("No notification channel configured, alert is only recorded")
+        }
+        // the alert is still exported with the run events
+        Err(err) => tracing::error!("Failed to route alert to notification channel: {:?}", err),
     }
-    Some(Box::pin(fun(tracer_client, service_url, api_key, message)))
+    Ok(Response::Ok)
 }
 
-pub fn process_start_run_command<'a>(
-    tracer_client: &'a Arc<Mutex<TracerClient>>,
-    stream: &'a mut UnixStream,
-) -> ProcessOutput<'a> {
-    async fn fun<'a>(
-        tracer_client: &'a Arc<Mutex<TracerClient>>,
-        stream: &'a mut UnixStream,
-    ) -> Result<String, anyhow::Error> {
-        tracer_client.lock().await.start_new_run(None).await?;
+pub async fn process_start_run_command(
+    tracer_client: &Arc<Mutex<TracerClient>>,
+    if_idle: bool,
+) -> Result<Response> {
+    let mut guard = tracer_client.lock().await;
+    if let Some(run) = guard.get_run_metadata().filter(|_| if_idle) {
+        bail!("Run {} ({}) is already in progress", run.name, run.id);
+    }
+    guard.start_new_run(None).await?;
 
-        let guard = tracer_client.lock().await;
+    let info = guard
+        .get_run_metadata()
+        .context("The run was started but has no metadata")?;
+
+    Ok(Response::RunStarted(StartRunResponse {
+        run_name: info.name,
+        run_id: info.id,
+        pipeline_name: guard.get_pipeline_name().to_string(),
+    }))
+}
 
-        let info = guard.get_run_metadata();
+pub async fn process_info_command(tracer_client: &Arc<Mutex<TracerClient>>) -> Result<Response> {
+    let guard = tracer_client.lock().await;
 
-        let output = if let Some(info) = info {
-            json!({
-                "run_name": info.name,
-                "run_id": info.id,
-                "pipeline_name": guard.get_pipeline_name(),
-            })
-        } else {
-            json!({
-                "run_name": "",
-                "run_id": "",
-                "pipeline_name": "",
-            })
-        };
+    let response_inner: Option<InnerInfoResponse> = guard.get_run_metadata().map(|out| out.into());
 
-        stream
-            .write_all(serde_json::to_string(&output)?.as_bytes())
-            .await?;
+    let preview = guard.process_watcher.preview_targets();
+    let preview_len = guard.process_watcher.preview_targets_count();
 
-        stream.flush().await?;
+    Ok(Response::Info(InfoResponse::new(
+        preview,
+        preview_len,
+        response_inner,
+    )))
+}
 
-        Ok("".to_string())
-    }
+pub async fn process_end_run_command(
+    tracer_client: &Arc<Mutex<TracerClient>>,
+    completion: Option<RunCompletion>,
+) -> Result<Response> {
+    let mut tracer_client = tracer_client.lock().await;
+    tracer_client.stop_run_with_completion(completion).await?;
+    Ok(Response::Ok)
+}
 
-    Some(Box::pin(fun(tracer_client, stream)))
+pub async fn process_set_run_root_pid_command(
+    tracer_client: &Arc<Mutex<TracerClient>>,
+    pid: u32,
+) -> Result<Response> {
+    tracer_client.lock().await.set_run_root_pid(pid);
+    Ok(Response::Ok)
 }
 
-pub fn process_info_command<'a>(
-    tracer_client: &'a Arc<Mutex<TracerClient>>,
-    stream: &'a mut UnixStream,
-) -> ProcessOutput<'a> {
-    async fn fun<'a>(
-        tracer_client: &'a Arc<Mutex<TracerClient>>,
-        stream: &'a mut UnixStream,
-    ) -> Result<String, anyhow::Error> {
-        let guard = tracer_client.lock().await;
+pub async fn process_refresh_config_command(
+    tracer_client: &Arc<Mutex<TracerClient>>,
+    config: &Arc<RwLock<Config>>,
+) -> Result<Response> {
+    let config_file = ConfigManager::load_config();
 
-        let response_inner: Option<InnerInfoResponse> =
-            guard.get_run_metadata().map(|out| out.into());
+    tracer_client.lock().await.reload_config_file(&config_file);
+    config.write().await.clone_from(&config_file);
+    Ok(Response::Ok)
+}
 
-        let preview = guard.process_watcher.preview_targets();
-        let preview_len = guard.process_watcher.preview_targets_count();
+pub async fn process_tag_command(
+    tracer_client: &Arc<Mutex<TracerClient>>,
+    tags: Vec<String>,
+) -> Result<Response> {
+    send_update_tags_event(&mut tracer_client.lock().await.logs, tags);
+    Ok(Response::Ok)
+}
 
-        let output = InfoResponse::new(preview, preview_len, response_inner);
+pub async fn process_log_short_lived_process_command(
+    tracer_client: &Arc<Mutex<TracerClient>>,
+    log: ShortLivedProcessLog,
+) -> Result<Response> {
+    let mut tracer_client = tracer_client.lock().await;
+    tracer_client.fill_logs_with_short_lived_process(log)?;
+    Ok(Response::Ok)
+}
 
-        stream
-            .write_all(serde_json::to_string(&output)?.as_bytes())
-            .await?;
+pub async fn process_upload_command(
+    tracer_client: &Arc<Mutex<TracerClient>>,
+    file_path: &Path,
+) -> Result<Response> {
+    let (service_url, api_key) = {
+        let tracer_client = tracer_client.lock().await;
+        let service_url = tracer_client.get_service_url().to_owned();
+        let api_key = tracer_client.get_api_key().to_owned();
+        (service_url, api_key)
+    };
 
-        stream.flush().await?;
+    let logger = Logger::new();
 
-        Ok("".to_string())
-    }
+    logger.log("server.rs//process_upload_command", None).await;
 
-    Some(Box::pin(fun(tracer_client, stream)))
-}
+    let file_path = file_path
+        .to_str()
+        .context("The file path is not valid unicode")?;
+    upload_from_file_path(&service_url, &api_key, file_path, None).await?;
 
-// NOTE: outputs data
-pub fn process_end_run_command(tracer_client: &Arc<Mutex<TracerClient>>) -> ProcessOutput<'_> {
-    Some(Box::pin(async move {
-        let mut tracer_client = tracer_client.lock().await;
-        tracer_client.stop_run().await?;
-        Ok("".to_string())
-    }))
+    logger.log("process_upload_command completed", None).await;
+    Ok(Response::Ok)
 }
 
-pub fn process_refresh_config_command<'a>(
-    tracer_client: &'a Arc<Mutex<TracerClient>>,
-    config: &'a Arc<RwLock<Config>>,
-) -> ProcessOutput<'a> {
-    let config_file = ConfigManager::load_config();
+/// Parses a request, rejecting clients that speak another protocol version
+pub fn parse_request(message: &str) -> Result<Request> {
+    let parsed: Value = serde_json::from_str(message).context("Invalid JSON")?;
 
-    async fn fun<'a>(
-        tracer_client: &'a Arc<Mutex<TracerClient>>,
-        config: &'a Arc<RwLock<Config>>,
-        config_file: crate::config_manager::Config,
-    ) -> Result<String, anyhow::Error> {
-        tracer_client.lock().await.reload_config_file(&config_file);
-        config.write().await.clone_from(&config_file);
-        Ok("".to_string())
+    let version = parsed.get("version").and_then(|version| version.as_u64());
+    if version != Some(PROTOCOL_VERSION as u64) {
+        bail!(version_mismatch_error(
+            version,
+            Some(PROTOCOL_VERSION as u64)
+        ));
     }
 
-    Some(Box::pin(fun(tracer_client, config, config_file)))
+    let request: VersionedRequest = serde_json::from_value(parsed).context("Invalid request")?;
+    Ok(request.request)
 }
 
-// TODO: should this be an event ?
-pub fn process_tag_command<'a>(
-    service_url: &'a str,
-    api_key: &'a str,
-    object: &serde_json::Map<String, serde_json::Value>,
-) -> ProcessOutput<'a> {
-    if !object.contains_key("tags") {
-        return None;
-    };
-
-    let tags_json = object.get("tags").unwrap().as_array().unwrap();
-
-    let tags: Vec<String> = tags_json
-        .iter()
-        .map(|tag| tag.as_str().unwrap().to_string())
-        .collect();
+pub async fn handle_request(
+    request: Request,
+    tracer_client: &Arc<Mutex<TracerClient>>,
+    config: &Arc<RwLock<Config>>,
+) -> Result<Response> {
+    match request {
+        // the server loop stops itself after replying
+        Request::Terminate => Ok(Response::Ok),
+        Request::Log { message, severity } => {
+            process_log_command(tracer_client, message, severity).await
+        }
+        Request::Alert { message } => process_alert_command(tracer_client, config, message).await,
+        Request::Start { if_idle } => process_start_run_command(tracer_client, if_idle).await,
+        Request::End { completion } => process_end_run_command(tracer_client, completion).await,
+        Request::SetRunRootPid { pid } => {
+            process_set_run_root_pid_command(tracer_client, pid).await
+        }
+        Request::RefreshConfig => process_refresh_config_command(tracer_client, config).await,
+        Request::Tag { tags } => process_tag_command(tracer_client, tags).await,
+        Request::LogShortLivedProcess { log } => {
+            process_log_short_lived_process_command(tracer_client, *log).await
+        }
+        Request::Info => process_info_command(tracer_client).await,
+        Request::Upload { file_path } => process_upload_command(tracer_client, &file_path).await,
+    }
+}
 
-    Some(Box::pin(send_update_tags_event(service_url, api_key, tags)))
+async fn write_response(stream: &mut UnixStream, response: Response) -> Result<()> {
+    let response = serde_json::to_string(&VersionedResponse::new(response))?;
+    stream.write_all(response.as_bytes()).await?;
+    stream.flush().await?;
+    Ok(())
 }
 
-pub fn process_log_short_lived_process_command<'a>(
-    tracer_client: &'a Arc<Mutex<TracerClient>>,
-    object: &serde_json::Map<String, serde_json::Value>,
-) -> ProcessOutput<'a> {
-    if !object.contains_key("log") {
-        return None;
-    };
+/// Uploads stream a whole file to the service, everything else only touches in-memory state
+fn request_timeout(request: &Request) -> Duration {
+    match request {
+        Request::Upload { .. } => UPLOAD_REQUEST_TIMEOUT,
+        _ => REQUEST_TIMEOUT,
+    }
+}
 
-    let log: ShortLivedProcessLog =
-        serde_json::from_value(object.get("log").unwrap().clone()).unwrap();
+/// Runs the request in a task of its own that always finishes, the timeout only stops the wait for its reply.
+/// Dropping a handler midway could leave a run half started or half stopped
+async fn run_request(
+    request: Request,
+    tracer_client: Arc<Mutex<TracerClient>>,
+    config: Arc<RwLock<Config>>,
+    request_timeout: Duration,
+) -> Result<Response> {
+    let handler =
+        tokio::spawn(async move { handle_request(request, &tracer_client, &config).await });
+    match timeout(request_timeout, handler).await {
+        Ok(response) => response.context("The request handler panicked")?,
+        Err(_) => Err(anyhow!(
+            "The request timed out after {}s, the daemon finishes it in the background",
+            request_timeout.as_secs()
+        )),
+    }
+}
 
-    Some(Box::pin(async move {
-        let mut tracer_client = tracer_client.lock().await;
-        tracer_client.fill_logs_with_short_lived_process(log)?;
-        Ok("".to_string())
-    }))
+async fn read_request(stream: &mut UnixStream) -> Result<String> {
+    let mut message = String::new();
+    timeout(
+        READ_TIMEOUT,
+        stream.take(MAX_REQUEST_BYTES).read_to_string(&mut message),
+    )
+    .await
+    .context("Timed out reading the request")??;
+    Ok(message)
 }
 
-pub fn process_upload_command<'a>(
-    service_url: &'a str,
-    api_key: &'a str,
-    object: &'a serde_json::Map<String, serde_json::Value>,
-) -> ProcessOutput<'a> {
-    if !object.contains_key("file_path") {
-        return None;
+async fn handle_connection(
+    mut stream: UnixStream,
+    tracer_client: Arc<Mutex<TracerClient>>,
+    config: Arc<RwLock<Config>>,
+    cancellation_token: CancellationToken,
+) {
+    let logger = Logger::new();
+
+    let message = match read_request(&mut stream).await {
+        Ok(message) => message,
+        Err(err) => {
+            eprintln!("Error reading from socket: {:#}", err);
+            return;
+        }
     };
 
-    Some(Box::pin(async move {
-        let logger = Logger::new();
+    logger
+        .log(&format!("Received request: {}", message), None)
+        .await;
 
-        logger.log("server.rs//process_upload_command", None).await;
+    let request = parse_request(&message);
+    let terminate = matches!(request, Ok(Request::Terminate));
 
-        upload_from_file_path(
-            service_url,
-            api_key,
-            object.get("file_path").unwrap().as_str().unwrap(),
-            None,
-        )
-        .await?;
+    let response = match request {
+        Ok(request) => {
+            let request_timeout = request_timeout(&request);
+            run_request(request, tracer_client, config, request_timeout).await
+        }
+        Err(err) => Err(err),
+    }
+    .unwrap_or_else(|err| {
+        eprintln!("Failed to process request: {:#}", err);
+        Response::Error(format!("{:#}", err))
+    });
 
-        logger.log("process_upload_command completed", None).await;
-        Ok("Upload command processed".to_string())
-    }))
+    if let Err(err) = write_response(&mut stream, response).await {
+        eprintln!("Error writing to socket: {}", err);
+    }
+
+    if terminate {
+        cancellation_token.cancel();
+    }
 }
 
 pub async fn run_server(
@@ -252,82 +303,219 @@ pub async fn run_server(
     config: Arc<RwLock<Config>>,
 ) -> Result<(), anyhow::Error> {
     if std::fs::metadata(socket_path).is_ok() {
-        std::fs::remove_file(socket_path)
-            .unwrap_or_else(|_| panic!("Failed to remove existing socket file"));
+        std::fs::remove_file(socket_path).context("Failed to remove existing socket file")?;
     }
-    let listener = UnixListener::bind(socket_path).expect("Failed to bind to unix socket");
-    loop {
-        let (mut stream, _) = listener.accept().await.unwrap();
+    let listener = UnixListener::bind(socket_path).context("Failed to bind to unix socket")?;
 
-        let mut message = String::new();
+    loop {
+        let stream = tokio::select! {
+            _ = cancellation_token.cancelled() => return Ok(()),
+            accepted = listener.accept() => match accepted {
+                Ok((stream, _)) => stream,
+                Err(err) => {
+                    // usually running out of file descriptors, give in-flight requests time to finish
+                    tracing::error!("Failed to accept socket connection: {}", err);
+                    eprintln!("Failed to accept socket connection: {}", err);
+                    sleep(ACCEPT_ERROR_BACKOFF).await;
+                    continue;
+                }
+            },
+        };
 
-        let logger = Logger::new();
+        tokio::spawn(handle_connection(
+            stream,
+            tracer_client.clone(),
+            config.clone(),
+            cancellation_token.clone(),
+        ));
+    }
+}
 
-        let result = stream.read_to_string(&mut message).await;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::daemon_communication::client::{send_log_request, send_terminate_request};
+    use serde_json::json;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_parse_request() {
+        let request = parse_request(
+            &json!({
+                "version": PROTOCOL_VERSION,
+                "command": "log",
+                "message": "hello"
+            })
+            .to_string(),
+        )
+        .unwrap();
 
-        if result.is_err() {
-            eprintln!("Error reading from socket: {}", result.err().unwrap());
-            continue;
-        }
+        // severity defaults for clients that don't send it
+        assert!(matches!(
+            request,
+            Request::Log { message, severity: LogSeverity::Info } if message == "hello"
+        ));
+    }
 
-        let json_parse_result = serde_json::from_str(&message);
+    #[test]
+    fn test_parse_request_rejects_other_versions() {
+        let unversioned = json!({ "command": "terminate" }).to_string();
+        let err = parse_request(&unversioned).unwrap_err();
+        assert!(err.to_string().contains("Protocol version mismatch"));
 
-        if json_parse_result.is_err() {
-            eprintln!("Error parsing JSON: {}", json_parse_result.err().unwrap());
-            continue;
-        }
+        let newer = json!({ "version": PROTOCOL_VERSION + 1, "command": "terminate" }).to_string();
+        let err = parse_request(&newer).unwrap_err();
+        assert!(err.to_string().contains("Protocol version mismatch"));
+    }
 
-        let parsed: Value = json_parse_result.unwrap();
+    #[test]
+    fn test_parse_request_rejects_unknown_commands() {
+        let message = json!({ "version": PROTOCOL_VERSION, "command": "explode" }).to_string();
+        assert!(parse_request(&message).is_err());
 
-        if !parsed.is_object() {
-            eprintln!("Invalid JSON received: {}", message);
-            continue;
-        }
+        // a known command with missing fields is rejected instead of panicking
+        let message = json!({ "version": PROTOCOL_VERSION, "command": "tag" }).to_string();
+        assert!(parse_request(&message).is_err());
+    }
 
-        let object = parsed.as_object().unwrap();
+    #[test]
+    fn test_response_roundtrip() {
+        let response = VersionedResponse::new(Response::RunStarted(StartRunResponse {
+            run_name: "run".to_string(),
+            run_id: "id".to_string(),
+            pipeline_name: "pipeline".to_string(),
+        }));
+
+        let json = serde_json::to_value(&response).unwrap();
+        assert_eq!(json["version"], PROTOCOL_VERSION);
+        assert_eq!(json["status"], "run_started");
+        assert_eq!(json["data"]["run_name"], "run");
+
+        let parsed: VersionedResponse = serde_json::from_value(json).unwrap();
+        assert!(matches!(parsed.response, Response::RunStarted(run) if run.run_id == "id"));
+    }
 
-        if !object.contains_key("command") {
-            eprintln!("Invalid JSON, no command field, received: {}", message);
-            continue;
-        }
+    async fn offline_tracer_client(work_dir: &str) -> Arc<Mutex<TracerClient>> {
+        Arc::new(Mutex::new(
+            crate::tracer_client::offline_tracer_client(work_dir).await,
+        ))
+    }
 
-        let command = object.get("command").unwrap().as_str().unwrap();
+    #[tokio::test]
+    async fn test_timed_out_requests_still_finish() {
+        let temp_dir = tempdir().unwrap();
+        let tracer_client = offline_tracer_client(temp_dir.path().to_str().unwrap()).await;
+        let config = Arc::new(RwLock::new(ConfigManager::load_default_config()));
 
-        let (service_url, api_key) = {
-            let tracer_client = tracer_client.lock().await;
-            let service_url = tracer_client.get_service_url().to_owned();
-            let api_key = tracer_client.get_api_key().to_owned();
-            (service_url, api_key)
+        let guard = tracer_client.lock().await;
+        let request = Request::Log {
+            message: "late message".to_string(),
+            severity: LogSeverity::Info,
+        };
+        let response = run_request(
+            request,
+            tracer_client.clone(),
+            config,
+            Duration::from_millis(50),
+        )
+        .await;
+        assert!(response.unwrap_err().to_string().contains("timed out"));
+        drop(guard);
+
+        // the handler wasn't dropped with the wait
+        let logged = || async {
+            tracer_client
+                .lock()
+                .await
+                .logs
+                .get_events()
+                .iter()
+                .any(|event| event.message == "late message")
         };
+        timeout(Duration::from_secs(5), async {
+            while !logged().await {
+                sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .unwrap();
+    }
 
-        logger
-            .log(&format!("Received command: {}, {}", command, message), None)
-            .await;
+    #[tokio::test]
+    async fn test_start_if_idle_keeps_the_active_run() {
+        let temp_dir = tempdir().unwrap();
+        let tracer_client = offline_tracer_client(temp_dir.path().to_str().unwrap()).await;
 
-        let result = match command {
-            "terminate" => {
-                cancellation_token.cancel();
-                return Ok(());
-            }
-            "log" => process_log_command(&service_url, &api_key, object, &tracer_client),
-            "alert" => process_alert_command(&service_url, &api_key, object, &tracer_client),
-            "start" => process_start_run_command(&tracer_client, &mut stream),
-            "end" => process_end_run_command(&tracer_client),
-            "refresh_config" => process_refresh_config_command(&tracer_client, &config),
-            "tag" => process_tag_command(&service_url, &api_key, object),
-            "log_short_lived_process" => {
-                process_log_short_lived_process_command(&tracer_client, object)
-            }
-            "info" => process_info_command(&tracer_client, &mut stream),
-            "upload" => process_upload_command(&service_url, &api_key, object),
-            _ => {
-                eprintln!("Invalid command: {}", command);
-                None
-            }
+        let Response::RunStarted(first) = process_start_run_command(&tracer_client, true)
+            .await
+            .unwrap()
+        else {
+            panic!("the run wasn't started");
+        };
+
+        let err = process_start_run_command(&tracer_client, true)
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("already in progress"));
+        let active = tracer_client.lock().await.get_run_metadata().unwrap();
+        assert_eq!(active.id, first.run_id);
+
+        // `tracer start` still replaces the run
+        let Response::RunStarted(second) = process_start_run_command(&tracer_client, false)
+            .await
+            .unwrap()
+        else {
+            panic!("the run wasn't started");
         };
+        assert_ne!(second.run_id, first.run_id);
+    }
 
-        if let Some(future) = result {
-            future.await?;
+    #[tokio::test]
+    async fn test_server_handles_concurrent_connections() {
+        let temp_dir = tempdir().unwrap();
+        let work_dir = temp_dir.path().to_str().unwrap();
+        let socket_path = temp_dir.path().join("tracerd.sock");
+        let socket_path = socket_path.to_str().unwrap().to_string();
+
+        let tracer_client = offline_tracer_client(work_dir).await;
+        let config = Arc::new(RwLock::new(ConfigManager::load_default_config()));
+        let cancellation_token = CancellationToken::new();
+
+        let server = tokio::spawn({
+            let socket_path = socket_path.clone();
+            let tracer_client = tracer_client.clone();
+            let cancellation_token = cancellation_token.clone();
+            async move { run_server(tracer_client, &socket_path, cancellation_token, config).await }
+        });
+        while UnixStream::connect(&socket_path).await.is_err() {
+            sleep(Duration::from_millis(10)).await;
         }
+
+        // a client that never finishes its request must not hold up the others
+        let _stalled = UnixStream::connect(&socket_path).await.unwrap();
+
+        let requests = (0..50)
+            .map(|i| send_log_request(&socket_path, format!("message {i}"), LogSeverity::Info));
+        for result in futures::future::join_all(requests).await {
+            result.unwrap();
+        }
+
+        let logged = tracer_client
+            .lock()
+            .await
+            .logs
+            .get_events()
+            .iter()
+            .filter(|event| event.message.starts_with("message "))
+            .count();
+        assert_eq!(logged, 50);
+
+        send_terminate_request(&socket_path).await.unwrap();
+        timeout(Duration::from_secs(5), server)
+            .await
+            .unwrap()
+            .unwrap()
+            .unwrap();
+        assert!(cancellation_token.is_cancelled());
     }
 }
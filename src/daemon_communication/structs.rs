@@ -1,18 +1,126 @@
 use std::collections::HashSet;
+use std::path::PathBuf;
 
 use chrono::{DateTime, TimeDelta, Utc};
 use itertools::Itertools;
 
+use crate::extracts::process_watcher::ShortLivedProcessLog;
 use crate::tracer_client::RunMetadata;
+use crate::types::event::attributes::{log::LogSeverity, run::RunCompletion};
 
-#[derive(serde::Serialize, serde::Deserialize)]
+/// Version of the socket protocol between the CLI and the daemon.
+/// Bump it whenever `Request` or `Response` change in a way the other side can't read
+pub const PROTOCOL_VERSION: u32 = 1;
+
+/// A CLI command sent to the daemon over the unix socket
+#[derive(serde::Serialize, serde::Deserialize, Debug)]
+#[serde(tag = "command", rename_all = "snake_case")]
+pub enum Request {
+    Log {
+        message: String,
+        #[serde(default)]
+        severity: LogSeverity,
+    },
+    Alert {
+        message: String,
+    },
+    Terminate,
+    Start {
+        /// Refuse instead of ending the run in progress, sent by `tracer run`
+        #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+        if_idle: bool,
+    },
+    End {
+        /// Only sent by `tracer run`, which knows how the wrapped command exited
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        completion: Option<RunCompletion>,
+    },
+    SetRunRootPid {
+        pid: u32,
+    },
+    RefreshConfig,
+    Tag {
+        tags: Vec<String>,
+    },
+    LogShortLivedProcess {
+        log: Box<ShortLivedProcessLog>,
+    },
+    Info,
+    Upload {
+        file_path: PathBuf,
+    },
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug)]
+pub struct VersionedRequest {
+    pub version: u32,
+    #[serde(flatten)]
+    pub request: Request,
+}
+
+/// The daemon's reply, every request gets exactly one
+#[derive(serde::Serialize, serde::Deserialize, Debug)]
+#[serde(tag = "status", content = "data", rename_all = "snake_case")]
+pub enum Response {
+    Ok,
+    RunStarted(StartRunResponse),
+    Info(InfoResponse),
+    Error(String),
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug)]
+pub struct VersionedResponse {
+    pub version: u32,
+    #[serde(flatten)]
+    pub response: Response,
+}
+
+impl VersionedRequest {
+    pub fn new(request: Request) -> Self {
+        Self {
+            version: PROTOCOL_VERSION,
+            request,
+        }
+    }
+}
+
+impl VersionedResponse {
+    pub fn new(response: Response) -> Self {
+        Self {
+            version: PROTOCOL_VERSION,
+            response,
+        }
+    }
+}
+
+pub fn version_mismatch_error(cli_version: Option<u64>, daemon_version: Option<u64>) -> String {
+    let describe = |version: Option<u64>| {
+        version.map_or("an unversioned protocol".to_string(), |version| {
+            format!("protocol version {version}")
+        })
+    };
+    format!(
+        "Protocol version mismatch: the CLI speaks {} but the daemon speaks {}. Restart the daemon with this tracer binary: `tracer terminate && tracer init`",
+        describe(cli_version),
+        describe(daemon_version)
+    )
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug)]
+pub struct StartRunResponse {
+    pub run_name: String,
+    pub run_id: String,
+    pub pipeline_name: String,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Debug)]
 pub struct InfoResponse {
     pub inner: Option<InnerInfoResponse>,
     pub watched_processes_count: usize,
     pub previewed_processes: HashSet<String>,
 }
 
-#[derive(serde::Serialize, serde::Deserialize)]
+#[derive(serde::Serialize, serde::Deserialize, Debug)]
 pub struct InnerInfoResponse {
     pub run_name: String,
     pub run_id: String,
@@ -4,7 +4,15 @@ use crate::{
     extracts::metrics::SystemMetricsCollector,
     types::{
         aws::pricing::EC2FilterBuilder,
-        event::{attributes::system_metrics::SystemProperties, aws_metadata::AwsInstanceMetaData},
+        event::{
+            attributes::{
+                log::{LogProperties, LogSeverity},
+                system_metrics::SystemProperties,
+                EventAttributes,
+            },
+            aws_metadata::AwsInstanceMetaData,
+            Event,
+        },
     },
     utils::debug_log::Logger,
 };
@@ -12,35 +20,29 @@ pub mod recorder;
 mod run_details;
 use anyhow::Result;
 use chrono::Utc;
+use recorder::{EventRecorder, EventType};
 use run_details::{generate_run_id, generate_run_name};
-use serde_json::json;
 use sysinfo::System;
 use tracing::info;
 
-// FIXME: How should this be handled with the new architecture?
-pub async fn send_log_event(_api_key: &str, message: String) -> Result<String> {
-    let _log_entry = json!({
-        "message": message,
-        "process_type": "pipeline",
-        "process_status": "run_status_message",
-        "event_type": "process_status",
-        "timestamp": Utc::now().timestamp_millis() as f64 / 1000.,
-    });
-
-    todo!()
+/// Records a `tracer log` message with its severity
+pub fn send_log_event(logs: &mut EventRecorder, severity: LogSeverity, message: String) {
+    logs.record_event(
+        EventType::RunStatusMessage,
+        message,
+        Some(EventAttributes::Log(LogProperties { severity })),
+        Some(Utc::now()),
+    );
 }
 
-// FIXME: same with other events, how should it be handled now?
-pub async fn send_alert_event(message: String) -> Result<String> {
-    let _alert_entry = json!({
-        "message": message,
-        "process_type": "pipeline",
-        "process_status": "alert",
-        "event_type": "process_status",
-        "timestamp": Utc::now().timestamp_millis() as f64 / 1000.,
-    });
-
-    todo!()
+/// Records a `tracer alert` message, which is also routed to the notification channel.
+/// Returns the recorded event so it can be sent once the recorder is released
+pub fn send_alert_event(logs: &mut EventRecorder, message: String) -> Event {
+    logs.record_event(EventType::Alert, message, None, Some(Utc::now()));
+    logs.get_events()
+        .last()
+        .cloned()
+        .expect("alert event was just recorded")
 }
 
 pub struct RunEventOut {
@@ -62,24 +64,31 @@ async fn get_aws_instance_metadata() -> Option<AwsInstanceMetaData> {
 
 async fn gather_system_properties(
     system: &System,
-    pricing_client: &PricingClient,
+    pricing_client: Option<&PricingClient>,
+    offline: bool,
 ) -> SystemProperties {
-    let aws_metadata = get_aws_instance_metadata().await;
-    let is_aws_instance = aws_metadata.is_some();
-
-    let ec2_cost_analysis = if let Some(ref metadata) = &aws_metadata {
-        let filters = EC2FilterBuilder {
-            instance_type: metadata.instance_type.clone(),
-            region: metadata.region.clone(),
-        }
-        .to_filter();
-        pricing_client
-            .get_ec2_instance_price(filters)
-            .await
-            .map(|v| v.price_per_unit)
-    } else {
+    // IMDS is unreachable on air-gapped nodes, so don't wait on it
+    let aws_metadata = if offline {
         None
+    } else {
+        get_aws_instance_metadata().await
     };
+    let is_aws_instance = aws_metadata.is_some();
+
+    let ec2_cost_analysis =
+        if let (Some(metadata), Some(pricing_client)) = (&aws_metadata, pricing_client) {
+            let filters = EC2FilterBuilder {
+                instance_type: metadata.instance_type.clone(),
+                region: metadata.region.clone(),
+            }
+            .to_filter();
+            pricing_client
+                .get_ec2_instance_price(filters)
+                .await
+                .map(|v| v.price_per_unit)
+        } else {
+            None
+        };
 
     let system_disk_io = SystemMetricsCollector::gather_disk_data();
 
@@ -104,14 +113,15 @@ async fn gather_system_properties(
 pub async fn send_start_run_event(
     system: &System,
     pipeline_name: &str,
-    pricing_client: &PricingClient,
+    pricing_client: Option<&PricingClient>,
     tag_name: &Option<String>,
+    offline: bool,
 ) -> Result<RunEventOut> {
     info!("Starting new pipeline...");
 
     let logger = Logger::new();
 
-    let system_properties = gather_system_properties(system, pricing_client).await;
+    let system_properties = gather_system_properties(system, pricing_client, offline).await;
 
     let (run_name, run_id) = if let Some(tag) = tag_name {
         (tag.clone(), tag.clone())
@@ -151,20 +161,49 @@ pub async fn send_start_run_event(
     })
 }
 
-//FIXME: Should tag updates be parts of events?... how should it be handled and stored
-pub async fn send_update_tags_event(
-    _service_url: &str,
-    _api_key: &str,
-    tags: Vec<String>,
-) -> Result<String> {
-    let _tags_entry = json!({
-        "tags": tags,
-        "message": "[CLI] Updating tags",
-        "process_type": "pipeline",
-        "process_status": "tag_update",
-        "event_type": "process_status",
-        "timestamp": Utc::now().timestamp_millis() as f64 / 1000.,
-    });
-
-    todo!()
+/// Replaces the tags of the current run and records a `tags_updated` event.
+/// The tags given to `tracer init` are restored when the next run starts
+pub fn send_update_tags_event(logs: &mut EventRecorder, tags: Vec<String>) {
+    logs.update_tags(tags);
+    logs.record_event(
+        EventType::TagsUpdated,
+        "[CLI] Updating tags".to_string(),
+        None,
+        Some(Utc::now()),
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_log_event_keeps_severity() {
+        let mut logs = EventRecorder::default();
+        send_log_event(&mut logs, LogSeverity::Warning, "low disk".to_string());
+
+        let event = &logs.get_events()[0];
+        assert_eq!(event.process_status, "run_status_message");
+        assert!(matches!(
+            event.attributes,
+            Some(EventAttributes::Log(LogProperties {
+                severity: LogSeverity::Warning
+            }))
+        ));
+    }
+
+    #[test]
+    fn test_update_tags_applies_to_later_events() {
+        let mut logs = EventRecorder::default();
+        logs.update_run_details(None, None, None, vec!["initial".to_string()]);
+
+        send_update_tags_event(&mut logs, vec!["team=genomics".to_string()]);
+        let alert = send_alert_event(&mut logs, "failed".to_string());
+
+        let tags_updated = &logs.get_events()[0];
+        assert_eq!(tags_updated.process_status, "tags_updated");
+        assert_eq!(tags_updated.tags, vec!["team=genomics"]);
+        assert_eq!(alert.process_status, "alert");
+        assert_eq!(alert.tags, vec!["team=genomics"]);
+    }
 }
@@ -27,6 +27,9 @@ pub enum EventType {
     DataSamplesEvent,
     TestEvent, // Added TestEvent variant
     NextflowLogEvent,
+    TagsUpdated,
+    OutOfMemory,
+    UntrackedHeavyProcess,
 }
 
 impl EventType {
@@ -44,6 +47,9 @@ impl EventType {
             EventType::Alert => "alert",
             EventType::DataSamplesEvent => "datasets_in_process",
             EventType::NextflowLogEvent => "nextflow_log_event",
+            EventType::TagsUpdated => "tags_updated",
+            EventType::OutOfMemory => "out_of_memory",
+            EventType::UntrackedHeavyProcess => "untracked_heavy_process",
         }
     }
 }
@@ -96,6 +102,11 @@ impl EventRecorder {
         }
     }
 
+    /// Replaces the tags attached to the events recorded from now on
+    pub(crate) fn update_tags(&mut self, tags: Vec<String>) {
+        self.tags = tags;
+    }
+
     pub fn record_event(
         &mut self,
         event_type: EventType,
@@ -133,7 +144,6 @@ impl EventRecorder {
         self.events.is_empty()
     }
 
-    #[allow(dead_code)]
     pub fn len(&self) -> usize {
         self.events.len()
     }
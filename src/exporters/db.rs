@@ -1,9 +1,11 @@
 use anyhow::{Context, Result};
+use async_trait::async_trait;
 use log::info;
 use sqlx::pool::PoolOptions;
 use sqlx::types::Json;
 use sqlx::PgPool;
 
+use super::EventExporter;
 use crate::cloud_providers::aws::SecretsClient;
 use crate::config_manager::Config;
 use crate::types::aws::secrets::DatabaseAuth;
@@ -16,9 +18,7 @@ pub struct AuroraClient {
 }
 
 impl AuroraClient {
-    pub async fn new(config: &Config, pool_size: Option<u32>) -> Self {
-        let secrets_client = SecretsClient::new(config.aws_init_type.clone()).await;
-
+    pub async fn new(config: &Config, pool_size: Option<u32>) -> Result<Self> {
         // NOTE: conditional added to fix integrations tests with docker mostly
         let db_secrets = if std::env::var("USE_LOCAL_CREDENTIALS").is_ok() {
             let username =
@@ -28,10 +28,11 @@ impl AuroraClient {
 
             DatabaseAuth { username, password }
         } else {
-            secrets_client
+            SecretsClient::new(config.aws_init_type.clone())
+                .await?
                 .get_secrets(&config.database_secrets_arn)
                 .await
-                .expect("Failed to get secrets")
+                .context("Failed to get secrets")?
         };
 
         // encode password to escape special chars that would break url
@@ -48,11 +49,11 @@ impl AuroraClient {
             .max_connections(pool_size.unwrap_or(100))
             .connect(&url)
             .await
-            .expect("Failed establish connection");
+            .context("Failed establish connection")?;
 
         info!("Successfully created connection pool");
 
-        AuroraClient { pool }
+        Ok(AuroraClient { pool })
     }
 
     pub fn get_pool(&self) -> &PgPool {
@@ -129,3 +130,24 @@ impl AuroraClient {
         Ok(())
     }
 }
+
+#[async_trait]
+impl EventExporter for AuroraClient {
+    fn name(&self) -> &str {
+        "aurora"
+    }
+
+    async fn batch_insert_events(
+        &self,
+        run_name: &str,
+        run_id: &str,
+        pipeline_name: &str,
+        data: &[Event],
+    ) -> Result<()> {
+        AuroraClient::batch_insert_events(self, run_name, run_id, pipeline_name, data).await
+    }
+
+    async fn close(&self) -> Result<()> {
+        AuroraClient::close(self).await
+    }
+}
@@ -0,0 +1,164 @@
+use std::sync::Arc;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use futures::future::join_all;
+use itertools::Itertools;
+
+use super::EventExporter;
+use crate::types::event::Event;
+
+/// Sends every batch to all of its sinks concurrently.
+/// A failing sink doesn't prevent the others from receiving the batch
+pub struct FanOutExporter {
+    exporters: Vec<Arc<dyn EventExporter>>,
+}
+
+impl FanOutExporter {
+    pub fn new(exporters: Vec<Arc<dyn EventExporter>>) -> Self {
+        Self { exporters }
+    }
+
+    pub fn exporters(&self) -> &[Arc<dyn EventExporter>] {
+        &self.exporters
+    }
+}
+
+#[async_trait]
+impl EventExporter for FanOutExporter {
+    fn name(&self) -> &str {
+        "fan_out"
+    }
+
+    async fn batch_insert_events(
+        &self,
+        run_name: &str,
+        run_id: &str,
+        pipeline_name: &str,
+        data: &[Event],
+    ) -> Result<()> {
+        let results =
+            join_all(self.exporters.iter().map(|exporter| {
+                exporter.batch_insert_events(run_name, run_id, pipeline_name, data)
+            }))
+            .await;
+
+        let failed = self
+            .exporters
+            .iter()
+            .zip(results)
+            .filter_map(|(exporter, result)| {
+                result.err().map(|err| {
+                    tracing::error!("Exporter {} failed: {:?}", exporter.name(), err);
+                    format!("{}: {}", exporter.name(), err)
+                })
+            })
+            .collect_vec();
+
+        if !failed.is_empty() {
+            anyhow::bail!("Failed to export events to: {}", failed.join(", "))
+        }
+
+        Ok(())
+    }
+
+    async fn close(&self) -> Result<()> {
+        for exporter in &self.exporters {
+            if let Err(err) = exporter.close().await {
+                tracing::error!("Failed to close exporter {}: {:?}", exporter.name(), err);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events::recorder::{EventRecorder, EventType};
+    use std::sync::Mutex;
+
+    struct MockExporter {
+        name: String,
+        fail: bool,
+        received: Mutex<Vec<(String, usize)>>,
+    }
+
+    impl MockExporter {
+        fn new(name: &str, fail: bool) -> Arc<Self> {
+            Arc::new(Self {
+                name: name.to_string(),
+                fail,
+                received: Mutex::new(vec![]),
+            })
+        }
+    }
+
+    #[async_trait]
+    impl EventExporter for MockExporter {
+        fn name(&self) -> &str {
+            &self.name
+        }
+
+        async fn batch_insert_events(
+            &self,
+            _run_name: &str,
+            run_id: &str,
+            _pipeline_name: &str,
+            data: &[Event],
+        ) -> Result<()> {
+            if self.fail {
+                anyhow::bail!("mock failure")
+            }
+            self.received
+                .lock()
+                .unwrap()
+                .push((run_id.to_string(), data.len()));
+            Ok(())
+        }
+    }
+
+    fn test_events() -> Vec<Event> {
+        let mut recorder = EventRecorder::default();
+        recorder.record_event(EventType::TestEvent, "one".to_string(), None, None);
+        recorder.record_event(EventType::TestEvent, "two".to_string(), None, None);
+        recorder.get_events().to_vec()
+    }
+
+    #[tokio::test]
+    async fn test_fan_out_sends_batch_to_every_exporter() {
+        let first = MockExporter::new("first", false);
+        let second = MockExporter::new("second", false);
+        let fan_out = FanOutExporter::new(vec![first.clone(), second.clone()]);
+
+        fan_out
+            .batch_insert_events("run", "run-id", "pipeline", &test_events())
+            .await
+            .unwrap();
+
+        assert_eq!(
+            *first.received.lock().unwrap(),
+            vec![("run-id".to_string(), 2)]
+        );
+        assert_eq!(
+            *second.received.lock().unwrap(),
+            vec![("run-id".to_string(), 2)]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_fan_out_reports_failing_exporter() {
+        let healthy = MockExporter::new("healthy", false);
+        let broken = MockExporter::new("broken", true);
+        let fan_out = FanOutExporter::new(vec![broken.clone(), healthy.clone()]);
+
+        let result = fan_out
+            .batch_insert_events("run", "run-id", "pipeline", &test_events())
+            .await;
+
+        let err = result.unwrap_err().to_string();
+        assert!(err.contains("broken"));
+        assert!(!err.contains("healthy"));
+        assert_eq!(healthy.received.lock().unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,113 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use tokio::fs::OpenOptions;
+use tokio::io::AsyncWriteExt;
+
+use super::{resolve_export_dir, run_path_component, EventExporter};
+use crate::types::config::LocalFileExporterConfig;
+use crate::types::event::Event;
+
+/// Appends events as JSON lines to one `<run_id>.jsonl` file per run
+pub struct LocalFileExporter {
+    directory: PathBuf,
+}
+
+impl LocalFileExporter {
+    pub fn new(config: &LocalFileExporterConfig) -> Result<Self> {
+        Ok(Self {
+            directory: resolve_export_dir(config.directory.as_deref())?,
+        })
+    }
+
+    pub fn directory(&self) -> &Path {
+        &self.directory
+    }
+
+    pub fn run_file_path(&self, run_id: &str) -> PathBuf {
+        self.directory
+            .join(format!("{}.jsonl", run_path_component(run_id)))
+    }
+}
+
+#[async_trait]
+impl EventExporter for LocalFileExporter {
+    fn name(&self) -> &str {
+        "local_file"
+    }
+
+    async fn batch_insert_events(
+        &self,
+        _run_name: &str,
+        run_id: &str,
+        _pipeline_name: &str,
+        data: &[Event],
+    ) -> Result<()> {
+        if data.is_empty() {
+            return Ok(());
+        }
+
+        let mut lines = String::new();
+        for event in data {
+            lines.push_str(&serde_json::to_string(event)?);
+            lines.push('\n');
+        }
+
+        let path = self.run_file_path(run_id);
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .await
+            .with_context(|| format!("Failed to open export file {path:?}"))?;
+
+        file.write_all(lines.as_bytes())
+            .await
+            .context("Failed to write events to export file")?;
+        file.flush().await?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events::recorder::{EventRecorder, EventType};
+    use tempfile::tempdir;
+
+    #[tokio::test]
+    async fn test_local_file_exporter_appends_json_lines() {
+        let temp_dir = tempdir().unwrap();
+        let exporter = LocalFileExporter::new(&LocalFileExporterConfig {
+            enabled: true,
+            directory: Some(temp_dir.path().to_str().unwrap().to_string()),
+        })
+        .unwrap();
+
+        let mut recorder = EventRecorder::default();
+        recorder.record_event(EventType::TestEvent, "first".to_string(), None, None);
+        exporter
+            .batch_insert_events("run", "run-id", "pipeline", recorder.get_events())
+            .await
+            .unwrap();
+
+        recorder.clear();
+        recorder.record_event(EventType::TestEvent, "second".to_string(), None, None);
+        exporter
+            .batch_insert_events("run", "run-id", "pipeline", recorder.get_events())
+            .await
+            .unwrap();
+
+        let content = std::fs::read_to_string(exporter.run_file_path("run-id")).unwrap();
+        let events: Vec<Event> = content
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].message, "first");
+        assert_eq!(events[1].message, "second");
+    }
+}
@@ -1 +1,174 @@
+// src/exporters/mod.rs
 pub mod db;
+mod fan_out;
+mod local_file;
+mod otlp;
+mod parquet;
+mod prometheus;
+mod spool;
+
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+
+use crate::config_manager::{Config, ConfigManager};
+use crate::types::event::Event;
+use crate::SPOOL_DIR;
+
+pub use fan_out::FanOutExporter;
+pub use local_file::LocalFileExporter;
+pub use otlp::OtlpExporter;
+pub use parquet::ParquetExporter;
+pub use prometheus::PrometheusExporter;
+pub use spool::SpoolingExporter;
+
+/// A sink the daemon sends batches of run events to
+#[async_trait]
+pub trait EventExporter: Send + Sync {
+    /// Short identifier used in logs, e.g. "aurora"
+    fn name(&self) -> &str;
+
+    async fn batch_insert_events(
+        &self,
+        run_name: &str,
+        run_id: &str,
+        pipeline_name: &str,
+        data: &[Event],
+    ) -> Result<()>;
+
+    /// Flushes and releases any resources held by the sink
+    async fn close(&self) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Builds every sink enabled in the `[exporters]` section of the config.
+/// In offline mode, or when aurora can't be reached, events fall back to the local file sink.
+/// With `[spool]` enabled, every sink spools the batches it fails to receive and replays them later
+pub async fn build_exporters(config: &Config) -> Result<FanOutExporter> {
+    let mut exporters: Vec<Arc<dyn EventExporter>> = vec![];
+    let mut aurora_unavailable = false;
+
+    if config.exporters.aurora.enabled {
+        if config.offline {
+            tracing::info!("Offline mode enabled, skipping aurora exporter");
+        } else {
+            match db::AuroraClient::new(config, config.exporters.aurora.pool_size).await {
+                Ok(aurora) => exporters.push(Arc::new(aurora)),
+                Err(err) => {
+                    tracing::warn!("Failed to initialize aurora exporter: {:?}", err);
+                    println!("Warning: failed to connect to the database, events will be written locally: {err}");
+                    aurora_unavailable = true;
+                }
+            }
+        }
+    }
+
+    let local_file_fallback = config.offline || (aurora_unavailable && exporters.is_empty());
+
+    if config.exporters.local_file.enabled || local_file_fallback {
+        let local_file = LocalFileExporter::new(&config.exporters.local_file)?;
+        tracing::info!(
+            "Writing events to local files in {:?}",
+            local_file.directory()
+        );
+        exporters.push(Arc::new(local_file));
+    }
+
+    if config.exporters.parquet.enabled {
+        let parquet = ParquetExporter::new(&config.exporters.parquet)?;
+        tracing::info!("Writing parquet files in {:?}", parquet.directory());
+        exporters.push(Arc::new(parquet));
+    }
+
+    if exporters.is_empty() {
+        tracing::warn!("No event exporters enabled, run events will be dropped");
+    }
+
+    if config.spool.enabled {
+        let spool_root = Path::new(config.spool.directory.as_deref().unwrap_or(SPOOL_DIR));
+        exporters = exporters
+            .into_iter()
+            .map(|exporter| -> Result<Arc<dyn EventExporter>> {
+                Ok(Arc::new(SpoolingExporter::new(
+                    exporter,
+                    &config.spool,
+                    spool_root,
+                )?))
+            })
+            .collect::<Result<_>>()?;
+    }
+
+    // not spooled: the otlp exporter keeps unsent spans itself and scrapes
+    // only ever want the latest gauges
+    if config.exporters.otlp.enabled {
+        let otlp = OtlpExporter::new(&config.exporters.otlp)?;
+        tracing::info!("Exporting run traces to {}", otlp.endpoint());
+        exporters.push(Arc::new(otlp));
+    }
+
+    if config.exporters.prometheus.enabled {
+        let prometheus = PrometheusExporter::new(&config.exporters.prometheus).await?;
+        tracing::info!(
+            "Serving prometheus metrics on http://{}/metrics",
+            prometheus.local_addr()
+        );
+        exporters.push(Arc::new(prometheus));
+    }
+
+    Ok(FanOutExporter::new(exporters))
+}
+
+/// Creates the configured export directory, or falls back to the tracer exports dir
+pub(crate) fn resolve_export_dir(directory: Option<&str>) -> Result<PathBuf> {
+    match directory {
+        Some(directory) => {
+            let directory = PathBuf::from(directory);
+            std::fs::create_dir_all(&directory)
+                .with_context(|| format!("Failed to create export dir {directory:?}"))?;
+            ConfigManager::validate_path(&directory)?;
+            Ok(directory)
+        }
+        None => ConfigManager::get_tracer_parquet_export_dir(),
+    }
+}
+
+/// Makes a run name or id, which can be a user supplied tag, safe to use as a single path component.
+/// Only `[A-Za-z0-9._-]` is kept and `..` can't appear, so the path stays in the export directory
+pub(crate) fn run_path_component(name: &str) -> String {
+    let mut component: String = name
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-') {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    while component.contains("..") {
+        component = component.replace("..", "_.");
+    }
+    if component.is_empty() || component == "." {
+        component = "_".to_string();
+    }
+    component
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_run_path_component() {
+        assert_eq!(run_path_component("mystic-leopard-21"), "mystic-leopard-21");
+        assert_eq!(run_path_component("run_1.v2"), "run_1.v2");
+        assert_eq!(run_path_component("../../etc/x"), "_.__._etc_x");
+        assert_eq!(run_path_component("/etc/passwd"), "_etc_passwd");
+        assert_eq!(run_path_component("..."), "__.");
+        assert_eq!(run_path_component("."), "_");
+        assert_eq!(run_path_component(""), "_");
+    }
+}
@@ -0,0 +1,790 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use reqwest::Client;
+use serde_json::{json, Value};
+
+use super::EventExporter;
+use crate::events::recorder::EventType;
+use crate::types::config::OtlpExporterConfig;
+use crate::types::event::attributes::process::{CompletedProcess, ProcessExit, ProcessProperties};
+use crate::types::event::attributes::run::RunCompletion;
+use crate::types::event::attributes::system_metrics::SystemProperties;
+use crate::types::event::attributes::EventAttributes;
+use crate::types::event::Event;
+
+/// Spans kept for retry while the collector is unreachable, the oldest are dropped beyond this
+const MAX_PENDING_SPANS: usize = 10_000;
+
+/// `SPAN_KIND_INTERNAL` of the OTLP span kind enum
+const SPAN_KIND_INTERNAL: u8 = 1;
+/// `STATUS_CODE_ERROR` of the OTLP span status enum
+const STATUS_CODE_ERROR: u8 = 2;
+
+enum AttributeValue {
+    String(String),
+    Int(i64),
+    Double(f64),
+    Bool(bool),
+    StringArray(Vec<String>),
+}
+
+impl AttributeValue {
+    fn to_json(&self) -> Value {
+        match self {
+            // OTLP/JSON encodes 64 bit integers as strings
+            AttributeValue::String(value) => json!({ "stringValue": value }),
+            AttributeValue::Int(value) => json!({ "intValue": value.to_string() }),
+            AttributeValue::Double(value) => json!({ "doubleValue": value }),
+            AttributeValue::Bool(value) => json!({ "boolValue": value }),
+            AttributeValue::StringArray(values) => json!({
+                "arrayValue": {
+                    "values": values
+                        .iter()
+                        .map(|value| json!({ "stringValue": value }))
+                        .collect::<Vec<_>>()
+                }
+            }),
+        }
+    }
+}
+
+struct FinishedSpan {
+    trace_id: u128,
+    span_id: u64,
+    parent_span_id: Option<u64>,
+    name: String,
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+    attributes: Vec<(&'static str, AttributeValue)>,
+    /// Marks the span as errored, set for tools that exited non-zero or were killed
+    error: Option<String>,
+}
+
+impl FinishedSpan {
+    fn to_json(&self) -> Value {
+        let mut span = json!({
+            "traceId": format!("{:032x}", self.trace_id),
+            "spanId": format!("{:016x}", self.span_id),
+            "name": self.name,
+            "kind": SPAN_KIND_INTERNAL,
+            "startTimeUnixNano": unix_nanos(&self.start),
+            "endTimeUnixNano": unix_nanos(&self.end),
+            "attributes": self
+                .attributes
+                .iter()
+                .map(|(key, value)| json!({ "key": key, "value": value.to_json() }))
+                .collect::<Vec<_>>(),
+        });
+        if let Some(parent_span_id) = self.parent_span_id {
+            span["parentSpanId"] = json!(format!("{parent_span_id:016x}"));
+        }
+        if let Some(message) = &self.error {
+            span["status"] = json!({ "code": STATUS_CODE_ERROR, "message": message });
+        }
+        span
+    }
+}
+
+struct ToolSpan {
+    span_id: u64,
+    parent_span_id: u64,
+    start: DateTime<Utc>,
+    last_seen: DateTime<Utc>,
+    properties: ProcessProperties,
+    peak_memory: u64,
+    peak_cpu_utilization: f32,
+}
+
+impl ToolSpan {
+    fn update(&mut self, properties: &ProcessProperties, timestamp: DateTime<Utc>) {
+        self.last_seen = timestamp;
+        self.peak_memory = self.peak_memory.max(properties.process_memory_usage);
+        self.peak_cpu_utilization = self
+            .peak_cpu_utilization
+            .max(properties.process_cpu_utilization);
+        self.properties = properties.clone();
+    }
+
+    fn finish(
+        self,
+        trace_id: u128,
+        end: DateTime<Utc>,
+        completed: Option<&CompletedProcess>,
+    ) -> FinishedSpan {
+        let properties = &self.properties;
+        let mut attributes = vec![
+            (
+                "tracer.tool.name",
+                AttributeValue::String(properties.tool_name.clone()),
+            ),
+            ("process.pid", int_attribute(&properties.tool_pid)),
+            (
+                "process.parent_pid",
+                int_attribute(&properties.tool_parent_pid),
+            ),
+            (
+                "process.executable.path",
+                AttributeValue::String(properties.tool_binary_path.clone()),
+            ),
+            (
+                "process.command_line",
+                AttributeValue::String(properties.tool_cmd.clone()),
+            ),
+            (
+                "tracer.process.cpu_utilization",
+                AttributeValue::Double(properties.process_cpu_utilization as f64),
+            ),
+            (
+                "tracer.process.cpu_utilization.peak",
+                AttributeValue::Double(self.peak_cpu_utilization as f64),
+            ),
+            (
+                "tracer.process.memory.usage",
+                AttributeValue::Int(properties.process_memory_usage as i64),
+            ),
+            (
+                "tracer.process.memory.peak",
+                AttributeValue::Int(self.peak_memory as i64),
+            ),
+            (
+                "tracer.process.memory.virtual",
+                AttributeValue::Int(properties.process_memory_virtual as i64),
+            ),
+            (
+                "tracer.process.disk.read_bytes",
+                AttributeValue::Int(properties.process_disk_usage_read_total as i64),
+            ),
+            (
+                "tracer.process.disk.written_bytes",
+                AttributeValue::Int(properties.process_disk_usage_write_total as i64),
+            ),
+            (
+                "tracer.process.run_time_sec",
+                AttributeValue::Int(properties.process_run_time as i64),
+            ),
+        ];
+        if let Some(container) = &properties.container {
+            attributes.push((
+                "container.runtime",
+                AttributeValue::String(container.runtime.as_str().to_string()),
+            ));
+            let identity = [
+                ("container.id", &container.container_id),
+                ("container.image.name", &container.image_name),
+            ];
+            for (key, value) in identity {
+                if let Some(value) = value {
+                    attributes.push((key, AttributeValue::String(value.clone())));
+                }
+            }
+            if let Some(digest) = &container.image_digest {
+                attributes.push((
+                    "container.image.repo_digests",
+                    AttributeValue::StringArray(vec![digest.clone()]),
+                ));
+            }
+        }
+        if let Some(cgroup) = &properties.cgroup {
+            attributes.push((
+                "tracer.cgroup.path",
+                AttributeValue::String(cgroup.cgroup_path.clone()),
+            ));
+            let accounting = [
+                ("tracer.cgroup.memory.peak", cgroup.memory_peak),
+                ("tracer.cgroup.memory.limit", cgroup.memory_limit),
+                ("tracer.cgroup.oom_kills", cgroup.oom_kills),
+            ];
+            for (key, value) in accounting {
+                if let Some(value) = value {
+                    attributes.push((key, AttributeValue::Int(value as i64)));
+                }
+            }
+        }
+        let mut error = None;
+        if let Some(completed) = completed {
+            attributes.push((
+                "tracer.process.duration_sec",
+                AttributeValue::Int(completed.duration_sec as i64),
+            ));
+            if let Some(exit_code) = completed.exit_code {
+                attributes.push(("process.exit.code", AttributeValue::Int(exit_code as i64)));
+            }
+            if let Some(signal) = completed.signal {
+                attributes.push(("tracer.process.signal", AttributeValue::Int(signal as i64)));
+                attributes.push((
+                    "tracer.process.core_dumped",
+                    AttributeValue::Bool(completed.core_dumped),
+                ));
+            }
+            let usage = [
+                (
+                    "tracer.process.memory.peak_rss",
+                    completed.peak_memory_usage,
+                ),
+                (
+                    "tracer.process.memory.peak_virtual",
+                    completed.peak_memory_virtual,
+                ),
+                ("tracer.process.disk.read_total", completed.disk_read_total),
+                (
+                    "tracer.process.disk.written_total",
+                    completed.disk_write_total,
+                ),
+                ("tracer.process.max_threads", completed.max_threads),
+            ];
+            for (key, value) in usage {
+                if let Some(value) = value {
+                    attributes.push((key, AttributeValue::Int(value as i64)));
+                }
+            }
+            if let Some(user_time) = completed.cpu_user_time_sec {
+                attributes.push((
+                    "tracer.process.cpu.user_time_sec",
+                    AttributeValue::Double(user_time),
+                ));
+            }
+            if let Some(system_time) = completed.cpu_system_time_sec {
+                attributes.push((
+                    "tracer.process.cpu.system_time_sec",
+                    AttributeValue::Double(system_time),
+                ));
+            }
+            let files = [
+                ("tracer.process.files.read", &completed.files_read),
+                ("tracer.process.files.written", &completed.files_written),
+            ];
+            for (key, files) in files {
+                if !files.is_empty() {
+                    let paths = files.iter().map(|file| file.file_path.clone()).collect();
+                    attributes.push((key, AttributeValue::StringArray(paths)));
+                }
+            }
+            if completed.failed {
+                let exit = ProcessExit {
+                    exit_code: completed.exit_code,
+                    signal: completed.signal,
+                    core_dumped: completed.core_dumped,
+                };
+                error = Some(format!("{} {}", properties.tool_name, exit.describe()));
+            }
+        }
+
+        FinishedSpan {
+            trace_id,
+            span_id: self.span_id,
+            parent_span_id: Some(self.parent_span_id),
+            name: properties.tool_name.clone(),
+            start: self.start,
+            end,
+            attributes,
+            error,
+        }
+    }
+}
+
+struct RunTrace {
+    trace_id: u128,
+    span_id: u64,
+    run_id: String,
+    run_name: String,
+    pipeline_name: String,
+    tags: Vec<String>,
+    start: DateTime<Utc>,
+    system_properties: Option<SystemProperties>,
+    /// Set when the run wraps a command, see `tracer run`
+    completion: Option<RunCompletion>,
+    /// Tool spans still running, by pid
+    tools: HashMap<String, ToolSpan>,
+}
+
+impl RunTrace {
+    fn new(event: &Event) -> Self {
+        let run_id = event.run_id.clone().unwrap_or_default();
+        // reuse the run uuid, so a trace can be looked up by run id
+        let trace_id = uuid::Uuid::parse_str(&run_id)
+            .map(|uuid| uuid.as_u128())
+            .unwrap_or_else(|_| rand::random());
+        let system_properties = match &event.attributes {
+            Some(EventAttributes::SystemProperties(properties)) => Some(properties.clone()),
+            _ => None,
+        };
+
+        Self {
+            trace_id,
+            span_id: new_span_id(),
+            run_id,
+            run_name: event.run_name.clone().unwrap_or_default(),
+            pipeline_name: event.pipeline_name.clone().unwrap_or_default(),
+            tags: event.tags.clone(),
+            start: event.timestamp,
+            system_properties,
+            completion: None,
+            tools: HashMap::new(),
+        }
+    }
+
+    fn record_tool(&mut self, properties: &ProcessProperties, timestamp: DateTime<Utc>) {
+        if let Some(tool) = self.tools.get_mut(&properties.tool_pid) {
+            tool.update(properties, timestamp);
+            return;
+        }
+
+        // nest under the parent tool while it runs, otherwise directly under the run
+        let parent_span_id = self
+            .tools
+            .get(&properties.tool_parent_pid)
+            .map(|parent| parent.span_id)
+            .unwrap_or(self.span_id);
+        let start = DateTime::parse_from_rfc3339(&properties.start_timestamp)
+            .map(|start| start.with_timezone(&Utc))
+            .unwrap_or(timestamp);
+
+        self.tools.insert(
+            properties.tool_pid.clone(),
+            ToolSpan {
+                span_id: new_span_id(),
+                parent_span_id,
+                start,
+                last_seen: timestamp,
+                properties: properties.clone(),
+                peak_memory: properties.process_memory_usage,
+                peak_cpu_utilization: properties.process_cpu_utilization,
+            },
+        );
+    }
+
+    fn finish_tool(
+        &mut self,
+        completed: &CompletedProcess,
+        end: DateTime<Utc>,
+    ) -> Option<FinishedSpan> {
+        self.tools
+            .remove(&completed.tool_pid)
+            .map(|tool| tool.finish(self.trace_id, end, Some(completed)))
+    }
+
+    fn finish(self, end: DateTime<Utc>) -> Vec<FinishedSpan> {
+        // tools that never reported an exit end when they were last seen
+        let mut spans: Vec<FinishedSpan> = self
+            .tools
+            .into_values()
+            .map(|tool| {
+                let last_seen = tool.last_seen;
+                tool.finish(self.trace_id, last_seen, None)
+            })
+            .collect();
+
+        let mut attributes = vec![
+            ("tracer.run.id", AttributeValue::String(self.run_id)),
+            (
+                "tracer.run.name",
+                AttributeValue::String(self.run_name.clone()),
+            ),
+            (
+                "tracer.pipeline.name",
+                AttributeValue::String(self.pipeline_name),
+            ),
+            ("tracer.tags", AttributeValue::StringArray(self.tags)),
+        ];
+        if let Some(completion) = self.completion {
+            attributes.push((
+                "process.command_line",
+                AttributeValue::String(completion.command),
+            ));
+            if let Some(exit_code) = completion.exit_code {
+                attributes.push(("process.exit.code", AttributeValue::Int(exit_code as i64)));
+            }
+            if let Some(signal) = completion.signal {
+                attributes.push(("tracer.run.signal", AttributeValue::Int(signal as i64)));
+            }
+        }
+        if let Some(properties) = self.system_properties {
+            if let Some(hostname) = properties.hostname {
+                attributes.push(("host.name", AttributeValue::String(hostname)));
+            }
+            if let Some(arch) = properties.arch {
+                attributes.push(("host.arch", AttributeValue::String(arch)));
+            }
+            attributes.push((
+                "tracer.host.num_cpus",
+                AttributeValue::Int(properties.num_cpus as i64),
+            ));
+            attributes.push((
+                "tracer.host.total_memory",
+                AttributeValue::Int(properties.total_memory as i64),
+            ));
+            if let Some(cost) = properties.ec2_cost_per_hour {
+                attributes.push(("tracer.ec2_cost_per_hour", AttributeValue::Double(cost)));
+            }
+        }
+
+        spans.push(FinishedSpan {
+            trace_id: self.trace_id,
+            span_id: self.span_id,
+            parent_span_id: None,
+            name: self.run_name,
+            start: self.start,
+            end,
+            attributes,
+            error: None,
+        });
+        spans
+    }
+}
+
+/// Turns the run and tool lifecycle events into spans
+#[derive(Default)]
+struct TraceState {
+    run: Option<RunTrace>,
+    pending: Vec<FinishedSpan>,
+}
+
+impl TraceState {
+    fn update(&mut self, data: &[Event]) {
+        for event in data {
+            let status = event.process_status.as_str();
+
+            if status == EventType::NewRun.as_str() {
+                self.finish_run(event.timestamp);
+                self.run = Some(RunTrace::new(event));
+                continue;
+            }
+            if status == EventType::FinishedRun.as_str() {
+                if let (Some(run), Some(EventAttributes::RunCompletion(completion))) =
+                    (self.run.as_mut(), &event.attributes)
+                {
+                    run.completion = Some(completion.clone());
+                }
+                self.finish_run(event.timestamp);
+                continue;
+            }
+
+            let Some(run) = self.run.as_mut() else {
+                continue;
+            };
+            match &event.attributes {
+                Some(EventAttributes::Process(properties)) => {
+                    run.record_tool(properties, event.timestamp)
+                }
+                Some(EventAttributes::CompletedProcess(completed)) => {
+                    if let Some(span) = run.finish_tool(completed, event.timestamp) {
+                        self.pending.push(span);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn finish_run(&mut self, end: DateTime<Utc>) {
+        if let Some(run) = self.run.take() {
+            self.pending.extend(run.finish(end));
+        }
+    }
+
+    /// Puts spans that failed to export back in front of the newer ones
+    fn requeue(&mut self, mut spans: Vec<FinishedSpan>) {
+        spans.append(&mut self.pending);
+        if spans.len() > MAX_PENDING_SPANS {
+            let dropped = spans.len() - MAX_PENDING_SPANS;
+            tracing::warn!("Dropping {} spans the collector failed to receive", dropped);
+            spans.drain(..dropped);
+        }
+        self.pending = spans;
+    }
+}
+
+/// Exports runs as OTLP traces over HTTP: one trace per run,
+/// one span per tool process nested by the process tree
+pub struct OtlpExporter {
+    client: Client,
+    endpoint: String,
+    service_name: String,
+    state: Mutex<TraceState>,
+}
+
+impl OtlpExporter {
+    pub fn new(config: &OtlpExporterConfig) -> Result<Self> {
+        let mut headers = HeaderMap::new();
+        for (name, value) in &config.headers {
+            headers.insert(
+                HeaderName::from_bytes(name.as_bytes())
+                    .with_context(|| format!("Invalid OTLP header name {name}"))?,
+                HeaderValue::from_str(value)
+                    .with_context(|| format!("Invalid OTLP header value for {name}"))?,
+            );
+        }
+
+        let client = Client::builder()
+            .default_headers(headers)
+            .timeout(Duration::from_millis(config.timeout_ms))
+            .build()
+            .context("Failed to build OTLP http client")?;
+
+        Ok(Self {
+            client,
+            endpoint: config.endpoint.clone(),
+            service_name: config.service_name.clone(),
+            state: Mutex::new(TraceState::default()),
+        })
+    }
+
+    pub fn endpoint(&self) -> &str {
+        &self.endpoint
+    }
+
+    fn payload(&self, spans: &[FinishedSpan]) -> Value {
+        json!({
+            "resourceSpans": [{
+                "resource": {
+                    "attributes": [
+                        { "key": "service.name", "value": { "stringValue": self.service_name } },
+                        { "key": "service.version", "value": { "stringValue": env!("CARGO_PKG_VERSION") } },
+                    ]
+                },
+                "scopeSpans": [{
+                    "scope": { "name": "tracer", "version": env!("CARGO_PKG_VERSION") },
+                    "spans": spans.iter().map(FinishedSpan::to_json).collect::<Vec<_>>(),
+                }]
+            }]
+        })
+    }
+
+    async fn flush(&self) -> Result<()> {
+        let spans = std::mem::take(&mut self.state.lock().unwrap().pending);
+        if spans.is_empty() {
+            return Ok(());
+        }
+
+        let result = self
+            .client
+            .post(&self.endpoint)
+            .json(&self.payload(&spans))
+            .send()
+            .await
+            .and_then(|response| response.error_for_status());
+
+        if let Err(err) = result {
+            self.state.lock().unwrap().requeue(spans);
+            return Err(err).context("Failed to export spans to the OTLP collector");
+        }
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl EventExporter for OtlpExporter {
+    fn name(&self) -> &str {
+        "otlp"
+    }
+
+    async fn batch_insert_events(
+        &self,
+        _run_name: &str,
+        _run_id: &str,
+        _pipeline_name: &str,
+        data: &[Event],
+    ) -> Result<()> {
+        self.state.lock().unwrap().update(data);
+        self.flush().await
+    }
+
+    async fn close(&self) -> Result<()> {
+        self.state.lock().unwrap().finish_run(Utc::now());
+        self.flush().await
+    }
+}
+
+fn new_span_id() -> u64 {
+    // an all zero span id is invalid
+    rand::random::<u64>().max(1)
+}
+
+fn unix_nanos(timestamp: &DateTime<Utc>) -> String {
+    timestamp
+        .timestamp_nanos_opt()
+        .unwrap_or_default()
+        .to_string()
+}
+
+fn int_attribute(value: &str) -> AttributeValue {
+    value
+        .parse()
+        .map(AttributeValue::Int)
+        .unwrap_or_else(|_| AttributeValue::String(value.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events::recorder::EventRecorder;
+
+    const RUN_ID: &str = "6f1d2a3c-4b5e-4f60-9a7b-8c9d0e1f2a3b";
+
+    fn tool_properties(tool_name: &str, pid: &str, parent_pid: &str) -> ProcessProperties {
+        ProcessProperties {
+            tool_name: tool_name.to_string(),
+            tool_pid: pid.to_string(),
+            tool_parent_pid: parent_pid.to_string(),
+            tool_binary_path: format!("/usr/bin/{tool_name}"),
+            tool_cmd: tool_name.to_string(),
+            start_timestamp: Utc::now().to_rfc3339(),
+            process_cpu_utilization: 10.0,
+            process_memory_usage: 100,
+            process_memory_virtual: 200,
+            process_run_time: 0,
+            process_disk_usage_read_last_interval: 0,
+            process_disk_usage_write_last_interval: 0,
+            process_disk_usage_read_total: 0,
+            process_disk_usage_write_total: 0,
+            process_status: "Run".to_string(),
+            input_files: None,
+            cgroup: None,
+            container: None,
+        }
+    }
+
+    fn completed(tool_name: &str, pid: &str, wait_status: u32) -> Option<EventAttributes> {
+        let mut completed = CompletedProcess {
+            tool_name: tool_name.to_string(),
+            tool_pid: pid.to_string(),
+            duration_sec: 1,
+            ..Default::default()
+        };
+        completed.set_exit(ProcessExit::from_wait_status(wait_status));
+        Some(EventAttributes::CompletedProcess(completed))
+    }
+
+    fn run_events() -> Vec<Event> {
+        let mut recorder = EventRecorder::default();
+        recorder.update_run_details(
+            Some("pipeline".to_string()),
+            Some("run".to_string()),
+            Some(RUN_ID.to_string()),
+            vec!["team=genomics".to_string()],
+        );
+
+        recorder.record_event(EventType::NewRun, "start".to_string(), None, None);
+        recorder.record_event(
+            EventType::ToolExecution,
+            "nextflow".to_string(),
+            Some(EventAttributes::Process(tool_properties(
+                "nextflow", "10", "1",
+            ))),
+            None,
+        );
+        recorder.record_event(
+            EventType::ToolExecution,
+            "bwa".to_string(),
+            Some(EventAttributes::Process(tool_properties("bwa", "11", "10"))),
+            None,
+        );
+        let mut peak = tool_properties("bwa", "11", "10");
+        peak.process_memory_usage = 500;
+        recorder.record_event(
+            EventType::ToolMetricEvent,
+            "bwa".to_string(),
+            Some(EventAttributes::Process(peak)),
+            None,
+        );
+        recorder.record_event(
+            EventType::FinishedToolExecution,
+            "bwa".to_string(),
+            completed("bwa", "11", 1 << 8),
+            None,
+        );
+        recorder.record_event(
+            EventType::FinishedToolExecution,
+            "nextflow".to_string(),
+            completed("nextflow", "10", 0),
+            None,
+        );
+        recorder.record_event(EventType::FinishedRun, "done".to_string(), None, None);
+        recorder.get_events().to_vec()
+    }
+
+    #[test]
+    fn test_tool_spans_are_nested_by_process_tree() {
+        let mut state = TraceState::default();
+        state.update(&run_events());
+
+        assert!(state.run.is_none());
+        assert_eq!(state.pending.len(), 3);
+
+        let span = |name: &str| state.pending.iter().find(|span| span.name == name).unwrap();
+        let (run, nextflow, bwa) = (span("run"), span("nextflow"), span("bwa"));
+
+        let trace_id = uuid::Uuid::parse_str(RUN_ID).unwrap().as_u128();
+        assert!(state.pending.iter().all(|span| span.trace_id == trace_id));
+        assert_eq!(run.parent_span_id, None);
+        assert_eq!(nextflow.parent_span_id, Some(run.span_id));
+        assert_eq!(bwa.parent_span_id, Some(nextflow.span_id));
+
+        let peak_memory = bwa
+            .attributes
+            .iter()
+            .find(|(key, _)| *key == "tracer.process.memory.peak")
+            .unwrap();
+        assert!(matches!(peak_memory.1, AttributeValue::Int(500)));
+    }
+
+    #[test]
+    fn test_otlp_json_payload() {
+        let exporter = OtlpExporter::new(&OtlpExporterConfig::default()).unwrap();
+        let mut state = TraceState::default();
+        state.update(&run_events());
+
+        let payload = exporter.payload(&state.pending);
+        let spans = payload["resourceSpans"][0]["scopeSpans"][0]["spans"]
+            .as_array()
+            .unwrap();
+        assert_eq!(spans.len(), 3);
+
+        let run = spans.iter().find(|span| span["name"] == "run").unwrap();
+        assert_eq!(run["traceId"], RUN_ID.replace('-', ""));
+        assert!(run.get("parentSpanId").is_none());
+        assert!(run["attributes"]
+            .as_array()
+            .unwrap()
+            .contains(&json!({ "key": "tracer.run.id", "value": { "stringValue": RUN_ID } })));
+
+        let bwa = spans.iter().find(|span| span["name"] == "bwa").unwrap();
+        assert_eq!(bwa["spanId"].as_str().unwrap().len(), 16);
+        assert!(bwa["parentSpanId"].is_string());
+        assert_eq!(bwa["status"]["code"], STATUS_CODE_ERROR);
+        assert!(bwa["attributes"]
+            .as_array()
+            .unwrap()
+            .contains(&json!({ "key": "process.exit.code", "value": { "intValue": "1" } })));
+
+        let nextflow = spans
+            .iter()
+            .find(|span| span["name"] == "nextflow")
+            .unwrap();
+        assert!(nextflow.get("status").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_spans_are_kept_while_collector_is_unreachable() {
+        let exporter = OtlpExporter::new(&OtlpExporterConfig {
+            endpoint: "http://127.0.0.1:1/v1/traces".to_string(),
+            timeout_ms: 1_000,
+            ..Default::default()
+        })
+        .unwrap();
+
+        let result = exporter
+            .batch_insert_events("run", RUN_ID, "pipeline", &run_events())
+            .await;
+
+        assert!(result.is_err());
+        assert_eq!(exporter.state.lock().unwrap().pending.len(), 3);
+    }
+}
@@ -0,0 +1,660 @@
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use anyhow::{Context, Result};
+use arrow_array::builder::{
+    BooleanBuilder, Float64Builder, Int64Builder, ListBuilder, StringBuilder,
+    TimestampMillisecondBuilder, UInt64Builder,
+};
+use arrow_array::{ArrayRef, RecordBatch};
+use arrow_schema::{DataType, Field, Schema, SchemaRef, TimeUnit};
+use async_trait::async_trait;
+use parquet::arrow::ArrowWriter;
+use parquet::basic::Compression;
+use parquet::file::properties::WriterProperties;
+
+use super::{resolve_export_dir, run_path_component, EventExporter};
+use crate::events::recorder::EventType;
+use crate::types::config::{ParquetExporterConfig, ParquetRotation};
+use crate::types::event::attributes::process::{
+    CompletedProcess, ProcessProperties, UntrackedHeavyProcess,
+};
+use crate::types::event::attributes::run::RunCompletion;
+use crate::types::event::attributes::system_metrics::{
+    CgroupStatistic, SystemMetric, SystemProperties,
+};
+use crate::types::event::attributes::EventAttributes;
+use crate::types::event::Event;
+
+/// Writes run events to parquet files under `<directory>/<run_name>/`.
+/// Every `EventAttributes` variant is flattened into nullable top level columns,
+/// so a row only fills the columns of its own attributes
+pub struct ParquetExporter {
+    directory: PathBuf,
+    rotation: ParquetRotation,
+    schema: SchemaRef,
+    current_run: Mutex<Option<RunWriter>>,
+}
+
+struct RunWriter {
+    run_id: String,
+    path: PathBuf,
+    writer: ArrowWriter<File>,
+}
+
+enum Cell {
+    Utf8(Option<String>),
+    Int64(Option<i64>),
+    UInt64(Option<u64>),
+    Float64(Option<f64>),
+    Boolean(Option<bool>),
+    TimestampMs(i64),
+    Utf8List(Vec<String>),
+}
+
+#[derive(Clone, Copy)]
+enum Kind {
+    Utf8,
+    Int64,
+    UInt64,
+    Float64,
+    Boolean,
+    TimestampMs,
+    Utf8List,
+}
+
+struct Column {
+    name: &'static str,
+    kind: Kind,
+    extract: fn(&Event) -> Cell,
+}
+
+fn process(event: &Event) -> Option<&ProcessProperties> {
+    match &event.attributes {
+        Some(EventAttributes::Process(properties)) => Some(properties),
+        _ => None,
+    }
+}
+
+fn completed_process(event: &Event) -> Option<&CompletedProcess> {
+    match &event.attributes {
+        Some(EventAttributes::CompletedProcess(properties)) => Some(properties),
+        _ => None,
+    }
+}
+
+/// Syslog events carry the system metrics at the time of the error
+fn system_metric(event: &Event) -> Option<&SystemMetric> {
+    match &event.attributes {
+        Some(EventAttributes::SystemMetric(metric)) => Some(metric),
+        Some(EventAttributes::Syslog(syslog)) => Some(&syslog.system_metrics),
+        _ => None,
+    }
+}
+
+fn system_properties(event: &Event) -> Option<&SystemProperties> {
+    match &event.attributes {
+        Some(EventAttributes::SystemProperties(properties)) => Some(properties),
+        _ => None,
+    }
+}
+
+fn run_completion(event: &Event) -> Option<&RunCompletion> {
+    match &event.attributes {
+        Some(EventAttributes::RunCompletion(completion)) => Some(completion),
+        _ => None,
+    }
+}
+
+/// Tool events carry the tool's cgroup, `out_of_memory` events the cgroup that ran out
+fn cgroup(event: &Event) -> Option<&CgroupStatistic> {
+    match &event.attributes {
+        Some(EventAttributes::Process(properties)) => properties.cgroup.as_ref(),
+        Some(EventAttributes::Cgroup(statistic)) => Some(statistic),
+        _ => None,
+    }
+}
+
+fn untracked_heavy_process(event: &Event) -> Option<&UntrackedHeavyProcess> {
+    match &event.attributes {
+        Some(EventAttributes::UntrackedHeavyProcess(untracked)) => Some(untracked),
+        _ => None,
+    }
+}
+
+fn attributes_type(event: &Event) -> Option<&'static str> {
+    event
+        .attributes
+        .as_ref()
+        .map(|attributes| match attributes {
+            EventAttributes::Process(_) => "process",
+            EventAttributes::CompletedProcess(_) => "completed_process",
+            EventAttributes::SystemMetric(_) => "system_metric",
+            EventAttributes::Syslog(_) => "syslog",
+            EventAttributes::SystemProperties(_) => "system_properties",
+            EventAttributes::ProcessDatasetStats(_) => "process_dataset_stats",
+            EventAttributes::NextflowLog(_) => "nextflow_log",
+            EventAttributes::Log(_) => "log",
+            EventAttributes::RunCompletion(_) => "run_completion",
+            EventAttributes::Cgroup(_) => "cgroup",
+            EventAttributes::UntrackedHeavyProcess(_) => "untracked_heavy_process",
+            EventAttributes::Other(_) => "other",
+        })
+}
+
+fn to_json<T: serde::Serialize>(value: &T) -> Option<String> {
+    serde_json::to_string(value).ok()
+}
+
+#[rustfmt::skip]
+const COLUMNS: &[Column] = &[
+    // Event
+    Column { name: "timestamp", kind: Kind::TimestampMs, extract: |e| Cell::TimestampMs(e.timestamp.timestamp_millis()) },
+    Column { name: "message", kind: Kind::Utf8, extract: |e| Cell::Utf8(Some(e.message.clone())) },
+    Column { name: "event_type", kind: Kind::Utf8, extract: |e| Cell::Utf8(Some(e.event_type.clone())) },
+    Column { name: "process_type", kind: Kind::Utf8, extract: |e| Cell::Utf8(Some(e.process_type.clone())) },
+    Column { name: "process_status", kind: Kind::Utf8, extract: |e| Cell::Utf8(Some(e.process_status.clone())) },
+    Column { name: "pipeline_name", kind: Kind::Utf8, extract: |e| Cell::Utf8(e.pipeline_name.clone()) },
+    Column { name: "run_name", kind: Kind::Utf8, extract: |e| Cell::Utf8(e.run_name.clone()) },
+    Column { name: "run_id", kind: Kind::Utf8, extract: |e| Cell::Utf8(e.run_id.clone()) },
+    Column { name: "aws_batch_job_id", kind: Kind::Utf8, extract: |e| Cell::Utf8(e.aws_batch_job_id.clone()) },
+    Column { name: "tags", kind: Kind::Utf8List, extract: |e| Cell::Utf8List(e.tags.clone()) },
+    Column { name: "attributes_type", kind: Kind::Utf8, extract: |e| Cell::Utf8(attributes_type(e).map(String::from)) },
+    // Process and CompletedProcess
+    Column { name: "tool_name", kind: Kind::Utf8, extract: |e| Cell::Utf8(process(e).map(|p| p.tool_name.clone()).or_else(|| completed_process(e).map(|p| p.tool_name.clone()))) },
+    Column { name: "tool_pid", kind: Kind::Utf8, extract: |e| Cell::Utf8(process(e).map(|p| p.tool_pid.clone()).or_else(|| completed_process(e).map(|p| p.tool_pid.clone()))) },
+    Column { name: "tool_parent_pid", kind: Kind::Utf8, extract: |e| Cell::Utf8(process(e).map(|p| p.tool_parent_pid.clone())) },
+    Column { name: "tool_binary_path", kind: Kind::Utf8, extract: |e| Cell::Utf8(process(e).map(|p| p.tool_binary_path.clone())) },
+    Column { name: "tool_cmd", kind: Kind::Utf8, extract: |e| Cell::Utf8(process(e).map(|p| p.tool_cmd.clone())) },
+    Column { name: "start_timestamp", kind: Kind::Utf8, extract: |e| Cell::Utf8(process(e).map(|p| p.start_timestamp.clone())) },
+    Column { name: "process_cpu_utilization", kind: Kind::Float64, extract: |e| Cell::Float64(process(e).map(|p| p.process_cpu_utilization as f64)) },
+    Column { name: "process_memory_usage", kind: Kind::UInt64, extract: |e| Cell::UInt64(process(e).map(|p| p.process_memory_usage)) },
+    Column { name: "process_memory_virtual", kind: Kind::UInt64, extract: |e| Cell::UInt64(process(e).map(|p| p.process_memory_virtual)) },
+    Column { name: "process_run_time", kind: Kind::UInt64, extract: |e| Cell::UInt64(process(e).map(|p| p.process_run_time)) },
+    Column { name: "process_disk_usage_read_last_interval", kind: Kind::UInt64, extract: |e| Cell::UInt64(process(e).map(|p| p.process_disk_usage_read_last_interval)) },
+    Column { name: "process_disk_usage_write_last_interval", kind: Kind::UInt64, extract: |e| Cell::UInt64(process(e).map(|p| p.process_disk_usage_write_last_interval)) },
+    Column { name: "process_disk_usage_read_total", kind: Kind::UInt64, extract: |e| Cell::UInt64(process(e).map(|p| p.process_disk_usage_read_total)) },
+    Column { name: "process_disk_usage_write_total", kind: Kind::UInt64, extract: |e| Cell::UInt64(process(e).map(|p| p.process_disk_usage_write_total)) },
+    Column { name: "tool_process_status", kind: Kind::Utf8, extract: |e| Cell::Utf8(process(e).map(|p| p.process_status.clone())) },
+    Column { name: "input_files", kind: Kind::Utf8, extract: |e| Cell::Utf8(process(e).and_then(|p| p.input_files.as_ref()).and_then(to_json)) },
+    Column { name: "duration_sec", kind: Kind::UInt64, extract: |e| Cell::UInt64(completed_process(e).map(|p| p.duration_sec)) },
+    Column { name: "tool_exit_code", kind: Kind::Int64, extract: |e| Cell::Int64(completed_process(e).and_then(|p| p.exit_code).map(i64::from)) },
+    Column { name: "tool_signal", kind: Kind::Int64, extract: |e| Cell::Int64(completed_process(e).and_then(|p| p.signal).map(i64::from)) },
+    Column { name: "tool_core_dumped", kind: Kind::Boolean, extract: |e| Cell::Boolean(completed_process(e).map(|p| p.core_dumped)) },
+    Column { name: "tool_failed", kind: Kind::Boolean, extract: |e| Cell::Boolean(completed_process(e).map(|p| p.failed)) },
+    Column { name: "peak_memory_usage", kind: Kind::UInt64, extract: |e| Cell::UInt64(completed_process(e).and_then(|p| p.peak_memory_usage)) },
+    Column { name: "peak_memory_virtual", kind: Kind::UInt64, extract: |e| Cell::UInt64(completed_process(e).and_then(|p| p.peak_memory_virtual)) },
+    Column { name: "cpu_user_time_sec", kind: Kind::Float64, extract: |e| Cell::Float64(completed_process(e).and_then(|p| p.cpu_user_time_sec)) },
+    Column { name: "cpu_system_time_sec", kind: Kind::Float64, extract: |e| Cell::Float64(completed_process(e).and_then(|p| p.cpu_system_time_sec)) },
+    Column { name: "disk_read_total", kind: Kind::UInt64, extract: |e| Cell::UInt64(completed_process(e).and_then(|p| p.disk_read_total)) },
+    Column { name: "disk_write_total", kind: Kind::UInt64, extract: |e| Cell::UInt64(completed_process(e).and_then(|p| p.disk_write_total)) },
+    Column { name: "files_read", kind: Kind::Utf8, extract: |e| Cell::Utf8(completed_process(e).map(|p| &p.files_read).filter(|f| !f.is_empty()).and_then(to_json)) },
+    Column { name: "files_written", kind: Kind::Utf8, extract: |e| Cell::Utf8(completed_process(e).map(|p| &p.files_written).filter(|f| !f.is_empty()).and_then(to_json)) },
+    Column { name: "max_threads", kind: Kind::UInt64, extract: |e| Cell::UInt64(completed_process(e).and_then(|p| p.max_threads)) },
+    // Process container
+    Column { name: "container_runtime", kind: Kind::Utf8, extract: |e| Cell::Utf8(process(e).and_then(|p| p.container.as_ref()).map(|c| c.runtime.as_str().to_string())) },
+    Column { name: "container_id", kind: Kind::Utf8, extract: |e| Cell::Utf8(process(e).and_then(|p| p.container.as_ref()).and_then(|c| c.container_id.clone())) },
+    Column { name: "container_image", kind: Kind::Utf8, extract: |e| Cell::Utf8(process(e).and_then(|p| p.container.as_ref()).and_then(|c| c.image_name.clone())) },
+    Column { name: "container_image_digest", kind: Kind::Utf8, extract: |e| Cell::Utf8(process(e).and_then(|p| p.container.as_ref()).and_then(|c| c.image_digest.clone())) },
+    // Process and Cgroup
+    Column { name: "cgroup_path", kind: Kind::Utf8, extract: |e| Cell::Utf8(cgroup(e).map(|c| c.cgroup_path.clone())) },
+    Column { name: "cgroup_memory_current", kind: Kind::UInt64, extract: |e| Cell::UInt64(cgroup(e).and_then(|c| c.memory_current)) },
+    Column { name: "cgroup_memory_peak", kind: Kind::UInt64, extract: |e| Cell::UInt64(cgroup(e).and_then(|c| c.memory_peak)) },
+    Column { name: "cgroup_memory_limit", kind: Kind::UInt64, extract: |e| Cell::UInt64(cgroup(e).and_then(|c| c.memory_limit)) },
+    Column { name: "cgroup_cpu_usage_usec", kind: Kind::UInt64, extract: |e| Cell::UInt64(cgroup(e).and_then(|c| c.cpu_usage_usec)) },
+    Column { name: "cgroup_cpu_user_usec", kind: Kind::UInt64, extract: |e| Cell::UInt64(cgroup(e).and_then(|c| c.cpu_user_usec)) },
+    Column { name: "cgroup_cpu_system_usec", kind: Kind::UInt64, extract: |e| Cell::UInt64(cgroup(e).and_then(|c| c.cpu_system_usec)) },
+    Column { name: "cgroup_cpu_throttled_usec", kind: Kind::UInt64, extract: |e| Cell::UInt64(cgroup(e).and_then(|c| c.cpu_throttled_usec)) },
+    Column { name: "cgroup_cpu_limit_cores", kind: Kind::Float64, extract: |e| Cell::Float64(cgroup(e).and_then(|c| c.cpu_limit_cores)) },
+    Column { name: "cgroup_io_read_bytes", kind: Kind::UInt64, extract: |e| Cell::UInt64(cgroup(e).and_then(|c| c.io_read_bytes)) },
+    Column { name: "cgroup_io_write_bytes", kind: Kind::UInt64, extract: |e| Cell::UInt64(cgroup(e).and_then(|c| c.io_write_bytes)) },
+    Column { name: "cgroup_oom_events", kind: Kind::UInt64, extract: |e| Cell::UInt64(cgroup(e).and_then(|c| c.oom_events)) },
+    Column { name: "cgroup_oom_kills", kind: Kind::UInt64, extract: |e| Cell::UInt64(cgroup(e).and_then(|c| c.oom_kills)) },
+    // UntrackedHeavyProcess
+    Column { name: "untracked_binary_path", kind: Kind::Utf8, extract: |e| Cell::Utf8(untracked_heavy_process(e).map(|u| u.binary_path.clone())) },
+    Column { name: "untracked_first_argument", kind: Kind::Utf8, extract: |e| Cell::Utf8(untracked_heavy_process(e).and_then(|u| u.first_argument.clone())) },
+    Column { name: "untracked_process_name", kind: Kind::Utf8, extract: |e| Cell::Utf8(untracked_heavy_process(e).map(|u| u.process_name.clone())) },
+    Column { name: "untracked_example_cmd", kind: Kind::Utf8, extract: |e| Cell::Utf8(untracked_heavy_process(e).map(|u| u.example_cmd.clone())) },
+    Column { name: "untracked_process_count", kind: Kind::UInt64, extract: |e| Cell::UInt64(untracked_heavy_process(e).map(|u| u.process_count)) },
+    Column { name: "untracked_cpu_time_sec", kind: Kind::Float64, extract: |e| Cell::Float64(untracked_heavy_process(e).map(|u| u.cpu_time_sec)) },
+    Column { name: "untracked_peak_memory_usage", kind: Kind::UInt64, extract: |e| Cell::UInt64(untracked_heavy_process(e).map(|u| u.peak_memory_usage)) },
+    Column { name: "untracked_longest_duration_sec", kind: Kind::UInt64, extract: |e| Cell::UInt64(untracked_heavy_process(e).map(|u| u.longest_duration_sec)) },
+    // SystemMetric and Syslog
+    Column { name: "events_name", kind: Kind::Utf8, extract: |e| Cell::Utf8(system_metric(e).map(|m| m.events_name.clone())) },
+    Column { name: "system_memory_total", kind: Kind::UInt64, extract: |e| Cell::UInt64(system_metric(e).map(|m| m.system_memory_total)) },
+    Column { name: "system_memory_used", kind: Kind::UInt64, extract: |e| Cell::UInt64(system_metric(e).map(|m| m.system_memory_used)) },
+    Column { name: "system_memory_available", kind: Kind::UInt64, extract: |e| Cell::UInt64(system_metric(e).map(|m| m.system_memory_available)) },
+    Column { name: "system_memory_utilization", kind: Kind::Float64, extract: |e| Cell::Float64(system_metric(e).map(|m| m.system_memory_utilization)) },
+    Column { name: "system_memory_swap_total", kind: Kind::UInt64, extract: |e| Cell::UInt64(system_metric(e).map(|m| m.system_memory_swap_total)) },
+    Column { name: "system_memory_swap_used", kind: Kind::UInt64, extract: |e| Cell::UInt64(system_metric(e).map(|m| m.system_memory_swap_used)) },
+    Column { name: "system_cpu_utilization", kind: Kind::Float64, extract: |e| Cell::Float64(system_metric(e).map(|m| m.system_cpu_utilization as f64)) },
+    Column { name: "system_disk_io", kind: Kind::Utf8, extract: |e| Cell::Utf8(system_metric(e).map(|m| &m.system_disk_io).or_else(|| system_properties(e).map(|p| &p.system_disk_io)).and_then(to_json)) },
+    Column { name: "system_cgroups", kind: Kind::Utf8, extract: |e| Cell::Utf8(system_metric(e).map(|m| &m.system_cgroups).filter(|c| !c.is_empty()).and_then(to_json)) },
+    Column { name: "tracer_cpu_utilization", kind: Kind::Float64, extract: |e| Cell::Float64(system_metric(e).and_then(|m| m.tracer_cpu_utilization).map(f64::from)) },
+    Column { name: "tracer_memory_usage", kind: Kind::UInt64, extract: |e| Cell::UInt64(system_metric(e).and_then(|m| m.tracer_memory_usage)) },
+    Column { name: "error_display_name", kind: Kind::Utf8, extract: |e| Cell::Utf8(match &e.attributes { Some(EventAttributes::Syslog(s)) => Some(s.error_display_name.clone()), _ => None }) },
+    Column { name: "error_id", kind: Kind::Utf8, extract: |e| Cell::Utf8(match &e.attributes { Some(EventAttributes::Syslog(s)) => Some(s.error_id.clone()), _ => None }) },
+    Column { name: "error_line", kind: Kind::Utf8, extract: |e| Cell::Utf8(match &e.attributes { Some(EventAttributes::Syslog(s)) => Some(s.error_line.clone()), _ => None }) },
+    Column { name: "file_line_number", kind: Kind::UInt64, extract: |e| Cell::UInt64(match &e.attributes { Some(EventAttributes::Syslog(s)) => Some(s.file_line_number), _ => None }) },
+    Column { name: "file_previous_logs", kind: Kind::Utf8List, extract: |e| Cell::Utf8List(match &e.attributes { Some(EventAttributes::Syslog(s)) => s.file_previous_logs.clone(), _ => vec![] }) },
+    // SystemProperties
+    Column { name: "os", kind: Kind::Utf8, extract: |e| Cell::Utf8(system_properties(e).and_then(|p| p.os.clone())) },
+    Column { name: "os_version", kind: Kind::Utf8, extract: |e| Cell::Utf8(system_properties(e).and_then(|p| p.os_version.clone())) },
+    Column { name: "kernel_version", kind: Kind::Utf8, extract: |e| Cell::Utf8(system_properties(e).and_then(|p| p.kernel_version.clone())) },
+    Column { name: "arch", kind: Kind::Utf8, extract: |e| Cell::Utf8(system_properties(e).and_then(|p| p.arch.clone())) },
+    Column { name: "num_cpus", kind: Kind::UInt64, extract: |e| Cell::UInt64(system_properties(e).map(|p| p.num_cpus as u64)) },
+    Column { name: "hostname", kind: Kind::Utf8, extract: |e| Cell::Utf8(system_properties(e).and_then(|p| p.hostname.clone())) },
+    Column { name: "total_memory", kind: Kind::UInt64, extract: |e| Cell::UInt64(system_properties(e).map(|p| p.total_memory)) },
+    Column { name: "total_swap", kind: Kind::UInt64, extract: |e| Cell::UInt64(system_properties(e).map(|p| p.total_swap)) },
+    Column { name: "uptime", kind: Kind::UInt64, extract: |e| Cell::UInt64(system_properties(e).map(|p| p.uptime)) },
+    Column { name: "aws_metadata", kind: Kind::Utf8, extract: |e| Cell::Utf8(system_properties(e).and_then(|p| p.aws_metadata.as_ref()).and_then(to_json)) },
+    Column { name: "is_aws_instance", kind: Kind::Boolean, extract: |e| Cell::Boolean(system_properties(e).map(|p| p.is_aws_instance)) },
+    Column { name: "ec2_cost_per_hour", kind: Kind::Float64, extract: |e| Cell::Float64(system_properties(e).and_then(|p| p.ec2_cost_per_hour)) },
+    // ProcessDatasetStats
+    Column { name: "datasets", kind: Kind::Utf8, extract: |e| Cell::Utf8(match &e.attributes { Some(EventAttributes::ProcessDatasetStats(d)) => Some(d.datasets.clone()), _ => None }) },
+    Column { name: "datasets_total", kind: Kind::UInt64, extract: |e| Cell::UInt64(match &e.attributes { Some(EventAttributes::ProcessDatasetStats(d)) => Some(d.total), _ => None }) },
+    // NextflowLog
+    Column { name: "session_uuid", kind: Kind::Utf8, extract: |e| Cell::Utf8(match &e.attributes { Some(EventAttributes::NextflowLog(l)) => l.session_uuid.clone(), _ => None }) },
+    Column { name: "jobs_ids", kind: Kind::Utf8List, extract: |e| Cell::Utf8List(match &e.attributes { Some(EventAttributes::NextflowLog(l)) => l.jobs_ids.clone().unwrap_or_default(), _ => vec![] }) },
+    // Log
+    Column { name: "log_severity", kind: Kind::Utf8, extract: |e| Cell::Utf8(match &e.attributes { Some(EventAttributes::Log(l)) => Some(l.severity.as_str().to_string()), _ => None }) },
+    // RunCompletion
+    Column { name: "run_command", kind: Kind::Utf8, extract: |e| Cell::Utf8(run_completion(e).map(|r| r.command.clone())) },
+    Column { name: "run_exit_code", kind: Kind::Int64, extract: |e| Cell::Int64(run_completion(e).and_then(|r| r.exit_code.map(i64::from))) },
+    Column { name: "run_signal", kind: Kind::Int64, extract: |e| Cell::Int64(run_completion(e).and_then(|r| r.signal.map(i64::from))) },
+    // Other
+    Column { name: "attributes_json", kind: Kind::Utf8, extract: |e| Cell::Utf8(match &e.attributes { Some(EventAttributes::Other(value)) => Some(value.to_string()), _ => None }) },
+];
+
+impl Kind {
+    fn data_type(&self) -> DataType {
+        match self {
+            Kind::Utf8 => DataType::Utf8,
+            Kind::Int64 => DataType::Int64,
+            Kind::UInt64 => DataType::UInt64,
+            Kind::Float64 => DataType::Float64,
+            Kind::Boolean => DataType::Boolean,
+            Kind::TimestampMs => DataType::Timestamp(TimeUnit::Millisecond, Some("UTC".into())),
+            Kind::Utf8List => DataType::List(Arc::new(Field::new("item", DataType::Utf8, true))),
+        }
+    }
+}
+
+fn events_schema() -> SchemaRef {
+    Arc::new(Schema::new(
+        COLUMNS
+            .iter()
+            .map(|column| {
+                let nullable = !matches!(column.kind, Kind::TimestampMs | Kind::Utf8List);
+                Field::new(column.name, column.kind.data_type(), nullable)
+            })
+            .collect::<Vec<_>>(),
+    ))
+}
+
+fn build_column(column: &Column, data: &[Event]) -> ArrayRef {
+    let cells = data.iter().map(column.extract);
+
+    match column.kind {
+        Kind::Utf8 => {
+            let mut builder = StringBuilder::new();
+            cells.for_each(|cell| match cell {
+                Cell::Utf8(value) => builder.append_option(value),
+                _ => builder.append_null(),
+            });
+            Arc::new(builder.finish())
+        }
+        Kind::Int64 => {
+            let mut builder = Int64Builder::new();
+            cells.for_each(|cell| match cell {
+                Cell::Int64(value) => builder.append_option(value),
+                _ => builder.append_null(),
+            });
+            Arc::new(builder.finish())
+        }
+        Kind::UInt64 => {
+            let mut builder = UInt64Builder::new();
+            cells.for_each(|cell| match cell {
+                Cell::UInt64(value) => builder.append_option(value),
+                _ => builder.append_null(),
+            });
+            Arc::new(builder.finish())
+        }
+        Kind::Float64 => {
+            let mut builder = Float64Builder::new();
+            cells.for_each(|cell| match cell {
+                Cell::Float64(value) => builder.append_option(value),
+                _ => builder.append_null(),
+            });
+            Arc::new(builder.finish())
+        }
+        Kind::Boolean => {
+            let mut builder = BooleanBuilder::new();
+            cells.for_each(|cell| match cell {
+                Cell::Boolean(value) => builder.append_option(value),
+                _ => builder.append_null(),
+            });
+            Arc::new(builder.finish())
+        }
+        Kind::TimestampMs => {
+            let mut builder = TimestampMillisecondBuilder::new().with_timezone("UTC");
+            cells.for_each(|cell| match cell {
+                Cell::TimestampMs(value) => builder.append_value(value),
+                _ => builder.append_value(0),
+            });
+            Arc::new(builder.finish())
+        }
+        Kind::Utf8List => {
+            let mut builder = ListBuilder::new(StringBuilder::new());
+            cells.for_each(|cell| {
+                if let Cell::Utf8List(values) = cell {
+                    values
+                        .into_iter()
+                        .for_each(|value| builder.values().append_value(value));
+                }
+                builder.append(true);
+            });
+            Arc::new(builder.finish())
+        }
+    }
+}
+
+impl ParquetExporter {
+    pub fn new(config: &ParquetExporterConfig) -> Result<Self> {
+        Ok(Self {
+            directory: resolve_export_dir(config.directory.as_deref())?,
+            rotation: config.rotation,
+            schema: events_schema(),
+            current_run: Mutex::new(None),
+        })
+    }
+
+    pub fn directory(&self) -> &Path {
+        &self.directory
+    }
+
+    pub fn to_record_batch(&self, data: &[Event]) -> Result<RecordBatch> {
+        let columns = COLUMNS
+            .iter()
+            .map(|column| build_column(column, data))
+            .collect::<Vec<_>>();
+
+        RecordBatch::try_new(self.schema.clone(), columns)
+            .context("Failed to build parquet record batch")
+    }
+
+    fn run_directory(&self, run_name: &str) -> Result<PathBuf> {
+        let run_directory = self.directory.join(run_path_component(run_name));
+        std::fs::create_dir_all(&run_directory)
+            .with_context(|| format!("Failed to create export dir {run_directory:?}"))?;
+        Ok(run_directory)
+    }
+
+    /// Never truncates, exported files are only ever added
+    fn create_writer(&self, path: &Path) -> Result<ArrowWriter<File>> {
+        let file = File::create_new(path).with_context(|| format!("Failed to create {path:?}"))?;
+        let properties = WriterProperties::builder()
+            .set_compression(Compression::SNAPPY)
+            .build();
+        ArrowWriter::try_new(file, self.schema.clone(), Some(properties))
+            .context("Failed to create parquet writer")
+    }
+
+    fn write_batch_file(&self, run_name: &str, batch: &RecordBatch) -> Result<()> {
+        let path = self
+            .run_directory(run_name)?
+            .join(format!("{}.parquet", uuid::Uuid::new_v4()));
+        let mut writer = self.create_writer(&path)?;
+        writer.write(batch)?;
+        writer.close()?;
+        Ok(())
+    }
+
+    fn write_run_file(
+        &self,
+        run_name: &str,
+        run_id: &str,
+        batch: &RecordBatch,
+        finished: bool,
+    ) -> Result<()> {
+        let mut current_run = self.current_run.lock().unwrap();
+
+        if current_run
+            .as_ref()
+            .is_some_and(|current| current.run_id != run_id)
+        {
+            Self::finish_run_writer(current_run.take())?;
+        }
+
+        if current_run.is_none() {
+            // a run resumed after a restart, or replayed from the spool, gets a file of its own
+            let run_directory = self.run_directory(run_name)?;
+            let file_stem = run_path_component(run_id);
+            let path = std::iter::once(format!("{file_stem}.parquet"))
+                .chain((1..).map(|seq| format!("{file_stem}-{seq}.parquet")))
+                .map(|file_name| run_directory.join(file_name))
+                .find(|path| !path.exists())
+                .unwrap();
+            *current_run = Some(RunWriter {
+                run_id: run_id.to_string(),
+                writer: self.create_writer(&path)?,
+                path,
+            });
+        }
+
+        let run_writer = current_run.as_mut().unwrap();
+        run_writer.writer.write(batch)?;
+        // one row group per batch keeps the memory held by the open writer small
+        run_writer.writer.flush()?;
+
+        if finished {
+            Self::finish_run_writer(current_run.take())?;
+        }
+
+        Ok(())
+    }
+
+    fn finish_run_writer(run_writer: Option<RunWriter>) -> Result<()> {
+        if let Some(run_writer) = run_writer {
+            run_writer
+                .writer
+                .close()
+                .with_context(|| format!("Failed to finalize {:?}", run_writer.path))?;
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl EventExporter for ParquetExporter {
+    fn name(&self) -> &str {
+        "parquet"
+    }
+
+    async fn batch_insert_events(
+        &self,
+        run_name: &str,
+        run_id: &str,
+        _pipeline_name: &str,
+        data: &[Event],
+    ) -> Result<()> {
+        if data.is_empty() {
+            return Ok(());
+        }
+
+        let batch = self.to_record_batch(data)?;
+
+        match self.rotation {
+            ParquetRotation::PerBatch => self.write_batch_file(run_name, &batch),
+            ParquetRotation::PerRun => {
+                let finished = data
+                    .iter()
+                    .any(|event| event.process_status == EventType::FinishedRun.as_str());
+                self.write_run_file(run_name, run_id, &batch, finished)
+            }
+        }
+    }
+
+    async fn close(&self) -> Result<()> {
+        Self::finish_run_writer(self.current_run.lock().unwrap().take())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events::recorder::EventRecorder;
+    use crate::types::event::attributes::process::CompletedProcess;
+    use arrow_array::cast::AsArray;
+    use arrow_array::types::UInt64Type;
+    use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+    use tempfile::tempdir;
+
+    fn exporter(directory: &Path, rotation: ParquetRotation) -> ParquetExporter {
+        ParquetExporter::new(&ParquetExporterConfig {
+            enabled: true,
+            directory: Some(directory.to_str().unwrap().to_string()),
+            rotation,
+        })
+        .unwrap()
+    }
+
+    fn read_rows(path: &Path) -> Vec<RecordBatch> {
+        let file = File::open(path).unwrap();
+        ParquetRecordBatchReaderBuilder::try_new(file)
+            .unwrap()
+            .build()
+            .unwrap()
+            .map(|batch| batch.unwrap())
+            .collect()
+    }
+
+    fn completed_process_event(recorder: &mut EventRecorder) {
+        recorder.record_event(
+            EventType::FinishedToolExecution,
+            "STAR exited".to_string(),
+            Some(EventAttributes::CompletedProcess(CompletedProcess {
+                tool_name: "STAR".to_string(),
+                tool_pid: "42".to_string(),
+                duration_sec: 12,
+                ..Default::default()
+            })),
+            None,
+        );
+    }
+
+    #[tokio::test]
+    async fn test_per_run_file_is_finalized_on_finished_run() {
+        let temp_dir = tempdir().unwrap();
+        let exporter = exporter(temp_dir.path(), ParquetRotation::PerRun);
+        let mut recorder = EventRecorder::default();
+
+        completed_process_event(&mut recorder);
+        exporter
+            .batch_insert_events("run", "run-id", "pipeline", recorder.get_events())
+            .await
+            .unwrap();
+
+        recorder.clear();
+        recorder.record_event(EventType::FinishedRun, "done".to_string(), None, None);
+        exporter
+            .batch_insert_events("run", "run-id", "pipeline", recorder.get_events())
+            .await
+            .unwrap();
+
+        let batches = read_rows(&temp_dir.path().join("run").join("run-id.parquet"));
+        let rows: usize = batches.iter().map(|batch| batch.num_rows()).sum();
+        assert_eq!(rows, 2);
+
+        let first = &batches[0];
+        let tool_name = first
+            .column_by_name("tool_name")
+            .unwrap()
+            .as_string::<i32>();
+        assert_eq!(tool_name.value(0), "STAR");
+        let duration = first
+            .column_by_name("duration_sec")
+            .unwrap()
+            .as_primitive::<UInt64Type>();
+        assert_eq!(duration.value(0), 12);
+    }
+
+    #[tokio::test]
+    async fn test_per_run_file_is_never_overwritten() {
+        let temp_dir = tempdir().unwrap();
+        let mut recorder = EventRecorder::default();
+        completed_process_event(&mut recorder);
+
+        // the daemon restarts mid-run, each exporter writes its own file
+        for _ in 0..2 {
+            let exporter = exporter(temp_dir.path(), ParquetRotation::PerRun);
+            exporter
+                .batch_insert_events("run", "run-id", "pipeline", recorder.get_events())
+                .await
+                .unwrap();
+            exporter.close().await.unwrap();
+        }
+
+        let run_directory = temp_dir.path().join("run");
+        for file_name in ["run-id.parquet", "run-id-1.parquet"] {
+            let rows: usize = read_rows(&run_directory.join(file_name))
+                .iter()
+                .map(|batch| batch.num_rows())
+                .sum();
+            assert_eq!(rows, 1);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_run_tags_stay_in_the_export_directory() {
+        let temp_dir = tempdir().unwrap();
+        let export_dir = temp_dir.path().join("exports");
+        let mut recorder = EventRecorder::default();
+        completed_process_event(&mut recorder);
+
+        for rotation in [ParquetRotation::PerRun, ParquetRotation::PerBatch] {
+            let exporter = exporter(&export_dir, rotation);
+            exporter
+                .batch_insert_events("../../x", "/tmp/x", "pipeline", recorder.get_events())
+                .await
+                .unwrap();
+            exporter.close().await.unwrap();
+        }
+
+        let entries: Vec<_> = std::fs::read_dir(temp_dir.path())
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect();
+        assert_eq!(entries, ["exports"]);
+        let run_directory = export_dir.join(run_path_component("../../x"));
+        assert_eq!(std::fs::read_dir(&run_directory).unwrap().count(), 2);
+        assert!(run_directory.join("_tmp_x.parquet").exists());
+    }
+
+    #[tokio::test]
+    async fn test_per_batch_rotation_writes_a_file_per_batch() {
+        let temp_dir = tempdir().unwrap();
+        let exporter = exporter(temp_dir.path(), ParquetRotation::PerBatch);
+        let mut recorder = EventRecorder::default();
+
+        completed_process_event(&mut recorder);
+        for _ in 0..2 {
+            exporter
+                .batch_insert_events("run", "run-id", "pipeline", recorder.get_events())
+                .await
+                .unwrap();
+        }
+
+        let files: Vec<_> = std::fs::read_dir(temp_dir.path().join("run"))
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .collect();
+        assert_eq!(files.len(), 2);
+        assert!(files.iter().all(|file| read_rows(file)
+            .iter()
+            .map(|b| b.num_rows())
+            .sum::<usize>()
+            == 1));
+    }
+}
@@ -0,0 +1,710 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write as _;
+use std::net::SocketAddr;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::task::JoinHandle;
+use tokio::time::timeout;
+
+use super::EventExporter;
+use crate::events::recorder::EventType;
+use crate::types::config::PrometheusExporterConfig;
+use crate::types::event::attributes::process::ProcessProperties;
+use crate::types::event::attributes::system_metrics::{
+    CgroupStatistic, DiskStatistic, SystemMetric,
+};
+use crate::types::event::attributes::EventAttributes;
+use crate::types::event::Event;
+
+const MAX_REQUEST_SIZE: usize = 8 * 1024;
+/// A scraper that connects and never sends its request gets disconnected after this
+const READ_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Clone, Copy)]
+enum MetricType {
+    Gauge,
+    /// Only ever goes up, named `..._total`
+    Counter,
+}
+
+impl MetricType {
+    fn as_str(self) -> &'static str {
+        match self {
+            MetricType::Gauge => "gauge",
+            MetricType::Counter => "counter",
+        }
+    }
+}
+
+/// Metric name, help text and how to read the value
+type DiskGauge = (&'static str, &'static str, fn(&DiskStatistic) -> f64);
+type CgroupMetric = (
+    &'static str,
+    &'static str,
+    MetricType,
+    fn(&CgroupStatistic) -> Option<f64>,
+);
+type ToolMetric = (
+    &'static str,
+    &'static str,
+    MetricType,
+    fn(&[&ProcessProperties]) -> f64,
+);
+
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord)]
+struct ToolLabels {
+    tool_name: String,
+    run_id: String,
+    pipeline_name: String,
+    tags: String,
+}
+
+struct ToolSample {
+    labels: ToolLabels,
+    properties: ProcessProperties,
+}
+
+/// Latest gauges seen in the submitted batches
+#[derive(Default)]
+struct MetricsState {
+    system: Option<SystemMetric>,
+    /// Running tool processes by pid
+    tools: HashMap<String, ToolSample>,
+}
+
+impl MetricsState {
+    fn update(&mut self, data: &[Event]) {
+        for event in data {
+            match (event.process_status.as_str(), &event.attributes) {
+                (status, Some(EventAttributes::SystemMetric(metric)))
+                    if status == EventType::MetricEvent.as_str() =>
+                {
+                    self.system = Some(metric.clone());
+                }
+                (_, Some(EventAttributes::Process(properties))) => {
+                    let labels = ToolLabels {
+                        tool_name: properties.tool_name.clone(),
+                        run_id: event.run_id.clone().unwrap_or_default(),
+                        pipeline_name: event.pipeline_name.clone().unwrap_or_default(),
+                        tags: event.tags.join(","),
+                    };
+                    self.tools.insert(
+                        properties.tool_pid.clone(),
+                        ToolSample {
+                            labels,
+                            properties: properties.clone(),
+                        },
+                    );
+                }
+                (_, Some(EventAttributes::CompletedProcess(completed))) => {
+                    self.tools.remove(&completed.tool_pid);
+                }
+                (status, _) if status == EventType::FinishedRun.as_str() => {
+                    self.tools.clear();
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn render(&self) -> String {
+        let mut out = String::new();
+
+        if let Some(system) = &self.system {
+            let host_gauges: [(&str, &str, f64); 7] = [
+                (
+                    "tracer_system_memory_total_bytes",
+                    "Total memory of the host",
+                    system.system_memory_total as f64,
+                ),
+                (
+                    "tracer_system_memory_used_bytes",
+                    "Memory in use on the host",
+                    system.system_memory_used as f64,
+                ),
+                (
+                    "tracer_system_memory_available_bytes",
+                    "Memory available on the host",
+                    system.system_memory_available as f64,
+                ),
+                (
+                    "tracer_system_memory_utilization_percent",
+                    "Share of the host memory in use",
+                    system.system_memory_utilization,
+                ),
+                (
+                    "tracer_system_swap_total_bytes",
+                    "Total swap of the host",
+                    system.system_memory_swap_total as f64,
+                ),
+                (
+                    "tracer_system_swap_used_bytes",
+                    "Swap in use on the host",
+                    system.system_memory_swap_used as f64,
+                ),
+                (
+                    "tracer_system_cpu_utilization_percent",
+                    "CPU utilization of the host",
+                    system.system_cpu_utilization as f64,
+                ),
+            ];
+            for (name, help, value) in host_gauges {
+                write_header(&mut out, name, help, MetricType::Gauge);
+                write_sample(&mut out, name, &[], value);
+            }
+
+            let overhead_gauges = [
+                (
+                    "tracer_daemon_cpu_utilization_percent",
+                    "CPU used by the tracer daemon, in percent of one core",
+                    system.tracer_cpu_utilization.map(f64::from),
+                ),
+                (
+                    "tracer_daemon_memory_bytes",
+                    "Resident memory of the tracer daemon",
+                    system.tracer_memory_usage.map(|memory| memory as f64),
+                ),
+            ];
+            for (name, help, value) in overhead_gauges {
+                if let Some(value) = value {
+                    write_header(&mut out, name, help, MetricType::Gauge);
+                    write_sample(&mut out, name, &[], value);
+                }
+            }
+
+            let mut disks: Vec<_> = system.system_disk_io.iter().collect();
+            disks.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+            let disk_gauges: [DiskGauge; 4] = [
+                (
+                    "tracer_system_disk_total_bytes",
+                    "Total space of the disk",
+                    |d| d.disk_total_space as f64,
+                ),
+                (
+                    "tracer_system_disk_used_bytes",
+                    "Used space of the disk",
+                    |d| d.disk_used_space as f64,
+                ),
+                (
+                    "tracer_system_disk_available_bytes",
+                    "Available space of the disk",
+                    |d| d.disk_available_space as f64,
+                ),
+                (
+                    "tracer_system_disk_utilization_percent",
+                    "Share of the disk space in use",
+                    |d| d.disk_utilization,
+                ),
+            ];
+            for (name, help, value) in disk_gauges {
+                write_header(&mut out, name, help, MetricType::Gauge);
+                for (disk, statistic) in &disks {
+                    write_sample(&mut out, name, &[("disk", disk)], value(statistic));
+                }
+            }
+
+            let mut cgroups: Vec<_> = system.system_cgroups.iter().collect();
+            cgroups.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+            let cgroup_metrics: [CgroupMetric; 8] = [
+                (
+                    "tracer_cgroup_memory_bytes",
+                    "Memory in use by the cgroup",
+                    MetricType::Gauge,
+                    |c| c.memory_current.map(|v| v as f64),
+                ),
+                (
+                    "tracer_cgroup_memory_peak_bytes",
+                    "Peak memory of the cgroup",
+                    MetricType::Gauge,
+                    |c| c.memory_peak.map(|v| v as f64),
+                ),
+                (
+                    "tracer_cgroup_memory_limit_bytes",
+                    "Memory limit of the cgroup",
+                    MetricType::Gauge,
+                    |c| c.memory_limit.map(|v| v as f64),
+                ),
+                (
+                    "tracer_cgroup_cpu_seconds_total",
+                    "CPU time used by the cgroup",
+                    MetricType::Counter,
+                    |c| c.cpu_usage_usec.map(|v| v as f64 / 1_000_000.0),
+                ),
+                (
+                    "tracer_cgroup_cpu_limit_cores",
+                    "CPU limit of the cgroup in cores",
+                    MetricType::Gauge,
+                    |c| c.cpu_limit_cores,
+                ),
+                (
+                    "tracer_cgroup_io_read_bytes_total",
+                    "Bytes read by the cgroup",
+                    MetricType::Counter,
+                    |c| c.io_read_bytes.map(|v| v as f64),
+                ),
+                (
+                    "tracer_cgroup_io_written_bytes_total",
+                    "Bytes written by the cgroup",
+                    MetricType::Counter,
+                    |c| c.io_write_bytes.map(|v| v as f64),
+                ),
+                (
+                    "tracer_cgroup_oom_kills_total",
+                    "Processes of the cgroup killed by the OOM killer",
+                    MetricType::Counter,
+                    |c| c.oom_kills.map(|v| v as f64),
+                ),
+            ];
+            if !cgroups.is_empty() {
+                for (name, help, metric_type, value) in cgroup_metrics {
+                    write_header(&mut out, name, help, metric_type);
+                    for (cgroup, statistic) in &cgroups {
+                        if let Some(value) = value(statistic) {
+                            write_sample(&mut out, name, &[("cgroup", cgroup)], value);
+                        }
+                    }
+                }
+            }
+        }
+
+        // processes of the same tool in the same run are summed into one series
+        let mut tools: BTreeMap<&ToolLabels, Vec<&ProcessProperties>> = BTreeMap::new();
+        for sample in self.tools.values() {
+            tools
+                .entry(&sample.labels)
+                .or_default()
+                .push(&sample.properties);
+        }
+
+        let tool_metrics: [ToolMetric; 7] = [
+            (
+                "tracer_tool_processes",
+                "Running processes of the tool",
+                MetricType::Gauge,
+                |p| p.len() as f64,
+            ),
+            (
+                "tracer_tool_cpu_utilization_percent",
+                "CPU utilization of the tool",
+                MetricType::Gauge,
+                |p| p.iter().map(|p| p.process_cpu_utilization as f64).sum(),
+            ),
+            (
+                "tracer_tool_memory_bytes",
+                "Resident memory of the tool",
+                MetricType::Gauge,
+                |p| p.iter().map(|p| p.process_memory_usage as f64).sum(),
+            ),
+            (
+                "tracer_tool_memory_virtual_bytes",
+                "Virtual memory of the tool",
+                MetricType::Gauge,
+                |p| p.iter().map(|p| p.process_memory_virtual as f64).sum(),
+            ),
+            (
+                "tracer_tool_run_time_seconds",
+                "Run time of the longest running process of the tool",
+                MetricType::Gauge,
+                |p| {
+                    p.iter()
+                        .map(|p| p.process_run_time as f64)
+                        .fold(0.0, f64::max)
+                },
+            ),
+            (
+                "tracer_tool_disk_read_bytes_total",
+                "Bytes read by the tool",
+                MetricType::Counter,
+                |p| {
+                    p.iter()
+                        .map(|p| p.process_disk_usage_read_total as f64)
+                        .sum()
+                },
+            ),
+            (
+                "tracer_tool_disk_written_bytes_total",
+                "Bytes written by the tool",
+                MetricType::Counter,
+                |p| {
+                    p.iter()
+                        .map(|p| p.process_disk_usage_write_total as f64)
+                        .sum()
+                },
+            ),
+        ];
+
+        if !tools.is_empty() {
+            for (name, help, metric_type, value) in tool_metrics {
+                write_header(&mut out, name, help, metric_type);
+                for (labels, processes) in &tools {
+                    let labels = [
+                        ("tool_name", labels.tool_name.as_str()),
+                        ("run_id", labels.run_id.as_str()),
+                        ("pipeline_name", labels.pipeline_name.as_str()),
+                        ("tags", labels.tags.as_str()),
+                    ];
+                    write_sample(&mut out, name, &labels, value(processes));
+                }
+            }
+        }
+
+        out
+    }
+}
+
+fn write_header(out: &mut String, name: &str, help: &str, metric_type: MetricType) {
+    let _ = writeln!(out, "# HELP {name} {help}");
+    let _ = writeln!(out, "# TYPE {name} {}", metric_type.as_str());
+}
+
+fn write_sample(out: &mut String, name: &str, labels: &[(&str, &str)], value: f64) {
+    out.push_str(name);
+    if !labels.is_empty() {
+        let labels: Vec<String> = labels
+            .iter()
+            .map(|(key, value)| format!("{key}=\"{}\"", escape_label_value(value)))
+            .collect();
+        let _ = write!(out, "{{{}}}", labels.join(","));
+    }
+    let _ = writeln!(out, " {value}");
+}
+
+fn escape_label_value(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// Serves the latest host and per-tool gauges in the Prometheus text format on `/metrics`
+pub struct PrometheusExporter {
+    state: Arc<RwLock<MetricsState>>,
+    local_addr: SocketAddr,
+    server: JoinHandle<()>,
+}
+
+impl PrometheusExporter {
+    pub async fn new(config: &PrometheusExporterConfig) -> Result<Self> {
+        let listener = TcpListener::bind(&config.listen_address)
+            .await
+            .with_context(|| {
+                format!(
+                    "Failed to bind metrics endpoint to {}",
+                    config.listen_address
+                )
+            })?;
+        let local_addr = listener.local_addr()?;
+
+        let state = Arc::new(RwLock::new(MetricsState::default()));
+        let server = tokio::spawn(serve(listener, state.clone()));
+
+        Ok(Self {
+            state,
+            local_addr,
+            server,
+        })
+    }
+
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    pub fn render(&self) -> String {
+        self.state.read().unwrap().render()
+    }
+}
+
+#[async_trait]
+impl EventExporter for PrometheusExporter {
+    fn name(&self) -> &str {
+        "prometheus"
+    }
+
+    async fn batch_insert_events(
+        &self,
+        _run_name: &str,
+        _run_id: &str,
+        _pipeline_name: &str,
+        data: &[Event],
+    ) -> Result<()> {
+        self.state.write().unwrap().update(data);
+        Ok(())
+    }
+
+    async fn close(&self) -> Result<()> {
+        self.server.abort();
+        Ok(())
+    }
+}
+
+async fn serve(listener: TcpListener, state: Arc<RwLock<MetricsState>>) {
+    loop {
+        match listener.accept().await {
+            Ok((stream, _)) => {
+                let state = state.clone();
+                tokio::spawn(async move {
+                    if let Err(err) = handle_connection(stream, state, READ_TIMEOUT).await {
+                        tracing::debug!("Failed to serve metrics request: {:?}", err);
+                    }
+                });
+            }
+            Err(err) => tracing::warn!("Failed to accept metrics connection: {:?}", err),
+        }
+    }
+}
+
+async fn read_request(stream: &mut TcpStream) -> Result<Vec<u8>> {
+    let mut request = Vec::new();
+    let mut buffer = [0u8; 1024];
+
+    while !request.windows(4).any(|w| w == b"\r\n\r\n") && request.len() < MAX_REQUEST_SIZE {
+        let n = stream.read(&mut buffer).await?;
+        if n == 0 {
+            break;
+        }
+        request.extend_from_slice(&buffer[..n]);
+    }
+    Ok(request)
+}
+
+async fn handle_connection(
+    mut stream: TcpStream,
+    state: Arc<RwLock<MetricsState>>,
+    read_timeout: Duration,
+) -> Result<()> {
+    // the stream is dropped with the error, which closes the connection
+    let request = timeout(read_timeout, read_request(&mut stream))
+        .await
+        .context("Timed out reading the metrics request")??;
+
+    let request = String::from_utf8_lossy(&request);
+    let mut request_line = request
+        .lines()
+        .next()
+        .unwrap_or_default()
+        .split_whitespace();
+    let method = request_line.next().unwrap_or_default();
+    let path = request_line
+        .next()
+        .unwrap_or_default()
+        .split('?')
+        .next()
+        .unwrap_or_default();
+
+    let (status, content_type, body) = match (method, path) {
+        ("GET", "/metrics") => (
+            "200 OK",
+            "text/plain; version=0.0.4",
+            state.read().unwrap().render(),
+        ),
+        ("GET", _) => ("404 Not Found", "text/plain", "Not Found\n".to_string()),
+        _ => (
+            "405 Method Not Allowed",
+            "text/plain",
+            "Method Not Allowed\n".to_string(),
+        ),
+    };
+
+    let response = format!(
+        "HTTP/1.1 {status}\r\nContent-Type: {content_type}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    );
+    stream.write_all(response.as_bytes()).await?;
+    stream.shutdown().await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events::recorder::EventRecorder;
+    use crate::types::event::attributes::process::CompletedProcess;
+
+    fn tool_properties(tool_name: &str, pid: &str, memory: u64) -> ProcessProperties {
+        ProcessProperties {
+            tool_name: tool_name.to_string(),
+            tool_pid: pid.to_string(),
+            tool_parent_pid: "1".to_string(),
+            tool_binary_path: format!("/usr/bin/{tool_name}"),
+            tool_cmd: tool_name.to_string(),
+            start_timestamp: "test".to_string(),
+            process_cpu_utilization: 50.0,
+            process_memory_usage: memory,
+            process_memory_virtual: memory * 2,
+            process_run_time: 10,
+            process_disk_usage_read_last_interval: 0,
+            process_disk_usage_write_last_interval: 0,
+            process_disk_usage_read_total: 100,
+            process_disk_usage_write_total: 200,
+            process_status: "Run".to_string(),
+            input_files: None,
+            cgroup: None,
+            container: None,
+        }
+    }
+
+    fn recorder() -> EventRecorder {
+        let mut recorder = EventRecorder::default();
+        recorder.update_run_details(
+            Some("pipeline".to_string()),
+            Some("run".to_string()),
+            Some("run-id".to_string()),
+            vec!["team=genomics".to_string(), "env=\"prod\"".to_string()],
+        );
+        recorder
+    }
+
+    #[test]
+    fn test_render_host_and_tool_gauges() {
+        let mut recorder = recorder();
+        recorder.record_event(
+            EventType::MetricEvent,
+            "metrics".to_string(),
+            Some(EventAttributes::SystemMetric(SystemMetric {
+                events_name: "global_system_metrics".to_string(),
+                system_memory_total: 1024,
+                system_memory_used: 512,
+                system_memory_available: 512,
+                system_memory_utilization: 50.0,
+                system_memory_swap_total: 0,
+                system_memory_swap_used: 0,
+                system_cpu_utilization: 12.5,
+                system_disk_io: HashMap::from([(
+                    "/dev/sda1".to_string(),
+                    DiskStatistic {
+                        disk_total_space: 100,
+                        disk_used_space: 40,
+                        disk_available_space: 60,
+                        disk_utilization: 40.0,
+                    },
+                )]),
+                system_cgroups: HashMap::from([(
+                    "/docker/abc".to_string(),
+                    CgroupStatistic {
+                        cgroup_path: "/docker/abc".to_string(),
+                        memory_current: Some(256),
+                        memory_limit: Some(1024),
+                        oom_kills: Some(1),
+                        ..Default::default()
+                    },
+                )]),
+                tracer_cpu_utilization: Some(0.5),
+                tracer_memory_usage: None,
+            })),
+            None,
+        );
+        for pid in ["10", "11"] {
+            recorder.record_event(
+                EventType::ToolMetricEvent,
+                "bwa".to_string(),
+                Some(EventAttributes::Process(tool_properties("bwa", pid, 1000))),
+                None,
+            );
+        }
+
+        let mut state = MetricsState::default();
+        state.update(recorder.get_events());
+        let rendered = state.render();
+
+        assert!(rendered.contains("tracer_system_memory_used_bytes 512\n"));
+        assert!(rendered.contains("tracer_system_cpu_utilization_percent 12.5\n"));
+        assert!(rendered.contains("tracer_daemon_cpu_utilization_percent 0.5\n"));
+        assert!(!rendered.contains("tracer_daemon_memory_bytes"));
+        assert!(rendered.contains("tracer_system_disk_used_bytes{disk=\"/dev/sda1\"} 40\n"));
+        assert!(
+            rendered.contains("tracer_cgroup_memory_limit_bytes{cgroup=\"/docker/abc\"} 1024\n")
+        );
+        assert!(rendered.contains("tracer_cgroup_oom_kills_total{cgroup=\"/docker/abc\"} 1\n"));
+        assert!(rendered.contains("# TYPE tracer_cgroup_oom_kills_total counter\n"));
+        assert!(rendered.contains("# TYPE tracer_cgroup_memory_limit_bytes gauge\n"));
+        assert!(!rendered.contains("tracer_cgroup_memory_peak_bytes{"));
+        assert!(rendered.contains(
+            "tracer_tool_memory_bytes{tool_name=\"bwa\",run_id=\"run-id\",pipeline_name=\"pipeline\",tags=\"team=genomics,env=\\\"prod\\\"\"} 2000\n"
+        ));
+        assert!(rendered.contains("# TYPE tracer_tool_processes gauge\n"));
+        assert!(rendered.contains("# TYPE tracer_tool_disk_read_bytes_total counter\n"));
+
+        recorder.clear();
+        recorder.record_event(
+            EventType::FinishedToolExecution,
+            "bwa".to_string(),
+            Some(EventAttributes::CompletedProcess(CompletedProcess {
+                tool_name: "bwa".to_string(),
+                tool_pid: "10".to_string(),
+                duration_sec: 10,
+                ..Default::default()
+            })),
+            None,
+        );
+        state.update(recorder.get_events());
+        assert!(state.render().contains(
+            "tracer_tool_processes{tool_name=\"bwa\",run_id=\"run-id\",pipeline_name=\"pipeline\",tags=\"team=genomics,env=\\\"prod\\\"\"} 1\n"
+        ));
+
+        recorder.clear();
+        recorder.record_event(EventType::FinishedRun, "done".to_string(), None, None);
+        state.update(recorder.get_events());
+        assert!(!state.render().contains("tracer_tool_"));
+    }
+
+    #[tokio::test]
+    async fn test_metrics_endpoint_serves_gauges() {
+        let exporter = PrometheusExporter::new(&PrometheusExporterConfig {
+            enabled: true,
+            listen_address: "127.0.0.1:0".to_string(),
+        })
+        .await
+        .unwrap();
+
+        let mut recorder = recorder();
+        recorder.record_event(
+            EventType::ToolExecution,
+            "samtools".to_string(),
+            Some(EventAttributes::Process(tool_properties(
+                "samtools", "20", 10,
+            ))),
+            None,
+        );
+        exporter
+            .batch_insert_events("run", "run-id", "pipeline", recorder.get_events())
+            .await
+            .unwrap();
+
+        let url = format!("http://{}/metrics", exporter.local_addr());
+        let response = reqwest::get(&url).await.unwrap();
+        assert!(response.status().is_success());
+        let body = response.text().await.unwrap();
+        assert!(body.contains("tracer_tool_memory_bytes{tool_name=\"samtools\""));
+
+        let url = format!("http://{}/other", exporter.local_addr());
+        assert_eq!(reqwest::get(&url).await.unwrap().status(), 404);
+
+        exporter.close().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_stalled_scrapers_are_disconnected() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let mut client = TcpStream::connect(listener.local_addr().unwrap())
+            .await
+            .unwrap();
+        let (stream, _) = listener.accept().await.unwrap();
+
+        let state = Arc::new(RwLock::new(MetricsState::default()));
+        let err = handle_connection(stream, state, Duration::from_millis(50))
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("Timed out"));
+
+        let mut buffer = [0u8; 16];
+        assert_eq!(client.read(&mut buffer).await.unwrap(), 0);
+    }
+}
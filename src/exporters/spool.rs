@@ -0,0 +1,593 @@
+use std::io::Write as _;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use tokio::sync::Mutex;
+
+use super::EventExporter;
+use crate::types::config::SpoolConfig;
+use crate::types::event::Event;
+
+/// A batch a sink failed to receive, persisted as a single JSON file
+#[derive(Serialize, Deserialize)]
+pub struct SpooledBatch {
+    pub run_name: String,
+    pub run_id: String,
+    pub pipeline_name: String,
+    pub events: Vec<Event>,
+    /// Failed replays so far
+    #[serde(default)]
+    pub attempts: u32,
+}
+
+/// Batches that kept failing to replay, one JSON batch per line
+const DEAD_LETTER_FILE: &str = "dead_letter.jsonl";
+
+/// Directory of spooled batches. File names sort in the order batches were spooled
+pub struct Spool {
+    directory: PathBuf,
+    max_size_bytes: u64,
+    sequence: AtomicU64,
+}
+
+impl Spool {
+    pub fn new(directory: PathBuf, max_size_bytes: u64) -> Result<Self> {
+        std::fs::create_dir_all(&directory)
+            .with_context(|| format!("Failed to create spool dir {directory:?}"))?;
+
+        Ok(Self {
+            directory,
+            max_size_bytes,
+            sequence: AtomicU64::new(0),
+        })
+    }
+
+    pub fn directory(&self) -> &Path {
+        &self.directory
+    }
+
+    pub fn push(&self, batch: &SpooledBatch) -> Result<()> {
+        let sequence = self.sequence.fetch_add(1, Ordering::SeqCst);
+        let timestamp = Utc::now().timestamp_nanos_opt().unwrap_or_default();
+        let name = format!("{timestamp:020}-{sequence:06}.json");
+
+        self.write(&self.directory.join(name), batch)?;
+        self.enforce_size_cap()
+    }
+
+    /// Rewrites a spooled batch in place, keeping its position in the spool
+    pub fn update(&self, path: &Path, batch: &SpooledBatch) -> Result<()> {
+        self.write(path, batch)
+    }
+
+    fn write(&self, path: &Path, batch: &SpooledBatch) -> Result<()> {
+        // write then rename, so a crash never leaves a half written batch behind
+        let tmp_path = path.with_extension("json.tmp");
+        std::fs::write(&tmp_path, serde_json::to_vec(batch)?)
+            .with_context(|| format!("Failed to write spool file {tmp_path:?}"))?;
+        std::fs::rename(&tmp_path, path)?;
+        Ok(())
+    }
+
+    pub fn dead_letter_path(&self) -> PathBuf {
+        self.directory.join(DEAD_LETTER_FILE)
+    }
+
+    /// Moves a batch out of the spool into the dead-letter file
+    pub fn dead_letter(&self, path: &Path, batch: &SpooledBatch) -> Result<()> {
+        let dead_letter_path = self.dead_letter_path();
+        let mut line = serde_json::to_vec(batch)?;
+        line.push(b'\n');
+        std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&dead_letter_path)
+            .and_then(|mut file| file.write_all(&line))
+            .with_context(|| format!("Failed to write dead-letter file {dead_letter_path:?}"))?;
+        self.remove(path)?;
+        self.enforce_size_cap()
+    }
+
+    /// Spooled batches, oldest first
+    pub fn pending(&self) -> Result<Vec<PathBuf>> {
+        let mut files: Vec<PathBuf> = std::fs::read_dir(&self.directory)?
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+            .collect();
+        files.sort();
+        Ok(files)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pending().map(|files| files.is_empty()).unwrap_or(true)
+    }
+
+    pub fn load(&self, path: &Path) -> Result<SpooledBatch> {
+        let content =
+            std::fs::read(path).with_context(|| format!("Failed to read spool file {path:?}"))?;
+        serde_json::from_slice(&content)
+            .with_context(|| format!("Failed to parse spool file {path:?}"))
+    }
+
+    pub fn remove(&self, path: &Path) -> Result<()> {
+        std::fs::remove_file(path).with_context(|| format!("Failed to remove spool file {path:?}"))
+    }
+
+    /// Keeps the batches and the dead-letter file under the cap, dead-lettered batches go first
+    fn enforce_size_cap(&self) -> Result<()> {
+        let files = self.pending()?;
+        let sizes: Vec<u64> = files
+            .iter()
+            .map(|path| path.metadata().map(|m| m.len()).unwrap_or(0))
+            .collect();
+        let dead_letter_size = self
+            .dead_letter_path()
+            .metadata()
+            .map(|m| m.len())
+            .unwrap_or(0);
+        let mut total: u64 = sizes.iter().sum::<u64>() + dead_letter_size;
+
+        if total > self.max_size_bytes && dead_letter_size > 0 {
+            total -= self.trim_dead_letter(total - self.max_size_bytes)?;
+        }
+
+        for (path, size) in files.iter().zip(sizes) {
+            if total <= self.max_size_bytes {
+                break;
+            }
+            tracing::warn!(
+                "Spool {:?} exceeds {} bytes, dropping oldest batch {:?}",
+                self.directory,
+                self.max_size_bytes,
+                path
+            );
+            self.remove(path)?;
+            total -= size;
+        }
+
+        Ok(())
+    }
+
+    /// Drops the oldest dead-lettered batches until `excess` bytes are freed, returns the bytes freed
+    fn trim_dead_letter(&self, excess: u64) -> Result<u64> {
+        let path = self.dead_letter_path();
+        let content = std::fs::read(&path)
+            .with_context(|| format!("Failed to read dead-letter file {path:?}"))?;
+
+        let mut freed = 0;
+        let mut dropped = 0;
+        for line in content.split_inclusive(|byte| *byte == b'\n') {
+            if freed >= excess as usize {
+                break;
+            }
+            freed += line.len();
+            dropped += 1;
+        }
+
+        tracing::warn!(
+            "Spool {:?} exceeds {} bytes, dropping {} oldest dead-lettered batches",
+            self.directory,
+            self.max_size_bytes,
+            dropped
+        );
+        if freed == content.len() {
+            std::fs::remove_file(&path)
+                .with_context(|| format!("Failed to remove dead-letter file {path:?}"))?;
+        } else {
+            let tmp_path = path.with_extension("jsonl.tmp");
+            std::fs::write(&tmp_path, &content[freed..])
+                .with_context(|| format!("Failed to write dead-letter file {tmp_path:?}"))?;
+            std::fs::rename(&tmp_path, &path)?;
+        }
+        Ok(freed as u64)
+    }
+}
+
+struct Backoff {
+    failures: u32,
+    next_attempt: Option<Instant>,
+}
+
+/// Wraps a sink so batches it fails to receive are spooled to disk instead of lost.
+/// Spooled batches are replayed in order, with exponential backoff between attempts,
+/// before any new batch is sent to the sink
+pub struct SpoolingExporter {
+    inner: Arc<dyn EventExporter>,
+    spool: Spool,
+    initial_backoff: Duration,
+    max_backoff: Duration,
+    max_attempts: u32,
+    backoff: Mutex<Backoff>,
+}
+
+impl SpoolingExporter {
+    pub fn new(
+        inner: Arc<dyn EventExporter>,
+        config: &SpoolConfig,
+        spool_root: &Path,
+    ) -> Result<Self> {
+        let spool = Spool::new(
+            spool_root.join(inner.name()),
+            config.max_size_mb * 1024 * 1024,
+        )?;
+
+        Ok(Self {
+            inner,
+            spool,
+            initial_backoff: Duration::from_millis(config.initial_backoff_ms),
+            max_backoff: Duration::from_millis(config.max_backoff_ms),
+            max_attempts: config.max_attempts,
+            backoff: Mutex::new(Backoff {
+                failures: 0,
+                next_attempt: None,
+            }),
+        })
+    }
+
+    pub fn spool(&self) -> &Spool {
+        &self.spool
+    }
+
+    fn record_failure(&self, backoff: &mut Backoff) {
+        backoff.failures = backoff.failures.saturating_add(1);
+        let delay = self
+            .initial_backoff
+            .saturating_mul(2_u32.saturating_pow(backoff.failures - 1))
+            .min(self.max_backoff);
+        backoff.next_attempt = Some(Instant::now() + delay);
+    }
+
+    /// Replays spooled batches oldest first, stopping at the first failure.
+    /// A batch that failed `max_attempts` times is dead-lettered instead. Returns whether the spool was drained
+    async fn replay(&self, backoff: &mut Backoff) -> Result<bool> {
+        for path in self.spool.pending()? {
+            let mut batch = match self.spool.load(&path) {
+                Ok(batch) => batch,
+                Err(err) => {
+                    tracing::error!("Dropping unreadable spool file {:?}: {:?}", path, err);
+                    self.spool.remove(&path)?;
+                    continue;
+                }
+            };
+
+            if let Err(err) = self
+                .inner
+                .batch_insert_events(
+                    &batch.run_name,
+                    &batch.run_id,
+                    &batch.pipeline_name,
+                    &batch.events,
+                )
+                .await
+            {
+                tracing::warn!(
+                    "Replaying spooled batches to {} failed: {:?}",
+                    self.inner.name(),
+                    err
+                );
+                batch.attempts += 1;
+                if batch.attempts >= self.max_attempts {
+                    tracing::error!(
+                        "Spooled batch {:?} failed {} times, moving it to {:?}",
+                        path,
+                        batch.attempts,
+                        self.spool.dead_letter_path()
+                    );
+                    self.spool.dead_letter(&path, &batch)?;
+                    continue;
+                }
+                self.spool.update(&path, &batch)?;
+                self.record_failure(backoff);
+                return Ok(false);
+            }
+
+            self.spool.remove(&path)?;
+        }
+
+        if backoff.failures > 0 {
+            tracing::info!("Spool for {} replayed, sink recovered", self.inner.name());
+        }
+        backoff.failures = 0;
+        backoff.next_attempt = None;
+        Ok(true)
+    }
+
+    fn spool_batch(
+        &self,
+        run_name: &str,
+        run_id: &str,
+        pipeline_name: &str,
+        data: &[Event],
+    ) -> Result<()> {
+        self.spool.push(&SpooledBatch {
+            run_name: run_name.to_string(),
+            run_id: run_id.to_string(),
+            pipeline_name: pipeline_name.to_string(),
+            events: data.to_vec(),
+            attempts: 0,
+        })
+    }
+}
+
+#[async_trait]
+impl EventExporter for SpoolingExporter {
+    fn name(&self) -> &str {
+        self.inner.name()
+    }
+
+    async fn batch_insert_events(
+        &self,
+        run_name: &str,
+        run_id: &str,
+        pipeline_name: &str,
+        data: &[Event],
+    ) -> Result<()> {
+        let mut backoff = self.backoff.lock().await;
+
+        if !self.spool.is_empty() {
+            let retry_due = backoff
+                .next_attempt
+                .map_or(true, |next_attempt| Instant::now() >= next_attempt);
+
+            // keep the order: new batches queue behind the spooled ones
+            if !retry_due || !self.replay(&mut backoff).await? {
+                return self.spool_batch(run_name, run_id, pipeline_name, data);
+            }
+        }
+
+        if let Err(err) = self
+            .inner
+            .batch_insert_events(run_name, run_id, pipeline_name, data)
+            .await
+        {
+            tracing::warn!(
+                "Exporter {} failed, spooling batch to {:?}: {:?}",
+                self.inner.name(),
+                self.spool.directory(),
+                err
+            );
+            self.record_failure(&mut backoff);
+            return self.spool_batch(run_name, run_id, pipeline_name, data);
+        }
+
+        Ok(())
+    }
+
+    async fn close(&self) -> Result<()> {
+        // last chance to deliver, whatever is left stays on disk for the next daemon
+        let mut backoff = self.backoff.lock().await;
+        if !self.spool.is_empty() {
+            let _ = self.replay(&mut backoff).await;
+        }
+        self.inner.close().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events::recorder::{EventRecorder, EventType};
+    use std::sync::atomic::AtomicBool;
+    use tempfile::tempdir;
+
+    struct FlakyExporter {
+        available: AtomicBool,
+        received: std::sync::Mutex<Vec<String>>,
+    }
+
+    #[async_trait]
+    impl EventExporter for FlakyExporter {
+        fn name(&self) -> &str {
+            "flaky"
+        }
+
+        async fn batch_insert_events(
+            &self,
+            _run_name: &str,
+            _run_id: &str,
+            _pipeline_name: &str,
+            data: &[Event],
+        ) -> Result<()> {
+            if !self.available.load(Ordering::SeqCst) {
+                anyhow::bail!("connection refused")
+            }
+            if data.iter().any(|event| event.message == "poison") {
+                anyhow::bail!("rejected batch")
+            }
+            let mut received = self.received.lock().unwrap();
+            received.extend(data.iter().map(|event| event.message.clone()));
+            Ok(())
+        }
+    }
+
+    fn batch(message: &str) -> Vec<Event> {
+        let mut recorder = EventRecorder::default();
+        recorder.record_event(EventType::TestEvent, message.to_string(), None, None);
+        recorder.get_events().to_vec()
+    }
+
+    fn spooling_exporter(
+        root: &Path,
+        config: SpoolConfig,
+    ) -> (Arc<FlakyExporter>, SpoolingExporter) {
+        let flaky = Arc::new(FlakyExporter {
+            available: AtomicBool::new(false),
+            received: std::sync::Mutex::new(vec![]),
+        });
+        let exporter = SpoolingExporter::new(flaky.clone(), &config, root).unwrap();
+        (flaky, exporter)
+    }
+
+    #[tokio::test]
+    async fn test_failed_batches_are_replayed_in_order() {
+        let temp_dir = tempdir().unwrap();
+        let config = SpoolConfig {
+            initial_backoff_ms: 0,
+            ..Default::default()
+        };
+        let (flaky, exporter) = spooling_exporter(temp_dir.path(), config);
+
+        for message in ["first", "second"] {
+            exporter
+                .batch_insert_events("run", "run-id", "pipeline", &batch(message))
+                .await
+                .unwrap();
+        }
+        assert_eq!(exporter.spool().pending().unwrap().len(), 2);
+
+        flaky.available.store(true, Ordering::SeqCst);
+        exporter
+            .batch_insert_events("run", "run-id", "pipeline", &batch("third"))
+            .await
+            .unwrap();
+
+        assert!(exporter.spool().is_empty());
+        assert_eq!(
+            *flaky.received.lock().unwrap(),
+            vec!["first", "second", "third"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_replay_waits_for_backoff() {
+        let temp_dir = tempdir().unwrap();
+        let config = SpoolConfig {
+            initial_backoff_ms: 60_000,
+            ..Default::default()
+        };
+        let (flaky, exporter) = spooling_exporter(temp_dir.path(), config);
+
+        exporter
+            .batch_insert_events("run", "run-id", "pipeline", &batch("first"))
+            .await
+            .unwrap();
+
+        flaky.available.store(true, Ordering::SeqCst);
+        exporter
+            .batch_insert_events("run", "run-id", "pipeline", &batch("second"))
+            .await
+            .unwrap();
+
+        // still backing off, so the new batch queues behind the spooled one
+        assert!(flaky.received.lock().unwrap().is_empty());
+        assert_eq!(exporter.spool().pending().unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_failing_batch_is_dead_lettered() {
+        let temp_dir = tempdir().unwrap();
+        let config = SpoolConfig {
+            initial_backoff_ms: 0,
+            max_attempts: 2,
+            ..Default::default()
+        };
+        let (flaky, exporter) = spooling_exporter(temp_dir.path(), config);
+
+        exporter
+            .batch_insert_events("run", "run-id", "pipeline", &batch("poison"))
+            .await
+            .unwrap();
+        flaky.available.store(true, Ordering::SeqCst);
+
+        for message in ["second", "third"] {
+            exporter
+                .batch_insert_events("run", "run-id", "pipeline", &batch(message))
+                .await
+                .unwrap();
+        }
+
+        // the second failed replay moves the batch aside, unblocking the ones behind it
+        assert!(exporter.spool().is_empty());
+        assert_eq!(*flaky.received.lock().unwrap(), vec!["second", "third"]);
+
+        let dead_letters = std::fs::read_to_string(exporter.spool().dead_letter_path()).unwrap();
+        let dead_letters: Vec<SpooledBatch> = dead_letters
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(dead_letters.len(), 1);
+        assert_eq!(dead_letters[0].events[0].message, "poison");
+        assert_eq!(dead_letters[0].attempts, 2);
+    }
+
+    #[test]
+    fn test_spool_size_cap_drops_oldest() {
+        let temp_dir = tempdir().unwrap();
+        let spool = Spool::new(temp_dir.path().to_path_buf(), 1).unwrap();
+
+        for message in ["first", "second"] {
+            spool
+                .push(&SpooledBatch {
+                    run_name: "run".to_string(),
+                    run_id: "run-id".to_string(),
+                    pipeline_name: "pipeline".to_string(),
+                    events: batch(message),
+                    attempts: 0,
+                })
+                .unwrap();
+        }
+
+        // every batch is larger than the cap, so none of them are kept
+        assert!(spool.pending().unwrap().is_empty());
+
+        let spool = Spool::new(temp_dir.path().join("capped"), 10 * 1024 * 1024).unwrap();
+        for message in ["first", "second"] {
+            spool
+                .push(&SpooledBatch {
+                    run_name: "run".to_string(),
+                    run_id: "run-id".to_string(),
+                    pipeline_name: "pipeline".to_string(),
+                    events: batch(message),
+                    attempts: 0,
+                })
+                .unwrap();
+        }
+        let pending = spool.pending().unwrap();
+        assert_eq!(pending.len(), 2);
+        assert_eq!(spool.load(&pending[0]).unwrap().events[0].message, "first");
+    }
+
+    #[test]
+    fn test_spool_size_cap_counts_dead_letters() {
+        let temp_dir = tempdir().unwrap();
+        let spooled = |message: &str| SpooledBatch {
+            run_name: "run".to_string(),
+            run_id: "run-id".to_string(),
+            pipeline_name: "pipeline".to_string(),
+            events: batch(message),
+            attempts: 0,
+        };
+        let batch_size = serde_json::to_vec(&spooled("one")).unwrap().len() as u64;
+        // room for three dead-lettered lines, a batch and a newline each, and not a batch more
+        let spool = Spool::new(
+            temp_dir.path().to_path_buf(),
+            3 * (batch_size + 1) + batch_size / 2,
+        )
+        .unwrap();
+
+        for message in ["one", "two", "six"] {
+            spool.push(&spooled(message)).unwrap();
+        }
+        for path in spool.pending().unwrap() {
+            let batch = spool.load(&path).unwrap();
+            spool.dead_letter(&path, &batch).unwrap();
+        }
+        spool.push(&spooled("ten")).unwrap();
+
+        // the oldest dead letter made room for the new batch
+        assert_eq!(spool.pending().unwrap().len(), 1);
+        let dead_letters = std::fs::read_to_string(spool.dead_letter_path()).unwrap();
+        let dead_letters: Vec<SpooledBatch> = dead_letters
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(dead_letters.len(), 2);
+        assert_eq!(dead_letters[0].events[0].message, "two");
+    }
+}
@@ -0,0 +1,190 @@
+// src/extracts/cgroup.rs
+//! cgroup v2 accounting of the tracked tools, read from the unified hierarchy
+use std::path::{Path, PathBuf};
+
+use crate::types::event::attributes::system_metrics::CgroupStatistic;
+
+/// Mount points of the unified hierarchy, hybrid hosts mount it under `unified`
+const CGROUP_V2_ROOTS: [&str; 2] = ["/sys/fs/cgroup", "/sys/fs/cgroup/unified"];
+
+/// Path of the process' cgroup relative to the unified hierarchy, None on cgroup v1 only hosts
+pub fn cgroup_of(pid: u32) -> Option<String> {
+    let cgroups = std::fs::read_to_string(format!("/proc/{pid}/cgroup")).ok()?;
+    parse_proc_cgroup(&cgroups)
+}
+
+fn parse_proc_cgroup(cgroups: &str) -> Option<String> {
+    cgroups
+        .lines()
+        .find_map(|line| line.strip_prefix("0::"))
+        .map(|path| path.trim().to_string())
+}
+
+fn cgroup_v2_root() -> Option<&'static Path> {
+    CGROUP_V2_ROOTS
+        .iter()
+        .map(Path::new)
+        .find(|root| root.join("cgroup.controllers").exists())
+}
+
+/// The `oom_kill` count of `memory.events`, None when the cgroup or its memory controller is missing
+pub fn read_oom_kills(cgroup_path: &str) -> Option<u64> {
+    let dir = cgroup_v2_root()?.join(cgroup_path.trim_start_matches('/'));
+    let events = std::fs::read_to_string(dir.join("memory.events")).ok()?;
+    flat_keyed_value(&events, "oom_kill")
+}
+
+/// Reads the accounting of a cgroup, None when it no longer exists
+pub fn read_cgroup(cgroup_path: &str) -> Option<CgroupStatistic> {
+    read_cgroup_at(cgroup_v2_root()?, cgroup_path)
+}
+
+fn read_cgroup_at(root: &Path, cgroup_path: &str) -> Option<CgroupStatistic> {
+    let dir = root.join(cgroup_path.trim_start_matches('/'));
+    if !dir.is_dir() {
+        return None;
+    }
+    let read = |file: &str| std::fs::read_to_string(dir.join(file)).ok();
+
+    let mut statistic = CgroupStatistic {
+        cgroup_path: cgroup_path.to_string(),
+        memory_current: read("memory.current").and_then(|value| value.trim().parse().ok()),
+        memory_peak: read("memory.peak").and_then(|value| value.trim().parse().ok()),
+        ..Default::default()
+    };
+
+    if let Some(cpu_stat) = read("cpu.stat") {
+        let value = |key: &str| flat_keyed_value(&cpu_stat, key);
+        statistic.cpu_usage_usec = value("usage_usec");
+        statistic.cpu_user_usec = value("user_usec");
+        statistic.cpu_system_usec = value("system_usec");
+        statistic.cpu_throttled_usec = value("throttled_usec");
+    }
+    if let Some(io_stat) = read("io.stat") {
+        let (read_bytes, write_bytes) = parse_io_stat(&io_stat);
+        statistic.io_read_bytes = Some(read_bytes);
+        statistic.io_write_bytes = Some(write_bytes);
+    }
+    if let Some(events) = read("memory.events") {
+        statistic.oom_events = flat_keyed_value(&events, "oom");
+        statistic.oom_kills = flat_keyed_value(&events, "oom_kill");
+    }
+
+    // limits set on a parent, like a container's or a job's, apply to every cgroup below it
+    for dir in ancestors(root, &dir) {
+        let read = |file: &str| std::fs::read_to_string(dir.join(file)).ok();
+        if let Some(limit) = read("memory.max").and_then(|value| parse_memory_max(&value)) {
+            statistic.memory_limit = Some(statistic.memory_limit.map_or(limit, |l| l.min(limit)));
+        }
+        if let Some(cores) = read("cpu.max").and_then(|value| parse_cpu_max(&value)) {
+            statistic.cpu_limit_cores =
+                Some(statistic.cpu_limit_cores.map_or(cores, |c| c.min(cores)));
+        }
+    }
+
+    Some(statistic)
+}
+
+/// The cgroup's directory and its parents, down to but excluding the root that has no limits
+fn ancestors(root: &Path, dir: &Path) -> Vec<PathBuf> {
+    dir.ancestors()
+        .take_while(|dir| *dir != root && dir.starts_with(root))
+        .map(Path::to_path_buf)
+        .collect()
+}
+
+/// Value of `key` in a flat keyed file like `cpu.stat` or `memory.events`
+fn flat_keyed_value(content: &str, key: &str) -> Option<u64> {
+    content.lines().find_map(|line| {
+        let (name, value) = line.split_once(' ')?;
+        (name == key).then(|| value.trim().parse().ok())?
+    })
+}
+
+/// Bytes read and written summed over every device
+fn parse_io_stat(content: &str) -> (u64, u64) {
+    let mut totals = (0, 0);
+    for field in content.split_whitespace() {
+        match field.split_once('=') {
+            Some(("rbytes", value)) => totals.0 += value.parse::<u64>().unwrap_or(0),
+            Some(("wbytes", value)) => totals.1 += value.parse::<u64>().unwrap_or(0),
+            _ => {}
+        }
+    }
+    totals
+}
+
+fn parse_memory_max(content: &str) -> Option<u64> {
+    content.trim().parse().ok()
+}
+
+/// `$MAX $PERIOD` as a number of cores
+fn parse_cpu_max(content: &str) -> Option<f64> {
+    let (quota, period) = content.trim().split_once(' ')?;
+    let quota: f64 = quota.parse().ok()?;
+    let period: f64 = period.parse().ok()?;
+    (period > 0.0).then_some(quota / period)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_proc_cgroup() {
+        let hybrid = "4:memory:/docker/abc\n1:cpu:/\n0::/system.slice/docker-abc.scope\n";
+        assert_eq!(
+            parse_proc_cgroup(hybrid),
+            Some("/system.slice/docker-abc.scope".to_string())
+        );
+        assert_eq!(parse_proc_cgroup("4:memory:/docker/abc\n1:cpu:/\n"), None);
+    }
+
+    #[test]
+    fn test_read_cgroup_with_parent_limits() {
+        let root = tempfile::tempdir().unwrap();
+        let container = root.path().join("system.slice/docker-abc.scope");
+        let step = container.join("step");
+        std::fs::create_dir_all(&step).unwrap();
+
+        let write = |dir: &Path, file: &str, content: &str| {
+            std::fs::write(dir.join(file), content).unwrap();
+        };
+        write(&container, "memory.max", "1073741824\n");
+        write(&container, "cpu.max", "200000 100000\n");
+        write(&step, "memory.max", "max\n");
+        write(&step, "cpu.max", "max 100000\n");
+        write(&step, "memory.current", "52428800\n");
+        write(&step, "memory.peak", "1073741824\n");
+        write(
+            &step,
+            "cpu.stat",
+            "usage_usec 3000\nuser_usec 2000\nsystem_usec 1000\nnr_periods 0\nthrottled_usec 0\n",
+        );
+        write(
+            &step,
+            "io.stat",
+            "8:0 rbytes=4096 wbytes=1024 rios=1 wios=1\n8:16 rbytes=4096 wbytes=0 rios=1 wios=0\n",
+        );
+        write(
+            &step,
+            "memory.events",
+            "low 0\nhigh 0\nmax 12\noom 1\noom_kill 1\noom_group_kill 0\n",
+        );
+
+        let statistic = read_cgroup_at(root.path(), "/system.slice/docker-abc.scope/step").unwrap();
+        assert_eq!(statistic.cgroup_path, "/system.slice/docker-abc.scope/step");
+        assert_eq!(statistic.memory_current, Some(52428800));
+        assert_eq!(statistic.memory_peak, Some(1073741824));
+        assert_eq!(statistic.memory_limit, Some(1073741824));
+        assert_eq!(statistic.cpu_limit_cores, Some(2.0));
+        assert_eq!(statistic.cpu_usage_usec, Some(3000));
+        assert_eq!(statistic.cpu_user_usec, Some(2000));
+        assert_eq!(statistic.cpu_system_usec, Some(1000));
+        assert_eq!(statistic.io_read_bytes, Some(8192));
+        assert_eq!(statistic.io_write_bytes, Some(1024));
+        assert_eq!(statistic.oom_kills, Some(1));
+
+        assert_eq!(read_cgroup_at(root.path(), "/gone"), None);
+    }
+}
@@ -0,0 +1,444 @@
+// src/extracts/containers.rs
+//! Finds the container a process runs in, from its cgroup, the runtime's API and Apptainer's environment
+use anyhow::{bail, Context, Result};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::io::{Read, Write};
+use std::os::unix::net::UnixStream;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::types::event::attributes::process::{ContainerInfo, ContainerRuntime};
+
+const DOCKER_SOCKET: &str = "/var/run/docker.sock";
+const PODMAN_SOCKET: &str = "/run/podman/podman.sock";
+/// Inspections run on their own thread, this only bounds how long a hung runtime keeps one alive
+const API_TIMEOUT: Duration = Duration::from_secs(1);
+/// How long a container the runtime couldn't be asked about goes without an image before it's asked again
+const RETRY_INSPECTION_AFTER: Duration = Duration::from_secs(60);
+
+fn is_container_id(id: &str) -> bool {
+    id.len() == 64 && id.bytes().all(|byte| byte.is_ascii_hexdigit())
+}
+
+/// Runtime and container id from a cgroup path, like `/system.slice/docker-<id>.scope` or `/docker/<id>`
+pub fn container_from_cgroup(cgroup_path: &str) -> Option<(ContainerRuntime, String)> {
+    let segments: Vec<&str> = cgroup_path.split('/').collect();
+
+    // the innermost container wins, runtimes can nest cgroups like `libpod-<id>.scope/container`
+    for (index, segment) in segments.iter().enumerate().rev() {
+        let segment = segment.strip_suffix(".scope").unwrap_or(segment);
+        let prefixed = [
+            ("docker-", ContainerRuntime::Docker),
+            ("libpod-", ContainerRuntime::Podman),
+            ("cri-containerd-", ContainerRuntime::Containerd),
+        ]
+        .into_iter()
+        .find_map(|(prefix, runtime)| Some((runtime, segment.strip_prefix(prefix)?)));
+
+        let (runtime, id) = match prefixed {
+            Some(container) => container,
+            // the cgroupfs drivers name the cgroup after the bare id
+            None => {
+                let parents = &segments[..index];
+                if parents.contains(&"docker") {
+                    (ContainerRuntime::Docker, segment)
+                } else if parents.iter().any(|parent| parent.starts_with("kubepods")) {
+                    (ContainerRuntime::Containerd, segment)
+                } else {
+                    continue;
+                }
+            }
+        };
+        if is_container_id(id) {
+            return Some((runtime, id.to_string()));
+        }
+    }
+
+    None
+}
+
+/// The image of a process started by `apptainer` or `singularity`, read from its environment
+fn apptainer_from_environ(environ: &[u8]) -> Option<ContainerInfo> {
+    let image = environ
+        .split(|byte| *byte == 0)
+        .filter_map(|variable| std::str::from_utf8(variable).ok())
+        .find_map(|variable| {
+            variable
+                .strip_prefix("APPTAINER_CONTAINER=")
+                .or_else(|| variable.strip_prefix("SINGULARITY_CONTAINER="))
+        })?;
+
+    Some(ContainerInfo {
+        runtime: ContainerRuntime::Apptainer,
+        container_id: None,
+        image_name: Some(image.to_string()),
+        image_digest: None,
+    })
+}
+
+/// Socket of a `unix://` `DOCKER_HOST` or `CONTAINER_HOST`
+fn socket_from_env(variable: &str) -> Option<PathBuf> {
+    std::env::var(variable)
+        .ok()?
+        .strip_prefix("unix://")
+        .map(PathBuf::from)
+}
+
+fn default_sockets(runtime: ContainerRuntime) -> Vec<PathBuf> {
+    match runtime {
+        ContainerRuntime::Docker => socket_from_env("DOCKER_HOST")
+            .into_iter()
+            .chain([PathBuf::from(DOCKER_SOCKET)])
+            .collect(),
+        // rootless podman serves its API from the user's runtime directory
+        ContainerRuntime::Podman => socket_from_env("CONTAINER_HOST")
+            .into_iter()
+            .chain([PathBuf::from(PODMAN_SOCKET)])
+            .chain(
+                std::env::var("XDG_RUNTIME_DIR")
+                    .ok()
+                    .map(|dir| Path::new(&dir).join("podman/podman.sock")),
+            )
+            .collect(),
+        // containerd's API is gRPC, its images can't be looked up
+        ContainerRuntime::Containerd | ContainerRuntime::Apptainer => vec![],
+    }
+}
+
+/// Sends a GET to the Docker Engine API, podman serves the same API
+fn api_get(socket: &Path, path: &str) -> Result<Value> {
+    let mut stream = UnixStream::connect(socket)
+        .with_context(|| format!("Failed to connect to {}", socket.display()))?;
+    stream.set_read_timeout(Some(API_TIMEOUT))?;
+    stream.set_write_timeout(Some(API_TIMEOUT))?;
+
+    // HTTP/1.0 so the body is neither chunked nor kept alive, it ends with the stream.
+    // Sent in one write, the request line shouldn't reach the runtime in pieces
+    stream.write_all(format!("GET {path} HTTP/1.0\r\nHost: localhost\r\n\r\n").as_bytes())?;
+    let mut response = Vec::new();
+    stream.read_to_end(&mut response)?;
+
+    parse_http_response(&response)
+}
+
+fn parse_http_response(response: &[u8]) -> Result<Value> {
+    let response = std::str::from_utf8(response).context("Response isn't valid UTF-8")?;
+    let (head, body) = response
+        .split_once("\r\n\r\n")
+        .context("Response has no body")?;
+    let status = head
+        .split_whitespace()
+        .nth(1)
+        .context("Response has no status")?;
+    if status != "200" {
+        bail!("Request failed with status {status}: {}", body.trim());
+    }
+
+    serde_json::from_str(body).context("Failed to parse the response")
+}
+
+/// Asks the runtime at `socket` for the container's image name and digest
+fn inspect_container(socket: &Path, container: &mut ContainerInfo) -> Result<()> {
+    let id = container.container_id.as_deref().unwrap_or_default();
+    let inspect = api_get(socket, &format!("/containers/{id}/json"))?;
+    container.image_name = inspect["Config"]["Image"].as_str().map(String::from);
+
+    let image_id = inspect["Image"]
+        .as_str()
+        .context("Container has no image")?;
+    let image = api_get(socket, &format!("/images/{image_id}/json"))?;
+    // locally built images have no repo digest
+    container.image_digest = image["RepoDigests"]
+        .as_array()
+        .and_then(|digests| digests.first())
+        .and_then(|digest| digest.as_str())
+        .and_then(|digest| digest.split_once('@'))
+        .map(|(_, digest)| digest.to_string())
+        .or_else(|| Some(image_id.to_string()));
+
+    Ok(())
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Inspection {
+    /// Asked on a background thread, processes get the container without its image until it answers
+    Pending,
+    Resolved(ContainerInfo),
+    /// Asked again by the first process after `RETRY_INSPECTION_AFTER`
+    Failed(Instant),
+}
+
+/// Attributes processes to containers, asking the runtime once per container
+#[derive(Default)]
+pub struct ContainerResolver {
+    /// Shared with the inspection threads, which fill it in when the runtime answers
+    containers: Arc<Mutex<HashMap<String, Inspection>>>,
+    /// Sockets to ask instead of the runtime's default ones
+    sockets: HashMap<ContainerRuntime, PathBuf>,
+}
+
+impl ContainerResolver {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn set_socket(&mut self, runtime: ContainerRuntime, socket: PathBuf) {
+        self.sockets.insert(runtime, socket);
+    }
+
+    /// The container of a process, None when it doesn't run in one.
+    /// Never waits for the runtime, the image is missing until an inspection started here answers
+    pub fn resolve(&mut self, pid: u32, cgroup_path: Option<&str>) -> Option<ContainerInfo> {
+        let Some((runtime, id)) = cgroup_path.and_then(container_from_cgroup) else {
+            // Apptainer doesn't get a cgroup of its own
+            let environ = std::fs::read(format!("/proc/{pid}/environ")).ok()?;
+            return apptainer_from_environ(&environ);
+        };
+
+        let container = ContainerInfo {
+            runtime,
+            container_id: Some(id.clone()),
+            image_name: None,
+            image_digest: None,
+        };
+        let mut containers = self.containers.lock().unwrap();
+        match containers.get(&id) {
+            Some(Inspection::Resolved(container)) => return Some(container.clone()),
+            Some(Inspection::Pending) => return Some(container),
+            Some(Inspection::Failed(at)) if at.elapsed() < RETRY_INSPECTION_AFTER => {
+                return Some(container)
+            }
+            _ => {}
+        }
+
+        let sockets = match self.sockets.get(&runtime) {
+            Some(socket) => vec![socket.clone()],
+            None => default_sockets(runtime),
+        };
+        if sockets.is_empty() {
+            // there's nothing to ask, the container is as resolved as it gets
+            containers.insert(id, Inspection::Resolved(container.clone()));
+            return Some(container);
+        }
+        let sockets: Vec<PathBuf> = sockets
+            .into_iter()
+            .filter(|socket| socket.exists())
+            .collect();
+        if sockets.is_empty() {
+            containers.insert(id, Inspection::Failed(Instant::now()));
+            return Some(container);
+        }
+
+        containers.insert(id.clone(), Inspection::Pending);
+        let shared = Arc::clone(&self.containers);
+        let mut inspected = container.clone();
+        std::thread::spawn(move || {
+            let result = sockets
+                .iter()
+                .map(|socket| inspect_container(socket, &mut inspected))
+                .find(|result| result.is_ok());
+            let inspection = match result {
+                Some(_) => Inspection::Resolved(inspected),
+                None => {
+                    tracing::debug!("Failed to inspect {} container {}", runtime.as_str(), id);
+                    Inspection::Failed(Instant::now())
+                }
+            };
+            shared.lock().unwrap().insert(id, inspection);
+        });
+        Some(container)
+    }
+
+    /// The container with its image, when the runtime answered since it was resolved
+    pub fn latest(&self, container: ContainerInfo) -> ContainerInfo {
+        let Some(id) = container.container_id.as_ref() else {
+            return container;
+        };
+        match self.containers.lock().unwrap().get(id) {
+            Some(Inspection::Resolved(resolved)) => resolved.clone(),
+            _ => container,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::net::UnixListener;
+
+    const ID: &str = "4c5e9d1b0f3a2e7d8c6b5a4f3e2d1c0b9a8f7e6d5c4b3a2f1e0d9c8b7a6f5e4d";
+
+    #[test]
+    fn test_container_from_cgroup() {
+        let cases = [
+            (
+                format!("/system.slice/docker-{ID}.scope"),
+                ContainerRuntime::Docker,
+            ),
+            (format!("/docker/{ID}"), ContainerRuntime::Docker),
+            (
+                format!("/user.slice/user-1000.slice/user@1000.service/user.slice/libpod-{ID}.scope/container"),
+                ContainerRuntime::Podman,
+            ),
+            (
+                format!("/kubepods.slice/kubepods-burstable.slice/cri-containerd-{ID}.scope"),
+                ContainerRuntime::Containerd,
+            ),
+            (
+                format!("/kubepods/burstable/pod1234/{ID}"),
+                ContainerRuntime::Containerd,
+            ),
+        ];
+        for (cgroup, runtime) in cases {
+            assert_eq!(
+                container_from_cgroup(&cgroup),
+                Some((runtime, ID.to_string())),
+                "{cgroup}"
+            );
+        }
+
+        assert_eq!(
+            container_from_cgroup("/user.slice/user-1000.slice/session-2.scope"),
+            None
+        );
+        assert_eq!(
+            container_from_cgroup(&format!("/machine.slice/libpod-conmon-{ID}.scope")),
+            None
+        );
+    }
+
+    #[test]
+    fn test_apptainer_from_environ() {
+        let environ = b"PATH=/usr/bin\0APPTAINER_CONTAINER=/images/samtools_1.17.sif\0APPTAINER_NAME=samtools_1.17.sif\0";
+        let container = apptainer_from_environ(environ).unwrap();
+        assert_eq!(container.runtime, ContainerRuntime::Apptainer);
+        assert_eq!(
+            container.image_name.as_deref(),
+            Some("/images/samtools_1.17.sif")
+        );
+
+        assert_eq!(apptainer_from_environ(b"PATH=/usr/bin\0"), None);
+    }
+
+    /// Answers the container and image inspect requests like the Docker Engine API
+    fn serve_engine_api(listener: UnixListener) -> std::thread::JoinHandle<Vec<String>> {
+        std::thread::spawn(move || {
+            let mut requests = vec![];
+            for _ in 0..2 {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut request = [0; 1024];
+                let read = stream.read(&mut request).unwrap();
+                let request = String::from_utf8_lossy(&request[..read]).to_string();
+
+                let body = if request.starts_with(&format!("GET /containers/{ID}/json")) {
+                    serde_json::json!({
+                        "Id": ID,
+                        "Image": "sha256:abcd",
+                        "Config": { "Image": "quay.io/biocontainers/samtools:1.17--h00cdaf9_0" }
+                    })
+                } else {
+                    serde_json::json!({
+                        "Id": "sha256:abcd",
+                        "RepoDigests": ["quay.io/biocontainers/samtools@sha256:1234"]
+                    })
+                };
+                write!(
+                    stream,
+                    "HTTP/1.0 200 OK\r\nContent-Type: application/json\r\n\r\n{body}"
+                )
+                .unwrap();
+                requests.push(request.lines().next().unwrap().to_string());
+            }
+            requests
+        })
+    }
+
+    /// What the inspection of the container ended with, once it's no longer pending
+    fn inspected(resolver: &ContainerResolver) -> Inspection {
+        for _ in 0..100 {
+            match resolver.containers.lock().unwrap().get(ID) {
+                Some(Inspection::Pending) | None => {}
+                Some(inspection) => return inspection.clone(),
+            }
+            std::thread::sleep(Duration::from_millis(20));
+        }
+        panic!("the container is still being inspected");
+    }
+
+    #[test]
+    fn test_resolve_asks_the_engine_api_once() {
+        let dir = tempfile::tempdir().unwrap();
+        let socket = dir.path().join("docker.sock");
+        let server = serve_engine_api(UnixListener::bind(&socket).unwrap());
+
+        let mut resolver = ContainerResolver::new();
+        resolver.set_socket(ContainerRuntime::Docker, socket);
+        let cgroup = format!("/system.slice/docker-{ID}.scope");
+
+        // the runtime is asked in the background, the image isn't known yet
+        let container = resolver.resolve(1, Some(&cgroup)).unwrap();
+        assert_eq!(container.runtime, ContainerRuntime::Docker);
+        assert_eq!(container.container_id.as_deref(), Some(ID));
+        assert_eq!(container.image_name, None);
+
+        let Inspection::Resolved(resolved) = inspected(&resolver) else {
+            panic!("the container wasn't resolved");
+        };
+        assert_eq!(resolver.latest(container), resolved);
+        assert_eq!(
+            resolved.image_name.as_deref(),
+            Some("quay.io/biocontainers/samtools:1.17--h00cdaf9_0")
+        );
+        assert_eq!(resolved.image_digest.as_deref(), Some("sha256:1234"));
+
+        // the second process of the container comes from the cache
+        assert_eq!(resolver.resolve(2, Some(&cgroup)), Some(resolved));
+        assert_eq!(
+            server.join().unwrap(),
+            vec![
+                format!("GET /containers/{ID}/json HTTP/1.0"),
+                "GET /images/sha256:abcd/json HTTP/1.0".to_string()
+            ]
+        );
+    }
+
+    #[test]
+    fn test_failed_inspection_is_retried_later() {
+        let dir = tempfile::tempdir().unwrap();
+        // not a socket, connecting to it fails
+        let socket = dir.path().join("docker.sock");
+        std::fs::write(&socket, "").unwrap();
+
+        let mut resolver = ContainerResolver::new();
+        resolver.set_socket(ContainerRuntime::Docker, socket);
+        let cgroup = format!("/system.slice/docker-{ID}.scope");
+
+        let container = resolver.resolve(1, Some(&cgroup)).unwrap();
+        let Inspection::Failed(failed_at) = inspected(&resolver) else {
+            panic!("the inspection didn't fail");
+        };
+        assert_eq!(resolver.latest(container.clone()), container);
+
+        // not asked again right away
+        assert_eq!(resolver.resolve(2, Some(&cgroup)), Some(container.clone()));
+        assert_eq!(inspected(&resolver), Inspection::Failed(failed_at));
+
+        let expired = failed_at - RETRY_INSPECTION_AFTER;
+        resolver
+            .containers
+            .lock()
+            .unwrap()
+            .insert(ID.to_string(), Inspection::Failed(expired));
+        assert_eq!(resolver.resolve(3, Some(&cgroup)), Some(container));
+        assert!(matches!(inspected(&resolver), Inspection::Failed(at) if at > expired));
+    }
+
+    #[test]
+    fn test_failed_api_request_is_reported() {
+        let response = b"HTTP/1.0 404 Not Found\r\n\r\n{\"message\":\"No such container\"}";
+        let err = parse_http_response(response).unwrap_err();
+        assert!(err.to_string().contains("404"));
+    }
+}
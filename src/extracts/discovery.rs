@@ -0,0 +1,514 @@
+// src/extracts/discovery.rs
+//! Learning mode, measures the processes no target matched during a run.
+//! Heavy ones are reported at the end of the run with a target pack suggesting targets for them
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+
+use crate::exporters::run_path_component;
+use crate::extracts::process_usage::ProcessSample;
+use crate::types::config::DiscoveryConfig;
+use crate::types::event::attributes::process::UntrackedHeavyProcess;
+
+/// Untracked processes are read from `/proc` this often rather than on every poll
+const SAMPLE_INTERVAL: Duration = Duration::from_secs(5);
+/// Where the script, not the binary, names the tool
+const INTERPRETERS: [&str; 9] = [
+    "python", "Rscript", "R", "java", "perl", "ruby", "node", "bash", "sh",
+];
+pub const SUGGESTED_TARGET_PACK_FILE: &str = "suggested_targets.toml";
+/// Distinct binary and first argument pairs kept for a run, further ones are left out of the report
+const MAX_GROUPS: usize = 4096;
+
+fn is_interpreter(binary_name: &str) -> bool {
+    // versioned binaries like python3.12
+    let name = binary_name.trim_end_matches(|c: char| c.is_ascii_digit() || c == '.');
+    INTERPRETERS.contains(&name)
+}
+
+fn base_name(path: &str) -> &str {
+    Path::new(path)
+        .file_name()
+        .and_then(|name| name.to_str())
+        .unwrap_or(path)
+}
+
+/// First argument that isn't an option: the script's base name for interpreters, a subcommand otherwise.
+/// Arguments of other binaries that look like paths are input files, they'd split a tool into one group per input.
+/// Numbers are taken as the values of options, like `--threads 8`
+pub fn first_argument(binary_path: &str, cmd: &[String]) -> Option<String> {
+    let argument = cmd
+        .iter()
+        .skip(1)
+        .find(|arg| !arg.starts_with('-') && arg.parse::<f64>().is_err())?;
+    if is_interpreter(base_name(binary_path)) {
+        return Some(base_name(argument).to_string());
+    }
+    (!argument.contains(['/', '.'])).then(|| argument.clone())
+}
+
+/// An untracked process, as of its last sample
+#[derive(Debug, Clone)]
+struct Candidate {
+    start_time: DateTime<Utc>,
+    last_seen: DateTime<Utc>,
+    binary_path: String,
+    first_argument: Option<String>,
+    process_name: String,
+    cmd: String,
+    cpu_time_sec: f64,
+    peak_memory: u64,
+    /// Seen as an ancestor of a tool, like a workflow manager or a job script
+    wrapper: bool,
+}
+
+/// What the process watcher knows of a process no target matched
+pub struct UntrackedProcess<'a> {
+    pub pid: u32,
+    pub start_time: DateTime<Utc>,
+    pub name: &'a str,
+    pub cmd: &'a [String],
+    pub binary_path: &'a str,
+    pub is_wrapper: bool,
+}
+
+/// Binary path and first argument
+type GroupKey = (String, Option<String>);
+
+/// Exited processes of the same binary and first argument, added up
+struct Group {
+    summary: UntrackedHeavyProcess,
+    /// CPU time of the process `example_cmd` is from
+    heaviest_cpu: f64,
+}
+
+pub struct Discovery {
+    config: DiscoveryConfig,
+    since: DateTime<Utc>,
+    last_sample: Option<Instant>,
+    /// Processes seen in the last sample
+    candidates: HashMap<u32, Candidate>,
+    /// Candidates that exited, or whose pid was reused, folded into their group
+    groups: HashMap<GroupKey, Group>,
+}
+
+impl Discovery {
+    pub fn new(config: DiscoveryConfig, since: DateTime<Utc>) -> Self {
+        Discovery {
+            config,
+            since,
+            last_sample: None,
+            candidates: HashMap::new(),
+            groups: HashMap::new(),
+        }
+    }
+
+    /// Processes started before the run, like the host's services, aren't candidates
+    pub fn since(&self) -> DateTime<Utc> {
+        self.since
+    }
+
+    /// Whether untracked processes are due for another sample
+    pub fn due(&mut self) -> bool {
+        if self
+            .last_sample
+            .is_some_and(|last_sample| last_sample.elapsed() < SAMPLE_INTERVAL)
+        {
+            return false;
+        }
+        self.last_sample = Some(Instant::now());
+        true
+    }
+
+    pub fn sample(
+        &mut self,
+        process: UntrackedProcess,
+        sample: &ProcessSample,
+        now: DateTime<Utc>,
+    ) {
+        let candidate = Candidate {
+            start_time: process.start_time,
+            last_seen: now,
+            binary_path: process.binary_path.to_string(),
+            first_argument: first_argument(process.binary_path, process.cmd),
+            process_name: process.name.to_string(),
+            cmd: process.cmd.join(" "),
+            cpu_time_sec: sample.cpu_time_sec().unwrap_or_default(),
+            peak_memory: sample
+                .peak_rss_bytes
+                .or(sample.rss_bytes)
+                .unwrap_or_default(),
+            wrapper: process.is_wrapper,
+        };
+
+        match self.candidates.get_mut(&process.pid) {
+            Some(known) if known.start_time == candidate.start_time => {
+                known.last_seen = now;
+                known.cpu_time_sec = known.cpu_time_sec.max(candidate.cpu_time_sec);
+                known.peak_memory = known.peak_memory.max(candidate.peak_memory);
+                known.wrapper |= candidate.wrapper;
+                // an exec keeps the pid, the last binary names the process
+                if known.binary_path != candidate.binary_path || known.cmd != candidate.cmd {
+                    known.binary_path = candidate.binary_path;
+                    known.first_argument = candidate.first_argument;
+                    known.process_name = candidate.process_name;
+                    known.cmd = candidate.cmd;
+                }
+            }
+            _ => {
+                if let Some(replaced) = self.candidates.insert(process.pid, candidate) {
+                    fold(&mut self.groups, &replaced);
+                }
+            }
+        }
+    }
+
+    /// Folds the candidates missing from the sample taken at `now` into their groups, they exited
+    pub fn retire_unseen(&mut self, now: DateTime<Utc>) {
+        let groups = &mut self.groups;
+        self.candidates.retain(|_, candidate| {
+            let seen = candidate.last_seen == now;
+            if !seen {
+                fold(groups, candidate);
+            }
+            seen
+        });
+    }
+
+    /// Groups of processes by binary and first argument that reached a threshold, the heaviest first
+    pub fn heavy_processes(mut self) -> Vec<UntrackedHeavyProcess> {
+        for candidate in self.candidates.values() {
+            fold(&mut self.groups, candidate);
+        }
+
+        let mut heavy: Vec<UntrackedHeavyProcess> = self
+            .groups
+            .into_values()
+            .map(|group| group.summary)
+            .filter(|group| {
+                group.cpu_time_sec >= self.config.min_cpu_time_sec
+                    || group.peak_memory_usage >= self.config.min_memory_mb * 1024 * 1024
+                    || group.longest_duration_sec >= self.config.min_duration_sec
+            })
+            .collect();
+        heavy.sort_by(|a, b| b.cpu_time_sec.total_cmp(&a.cpu_time_sec));
+        heavy
+    }
+}
+
+fn fold(groups: &mut HashMap<GroupKey, Group>, candidate: &Candidate) {
+    if candidate.wrapper {
+        return;
+    }
+    let key = (
+        candidate.binary_path.clone(),
+        candidate.first_argument.clone(),
+    );
+    if groups.len() >= MAX_GROUPS && !groups.contains_key(&key) {
+        return;
+    }
+    let group = groups.entry(key).or_insert_with(|| Group {
+        summary: UntrackedHeavyProcess {
+            binary_path: candidate.binary_path.clone(),
+            first_argument: candidate.first_argument.clone(),
+            process_name: candidate.process_name.clone(),
+            ..Default::default()
+        },
+        heaviest_cpu: -1.0,
+    });
+    if candidate.cpu_time_sec > group.heaviest_cpu {
+        group.heaviest_cpu = candidate.cpu_time_sec;
+        group.summary.example_cmd = candidate.cmd.clone();
+    }
+    let summary = &mut group.summary;
+    let duration = (candidate.last_seen - candidate.start_time).num_seconds();
+    summary.process_count += 1;
+    summary.cpu_time_sec += candidate.cpu_time_sec;
+    summary.peak_memory_usage = summary.peak_memory_usage.max(candidate.peak_memory);
+    summary.longest_duration_sec = summary.longest_duration_sec.max(duration.max(0) as u64);
+}
+
+/// A target for the group, as an inline TOML `match_type` and `display_name`
+fn suggested_target(untracked: &UntrackedHeavyProcess) -> (toml::Value, toml::Value) {
+    let binary_name = base_name(&untracked.binary_path);
+    let table = |key: &str, value: toml::Value| {
+        toml::Value::Table(toml::map::Map::from_iter([(key.to_string(), value)]))
+    };
+    let string = |value: &str| toml::Value::String(value.to_string());
+
+    match &untracked.first_argument {
+        Some(argument) => {
+            let (command_content, display_name) = if is_interpreter(binary_name) {
+                let script = Path::new(argument).file_stem().and_then(|s| s.to_str());
+                (argument.clone(), script.unwrap_or(argument).to_string())
+            } else {
+                let subcommand = format!("{binary_name} {argument}");
+                (subcommand.clone(), subcommand)
+            };
+            let command_contains = toml::Value::Table(toml::map::Map::from_iter([
+                ("process_name".to_string(), string(&untracked.process_name)),
+                ("command_content".to_string(), string(&command_content)),
+            ]));
+            (
+                table("CommandContains", command_contains),
+                table("Name", string(&display_name)),
+            )
+        }
+        None => (
+            table("BinPathLastComponent", string(binary_name)),
+            table("Name", string(binary_name)),
+        ),
+    }
+}
+
+/// A target pack with a target per group, commented with what the group used
+pub fn suggest_target_pack(run_name: &str, heavy: &[UntrackedHeavyProcess]) -> String {
+    let mut pack = String::new();
+    let _ = writeln!(
+        pack,
+        "# Suggested by tracer's discovery mode at the end of run {run_name}."
+    );
+    let _ = writeln!(
+        pack,
+        "# Review the targets, then copy this file to ~/.config/tracer/targets.d/ to track them."
+    );
+    let _ = writeln!(pack, "version = \"{}\"", Utc::now().format("%Y-%m-%d"));
+
+    for untracked in heavy {
+        let (match_type, display_name) = suggested_target(untracked);
+        let _ = writeln!(
+            pack,
+            "\n# {} processes, {:.0} cpu seconds, peak memory {} MB, longest {} s",
+            untracked.process_count,
+            untracked.cpu_time_sec,
+            untracked.peak_memory_usage / (1024 * 1024),
+            untracked.longest_duration_sec
+        );
+        let _ = writeln!(pack, "# {}", untracked.example_cmd.replace('\n', " "));
+        let _ = writeln!(pack, "[[targets]]");
+        let _ = writeln!(pack, "match_type = {match_type}");
+        let _ = writeln!(pack, "display_name = {display_name}");
+        let _ = writeln!(pack, "merge_with_parents = true");
+        let _ = writeln!(pack, "force_ancestor_to_match = true");
+    }
+    pack
+}
+
+/// Writes the suggested target pack to `<directory>/<run_name>/`
+pub fn write_suggested_target_pack(
+    directory: &Path,
+    run_name: &str,
+    heavy: &[UntrackedHeavyProcess],
+) -> Result<PathBuf> {
+    let directory = directory.join(run_path_component(run_name));
+    std::fs::create_dir_all(&directory)
+        .with_context(|| format!("Failed to create {directory:?}"))?;
+    let path = directory.join(SUGGESTED_TARGET_PACK_FILE);
+    std::fs::write(&path, suggest_target_pack(run_name, heavy))
+        .with_context(|| format!("Failed to write {path:?}"))?;
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config_manager::target_process::target_packs::TargetPack;
+    use crate::config_manager::target_process::TargetMatchable;
+    use chrono::TimeDelta;
+
+    fn sample(cpu_time_sec: f64, rss_mb: u64) -> ProcessSample {
+        ProcessSample {
+            cpu_user_time_sec: Some(cpu_time_sec),
+            cpu_system_time_sec: Some(0.0),
+            peak_rss_bytes: Some(rss_mb * 1024 * 1024),
+            ..Default::default()
+        }
+    }
+
+    fn argv(cmd: &str) -> Vec<String> {
+        cmd.split_whitespace().map(str::to_string).collect()
+    }
+
+    #[test]
+    fn test_first_argument() {
+        let first = |binary: &str, cmd: &str| first_argument(binary, &argv(cmd));
+        assert_eq!(
+            first(
+                "/usr/bin/python3.12",
+                "python3 -u /work/a1/bin/call_peaks.py x.bam"
+            ),
+            Some("call_peaks.py".to_string())
+        );
+        assert_eq!(
+            first(
+                "/usr/bin/java",
+                "java -Xmx4g -jar /opt/picard.jar MarkDuplicates"
+            ),
+            Some("picard.jar".to_string())
+        );
+        assert_eq!(
+            first("/opt/bin/kraken2", "kraken2 classify --db /db"),
+            Some("classify".to_string())
+        );
+        assert_eq!(
+            first("/opt/bin/mytool", "mytool --threads 8 /data/a.bam"),
+            None
+        );
+        assert_eq!(first("/opt/bin/mytool", "mytool"), None);
+    }
+
+    #[test]
+    fn test_heavy_processes_are_grouped_and_suggested() {
+        let since = Utc::now();
+        let config = DiscoveryConfig {
+            enabled: true,
+            min_cpu_time_sec: 100.0,
+            min_memory_mb: 4096,
+            min_duration_sec: 3600,
+            directory: None,
+        };
+        let mut discovery = Discovery::new(config, since);
+        let mut sample_process = |pid, cmd: &str, binary_path, sample: ProcessSample, minutes| {
+            let cmd = argv(cmd);
+            let process = UntrackedProcess {
+                pid,
+                start_time: since,
+                name: base_name(binary_path),
+                cmd: &cmd,
+                binary_path,
+                is_wrapper: false,
+            };
+            discovery.sample(process, &sample, since + TimeDelta::minutes(minutes));
+        };
+
+        // two runs of the same script add up
+        let script = "python3 /work/a1/bin/call_peaks.py a.bam";
+        sample_process(10, script, "/usr/bin/python3", sample(20.0, 100), 1);
+        sample_process(10, script, "/usr/bin/python3", sample(60.0, 300), 2);
+        let script = "python3 /work/b2/bin/call_peaks.py b.bam";
+        sample_process(11, script, "/usr/bin/python3", sample(50.0, 200), 1);
+        // light, under every threshold
+        sample_process(
+            12,
+            "python3 /work/c3/bin/plot.py",
+            "/usr/bin/python3",
+            sample(5.0, 50),
+            1,
+        );
+        // heavy on memory only
+        sample_process(
+            13,
+            "vcf-merge --all a.vcf",
+            "/opt/bin/vcf-merge",
+            sample(1.0, 8192),
+            1,
+        );
+        // long running but a wrapper of tools
+        let wrapper = argv("bash .command.run");
+        discovery.sample(
+            UntrackedProcess {
+                pid: 14,
+                start_time: since,
+                name: "bash",
+                cmd: &wrapper,
+                binary_path: "/usr/bin/bash",
+                is_wrapper: true,
+            },
+            &sample(1.0, 10),
+            since + TimeDelta::hours(3),
+        );
+
+        let heavy = discovery.heavy_processes();
+        assert_eq!(heavy.len(), 2);
+        assert_eq!(heavy[0].first_argument.as_deref(), Some("call_peaks.py"));
+        assert_eq!(heavy[0].process_count, 2);
+        assert_eq!(heavy[0].cpu_time_sec, 110.0);
+        assert_eq!(heavy[0].peak_memory_usage, 300 * 1024 * 1024);
+        assert_eq!(heavy[0].longest_duration_sec, 120);
+        assert_eq!(
+            heavy[0].example_cmd,
+            "python3 /work/a1/bin/call_peaks.py a.bam"
+        );
+        assert_eq!(heavy[1].binary_path, "/opt/bin/vcf-merge");
+        assert_eq!(heavy[1].first_argument, None);
+
+        // the suggestion loads as a target pack and tracks what it was made from
+        let pack: TargetPack = toml::from_str(&suggest_target_pack("run-1", &heavy)).unwrap();
+        assert_eq!(pack.targets.len(), 2);
+        assert!(pack.targets[0].matches(
+            "python3",
+            "python3 /work/z9/bin/call_peaks.py z.bam",
+            "/usr/bin/python3"
+        ));
+        assert!(!pack.targets[0].matches("python3", "python3 plot.py", "/usr/bin/python3"));
+        assert!(pack.targets[1].matches("vcf-merge", "vcf-merge b.vcf", "/opt/bin/vcf-merge"));
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = write_suggested_target_pack(dir.path(), "run-1", &heavy).unwrap();
+        assert_eq!(
+            path,
+            dir.path().join("run-1").join(SUGGESTED_TARGET_PACK_FILE)
+        );
+        assert!(TargetPack::load(&path).is_ok());
+    }
+
+    #[test]
+    fn test_exited_processes_are_folded_into_groups() {
+        let since = Utc::now();
+        let config = DiscoveryConfig {
+            enabled: true,
+            min_cpu_time_sec: 100.0,
+            ..Default::default()
+        };
+        let mut discovery = Discovery::new(config, since);
+        let cmd = argv("python3 /work/a1/bin/call_peaks.py a.bam");
+        let sample_process = |discovery: &mut Discovery, pid, start_minute, minute| {
+            let process = UntrackedProcess {
+                pid,
+                start_time: since + TimeDelta::minutes(start_minute),
+                name: "python3",
+                cmd: &cmd,
+                binary_path: "/usr/bin/python3",
+                is_wrapper: false,
+            };
+            let now = since + TimeDelta::minutes(minute);
+            discovery.sample(process, &sample(40.0, 100), now);
+            now
+        };
+
+        sample_process(&mut discovery, 10, 0, 1);
+        let now = sample_process(&mut discovery, 11, 0, 1);
+        discovery.retire_unseen(now);
+        assert_eq!(discovery.candidates.len(), 2);
+
+        // pid 10 exited
+        let now = sample_process(&mut discovery, 11, 0, 2);
+        discovery.retire_unseen(now);
+        assert_eq!(discovery.candidates.len(), 1);
+        assert_eq!(discovery.groups.len(), 1);
+
+        // pid 11 was reused by another run of the script
+        let now = sample_process(&mut discovery, 11, 3, 3);
+        discovery.retire_unseen(now);
+        assert_eq!(discovery.candidates.len(), 1);
+
+        let heavy = discovery.heavy_processes();
+        assert_eq!(heavy.len(), 1);
+        assert_eq!(heavy[0].process_count, 3);
+        assert_eq!(heavy[0].cpu_time_sec, 120.0);
+        assert_eq!(heavy[0].longest_duration_sec, 120);
+    }
+
+    #[test]
+    fn test_suggested_target_pack_stays_in_the_directory() {
+        let dir = tempfile::tempdir().unwrap();
+        let directory = dir.path().join("exports");
+        let path = write_suggested_target_pack(&directory, "../../x", &[]).unwrap();
+        assert!(path.starts_with(&directory));
+        assert_eq!(path.parent().unwrap().parent().unwrap(), directory);
+    }
+}
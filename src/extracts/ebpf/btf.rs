@@ -0,0 +1,433 @@
+// src/extracts/ebpf/btf.rs
+//! Offsets of the kernel struct fields the eBPF programs read, looked up in the kernel's own BTF.
+//! The programs aren't built against kernel headers, and the layouts differ between kernels and configs
+use anyhow::{bail, Context, Result};
+use std::collections::HashMap;
+
+const VMLINUX_BTF: &str = "/sys/kernel/btf/vmlinux";
+const BTF_MAGIC: u16 = 0xeb9f;
+const HEADER_LEN: usize = 24;
+const KIND_STRUCT: u32 = 4;
+const KIND_UNION: u32 = 5;
+
+/// Byte offsets handed to the programs as a global, kept in sync with `TaskOffsets` in tracer-ebpf/src/main.rs
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct TaskOffsets {
+    /// Of `struct task_struct`
+    pub exit_code: u32,
+    pub tgid: u32,
+    pub real_parent: u32,
+    pub utime: u32,
+    pub stime: u32,
+    pub signal: u32,
+    /// Of `struct signal_struct`, where the times of exited threads and reaped children add up
+    pub signal_utime: u32,
+    pub signal_stime: u32,
+    pub signal_cutime: u32,
+    pub signal_cstime: u32,
+    pub signal_maxrss: u32,
+    /// Zero when the offsets couldn't be read, the programs leave the task's fields out then
+    pub resolved: u32,
+}
+
+// SAFETY: plain integers without padding
+unsafe impl aya::Pod for TaskOffsets {}
+
+struct Member {
+    name_off: u32,
+    type_id: u32,
+    bit_offset: u32,
+}
+
+struct Composite {
+    name_off: u32,
+    members: Vec<Member>,
+}
+
+/// The structs and unions of a BTF blob, enough to find where a member is
+pub struct Btf {
+    strings: Vec<u8>,
+    /// By type id, ids of other kinds aren't kept
+    composites: HashMap<u32, Composite>,
+}
+
+fn u32_at(data: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_ne_bytes(
+        data.get(offset..offset + 4)?.try_into().ok()?,
+    ))
+}
+
+impl Btf {
+    pub fn parse(data: &[u8]) -> Result<Btf> {
+        if data.len() < HEADER_LEN || u16::from_ne_bytes([data[0], data[1]]) != BTF_MAGIC {
+            bail!("Not a BTF blob");
+        }
+        let field = |offset| u32_at(data, offset).context("Truncated BTF header");
+        let header_len = field(4)? as usize;
+        let (type_off, type_len) = (field(8)? as usize, field(12)? as usize);
+        let (str_off, str_len) = (field(16)? as usize, field(20)? as usize);
+        let types = data
+            .get(header_len + type_off..header_len + type_off + type_len)
+            .context("Truncated BTF types")?;
+        let strings = data
+            .get(header_len + str_off..header_len + str_off + str_len)
+            .context("Truncated BTF strings")?
+            .to_vec();
+
+        let mut composites = HashMap::new();
+        let mut offset = 0;
+        // type ids start at 1, 0 is void
+        let mut type_id = 1;
+        while offset < types.len() {
+            let start = offset;
+            let record = |at| u32_at(types, start + at).context("Truncated BTF type");
+            let (name_off, info) = (record(0)?, record(4)?);
+            let kind = (info >> 24) & 0x1f;
+            let vlen = (info & 0xffff) as usize;
+            let kind_flag = info >> 31 == 1;
+            offset += 12;
+
+            let extra = match kind {
+                // int, var, decl tag
+                1 | 14 | 17 => 4,
+                3 => 12,
+                KIND_STRUCT | KIND_UNION => {
+                    let members = (0..vlen)
+                        .map(|index| {
+                            let member = |at| record(12 + index * 12 + at);
+                            let bit_offset = member(8)?;
+                            Ok(Member {
+                                name_off: member(0)?,
+                                type_id: member(4)?,
+                                // the high bits are the bitfield size
+                                bit_offset: if kind_flag {
+                                    bit_offset & 0xff_ffff
+                                } else {
+                                    bit_offset
+                                },
+                            })
+                        })
+                        .collect::<Result<Vec<_>>>()?;
+                    composites.insert(type_id, Composite { name_off, members });
+                    vlen * 12
+                }
+                // enum, func proto
+                6 | 13 => vlen * 8,
+                // datasec, enum64
+                15 | 19 => vlen * 12,
+                // ptr, fwd, typedef, volatile, const, restrict, func, float, type tag
+                2 | 7..=12 | 16 | 18 => 0,
+                kind => bail!("Unknown BTF kind {kind}"),
+            };
+            offset += extra;
+            type_id += 1;
+        }
+
+        Ok(Btf {
+            strings,
+            composites,
+        })
+    }
+
+    fn name(&self, name_off: u32) -> &[u8] {
+        let name = self.strings.get(name_off as usize..).unwrap_or_default();
+        name.split(|byte| *byte == 0).next().unwrap_or_default()
+    }
+
+    /// Bit offset of `member` in `composite`, looking into anonymous structs and unions
+    fn find_member(&self, composite: &Composite, member: &str) -> Option<u32> {
+        composite.members.iter().find_map(|candidate| {
+            if self.name(candidate.name_off) == member.as_bytes() {
+                return Some(candidate.bit_offset);
+            }
+            if candidate.name_off != 0 {
+                return None;
+            }
+            let anonymous = self.composites.get(&candidate.type_id)?;
+            Some(candidate.bit_offset + self.find_member(anonymous, member)?)
+        })
+    }
+
+    /// Byte offset of `member` in the struct named `name`
+    pub fn member_offset(&self, name: &str, member: &str) -> Result<u32> {
+        let composite = self
+            .composites
+            .values()
+            .find(|composite| self.name(composite.name_off) == name.as_bytes())
+            .with_context(|| format!("struct {name} is missing from BTF"))?;
+        let bit_offset = self
+            .find_member(composite, member)
+            .with_context(|| format!("{name}.{member} is missing from BTF"))?;
+        Ok(bit_offset / 8)
+    }
+}
+
+impl TaskOffsets {
+    pub fn read() -> Result<TaskOffsets> {
+        let data =
+            std::fs::read(VMLINUX_BTF).with_context(|| format!("Failed to read {VMLINUX_BTF}"))?;
+        Self::from_btf(&Btf::parse(&data)?)
+    }
+
+    fn from_btf(btf: &Btf) -> Result<TaskOffsets> {
+        let task = |member| btf.member_offset("task_struct", member);
+        let signal = |member| btf.member_offset("signal_struct", member);
+        Ok(TaskOffsets {
+            exit_code: task("exit_code")?,
+            tgid: task("tgid")?,
+            real_parent: task("real_parent")?,
+            utime: task("utime")?,
+            stime: task("stime")?,
+            signal: task("signal")?,
+            signal_utime: signal("utime")?,
+            signal_stime: signal("stime")?,
+            signal_cutime: signal("cutime")?,
+            signal_cstime: signal("cstime")?,
+            signal_maxrss: signal("maxrss")?,
+            resolved: 1,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KIND_INT: u32 = 1;
+    const KIND_PTR: u32 = 2;
+    const KIND_ENUM: u32 = 6;
+    const KIND_FUNC_PROTO: u32 = 13;
+
+    /// Writes a BTF blob laid out like the kernel's, type records back to back after the header
+    struct BtfWriter {
+        types: Vec<u8>,
+        strings: Vec<u8>,
+        next_id: u32,
+    }
+
+    impl BtfWriter {
+        fn new() -> Self {
+            BtfWriter {
+                types: vec![],
+                // offset 0 is the empty name of anonymous types
+                strings: vec![0],
+                next_id: 1,
+            }
+        }
+
+        fn string(&mut self, name: &str) -> u32 {
+            if name.is_empty() {
+                return 0;
+            }
+            let offset = self.strings.len() as u32;
+            self.strings.extend_from_slice(name.as_bytes());
+            self.strings.push(0);
+            offset
+        }
+
+        fn words(&mut self, words: &[u32]) {
+            for word in words {
+                self.types.extend_from_slice(&word.to_ne_bytes());
+            }
+        }
+
+        /// Writes the common part of a type record, returns the type's id
+        fn record(
+            &mut self,
+            name: &str,
+            kind: u32,
+            vlen: usize,
+            kind_flag: bool,
+            size: u32,
+        ) -> u32 {
+            let name_off = self.string(name);
+            let info = (kind_flag as u32) << 31 | kind << 24 | vlen as u32;
+            self.words(&[name_off, info, size]);
+            self.next_id += 1;
+            self.next_id - 1
+        }
+
+        fn int(&mut self, name: &str, size: u32) -> u32 {
+            let id = self.record(name, KIND_INT, 0, false, size);
+            self.words(&[size * 8]);
+            id
+        }
+
+        fn ptr(&mut self, type_id: u32) -> u32 {
+            self.record("", KIND_PTR, 0, false, type_id)
+        }
+
+        fn enumeration(&mut self, name: &str, variants: &[&str]) -> u32 {
+            let id = self.record(name, KIND_ENUM, variants.len(), false, 4);
+            for (value, variant) in variants.iter().enumerate() {
+                let name_off = self.string(variant);
+                self.words(&[name_off, value as u32]);
+            }
+            id
+        }
+
+        fn func_proto(&mut self, return_type: u32, params: &[u32]) -> u32 {
+            let id = self.record("", KIND_FUNC_PROTO, params.len(), false, return_type);
+            for param in params {
+                self.words(&[0, *param]);
+            }
+            id
+        }
+
+        /// `members` are the name, type id and bit offset, with the bitfield size in the high bits with `kind_flag`
+        fn composite(
+            &mut self,
+            kind: u32,
+            name: &str,
+            size: u32,
+            kind_flag: bool,
+            members: &[(&str, u32, u32)],
+        ) -> u32 {
+            let name_offs: Vec<u32> = members.iter().map(|(name, ..)| self.string(name)).collect();
+            let id = self.record(name, kind, members.len(), kind_flag, size);
+            for (name_off, (_, type_id, bit_offset)) in name_offs.into_iter().zip(members) {
+                self.words(&[name_off, *type_id, *bit_offset]);
+            }
+            id
+        }
+
+        fn finish(self) -> Vec<u8> {
+            let mut data = BTF_MAGIC.to_ne_bytes().to_vec();
+            // version and flags
+            data.extend_from_slice(&[1, 0]);
+            for word in [
+                HEADER_LEN as u32,
+                0,
+                self.types.len() as u32,
+                self.types.len() as u32,
+                self.strings.len() as u32,
+            ] {
+                data.extend_from_slice(&word.to_ne_bytes());
+            }
+            data.extend(self.types);
+            data.extend(self.strings);
+            data
+        }
+    }
+
+    /// `task_struct` and `signal_struct` the way vmlinux declares the fields the programs read
+    fn kernel_like_btf() -> Vec<u8> {
+        let mut btf = BtfWriter::new();
+        let int = btf.int("int", 4);
+        let u64_id = btf.int("long long unsigned int", 8);
+        btf.enumeration("pid_type", &["PIDTYPE_PID", "PIDTYPE_TGID"]);
+        btf.func_proto(int, &[int, u64_id]);
+        let signal = btf.composite(
+            KIND_STRUCT,
+            "signal_struct",
+            64,
+            true,
+            &[
+                ("nr_threads", int, 0),
+                // a 1 bit bitfield, the size is in the high bits
+                ("group_exec_task", int, 1 << 24 | 32),
+                ("utime", u64_id, 64),
+                ("stime", u64_id, 128),
+                ("cutime", u64_id, 192),
+                ("cstime", u64_id, 256),
+                ("maxrss", u64_id, 320),
+            ],
+        );
+        let signal_ptr = btf.ptr(signal);
+        // randomized layouts put the fields in an anonymous struct, under an anonymous union here
+        let ids = btf.composite(
+            KIND_STRUCT,
+            "",
+            8,
+            false,
+            &[("pid", int, 0), ("tgid", int, 32)],
+        );
+        let anonymous = btf.composite(
+            KIND_UNION,
+            "",
+            8,
+            false,
+            &[("", ids, 0), ("ids", u64_id, 0)],
+        );
+        // the id of task_struct itself, pointed to before it's declared
+        let task_ptr = btf.ptr(btf.next_id + 1);
+        btf.composite(
+            KIND_STRUCT,
+            "task_struct",
+            128,
+            false,
+            &[
+                ("__state", int, 0),
+                ("exit_code", int, 32),
+                ("", anonymous, 128),
+                ("real_parent", task_ptr, 256),
+                ("utime", u64_id, 320),
+                ("stime", u64_id, 384),
+                ("signal", signal_ptr, 448),
+            ],
+        );
+        btf.finish()
+    }
+
+    #[test]
+    fn test_task_offsets_from_kernel_like_btf() {
+        let btf = Btf::parse(&kernel_like_btf()).unwrap();
+        assert_eq!(
+            TaskOffsets::from_btf(&btf).unwrap(),
+            TaskOffsets {
+                exit_code: 4,
+                tgid: 20,
+                real_parent: 32,
+                utime: 40,
+                stime: 48,
+                signal: 56,
+                signal_utime: 8,
+                signal_stime: 16,
+                signal_cutime: 24,
+                signal_cstime: 32,
+                signal_maxrss: 40,
+                resolved: 1,
+            }
+        );
+        assert_eq!(
+            btf.member_offset("signal_struct", "group_exec_task")
+                .unwrap(),
+            4
+        );
+        assert!(btf.member_offset("task_struct", "no_such_field").is_err());
+        assert!(btf.member_offset("mm_struct", "pgd").is_err());
+    }
+
+    #[test]
+    fn test_parse_rejects_malformed_btf() {
+        let data = kernel_like_btf();
+        assert!(Btf::parse(&data[..HEADER_LEN - 1]).is_err());
+        assert!(Btf::parse(&data[..data.len() - 1]).is_err());
+
+        let mut not_btf = data.clone();
+        not_btf[0] ^= 0xff;
+        assert!(Btf::parse(&not_btf).is_err());
+
+        let mut unknown_kind = BtfWriter::new();
+        unknown_kind.record("", 31, 0, false, 0);
+        assert!(Btf::parse(&unknown_kind.finish()).is_err());
+    }
+
+    #[test]
+    fn test_task_offsets_from_kernel_btf() {
+        let Ok(data) = std::fs::read(VMLINUX_BTF) else {
+            return;
+        };
+        let btf = Btf::parse(&data).unwrap();
+        let offsets = TaskOffsets::from_btf(&btf).unwrap();
+        assert_eq!(offsets.resolved, 1);
+        // fields of the same struct don't overlap
+        assert_ne!(offsets.exit_code, offsets.tgid);
+        assert_ne!(offsets.signal_utime, offsets.signal_cutime);
+        // `pid_t pid; pid_t tgid;` are declared together
+        let pid = btf.member_offset("task_struct", "pid").unwrap();
+        assert_eq!(offsets.tgid, pid + 4);
+        assert!(btf.member_offset("task_struct", "no_such_field").is_err());
+    }
+}
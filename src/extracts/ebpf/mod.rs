@@ -1,34 +1,431 @@
-// #include <linux/bpf.h>
-// #include <linux/ptrace.h>
-// #include <bpf/bpf_helpers.h>
+// src/extracts/ebpf/mod.rs
+//! Exec, file open and exit events from eBPF tracepoints, built with the `ebpf` feature.
+//! The programs are the `tracer-ebpf` crate, compiled to BPF bytecode with `make build-ebpf`
+//! and embedded in the binary, loading them needs CAP_BPF and CAP_PERFMON but no compiler.
+//! Events feed the process watcher like the proc connector's, with the files every process opens on top
+mod btf;
 
-// struct event {
-//     u32 pid;
-//     char comm[16];
-//     char filename[256];
-// };
+use crate::extracts::open_files::{self, AccessMode, OpenFile};
+use crate::extracts::proc_connector::{
+    self, EventFilter, ExecSnapshot, ProcEvent, ProcEventReceiver, ProcEventSender,
+};
+use crate::extracts::process_usage::ProcessSample;
+use crate::types::event::attributes::process::ProcessExit;
+use anyhow::{Context, Result};
+use aya::maps::{HashMap as BpfHashMap, MapData, RingBuf};
+use aya::programs::TracePoint;
+use aya::{include_bytes_aligned, Ebpf, EbpfLoader};
+use btf::TaskOffsets;
+use chrono::Utc;
+use std::collections::HashSet;
+use std::os::fd::AsRawFd;
+use std::path::PathBuf;
 
-// struct {
-//     __uint(type, BPF_MAP_TYPE_PERF_EVENT_ARRAY);
-//     __uint(key_size, sizeof(int));
-//     __uint(value_size, sizeof(u32));
-//     __uint(max_entries, 1024);
-// } events SEC(".maps");
+// kept in sync with `RawEvent` in tracer-ebpf/src/main.rs
+const EVENT_EXEC: u32 = 1;
+const EVENT_OPEN: u32 = 2;
+const EVENT_EXIT: u32 = 3;
+const PATH_LEN: usize = 256;
 
-// SEC("tracepoint/syscalls/sys_enter_execve")
-// int trace_execve(struct trace_event_raw_sys_enter *ctx)
-// {
-//     struct event event = {};
+/// Program name in tracer-ebpf, tracepoint category and tracepoint name
+const TRACEPOINTS: [(&str, &str, &str); 3] = [
+    ("tracer_exec", "sched", "sched_process_exec"),
+    ("tracer_openat", "syscalls", "sys_enter_openat"),
+    ("tracer_exit", "sched", "sched_process_exit"),
+];
+/// How long the reader thread blocks on an empty ring buffer
+const POLL_TIMEOUT_MS: i32 = 200;
 
-//     event.pid = bpf_get_current_pid_tgid() >> 32;
-//     bpf_get_current_comm(&event.comm, sizeof(event.comm));
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct RawEvent {
+    kind: u32,
+    pid: u32,
+    flags: u32,
+    path_len: u32,
+    /// Set when the fields below were read from the task, they're zero otherwise
+    task_read: u32,
+    parent_pid: u32,
+    /// Wait status, of exit events
+    exit_code: u32,
+    /// Directory fd of `openat`, relative paths are relative to it
+    dirfd: i32,
+    /// Cpu times in nanoseconds and peak RSS in pages, of the whole process at exit
+    utime_ns: u64,
+    stime_ns: u64,
+    children_utime_ns: u64,
+    children_stime_ns: u64,
+    maxrss_pages: u64,
+    comm: [u8; 16],
+    path: [u8; PATH_LEN],
+}
 
-//     const char *filename = (const char *)ctx->args[0];
-//     bpf_probe_read_user_str(event.filename, sizeof(event.filename), filename);
+impl RawEvent {
+    fn parse(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() < std::mem::size_of::<RawEvent>() {
+            return None;
+        }
+        // SAFETY: the length is checked above and RawEvent is plain integers, valid for any bytes.
+        // Ring buffer records are 8 byte aligned, read unaligned anyway
+        Some(unsafe { std::ptr::read_unaligned(bytes.as_ptr() as *const RawEvent) })
+    }
 
-//     bpf_perf_event_output(ctx, &events, BPF_F_CURRENT_CPU, &event, sizeof(event));
+    fn path(&self) -> String {
+        let len = (self.path_len as usize).min(PATH_LEN);
+        // the kernel counts the trailing nul
+        let path = self.path[..len].split(|byte| *byte == 0).next().unwrap();
+        String::from_utf8_lossy(path).into_owned()
+    }
 
-//     return 0;
-// }
+    fn comm(&self) -> String {
+        let comm = self.comm.split(|byte| *byte == 0).next().unwrap();
+        String::from_utf8_lossy(comm).into_owned()
+    }
 
-// char LICENSE[] SEC("license") = "GPL";
+    /// Usage the program read as the process exited, the peak RSS is only final once every thread is gone
+    fn usage(&self) -> ProcessSample {
+        let seconds = |ns: u64| Some(ns as f64 / 1e9);
+        // SAFETY: sysconf has no preconditions
+        let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) }.max(0) as u64;
+        ProcessSample {
+            cpu_user_time_sec: seconds(self.utime_ns),
+            cpu_system_time_sec: seconds(self.stime_ns),
+            children_cpu_user_time_sec: seconds(self.children_utime_ns),
+            children_cpu_system_time_sec: seconds(self.children_stime_ns),
+            peak_rss_bytes: (self.maxrss_pages > 0).then(|| self.maxrss_pages * page_size),
+            ..Default::default()
+        }
+    }
+}
+
+/// Exit status of a process that's exiting, the `exit_code` field of `/proc/<pid>/stat`.
+/// Only for kernels without BTF, the process is usually reaped by the time the event is read
+fn read_exit_status(pid: u32) -> Option<u32> {
+    let stat = std::fs::read_to_string(format!("/proc/{pid}/stat")).ok()?;
+    // field 52, counted from `state`, the 3rd field, after the parenthesized name
+    stat[stat.rfind(')')? + 1..]
+        .split_whitespace()
+        .nth(49)?
+        .parse()
+        .ok()
+}
+
+/// Directory a relative `openat` path is relative to, `None` when its fd no longer is a directory
+fn relative_to(event: &RawEvent) -> Option<PathBuf> {
+    let directory = if event.dirfd == libc::AT_FDCWD {
+        format!("/proc/{}/cwd", event.pid)
+    } else {
+        format!("/proc/{}/fd/{}", event.pid, event.dirfd)
+    };
+    // closed since and reused for a file or a socket
+    let directory = std::fs::read_link(directory).ok()?;
+    directory.is_dir().then_some(directory)
+}
+
+fn resolve(event: &RawEvent, filter: &EventFilter) -> Option<ProcEvent> {
+    match event.kind {
+        EVENT_EXEC => {
+            // gone already, the tracepoint still has what the process exec'd
+            let snapshot =
+                proc_connector::read_exec_snapshot(event.pid, None).unwrap_or_else(|| {
+                    let exe = event.path();
+                    ExecSnapshot {
+                        pid: event.pid,
+                        parent_pid: event.parent_pid,
+                        name: event.comm(),
+                        cmd: vec![exe.clone()],
+                        exe,
+                        cgroup: None,
+                        timestamp: Utc::now(),
+                    }
+                });
+            filter.matched(snapshot).map(ProcEvent::Exec)
+        }
+        EVENT_OPEN => {
+            let mut path = event.path();
+            if !path.starts_with('/') {
+                path = relative_to(event)?
+                    .join(path)
+                    .to_string_lossy()
+                    .into_owned();
+            }
+            if !open_files::is_data_path(&path) {
+                return None;
+            }
+            // every process on the host opens files, the watcher reads sizes of its tools' only
+            Some(ProcEvent::Open {
+                pid: event.pid,
+                file: OpenFile {
+                    path,
+                    mode: AccessMode::from_flags(event.flags),
+                    offset: 0,
+                    size: None,
+                },
+            })
+        }
+        // every process on the host exits, only the tools' exits are read
+        EVENT_EXIT if !filter.untrack(event.pid) => None,
+        EVENT_EXIT if event.task_read != 0 => Some(ProcEvent::Exit {
+            pid: event.pid,
+            exit: ProcessExit::from_wait_status(event.exit_code),
+            usage: Some(event.usage()),
+            timestamp: Utc::now(),
+        }),
+        EVENT_EXIT => Some(ProcEvent::Exit {
+            pid: event.pid,
+            exit: read_exit_status(event.pid)
+                .map(ProcessExit::from_wait_status)
+                .unwrap_or_default(),
+            usage: ProcessSample::read(event.pid),
+            timestamp: Utc::now(),
+        }),
+        _ => None,
+    }
+}
+
+/// The tracepoints' ring buffer and the map of pids whose opens they submit
+struct Maps {
+    events: RingBuf<MapData>,
+    tracked: BpfHashMap<MapData, u32, u8>,
+}
+
+fn load() -> Result<(Ebpf, Maps)> {
+    // kernels before 5.11 account BPF maps against the locked memory limit
+    let unlimited = libc::rlimit {
+        rlim_cur: libc::RLIM_INFINITY,
+        rlim_max: libc::RLIM_INFINITY,
+    };
+    // SAFETY: unlimited is a valid rlimit that outlives the call
+    unsafe { libc::setrlimit(libc::RLIMIT_MEMLOCK, &unlimited) };
+
+    // without them exit statuses and usage are read from /proc, often after the process was reaped
+    let offsets = TaskOffsets::read()
+        .map_err(|err| {
+            tracing::warn!("{:?}, exit statuses of eBPF events may be missing", err);
+        })
+        .unwrap_or_default();
+    let mut bpf = EbpfLoader::new()
+        .set_global("TASK_OFFSETS", &offsets, true)
+        .load(include_bytes_aligned!(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/tracer-ebpf/target/bpfel-unknown-none/release/tracer-ebpf"
+        )))
+        .context("Failed to load the eBPF programs, they need CAP_BPF and CAP_PERFMON")?;
+
+    for (name, category, tracepoint) in TRACEPOINTS {
+        let program: &mut TracePoint = bpf
+            .program_mut(name)
+            .with_context(|| format!("eBPF program {name} is missing"))?
+            .try_into()?;
+        program.load()?;
+        program
+            .attach(category, tracepoint)
+            .with_context(|| format!("Failed to attach to tracepoint {category}/{tracepoint}"))?;
+    }
+
+    let events = RingBuf::try_from(bpf.take_map("EVENTS").context("EVENTS map is missing")?)?;
+    let tracked = BpfHashMap::try_from(
+        bpf.take_map("TRACKED_PIDS")
+            .context("TRACKED_PIDS map is missing")?,
+    )?;
+    Ok((bpf, Maps { events, tracked }))
+}
+
+/// Adds the tools the filter tracks since the last call to the programs' map.
+/// The programs remove them when they exit, `synced` only keeps pids from being added twice
+fn sync_tracked(
+    tracked: &mut BpfHashMap<MapData, u32, u8>,
+    filter: &EventFilter,
+    synced: &mut HashSet<u32>,
+) {
+    let pids = filter.tracked_pids();
+    for pid in pids.difference(synced) {
+        // full of processes the tools started, this tool's opens go unreported
+        if let Err(err) = tracked.insert(pid, 1, 0) {
+            tracing::debug!("Failed to track pid {} in eBPF: {}", pid, err);
+        }
+    }
+    *synced = pids;
+}
+
+/// Reads the ring buffer until the receiver is dropped, the programs detach when `bpf` is dropped
+fn read_events(_bpf: Ebpf, mut maps: Maps, filter: EventFilter, sender: ProcEventSender) {
+    let mut synced = HashSet::new();
+    let mut poll_fd = libc::pollfd {
+        fd: maps.events.as_raw_fd(),
+        events: libc::POLLIN,
+        revents: 0,
+    };
+
+    loop {
+        // SAFETY: poll_fd is a single valid pollfd, the ring buffer's fd outlives the loop
+        unsafe { libc::poll(&mut poll_fd, 1, POLL_TIMEOUT_MS) };
+
+        let mut resolved = vec![];
+        while let Some(item) = maps.events.next() {
+            if let Some(event) = RawEvent::parse(&item) {
+                resolved.push(event);
+            }
+        }
+        // resolved after the items are released, so the kernel can reuse the space
+        for event in resolved.iter().filter_map(|event| resolve(event, &filter)) {
+            if !sender.send(event) {
+                return;
+            }
+        }
+        // tools matched just now and found by polling, the programs track their children too
+        sync_tracked(&mut maps.tracked, &filter, &mut synced);
+    }
+}
+
+/// Loads and attaches the tracepoints, events are read on a background thread.
+/// Fails without BPF support or the capabilities to load programs, callers fall back to the proc connector
+pub fn subscribe(filter: EventFilter) -> Result<ProcEventReceiver> {
+    let (bpf, maps) = load()?;
+    let (sender, receiver) = proc_connector::channel(filter.clone());
+
+    std::thread::Builder::new()
+        .name("ebpf-events".to_string())
+        .spawn(move || read_events(bpf, maps, filter, sender))?;
+
+    Ok(receiver)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config_manager::target_process::target_matching::TargetMatch;
+    use crate::config_manager::target_process::Target;
+
+    /// Above the largest pid the kernel hands out, so it's never running
+    const PID_MAX: u32 = 4_194_304;
+
+    fn bwa_filter() -> EventFilter {
+        EventFilter::new(vec![Target::new(TargetMatch::ProcessName(
+            "bwa".to_string(),
+        ))])
+    }
+
+    fn raw_event(kind: u32, pid: u32, flags: u32, path: &str) -> Vec<u8> {
+        let mut event = RawEvent {
+            kind,
+            pid,
+            flags,
+            path_len: path.len() as u32 + 1,
+            task_read: 0,
+            parent_pid: 0,
+            exit_code: 0,
+            dirfd: libc::AT_FDCWD,
+            utime_ns: 0,
+            stime_ns: 0,
+            children_utime_ns: 0,
+            children_stime_ns: 0,
+            maxrss_pages: 0,
+            comm: [0; 16],
+            path: [0; PATH_LEN],
+        };
+        event.comm[..4].copy_from_slice(b"bwa\0");
+        event.path[..path.len()].copy_from_slice(path.as_bytes());
+
+        // SAFETY: the slice covers exactly the event, which outlives it
+        let bytes = unsafe {
+            std::slice::from_raw_parts(
+                &event as *const RawEvent as *const u8,
+                std::mem::size_of::<RawEvent>(),
+            )
+        };
+        bytes.to_vec()
+    }
+
+    #[test]
+    fn test_parse_raw_event() {
+        let bytes = raw_event(EVENT_EXEC, PID_MAX, 0, "/usr/bin/bwa");
+        let event = RawEvent::parse(&bytes).unwrap();
+        assert_eq!(event.pid, PID_MAX);
+        assert_eq!(event.path(), "/usr/bin/bwa");
+        assert_eq!(event.comm(), "bwa");
+        assert!(RawEvent::parse(&bytes[..64]).is_none());
+
+        // a process that's gone is described by the event
+        let Some(ProcEvent::Exec(snapshot)) = resolve(&event, &bwa_filter()) else {
+            panic!("expected an exec event");
+        };
+        assert_eq!(snapshot.name, "bwa");
+        assert_eq!(snapshot.cmd, vec!["/usr/bin/bwa".to_string()]);
+
+        // no target matches it
+        assert!(resolve(&event, &EventFilter::default()).is_none());
+    }
+
+    #[test]
+    fn test_resolve_exit_event_read_from_the_task() {
+        let mut event =
+            RawEvent::parse(&raw_event(EVENT_EXEC, PID_MAX, 0, "/usr/bin/bwa")).unwrap();
+        event.task_read = 1;
+        event.parent_pid = 42;
+        let filter = bwa_filter();
+        let Some(ProcEvent::Exec(snapshot)) = resolve(&event, &filter) else {
+            panic!("expected an exec event");
+        };
+        assert_eq!(snapshot.parent_pid, 42);
+
+        // reaped long before the event is read, the program carries the status and usage
+        event.kind = EVENT_EXIT;
+        event.exit_code = 3 << 8;
+        event.utime_ns = 1_500_000_000;
+        event.children_stime_ns = 500_000_000;
+        let Some(ProcEvent::Exit { exit, usage, .. }) = resolve(&event, &filter) else {
+            panic!("expected an exit event");
+        };
+        assert_eq!(exit.exit_code, Some(3));
+        let usage = usage.unwrap();
+        assert_eq!(usage.cpu_user_time_sec, Some(1.5));
+        assert_eq!(usage.children_cpu_system_time_sec, Some(0.5));
+        assert_eq!(usage.peak_rss_bytes, None);
+        // reported once, the pid is no longer tracked
+        assert!(resolve(&event, &filter).is_none());
+
+        // killed by SIGKILL
+        event.kind = EVENT_EXEC;
+        resolve(&event, &filter);
+        event.kind = EVENT_EXIT;
+        event.exit_code = 9;
+        let Some(ProcEvent::Exit { exit, .. }) = resolve(&event, &filter) else {
+            panic!("expected an exit event");
+        };
+        assert_eq!(exit.signal, Some(9));
+    }
+
+    #[test]
+    fn test_resolve_open_event() {
+        let filter = EventFilter::default();
+        // O_WRONLY | O_CREAT, relative to the process' working directory
+        let event = RawEvent::parse(&raw_event(EVENT_OPEN, std::process::id(), 0o101, "out.sam"));
+        let Some(ProcEvent::Open { file, .. }) = resolve(&event.unwrap(), &filter) else {
+            panic!("expected an open event");
+        };
+        let cwd = std::env::current_dir().unwrap();
+        assert_eq!(file.path, cwd.join("out.sam").to_str().unwrap());
+        assert_eq!(file.mode, AccessMode::Write);
+
+        let event = RawEvent::parse(&raw_event(EVENT_OPEN, 1, 0, "/proc/self/status"));
+        assert!(resolve(&event.unwrap(), &filter).is_none());
+
+        // relative to the directory fd given to openat
+        let dir = tempfile::tempdir().unwrap();
+        let dir_file = std::fs::File::open(dir.path()).unwrap();
+        let mut event =
+            RawEvent::parse(&raw_event(EVENT_OPEN, std::process::id(), 0, "reads.bam")).unwrap();
+        event.dirfd = dir_file.as_raw_fd();
+        let Some(ProcEvent::Open { file, .. }) = resolve(&event, &filter) else {
+            panic!("expected an open event");
+        };
+        assert_eq!(file.path, dir.path().join("reads.bam").to_str().unwrap());
+
+        // the fd is no longer a directory, the path isn't guessed
+        drop(dir_file);
+        let file = std::fs::File::open(std::env::current_exe().unwrap()).unwrap();
+        event.dirfd = file.as_raw_fd();
+        assert!(resolve(&event, &filter).is_none());
+    }
+}
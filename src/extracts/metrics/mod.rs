@@ -1,18 +1,28 @@
 /// src/metrics/mod.rs
 use anyhow::Result;
 use chrono::Utc;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::time::Instant;
 use sysinfo::{Disks, System};
 
 use crate::{
     events::recorder::{EventRecorder, EventType},
+    extracts::cgroup,
+    extracts::process_usage::{CpuRate, ProcessSample},
     types::event::{
-        attributes::system_metrics::{DiskStatistic, SystemMetric},
+        attributes::system_metrics::{CgroupStatistic, DiskStatistic, SystemMetric},
         attributes::EventAttributes,
     },
 };
 
-pub struct SystemMetricsCollector;
+pub struct SystemMetricsCollector {
+    /// Last `oom_kill` count of each cgroup read so far, or seeded as a tool started in it.
+    /// Counts from before then aren't reported.
+    /// Cgroups stay until they're removed, so a tool killed by the OOM killer is still reported after it's gone
+    oom_kills: HashMap<String, u64>,
+    /// Cpu used by the daemon's own process
+    own_cpu: CpuRate,
+}
 
 impl Default for SystemMetricsCollector {
     fn default() -> Self {
@@ -22,7 +32,10 @@ impl Default for SystemMetricsCollector {
 
 impl SystemMetricsCollector {
     pub fn new() -> Self {
-        SystemMetricsCollector
+        SystemMetricsCollector {
+            oom_kills: HashMap::new(),
+            own_cpu: CpuRate::default(),
+        }
     }
 
     pub fn gather_disk_data() -> HashMap<String, DiskStatistic> {
@@ -73,17 +86,93 @@ impl SystemMetricsCollector {
             system_memory_swap_used: system.used_swap(),
             system_cpu_utilization: cpu_usage,
             system_disk_io: d_stats,
+            system_cgroups: HashMap::new(),
+            tracer_cpu_utilization: None,
+            tracer_memory_usage: None,
+        }
+    }
+
+    /// Cpu and memory used by the daemon since the previous metric, so users can tell what tracing costs
+    fn record_own_usage(&mut self, metric: &mut SystemMetric) {
+        let Some(sample) = ProcessSample::read(std::process::id()) else {
+            return;
+        };
+        if let Some(cpu_time) = sample.cpu_time_sec() {
+            self.own_cpu.update(cpu_time, Instant::now());
+        }
+        metric.tracer_cpu_utilization = self.own_cpu.utilization();
+        metric.tracer_memory_usage = sample.rss_bytes;
+    }
+
+    pub fn gather_cgroup_data(cgroup_paths: &HashSet<String>) -> HashMap<String, CgroupStatistic> {
+        cgroup_paths
+            .iter()
+            .filter_map(|path| Some((path.clone(), cgroup::read_cgroup(path)?)))
+            .collect()
+    }
+
+    /// Counts read as tools started, so kills before the cgroup's first read are reported too.
+    /// The cgroups are then read until they're removed, even once their tools are gone
+    pub fn seed_oom_kills(&mut self, baselines: HashMap<String, u64>) {
+        for (path, oom_kills) in baselines {
+            self.oom_kills.entry(path).or_insert(oom_kills);
+        }
+    }
+
+    /// Records an `out_of_memory` event for every cgroup whose OOM killer ran since the last read
+    fn record_oom_kills(
+        &mut self,
+        cgroups: &HashMap<String, CgroupStatistic>,
+        logs: &mut EventRecorder,
+    ) {
+        for (path, statistic) in cgroups {
+            let Some(oom_kills) = statistic.oom_kills else {
+                continue;
+            };
+            let previous = self.oom_kills.insert(path.clone(), oom_kills);
+            if !matches!(previous, Some(previous) if oom_kills > previous) {
+                continue;
+            }
+
+            let limit = statistic
+                .memory_limit
+                .map(|limit| format!("its memory limit of {limit} bytes"))
+                .unwrap_or_else(|| "its memory limit".to_string());
+            logs.record_event(
+                EventType::OutOfMemory,
+                format!(
+                    "[{}] Out of memory: {} process(es) killed in cgroup {} after reaching {}",
+                    Utc::now(),
+                    oom_kills - previous.unwrap_or_default(),
+                    path,
+                    limit
+                ),
+                Some(EventAttributes::Cgroup(statistic.clone())),
+                None,
+            );
         }
     }
 
-    pub fn collect_metrics(&self, system: &mut System, logs: &mut EventRecorder) -> Result<()> {
-        let attributes =
-            EventAttributes::SystemMetric(Self::gather_metrics_object_attributes(system));
+    pub fn collect_metrics(
+        &mut self,
+        system: &mut System,
+        cgroup_paths: &HashSet<String>,
+        logs: &mut EventRecorder,
+    ) -> Result<()> {
+        let mut cgroup_paths = cgroup_paths.clone();
+        cgroup_paths.extend(self.oom_kills.keys().cloned());
+
+        let mut metric = Self::gather_metrics_object_attributes(system);
+        metric.system_cgroups = Self::gather_cgroup_data(&cgroup_paths);
+        self.oom_kills
+            .retain(|path, _| metric.system_cgroups.contains_key(path));
+        self.record_oom_kills(&metric.system_cgroups, logs);
+        self.record_own_usage(&mut metric);
 
         logs.record_event(
             EventType::MetricEvent,
             format!("[{}] System's resources metric", Utc::now()),
-            Some(attributes),
+            Some(EventAttributes::SystemMetric(metric)),
             None,
         );
 
@@ -101,9 +190,11 @@ mod tests {
     fn test_collect_metrics() {
         let mut system = System::new_all();
         let mut logs = EventRecorder::default();
-        let collector = SystemMetricsCollector::new();
+        let mut collector = SystemMetricsCollector::new();
 
-        collector.collect_metrics(&mut system, &mut logs).unwrap();
+        collector
+            .collect_metrics(&mut system, &HashSet::new(), &mut logs)
+            .unwrap();
 
         let events = logs.get_events();
         assert_eq!(events.len(), 1);
@@ -115,9 +206,57 @@ mod tests {
         let attribute = event.attributes.clone().unwrap();
         if let EventAttributes::SystemMetric(system_metric) = attribute {
             assert_eq!(system_metric.events_name, "global_system_metrics");
+            #[cfg(target_os = "linux")]
+            assert!(system_metric.tracer_memory_usage.is_some());
+            // the daemon's cpu needs two readings a window apart
+            assert_eq!(system_metric.tracer_cpu_utilization, None);
         } else {
             // fail test
             panic!("Expected SystemMetric attribute type"); // Replace assert!(false)
         }
     }
+
+    fn cgroups(oom_kills: u64) -> HashMap<String, CgroupStatistic> {
+        HashMap::from([(
+            "/docker/abc".to_string(),
+            CgroupStatistic {
+                cgroup_path: "/docker/abc".to_string(),
+                memory_limit: Some(1024),
+                oom_kills: Some(oom_kills),
+                ..Default::default()
+            },
+        )])
+    }
+
+    #[test]
+    fn test_oom_kills_are_recorded_once() {
+        let mut logs = EventRecorder::default();
+        let mut collector = SystemMetricsCollector::new();
+
+        // kills from before the cgroup was first read aren't ours
+        collector.record_oom_kills(&cgroups(2), &mut logs);
+        collector.record_oom_kills(&cgroups(3), &mut logs);
+        collector.record_oom_kills(&cgroups(3), &mut logs);
+
+        let events = logs.get_events();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].process_status, EventType::OutOfMemory.as_str());
+        assert!(events[0]
+            .message
+            .ends_with("1 process(es) killed in cgroup /docker/abc after reaching its memory limit of 1024 bytes"));
+    }
+
+    #[test]
+    fn test_oom_kills_before_the_first_read_are_recorded() {
+        // a tool killed before the first read, its cgroup was seeded as it started
+        let mut logs = EventRecorder::default();
+        let mut collector = SystemMetricsCollector::new();
+        collector.seed_oom_kills(HashMap::from([("/docker/abc".to_string(), 0)]));
+        collector.record_oom_kills(&cgroups(1), &mut logs);
+
+        assert_eq!(logs.get_events().len(), 1);
+        assert!(logs.get_events()[0]
+            .message
+            .contains("1 process(es) killed in cgroup /docker/abc"));
+    }
 }
@@ -1,5 +1,13 @@
+pub mod cgroup;
+pub mod containers;
+pub mod discovery;
+#[cfg(all(feature = "ebpf", target_os = "linux"))]
+pub mod ebpf;
 pub mod file_watcher;
 pub mod metrics;
+pub mod open_files;
+pub mod proc_connector;
+pub mod process_usage;
 pub mod process_watcher;
 pub mod stdout;
 pub mod syslog;
@@ -0,0 +1,227 @@
+// src/extracts/open_files.rs
+//! Files a process has open, read from `/proc/<pid>/fd` and `/proc/<pid>/fdinfo`
+use std::collections::HashMap;
+use std::path::Path;
+
+use crate::types::event::attributes::process::{CompletedProcess, FileAccess};
+
+/// Pseudo filesystems, their files aren't data
+const IGNORED_PREFIXES: [&str; 3] = ["/proc/", "/sys/", "/dev/"];
+/// Access mode bits of the open flags, `O_ACCMODE`
+const ACCESS_MODE_MASK: u32 = 0o3;
+const O_WRONLY: u32 = 0o1;
+const O_RDWR: u32 = 0o2;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AccessMode {
+    Read,
+    Write,
+    ReadWrite,
+}
+
+impl AccessMode {
+    pub fn from_flags(flags: u32) -> Self {
+        match flags & ACCESS_MODE_MASK {
+            O_WRONLY => AccessMode::Write,
+            O_RDWR => AccessMode::ReadWrite,
+            _ => AccessMode::Read,
+        }
+    }
+
+    fn reads(&self) -> bool {
+        matches!(self, AccessMode::Read | AccessMode::ReadWrite)
+    }
+
+    fn writes(&self) -> bool {
+        matches!(self, AccessMode::Write | AccessMode::ReadWrite)
+    }
+}
+
+/// A regular file open in a process
+#[derive(Debug, Clone, PartialEq)]
+pub struct OpenFile {
+    pub path: String,
+    pub mode: AccessMode,
+    pub offset: u64,
+    pub size: Option<u64>,
+}
+
+/// Whether an absolute path can hold data, files of pseudo filesystems don't
+pub fn is_data_path(path: &str) -> bool {
+    path.starts_with('/') && !IGNORED_PREFIXES.iter().any(|p| path.starts_with(p))
+}
+
+/// Size of a regular file holding data, `None` for pseudo filesystems, directories and missing files
+pub fn data_file_size(path: &str) -> Option<u64> {
+    if !is_data_path(path) {
+        return None;
+    }
+    let metadata = std::fs::metadata(path).ok()?;
+    metadata.is_file().then_some(metadata.len())
+}
+
+/// Regular files the process has open, empty when its fds can't be read
+pub fn read_open_files(pid: u32) -> Vec<OpenFile> {
+    let Ok(fds) = std::fs::read_dir(format!("/proc/{pid}/fd")) else {
+        return vec![];
+    };
+
+    fds.flatten()
+        .filter_map(|fd| {
+            let target = std::fs::read_link(fd.path()).ok()?;
+            let path = target.to_str()?;
+            // also drops deleted files, their link ends with ` (deleted)`
+            let size = data_file_size(path)?;
+
+            let fdinfo = Path::new("/proc")
+                .join(pid.to_string())
+                .join("fdinfo")
+                .join(fd.file_name());
+            let (offset, flags) = parse_fdinfo(&std::fs::read_to_string(fdinfo).ok()?)?;
+
+            Some(OpenFile {
+                path: path.to_string(),
+                mode: AccessMode::from_flags(flags),
+                offset,
+                size: Some(size),
+            })
+        })
+        .collect()
+}
+
+/// `pos` and the octal `flags` of an fdinfo file
+fn parse_fdinfo(fdinfo: &str) -> Option<(u64, u32)> {
+    let mut offset = None;
+    let mut flags = None;
+    for line in fdinfo.lines() {
+        match line.split_once(':') {
+            Some(("pos", value)) => offset = value.trim().parse().ok(),
+            Some(("flags", value)) => flags = u32::from_str_radix(value.trim(), 8).ok(),
+            _ => {}
+        }
+    }
+    Some((offset?, flags?))
+}
+
+/// Files read and written by a tool over every sample of its fds
+#[derive(Debug, Clone, Default)]
+pub struct FileAccesses {
+    read: HashMap<String, FileAccess>,
+    written: HashMap<String, FileAccess>,
+}
+
+impl FileAccesses {
+    pub fn update(&mut self, files: &[OpenFile]) {
+        for file in files {
+            let lists = [
+                (file.mode.reads(), &mut self.read),
+                (file.mode.writes(), &mut self.written),
+            ];
+            for (accessed, list) in lists {
+                if !accessed {
+                    continue;
+                }
+                let access = list.entry(file.path.clone()).or_insert_with(|| FileAccess {
+                    file_path: file.path.clone(),
+                    ..Default::default()
+                });
+                access.max_offset = access.max_offset.max(file.offset);
+                access.file_size = file.size.or(access.file_size);
+            }
+        }
+    }
+
+    pub fn fill(&self, completed: &mut CompletedProcess) {
+        let sorted = |files: &HashMap<String, FileAccess>| {
+            let mut files: Vec<FileAccess> = files.values().cloned().collect();
+            files.sort_by(|a, b| a.file_path.cmp(&b.file_path));
+            files
+        };
+        completed.files_read = sorted(&self.read);
+        completed.files_written = sorted(&self.written);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Seek, SeekFrom, Write};
+
+    #[test]
+    fn test_parse_fdinfo() {
+        let fdinfo = "pos:\t4096\nflags:\t0100001\nmnt_id:\t25\nino:\t1234\n";
+        assert_eq!(parse_fdinfo(fdinfo), Some((4096, 0o100001)));
+        assert_eq!(AccessMode::from_flags(0o100001), AccessMode::Write);
+        assert_eq!(AccessMode::from_flags(0o100000), AccessMode::Read);
+        assert_eq!(AccessMode::from_flags(0o2), AccessMode::ReadWrite);
+        assert_eq!(parse_fdinfo("flags:\t02\n"), None);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_read_own_open_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("reads.fastq");
+        std::fs::write(&input, b"@read\nACGT\n+\nIIII\n").unwrap();
+        let output = dir.path().join("aligned.bam");
+
+        let mut reader = std::fs::File::open(&input).unwrap();
+        reader.seek(SeekFrom::Start(6)).unwrap();
+        let mut writer = std::fs::File::create(&output).unwrap();
+        writer.write_all(b"BAM\x01").unwrap();
+
+        let files = read_open_files(std::process::id());
+        let find = |path: &Path| {
+            files
+                .iter()
+                .find(|file| file.path == path.to_str().unwrap())
+                .cloned()
+                .unwrap()
+        };
+        let read = find(&input);
+        assert_eq!(read.mode, AccessMode::Read);
+        assert_eq!(read.offset, 6);
+        assert_eq!(read.size, Some(18));
+        let written = find(&output);
+        assert_eq!(written.mode, AccessMode::Write);
+        assert_eq!(written.offset, 4);
+
+        let mut accesses = FileAccesses::default();
+        accesses.update(&files);
+        let mut completed = CompletedProcess::default();
+        accesses.fill(&mut completed);
+        assert!(completed
+            .files_read
+            .iter()
+            .any(|file| file.file_path == input.to_str().unwrap() && file.max_offset == 6));
+        assert!(completed
+            .files_written
+            .iter()
+            .any(|file| file.file_path == output.to_str().unwrap() && file.file_size == Some(4)));
+    }
+
+    #[test]
+    fn test_accesses_keep_the_furthest_offset() {
+        let open = |mode, offset, size| OpenFile {
+            path: "/data/ref.fa".to_string(),
+            mode,
+            offset,
+            size: Some(size),
+        };
+        let mut accesses = FileAccesses::default();
+        accesses.update(&[open(AccessMode::Read, 100, 1000)]);
+        accesses.update(&[open(AccessMode::ReadWrite, 50, 1200)]);
+
+        let mut completed = CompletedProcess::default();
+        accesses.fill(&mut completed);
+        assert_eq!(
+            completed.files_read,
+            vec![FileAccess {
+                file_path: "/data/ref.fa".to_string(),
+                file_size: Some(1200),
+                max_offset: 100,
+            }]
+        );
+        assert_eq!(completed.files_written[0].max_offset, 50);
+    }
+}
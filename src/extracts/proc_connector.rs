@@ -0,0 +1,648 @@
+// src/extracts/proc_connector.rs
+//! Process exec/exit events from the Linux netlink proc connector.
+//! Polling `sysinfo` misses tools that start and finish between two polls, the connector
+//! reports every process as it happens and the exec'd process is read from `/proc` right away.
+use crate::config_manager::target_process::rules::{ProcFs, ProcessInfo};
+use crate::config_manager::target_process::Target;
+use crate::extracts::cgroup;
+use crate::extracts::open_files::OpenFile;
+use crate::extracts::process_usage::ProcessSample;
+use crate::types::event::attributes::process::ProcessExit;
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{Receiver, SyncSender, TrySendError};
+use std::sync::{Arc, RwLock};
+
+/// Events queued for the process watcher, newer ones are dropped and counted once it falls this far behind
+const EVENT_QUEUE_LEN: usize = 4096;
+
+const NLMSG_HDR_LEN: usize = 16;
+const CN_MSG_LEN: usize = 20;
+/// `what`, `cpu` and `timestamp_ns` precede the event data in `struct proc_event`
+const PROC_EVENT_HEADER_LEN: usize = 16;
+
+const PROC_EVENT_FORK: u32 = 0x0000_0001;
+const PROC_EVENT_EXEC: u32 = 0x0000_0002;
+const PROC_EVENT_EXIT: u32 = 0x8000_0000;
+
+/// A process as it looked right after it exec'd
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExecSnapshot {
+    pub pid: u32,
+    pub parent_pid: u32,
+    pub name: String,
+    pub cmd: Vec<String>,
+    pub exe: String,
+    /// cgroup v2 path, containers and scheduler jobs move their processes before exec
+    pub cgroup: Option<String>,
+    pub timestamp: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ProcEvent {
+    Exec(ExecSnapshot),
+    Exit {
+        pid: u32,
+        exit: ProcessExit,
+        /// Read while the process is a zombie, so cpu and io totals are final
+        usage: Option<ProcessSample>,
+        timestamp: DateTime<Utc>,
+    },
+    /// A file opened by any process, only reported by the eBPF backend
+    Open {
+        pid: u32,
+        file: OpenFile,
+    },
+}
+
+/// Events as they come off the socket, before `/proc` is read
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum RawProcEvent {
+    Fork { parent_tgid: u32, child_tgid: u32 },
+    Exec { tgid: u32 },
+    Exit { tgid: u32, exit_code: u32 },
+}
+
+/// What the event threads report, shared with the process watcher.
+/// Every process on the host execs and exits through them, only the ones a target matches are read in full
+#[derive(Clone, Default)]
+pub struct EventFilter {
+    targets: Arc<RwLock<Vec<Target>>>,
+    /// Tools the watcher follows, the only processes whose exit is reported
+    tracked: Arc<RwLock<HashSet<u32>>>,
+}
+
+impl EventFilter {
+    pub fn new(targets: Vec<Target>) -> Self {
+        EventFilter {
+            targets: Arc::new(RwLock::new(targets)),
+            ..Default::default()
+        }
+    }
+
+    pub fn set_targets(&self, targets: Vec<Target>) {
+        *self.targets.write().unwrap() = targets;
+    }
+
+    /// Tracks the tools found by polling and forgets the ones whose exit was never reported
+    pub fn sync_tracked(&self, tools: impl IntoIterator<Item = u32>) {
+        let mut tracked = self.tracked.write().unwrap();
+        tracked.extend(tools);
+        tracked.retain(|pid| is_running(*pid));
+    }
+
+    /// The exec'd process when a target matches it, its cgroup is read and it's tracked from then on
+    pub(crate) fn matched(&self, mut snapshot: ExecSnapshot) -> Option<ExecSnapshot> {
+        let info = ProcessInfo::from_argv(&snapshot.name, &snapshot.cmd, &snapshot.exe)
+            .set_pid(snapshot.pid, Some(snapshot.parent_pid));
+        if !self
+            .targets
+            .read()
+            .unwrap()
+            .iter()
+            .any(|target| target.matches_process(&info, &ProcFs))
+        {
+            return None;
+        }
+
+        snapshot.cgroup = cgroup::cgroup_of(snapshot.pid);
+        self.tracked.write().unwrap().insert(snapshot.pid);
+        Some(snapshot)
+    }
+
+    #[cfg(all(feature = "ebpf", target_os = "linux"))]
+    pub(crate) fn tracked_pids(&self) -> HashSet<u32> {
+        self.tracked.read().unwrap().clone()
+    }
+
+    /// Whether the exiting process was tracked, it no longer is
+    pub(crate) fn untrack(&self, pid: u32) -> bool {
+        self.tracked.write().unwrap().remove(&pid)
+    }
+}
+
+fn is_running(pid: u32) -> bool {
+    std::path::Path::new(&format!("/proc/{pid}")).exists()
+}
+
+/// Sending end of an event thread, events are dropped rather than queued without bound
+pub(crate) struct ProcEventSender {
+    sender: SyncSender<ProcEvent>,
+    dropped: Arc<AtomicU64>,
+}
+
+impl ProcEventSender {
+    /// `false` once the receiver is gone
+    pub(crate) fn send(&self, event: ProcEvent) -> bool {
+        match self.sender.try_send(event) {
+            Ok(()) => true,
+            Err(TrySendError::Full(_)) => {
+                self.dropped.fetch_add(1, Ordering::Relaxed);
+                true
+            }
+            Err(TrySendError::Disconnected(_)) => false,
+        }
+    }
+}
+
+/// Receiving end of the connector, drained by the process watcher on every poll
+pub struct ProcEventReceiver {
+    receiver: Receiver<ProcEvent>,
+    dropped: Arc<AtomicU64>,
+    filter: EventFilter,
+}
+
+impl ProcEventReceiver {
+    pub fn drain(&self) -> Vec<ProcEvent> {
+        let dropped = self.dropped.swap(0, Ordering::Relaxed);
+        if dropped > 0 {
+            tracing::warn!(
+                "Dropped {} process events, the process watcher fell behind",
+                dropped
+            );
+        }
+        self.receiver.try_iter().collect()
+    }
+
+    pub fn filter(&self) -> &EventFilter {
+        &self.filter
+    }
+}
+
+pub(crate) fn channel(filter: EventFilter) -> (ProcEventSender, ProcEventReceiver) {
+    let (sender, receiver) = std::sync::mpsc::sync_channel(EVENT_QUEUE_LEN);
+    let dropped = Arc::new(AtomicU64::new(0));
+    (
+        ProcEventSender {
+            sender,
+            dropped: dropped.clone(),
+        },
+        ProcEventReceiver {
+            receiver,
+            dropped,
+            filter,
+        },
+    )
+}
+
+fn read_u32(buf: &[u8], offset: usize) -> Option<u32> {
+    buf.get(offset..offset + 4)
+        .map(|bytes| u32::from_ne_bytes(bytes.try_into().unwrap()))
+}
+
+/// Parses every netlink message in a datagram, ignoring thread level events
+fn parse_datagram(buf: &[u8]) -> Vec<RawProcEvent> {
+    let mut events = vec![];
+    let mut offset = 0;
+
+    while let Some(message_len) = read_u32(buf, offset) {
+        let message_len = message_len as usize;
+        if message_len < NLMSG_HDR_LEN || offset + message_len > buf.len() {
+            break;
+        }
+
+        let data = offset + NLMSG_HDR_LEN + CN_MSG_LEN;
+        let event_data = data + PROC_EVENT_HEADER_LEN;
+        let field = |index: usize| read_u32(buf, event_data + index * 4);
+
+        let event = match read_u32(buf, data) {
+            Some(PROC_EVENT_FORK) => {
+                match (field(0), field(1), field(2), field(3)) {
+                    // a new thread shares its parent's tgid
+                    (Some(_), Some(parent_tgid), Some(child_pid), Some(child_tgid))
+                        if child_pid == child_tgid =>
+                    {
+                        Some(RawProcEvent::Fork {
+                            parent_tgid,
+                            child_tgid,
+                        })
+                    }
+                    _ => None,
+                }
+            }
+            Some(PROC_EVENT_EXEC) => field(1).map(|tgid| RawProcEvent::Exec { tgid }),
+            Some(PROC_EVENT_EXIT) => match (field(0), field(1), field(2)) {
+                (Some(pid), Some(tgid), Some(exit_code)) if pid == tgid => {
+                    Some(RawProcEvent::Exit { tgid, exit_code })
+                }
+                _ => None,
+            },
+            _ => None,
+        };
+        events.extend(event);
+
+        // netlink messages are 4 byte aligned
+        offset += (message_len + 3) & !3;
+    }
+
+    events
+}
+
+/// Reads what targets are matched on, the cgroup is left to `EventFilter::matched`
+pub(crate) fn read_exec_snapshot(pid: u32, forked_from: Option<u32>) -> Option<ExecSnapshot> {
+    let info = ProcessInfo::read(pid)?;
+    Some(ExecSnapshot {
+        pid,
+        parent_pid: info.parent_pid.or(forked_from).unwrap_or_default(),
+        name: info.name,
+        cmd: info.args,
+        exe: info.bin_path,
+        cgroup: None,
+        timestamp: Utc::now(),
+    })
+}
+
+/// Turns raw connector events into process events, reading exec'd processes from `/proc`
+struct EventResolver {
+    filter: EventFilter,
+    // forks whose child hasn't exec'd or exited yet, used when `/proc/<pid>/stat` is gone
+    forked_from: HashMap<u32, u32>,
+}
+
+impl EventResolver {
+    fn resolve(&mut self, event: RawProcEvent) -> Option<ProcEvent> {
+        match event {
+            RawProcEvent::Fork {
+                parent_tgid,
+                child_tgid,
+            } => {
+                self.forked_from.insert(child_tgid, parent_tgid);
+                None
+            }
+            RawProcEvent::Exec { tgid } => {
+                let forked_from = self.forked_from.remove(&tgid);
+                read_exec_snapshot(tgid, forked_from)
+                    .and_then(|snapshot| self.filter.matched(snapshot))
+                    .map(ProcEvent::Exec)
+            }
+            RawProcEvent::Exit { tgid, exit_code } => {
+                self.forked_from.remove(&tgid);
+                if !self.filter.untrack(tgid) {
+                    return None;
+                }
+                Some(ProcEvent::Exit {
+                    pid: tgid,
+                    // the kernel reports the raw wait status
+                    exit: ProcessExit::from_wait_status(exit_code),
+                    usage: ProcessSample::read(tgid),
+                    timestamp: Utc::now(),
+                })
+            }
+        }
+    }
+
+    /// Forgets forks whose exit was among the events the kernel dropped
+    fn forget_exited(&mut self) {
+        self.forked_from.retain(|pid, _| is_running(*pid));
+    }
+}
+
+/// Subscribes to the proc connector, which needs CAP_NET_ADMIN.
+/// Events are read on a background thread until the receiver is dropped
+#[cfg(target_os = "linux")]
+pub fn subscribe(filter: EventFilter) -> Result<ProcEventReceiver> {
+    let socket = sys::ProcConnectorSocket::open()?;
+    let (sender, receiver) = channel(filter.clone());
+
+    std::thread::Builder::new()
+        .name("proc-connector".to_string())
+        .spawn(move || {
+            let mut resolver = EventResolver {
+                filter,
+                forked_from: HashMap::new(),
+            };
+            let mut buf = vec![0u8; 8192];
+
+            loop {
+                let len = match socket.recv(&mut buf) {
+                    Ok(len) => len,
+                    // the kernel dropped events because we fell behind, keep reading
+                    Err(err) if err.raw_os_error() == Some(libc::ENOBUFS) => {
+                        tracing::warn!("Proc connector overflowed, some events were lost");
+                        resolver.forget_exited();
+                        continue;
+                    }
+                    Err(err) => {
+                        tracing::error!("Failed to read from the proc connector: {}", err);
+                        return;
+                    }
+                };
+
+                for event in parse_datagram(&buf[..len]) {
+                    if let Some(event) = resolver.resolve(event) {
+                        if !sender.send(event) {
+                            return;
+                        }
+                    }
+                }
+            }
+        })?;
+
+    Ok(receiver)
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn subscribe(_filter: EventFilter) -> Result<ProcEventReceiver> {
+    anyhow::bail!("The proc connector is only available on Linux")
+}
+
+#[cfg(target_os = "linux")]
+mod sys {
+    use super::{CN_MSG_LEN, NLMSG_HDR_LEN};
+    use anyhow::{bail, Result};
+    use std::io;
+    use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+
+    const NETLINK_CONNECTOR: libc::c_int = 11;
+    const CN_IDX_PROC: u32 = 1;
+    const CN_VAL_PROC: u32 = 1;
+    const PROC_CN_MCAST_LISTEN: u32 = 1;
+
+    pub struct ProcConnectorSocket {
+        fd: OwnedFd,
+    }
+
+    impl ProcConnectorSocket {
+        pub fn open() -> Result<Self> {
+            // SAFETY: plain socket syscall, the fd is owned right after
+            let fd = unsafe {
+                libc::socket(
+                    libc::AF_NETLINK,
+                    libc::SOCK_DGRAM | libc::SOCK_CLOEXEC,
+                    NETLINK_CONNECTOR,
+                )
+            };
+            if fd < 0 {
+                bail!(
+                    "Failed to open the netlink connector socket: {}",
+                    io::Error::last_os_error()
+                );
+            }
+            // SAFETY: fd is a valid socket that nothing else owns
+            let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+
+            // SAFETY: sockaddr_nl is plain data, all zeroes is a valid value
+            let mut address: libc::sockaddr_nl = unsafe { std::mem::zeroed() };
+            address.nl_family = libc::AF_NETLINK as libc::sa_family_t;
+            address.nl_groups = CN_IDX_PROC;
+            address.nl_pid = 0;
+
+            // SAFETY: address outlives the call and the length matches its type
+            let result = unsafe {
+                libc::bind(
+                    fd.as_raw_fd(),
+                    &address as *const libc::sockaddr_nl as *const libc::sockaddr,
+                    std::mem::size_of::<libc::sockaddr_nl>() as libc::socklen_t,
+                )
+            };
+            if result < 0 {
+                bail!(
+                    "Failed to bind the netlink connector socket: {}",
+                    io::Error::last_os_error()
+                );
+            }
+
+            let socket = Self { fd };
+            socket.send(&listen_message())?;
+            Ok(socket)
+        }
+
+        fn send(&self, message: &[u8]) -> Result<()> {
+            // SAFETY: message is a valid buffer of the given length
+            let sent = unsafe {
+                libc::send(
+                    self.fd.as_raw_fd(),
+                    message.as_ptr() as *const libc::c_void,
+                    message.len(),
+                    0,
+                )
+            };
+            if sent < 0 {
+                bail!(
+                    "Failed to subscribe to proc events: {}",
+                    io::Error::last_os_error()
+                );
+            }
+            Ok(())
+        }
+
+        pub fn recv(&self, buf: &mut [u8]) -> io::Result<usize> {
+            // SAFETY: buf is a valid, writable buffer of the given length
+            let len = unsafe {
+                libc::recv(
+                    self.fd.as_raw_fd(),
+                    buf.as_mut_ptr() as *mut libc::c_void,
+                    buf.len(),
+                    0,
+                )
+            };
+            if len < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(len as usize)
+        }
+    }
+
+    /// nlmsghdr + cn_msg + the multicast op
+    fn listen_message() -> Vec<u8> {
+        let len = NLMSG_HDR_LEN + CN_MSG_LEN + 4;
+        let mut message = Vec::with_capacity(len);
+
+        // nlmsghdr
+        message.extend_from_slice(&(len as u32).to_ne_bytes());
+        message.extend_from_slice(&(libc::NLMSG_DONE as u16).to_ne_bytes());
+        message.extend_from_slice(&0u16.to_ne_bytes());
+        message.extend_from_slice(&0u32.to_ne_bytes());
+        message.extend_from_slice(&std::process::id().to_ne_bytes());
+        // cn_msg
+        message.extend_from_slice(&CN_IDX_PROC.to_ne_bytes());
+        message.extend_from_slice(&CN_VAL_PROC.to_ne_bytes());
+        message.extend_from_slice(&0u32.to_ne_bytes());
+        message.extend_from_slice(&0u32.to_ne_bytes());
+        message.extend_from_slice(&4u16.to_ne_bytes());
+        message.extend_from_slice(&0u16.to_ne_bytes());
+        // enum proc_cn_mcast_op
+        message.extend_from_slice(&PROC_CN_MCAST_LISTEN.to_ne_bytes());
+
+        message
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config_manager::target_process::target_matching::TargetMatch;
+    use crate::extracts::open_files::AccessMode;
+
+    fn datagram(what: u32, fields: &[u32]) -> Vec<u8> {
+        let mut event = vec![];
+        event.extend_from_slice(&what.to_ne_bytes());
+        event.extend_from_slice(&0u32.to_ne_bytes());
+        event.extend_from_slice(&0u64.to_ne_bytes());
+        for field in fields {
+            event.extend_from_slice(&field.to_ne_bytes());
+        }
+
+        let len = NLMSG_HDR_LEN + CN_MSG_LEN + event.len();
+        let mut message = (len as u32).to_ne_bytes().to_vec();
+        message.resize(NLMSG_HDR_LEN + CN_MSG_LEN, 0);
+        message.extend(event);
+        message
+    }
+
+    #[test]
+    fn test_parse_datagram() {
+        assert_eq!(
+            parse_datagram(&datagram(PROC_EVENT_FORK, &[10, 10, 11, 11])),
+            vec![RawProcEvent::Fork {
+                parent_tgid: 10,
+                child_tgid: 11
+            }]
+        );
+        assert_eq!(
+            parse_datagram(&datagram(PROC_EVENT_EXEC, &[11, 11])),
+            vec![RawProcEvent::Exec { tgid: 11 }]
+        );
+        assert_eq!(
+            parse_datagram(&datagram(PROC_EVENT_EXIT, &[11, 11, 256, 17])),
+            vec![RawProcEvent::Exit {
+                tgid: 11,
+                exit_code: 256
+            }]
+        );
+
+        // threads starting and exiting are not processes
+        assert!(parse_datagram(&datagram(PROC_EVENT_FORK, &[10, 10, 12, 10])).is_empty());
+        assert!(parse_datagram(&datagram(PROC_EVENT_EXIT, &[12, 10, 0, 0])).is_empty());
+        // truncated messages are dropped
+        assert!(parse_datagram(&datagram(PROC_EVENT_EXEC, &[11, 11])[..20]).is_empty());
+    }
+
+    fn resolver(targets: Vec<Target>) -> EventResolver {
+        EventResolver {
+            filter: EventFilter::new(targets),
+            forked_from: HashMap::new(),
+        }
+    }
+
+    fn exit(tgid: u32) -> RawProcEvent {
+        RawProcEvent::Exit { tgid, exit_code: 0 }
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn test_resolve_exec_of_current_process() {
+        let pid = std::process::id();
+        let name = read_exec_snapshot(pid, None).unwrap().name;
+        let mut resolver = resolver(vec![Target::new(TargetMatch::ProcessName(name))]);
+
+        let Some(ProcEvent::Exec(snapshot)) = resolver.resolve(RawProcEvent::Exec { tgid: pid })
+        else {
+            panic!("expected an exec event");
+        };
+
+        assert_eq!(snapshot.pid, pid);
+        assert_eq!(snapshot.parent_pid, std::os::unix::process::parent_id());
+        assert!(!snapshot.cmd.is_empty());
+        assert!(!snapshot.name.is_empty());
+        assert!(matches!(
+            resolver.resolve(exit(pid)),
+            Some(ProcEvent::Exit { pid: exited, .. }) if exited == pid
+        ));
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn test_unmatched_processes_are_not_reported() {
+        let pid = std::process::id();
+        let mut resolver = resolver(vec![Target::new(TargetMatch::ProcessName(
+            "not-a-tool".to_string(),
+        ))]);
+
+        assert_eq!(resolver.resolve(RawProcEvent::Exec { tgid: pid }), None);
+        assert_eq!(resolver.resolve(exit(pid)), None);
+
+        // found by polling rather than by its exec
+        resolver.filter.sync_tracked([pid]);
+        assert!(resolver.resolve(exit(pid)).is_some());
+        assert_eq!(resolver.resolve(exit(pid)), None);
+    }
+
+    #[test]
+    fn test_forget_exited_forks() {
+        let mut resolver = resolver(vec![]);
+        for child_tgid in [std::process::id(), u32::MAX] {
+            resolver.resolve(RawProcEvent::Fork {
+                parent_tgid: 1,
+                child_tgid,
+            });
+        }
+
+        resolver.forget_exited();
+        assert_eq!(
+            resolver.forked_from,
+            HashMap::from([(std::process::id(), 1)])
+        );
+    }
+
+    #[test]
+    fn test_sync_tracked_forgets_exited_processes() {
+        let filter = EventFilter::new(vec![]);
+        filter.sync_tracked([std::process::id(), u32::MAX]);
+
+        assert!(filter.untrack(std::process::id()));
+        assert!(!filter.untrack(u32::MAX));
+    }
+
+    #[test]
+    fn test_full_channel_drops_events() {
+        let (sender, receiver) = channel(EventFilter::default());
+        let event = ProcEvent::Open {
+            pid: 1,
+            file: OpenFile {
+                path: "/data/reads.fastq".to_string(),
+                mode: AccessMode::Read,
+                offset: 0,
+                size: None,
+            },
+        };
+
+        for _ in 0..EVENT_QUEUE_LEN + 10 {
+            assert!(sender.send(event.clone()));
+        }
+
+        assert_eq!(receiver.dropped.load(Ordering::Relaxed), 10);
+        assert_eq!(receiver.drain().len(), EVENT_QUEUE_LEN);
+        assert_eq!(receiver.dropped.load(Ordering::Relaxed), 0);
+
+        drop(receiver);
+        assert!(!sender.send(event));
+    }
+
+    #[test]
+    fn test_subscribe_sees_short_lived_process() {
+        // needs CAP_NET_ADMIN, the daemon falls back to polling without it
+        let filter = EventFilter::new(vec![Target::new(TargetMatch::ProcessName(
+            "sh".to_string(),
+        ))]);
+        let Ok(receiver) = subscribe(filter) else {
+            return;
+        };
+
+        // lives long enough for its exec to be read
+        let child = std::process::Command::new("sh")
+            .args(["-c", "sleep 0.1; exit 3"])
+            .status()
+            .unwrap();
+        assert_eq!(child.code(), Some(3));
+        std::thread::sleep(std::time::Duration::from_millis(200));
+
+        let events = receiver.drain();
+        assert!(events.iter().any(|event| matches!(
+            event,
+            ProcEvent::Exit { exit, .. } if exit.exit_code == Some(3)
+        )));
+    }
+}
@@ -0,0 +1,399 @@
+// src/extracts/process_usage.rs
+//! Per process resource usage read from `/proc`, accumulated over a tool's lifetime
+use crate::types::event::attributes::process::CompletedProcess;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/// One reading of a process' counters, fields are None when their `/proc` file couldn't be read
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ProcessSample {
+    /// `VmHWM`, the kernel tracks the peak itself so it's exact between samples
+    pub peak_rss_bytes: Option<u64>,
+    /// `VmPeak`
+    pub peak_virtual_bytes: Option<u64>,
+    /// `VmRSS` and `VmSize`, summed when a process tree is aggregated
+    pub rss_bytes: Option<u64>,
+    pub virtual_bytes: Option<u64>,
+    pub threads: Option<u64>,
+    pub cpu_user_time_sec: Option<f64>,
+    pub cpu_system_time_sec: Option<f64>,
+    /// Cpu time of the children the process already waited for
+    pub children_cpu_user_time_sec: Option<f64>,
+    pub children_cpu_system_time_sec: Option<f64>,
+    pub read_bytes: Option<u64>,
+    pub written_bytes: Option<u64>,
+}
+
+impl ProcessSample {
+    /// Reads what's still readable, memory counters are gone once the process is a zombie
+    pub fn read(pid: u32) -> Option<Self> {
+        let stat = std::fs::read_to_string(format!("/proc/{pid}/stat")).ok()?;
+
+        let mut sample = ProcessSample::default();
+        sample.parse_stat(&stat);
+        if let Ok(status) = std::fs::read_to_string(format!("/proc/{pid}/status")) {
+            sample.parse_status(&status);
+        }
+        // only readable by the process owner or root
+        if let Ok(io) = std::fs::read_to_string(format!("/proc/{pid}/io")) {
+            sample.parse_io(&io);
+        }
+
+        Some(sample)
+    }
+
+    fn parse_stat(&mut self, stat: &str) {
+        let Some(fields) = stat.rfind(')').map(|end| &stat[end + 1..]) else {
+            return;
+        };
+        // fields after the name start at `state`, the 3rd field of the file
+        let fields: Vec<&str> = fields.split_whitespace().collect();
+        let ticks = |index: usize| {
+            fields
+                .get(index - 3)
+                .and_then(|value| value.parse::<u64>().ok())
+                .map(|ticks| ticks as f64 / clock_ticks_per_second())
+        };
+        self.cpu_user_time_sec = ticks(14);
+        self.cpu_system_time_sec = ticks(15);
+        self.children_cpu_user_time_sec = ticks(16);
+        self.children_cpu_system_time_sec = ticks(17);
+    }
+
+    fn parse_status(&mut self, status: &str) {
+        for line in status.lines() {
+            let Some((key, value)) = line.split_once(':') else {
+                continue;
+            };
+            let value = value.split_whitespace().next().and_then(|v| v.parse().ok());
+            match key {
+                "VmHWM" => self.peak_rss_bytes = value.map(|kb: u64| kb * 1024),
+                "VmPeak" => self.peak_virtual_bytes = value.map(|kb: u64| kb * 1024),
+                "VmRSS" => self.rss_bytes = value.map(|kb: u64| kb * 1024),
+                "VmSize" => self.virtual_bytes = value.map(|kb: u64| kb * 1024),
+                "Threads" => self.threads = value,
+                _ => {}
+            }
+        }
+    }
+
+    fn parse_io(&mut self, io: &str) {
+        for line in io.lines() {
+            match line.split_once(": ") {
+                Some(("read_bytes", value)) => self.read_bytes = value.parse().ok(),
+                Some(("write_bytes", value)) => self.written_bytes = value.parse().ok(),
+                _ => {}
+            }
+        }
+    }
+
+    /// Combines a process with its live descendants into one sample of the whole tree.
+    /// Reaped descendants still count towards cpu time through their parents' children times
+    pub fn with_descendants(&self, descendants: &[ProcessSample]) -> ProcessSample {
+        let tree = || std::iter::once(self).chain(descendants);
+        let cpu_time = |own: fn(&ProcessSample) -> Option<f64>,
+                        children: fn(&ProcessSample) -> Option<f64>| {
+            sum(tree().flat_map(|sample| [own(sample), children(sample)]))
+        };
+
+        let rss_bytes = sum(tree().map(|sample| sample.rss_bytes));
+        let virtual_bytes = sum(tree().map(|sample| sample.virtual_bytes));
+
+        ProcessSample {
+            // the root's own high-water mark can be higher than the tree's current total
+            peak_rss_bytes: max(self.peak_rss_bytes, rss_bytes),
+            peak_virtual_bytes: max(self.peak_virtual_bytes, virtual_bytes),
+            rss_bytes,
+            virtual_bytes,
+            threads: sum(tree().map(|sample| sample.threads)),
+            cpu_user_time_sec: cpu_time(
+                |sample| sample.cpu_user_time_sec,
+                |sample| sample.children_cpu_user_time_sec,
+            ),
+            cpu_system_time_sec: cpu_time(
+                |sample| sample.cpu_system_time_sec,
+                |sample| sample.children_cpu_system_time_sec,
+            ),
+            children_cpu_user_time_sec: None,
+            children_cpu_system_time_sec: None,
+            read_bytes: sum(tree().map(|sample| sample.read_bytes)),
+            written_bytes: sum(tree().map(|sample| sample.written_bytes)),
+        }
+    }
+
+    /// User and system cpu time
+    pub fn cpu_time_sec(&self) -> Option<f64> {
+        Some(self.cpu_user_time_sec? + self.cpu_system_time_sec?)
+    }
+}
+
+fn sum<T: std::ops::Add<Output = T> + Copy>(values: impl Iterator<Item = Option<T>>) -> Option<T> {
+    values.flatten().reduce(|total, value| total + value)
+}
+
+/// Cpu utilization from how fast a process' cpu time grows, in percent of one core
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CpuRate {
+    baseline: Option<(f64, Instant)>,
+    utilization: Option<f32>,
+}
+
+impl CpuRate {
+    /// Cpu time is counted in clock ticks, rates over shorter windows are mostly rounding
+    const MIN_WINDOW: Duration = Duration::from_secs(1);
+
+    pub fn update(&mut self, cpu_time_sec: f64, now: Instant) {
+        let Some((baseline_cpu, baseline_time)) = self.baseline else {
+            self.baseline = Some((cpu_time_sec, now));
+            return;
+        };
+        let window = now.saturating_duration_since(baseline_time);
+        if window < Self::MIN_WINDOW {
+            return;
+        }
+        // exited descendants that weren't waited for take their cpu time with them
+        let used = (cpu_time_sec - baseline_cpu).max(0.0);
+        self.utilization = Some((used / window.as_secs_f64() * 100.0) as f32);
+        self.baseline = Some((cpu_time_sec, now));
+    }
+
+    /// None until a full window was sampled
+    pub fn utilization(&self) -> Option<f32> {
+        self.utilization
+    }
+}
+
+fn clock_ticks_per_second() -> f64 {
+    // SAFETY: sysconf has no preconditions
+    let ticks = unsafe { libc::sysconf(libc::_SC_CLK_TCK) };
+    if ticks > 0 {
+        ticks as f64
+    } else {
+        100.0
+    }
+}
+
+/// A process of a tool's tree with its parent, `None` for the tool itself
+pub type TreeMember = (u32, Option<u32>, ProcessSample);
+
+/// Disk I/O of a tool's tree over its lifetime, kept per process across samples.
+/// A process' `/proc/<pid>/io` includes the children it reaped, so a descendant that's gone adds
+/// its last reading only when its parent isn't in the tree to have taken it over, like one reparented to init
+#[derive(Debug, Clone, Default)]
+pub struct TreeIo {
+    /// Parent, read and written bytes of the processes at the last sample
+    last: HashMap<u32, (Option<u32>, u64, u64)>,
+    /// Bytes of descendants that left the tree
+    retired_read: u64,
+    retired_written: u64,
+}
+
+impl TreeIo {
+    /// Read and written bytes of the tree so far, `None` when no process' I/O could be read
+    pub fn update(&mut self, tree: &[TreeMember]) -> (Option<u64>, Option<u64>) {
+        let current: HashMap<u32, (Option<u32>, u64, u64)> = tree
+            .iter()
+            .filter_map(|(pid, parent, sample)| {
+                Some((*pid, (*parent, sample.read_bytes?, sample.written_bytes?)))
+            })
+            .collect();
+        for (pid, (parent, read, written)) in &self.last {
+            let reaped_in_tree = parent.is_some_and(|parent| current.contains_key(&parent));
+            if !current.contains_key(pid) && !reaped_in_tree {
+                self.retired_read += read;
+                self.retired_written += written;
+            }
+        }
+        self.last = current;
+
+        if self.last.is_empty() && self.retired_read == 0 && self.retired_written == 0 {
+            return (None, None);
+        }
+        let read = self.last.values().map(|(_, read, _)| read).sum::<u64>();
+        let written = self
+            .last
+            .values()
+            .map(|(_, _, written)| written)
+            .sum::<u64>();
+        (
+            Some(self.retired_read + read),
+            Some(self.retired_written + written),
+        )
+    }
+}
+
+/// Peaks and totals over every sample of a process
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ProcessUsage {
+    pub peak_rss_bytes: Option<u64>,
+    pub peak_virtual_bytes: Option<u64>,
+    pub max_threads: Option<u64>,
+    pub cpu_user_time_sec: Option<f64>,
+    pub cpu_system_time_sec: Option<f64>,
+    pub read_bytes: Option<u64>,
+    pub written_bytes: Option<u64>,
+}
+
+fn max<T: PartialOrd + Copy>(current: Option<T>, sample: Option<T>) -> Option<T> {
+    match (current, sample) {
+        (Some(current), Some(sample)) if sample > current => Some(sample),
+        (None, sample) => sample,
+        (current, _) => current,
+    }
+}
+
+impl ProcessUsage {
+    /// Counters only grow, keeping the max also covers samples that failed to read a file
+    pub fn update(&mut self, sample: &ProcessSample) {
+        self.peak_rss_bytes = max(self.peak_rss_bytes, sample.peak_rss_bytes);
+        self.peak_virtual_bytes = max(self.peak_virtual_bytes, sample.peak_virtual_bytes);
+        self.max_threads = max(self.max_threads, sample.threads);
+        self.cpu_user_time_sec = max(self.cpu_user_time_sec, sample.cpu_user_time_sec);
+        self.cpu_system_time_sec = max(self.cpu_system_time_sec, sample.cpu_system_time_sec);
+        self.read_bytes = max(self.read_bytes, sample.read_bytes);
+        self.written_bytes = max(self.written_bytes, sample.written_bytes);
+    }
+
+    pub fn fill(&self, completed: &mut CompletedProcess) {
+        completed.peak_memory_usage = self.peak_rss_bytes;
+        completed.peak_memory_virtual = self.peak_virtual_bytes;
+        completed.max_threads = self.max_threads;
+        completed.cpu_user_time_sec = self.cpu_user_time_sec;
+        completed.cpu_system_time_sec = self.cpu_system_time_sec;
+        completed.disk_read_total = self.read_bytes;
+        completed.disk_write_total = self.written_bytes;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_proc_files() {
+        let mut sample = ProcessSample::default();
+        sample.parse_stat(
+            "4242 (samtools (sort)) S 1 4242 4242 0 -1 4194560 100 0 0 0 250 50 0 0 20 0 4 0",
+        );
+        sample.parse_status(
+            "Name:\tsamtools\nVmPeak:\t  20480 kB\nVmSize:\t  10240 kB\nVmHWM:\t    1024 kB\nVmRSS:\t     512 kB\nThreads:\t4\n",
+        );
+        sample.parse_io("rchar: 10\nwchar: 20\nread_bytes: 4096\nwrite_bytes: 8192\n");
+
+        let ticks = clock_ticks_per_second();
+        assert_eq!(sample.cpu_user_time_sec, Some(250.0 / ticks));
+        assert_eq!(sample.cpu_system_time_sec, Some(50.0 / ticks));
+        assert_eq!(sample.children_cpu_user_time_sec, Some(0.0));
+        assert_eq!(sample.rss_bytes, Some(512 * 1024));
+        assert_eq!(sample.virtual_bytes, Some(10240 * 1024));
+        assert_eq!(sample.peak_rss_bytes, Some(1024 * 1024));
+        assert_eq!(sample.peak_virtual_bytes, Some(20480 * 1024));
+        assert_eq!(sample.threads, Some(4));
+        assert_eq!(sample.read_bytes, Some(4096));
+        assert_eq!(sample.written_bytes, Some(8192));
+    }
+
+    #[test]
+    fn test_usage_keeps_peaks() {
+        let mut usage = ProcessUsage::default();
+        usage.update(&ProcessSample {
+            peak_rss_bytes: Some(100),
+            threads: Some(8),
+            cpu_user_time_sec: Some(1.0),
+            ..Default::default()
+        });
+        // a zombie has no memory counters left but its cpu time is final
+        usage.update(&ProcessSample {
+            threads: Some(1),
+            cpu_user_time_sec: Some(3.5),
+            ..Default::default()
+        });
+
+        assert_eq!(usage.peak_rss_bytes, Some(100));
+        assert_eq!(usage.max_threads, Some(8));
+        assert_eq!(usage.cpu_user_time_sec, Some(3.5));
+        assert_eq!(usage.read_bytes, None);
+    }
+
+    #[test]
+    fn test_cpu_rate() {
+        let start = Instant::now();
+        let mut rate = CpuRate::default();
+        rate.update(10.0, start);
+        rate.update(10.1, start + Duration::from_millis(5));
+        assert_eq!(rate.utilization(), None);
+
+        // two cores busy over two seconds
+        rate.update(14.0, start + Duration::from_secs(2));
+        assert_eq!(rate.utilization(), Some(200.0));
+
+        rate.update(12.0, start + Duration::from_secs(3));
+        assert_eq!(rate.utilization(), Some(0.0));
+    }
+
+    #[test]
+    fn test_tree_io_keeps_exited_children() {
+        let io = |read: u64, written: u64| ProcessSample {
+            read_bytes: Some(read),
+            written_bytes: Some(written),
+            ..Default::default()
+        };
+        let mut tree_io = TreeIo::default();
+        // a wrapper running two children one after the other
+        assert_eq!(
+            tree_io.update(&[(1, None, io(10, 0)), (2, Some(1), io(100, 40))]),
+            (Some(110), Some(40))
+        );
+        // the first child was reaped by the wrapper, whose counters took its bytes over
+        assert_eq!(
+            tree_io.update(&[(1, None, io(115, 40)), (3, Some(1), io(50, 60))]),
+            (Some(165), Some(100))
+        );
+        // the second one was reparented out of the tree before it exited
+        assert_eq!(
+            tree_io.update(&[(1, None, io(115, 40)), (3, Some(999), io(80, 60))]),
+            (Some(195), Some(100))
+        );
+        assert_eq!(
+            tree_io.update(&[(1, None, io(115, 40))]),
+            (Some(195), Some(100))
+        );
+
+        // `/proc/<pid>/io` is only readable by the owner
+        assert_eq!(
+            TreeIo::default().update(&[(1, None, ProcessSample::default())]),
+            (None, None)
+        );
+    }
+
+    #[test]
+    fn test_sample_with_descendants() {
+        let wrapper = ProcessSample {
+            peak_rss_bytes: Some(300),
+            rss_bytes: Some(100),
+            threads: Some(2),
+            cpu_user_time_sec: Some(1.0),
+            // a child that already exited
+            children_cpu_user_time_sec: Some(4.0),
+            ..Default::default()
+        };
+        let worker = ProcessSample {
+            peak_rss_bytes: Some(900),
+            rss_bytes: Some(800),
+            threads: Some(16),
+            cpu_user_time_sec: Some(10.0),
+            read_bytes: Some(4096),
+            ..Default::default()
+        };
+
+        let tree = wrapper.with_descendants(&[worker]);
+        assert_eq!(tree.rss_bytes, Some(900));
+        assert_eq!(tree.peak_rss_bytes, Some(900));
+        assert_eq!(tree.threads, Some(18));
+        assert_eq!(tree.cpu_user_time_sec, Some(15.0));
+        assert_eq!(tree.cpu_system_time_sec, None);
+        assert_eq!(tree.read_bytes, Some(4096));
+
+        assert_eq!(wrapper.with_descendants(&[]).peak_rss_bytes, Some(300));
+    }
+}
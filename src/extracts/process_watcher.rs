@@ -1,24 +1,40 @@
 // src/process_watcher.rs
 use crate::config_manager::target_process::{
-    targets_list::DATA_SAMPLES_EXT, Target, TargetMatchable,
+    rules::{ProcessInfo, ProcessLookup},
+    targets_list::DATA_SAMPLES_EXT,
+    Target,
 };
 use crate::events::recorder::{EventRecorder, EventType};
+use crate::extracts::cgroup;
+use crate::extracts::containers::ContainerResolver;
+use crate::extracts::discovery::{Discovery, UntrackedProcess};
 use crate::extracts::file_watcher::FileWatcher;
+use crate::extracts::open_files::{self, FileAccesses};
+use crate::extracts::proc_connector::ProcEvent;
+use crate::extracts::process_usage::{CpuRate, ProcessSample, ProcessUsage, TreeIo, TreeMember};
+use crate::types::config::DiscoveryConfig;
 use crate::types::event::attributes::process::InputFile;
 use crate::types::event::attributes::process::ProcessProperties;
-use crate::types::event::attributes::process::{CompletedProcess, DataSetsProcessed};
+use crate::types::event::attributes::process::{
+    CompletedProcess, ContainerInfo, DataSetsProcessed, ProcessExit, UntrackedHeavyProcess,
+};
 use crate::types::event::attributes::EventAttributes;
 use anyhow::Result;
 use chrono::{DateTime, Utc};
 use itertools::Itertools;
 use serde::Deserialize;
 use serde::Serialize;
-use std::collections::HashMap;
+use std::collections::hash_map::DefaultHasher;
 use std::collections::{hash_map::Entry::Vacant, HashSet};
+use std::collections::{BTreeMap, HashMap};
+use std::hash::{Hash, Hasher};
 use std::path::Path;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use sysinfo::ProcessStatus;
-use sysinfo::{Pid, Process, System};
+use sysinfo::{Pid, Process, ProcessRefreshKind, System, UpdateKind};
+
+/// How often a tool and its descendants are read from `/proc`, processes are polled far more often
+const TOOL_SAMPLE_INTERVAL: Duration = Duration::from_secs(1);
 
 pub struct ProcessWatcher {
     targets: Vec<Target>,
@@ -26,6 +42,13 @@ pub struct ProcessWatcher {
     process_tree: HashMap<Pid, ProcessTreeNode>,
     // We wanna track unique datasamples we come across when monitoring process args
     datasamples_tracker: HashSet<String>,
+    // Whether tool metrics include the descendants of the tool's process
+    aggregate_descendants: bool,
+    containers: ContainerResolver,
+    /// Set while a run is in discovery mode
+    discovery: Option<Discovery>,
+    /// `oom_kill` counts of tools' cgroups as the tools started, for the metrics collector to take
+    oom_kill_baselines: HashMap<String, u64>,
 }
 
 enum ProcLastUpdate {
@@ -38,9 +61,37 @@ pub struct Proc {
     start_time: DateTime<Utc>,
     last_update: ProcLastUpdate,
     just_started: bool,
+    usage: ProcessUsage,
+    /// Over the tool's subtree when aggregating descendants
+    cpu: CpuRate,
+    files: FileAccesses,
+    io: TreeIo,
+    /// When the tool's `/proc` entries were last read, `None` until the first read
+    last_sample: Option<Instant>,
+    /// cgroup v2 path the tool started in
+    cgroup: Option<String>,
+    container: Option<ContainerInfo>,
 }
 
-#[derive(Serialize, Deserialize)]
+impl Proc {
+    fn started(name: String, start_time: DateTime<Utc>) -> Self {
+        Proc {
+            name,
+            start_time,
+            last_update: ProcLastUpdate::RefreshesRemaining(2),
+            just_started: true,
+            usage: ProcessUsage::default(),
+            cpu: CpuRate::default(),
+            files: FileAccesses::default(),
+            io: TreeIo::default(),
+            last_sample: None,
+            cgroup: None,
+            container: None,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
 pub struct ShortLivedProcessLog {
     pub command: String,
     pub timestamp: String,
@@ -49,10 +100,14 @@ pub struct ShortLivedProcessLog {
 
 #[derive(Clone, Debug)]
 pub struct ProcessTreeNode {
-    pub properties: ProcessProperties,
-    pub children: Vec<ProcessTreeNode>,
+    pub children: Vec<Pid>,
     pub parent_id: Option<Pid>,
     pub start_time: DateTime<Utc>,
+    /// Hash of the start time, command and binary, it changes when the pid is reused. Only tools and their
+    /// descendants have their command and binary re-read, so it changes when they exec, not other processes
+    fingerprint: u64,
+    /// Indexes of the targets the process matched, kept until its fingerprint changes
+    matched_targets: Vec<usize>,
 }
 
 fn process_status_to_string(status: &ProcessStatus) -> String {
@@ -72,6 +127,51 @@ fn process_status_to_string(status: &ProcessStatus) -> String {
     }
 }
 
+/// What target rules see of a polled process
+pub fn process_info(proc: &Process) -> ProcessInfo {
+    let exe = proc.exe().and_then(|exe| exe.to_str()).unwrap_or_default();
+    ProcessInfo::from_argv(proc.name(), proc.cmd(), exe).set_pid(
+        proc.pid().as_u32(),
+        proc.parent().map(|parent| parent.as_u32()),
+    )
+}
+
+/// Ancestors are looked up among the polled processes, nothing is read from `/proc`
+impl ProcessLookup for HashMap<Pid, Process> {
+    fn parent_pid(&self, pid: u32) -> Option<u32> {
+        Some(self.get(&Pid::from_u32(pid))?.parent()?.as_u32())
+    }
+
+    fn process(&self, pid: u32) -> Option<ProcessInfo> {
+        self.get(&Pid::from_u32(pid)).map(process_info)
+    }
+}
+
+/// The process a `merge_with_parents` target tracks for `pid`: the parent of the topmost process
+/// matched by the target above it, or that topmost process when the ancestor is forced to match
+pub fn merged_ancestor(
+    pid: Pid,
+    parent_of: impl Fn(Pid) -> Option<Pid>,
+    is_matched: impl Fn(Pid) -> bool,
+    force_ancestor_to_match: bool,
+) -> Pid {
+    let mut parent = pid;
+    let mut last_valid_parent = pid;
+
+    while let Some(parent_id) = parent_of(parent) {
+        parent = parent_id;
+        if !is_matched(parent) {
+            if !force_ancestor_to_match {
+                last_valid_parent = parent;
+            }
+            break;
+        }
+        last_valid_parent = parent;
+    }
+
+    last_valid_parent
+}
+
 impl ProcessWatcher {
     pub fn new(targets: Vec<Target>) -> Self {
         ProcessWatcher {
@@ -79,6 +179,115 @@ impl ProcessWatcher {
             seen: HashMap::new(),
             process_tree: HashMap::new(),
             datasamples_tracker: HashSet::new(),
+            aggregate_descendants: true,
+            containers: ContainerResolver::new(),
+            discovery: None,
+            oom_kill_baselines: HashMap::new(),
+        }
+    }
+
+    /// Starts measuring the processes no target matches, those started from `since` on
+    pub fn start_discovery(&mut self, config: &DiscoveryConfig, since: DateTime<Utc>) {
+        self.discovery = config
+            .enabled
+            .then(|| Discovery::new(config.clone(), since));
+    }
+
+    /// The heavy untracked processes seen since discovery started
+    pub fn finish_discovery(&mut self) -> Vec<UntrackedHeavyProcess> {
+        self.discovery
+            .take()
+            .map(|discovery| discovery.heavy_processes())
+            .unwrap_or_default()
+    }
+
+    /// Samples processes outside of every tool's subtree, a tool's descendants are part of its metrics
+    fn sample_untracked(&mut self, system: &System) {
+        let Some(discovery) = self.discovery.as_mut() else {
+            return;
+        };
+        if !discovery.due() {
+            return;
+        }
+        let process_tree = &self.process_tree;
+        let parent = |pid: &Pid| process_tree.get(pid).and_then(|node| node.parent_id);
+
+        // processes above a tool are the wrappers running it
+        let mut wrappers = HashSet::new();
+        for pid in self.seen.keys() {
+            let mut ancestor = parent(pid);
+            while let Some(pid) = ancestor {
+                if !wrappers.insert(pid) {
+                    break;
+                }
+                ancestor = parent(&pid);
+            }
+        }
+        let in_tool_subtree = |pid: &Pid| {
+            std::iter::successors(Some(*pid), parent)
+                .take(process_tree.len())
+                .any(|pid| {
+                    process_tree
+                        .get(&pid)
+                        .is_some_and(|node| !node.matched_targets.is_empty())
+                })
+        };
+
+        let own_pid = Pid::from_u32(std::process::id());
+        let now = Utc::now();
+        for (pid, node) in process_tree {
+            if node.start_time < discovery.since() || *pid == own_pid || in_tool_subtree(pid) {
+                continue;
+            }
+            let Some(proc) = system.process(*pid) else {
+                continue;
+            };
+            let binary_path = proc.exe().and_then(|exe| exe.to_str()).unwrap_or_default();
+            // kernel threads have no binary
+            if proc.thread_kind().is_some() || binary_path.is_empty() {
+                continue;
+            }
+            let Some(sample) = ProcessSample::read(pid.as_u32()) else {
+                continue;
+            };
+            let process = UntrackedProcess {
+                pid: pid.as_u32(),
+                start_time: node.start_time,
+                name: proc.name(),
+                cmd: proc.cmd(),
+                binary_path,
+                is_wrapper: wrappers.contains(pid),
+            };
+            discovery.sample(process, &sample, now);
+        }
+        discovery.retire_unseen(now);
+    }
+
+    /// Reports the tracked process' own metrics only when disabled
+    pub fn set_aggregate_descendants(&mut self, aggregate_descendants: bool) {
+        self.aggregate_descendants = aggregate_descendants;
+    }
+
+    /// What's read for tools and their descendants, the rest of the host only needs the process list.
+    /// Their command and binary are re-read, so an exec is noticed by the next poll
+    fn tool_refresh_kind() -> ProcessRefreshKind {
+        ProcessRefreshKind::new()
+            .with_memory()
+            .with_disk_usage()
+            .with_cmd(UpdateKind::Always)
+            .with_exe(UpdateKind::Always)
+    }
+
+    fn refresh_tool(&self, system: &mut System, pid: Pid) {
+        for pid in std::iter::once(pid).chain(self.tool_descendants(pid)) {
+            system.refresh_process_specifics(pid, Self::tool_refresh_kind());
+        }
+    }
+
+    /// Reads the memory and disk usage of the tools and their descendants
+    pub fn refresh_tools(&self, system: &mut System) {
+        for pid in self.seen.keys() {
+            self.refresh_tool(system, *pid);
         }
     }
 
@@ -88,48 +297,33 @@ impl ProcessWatcher {
         event_logger: &mut EventRecorder,
         file_watcher: &FileWatcher,
     ) -> Result<()> {
-        for (pid, proc) in system.processes().iter() {
-            if !self.seen.contains_key(pid) {
-                let target = self.targets.iter().find(|target| {
-                    target.matches(
-                        proc.name(),
-                        &proc.cmd().join(" "),
-                        proc.exe()
-                            .unwrap_or_else(|| Path::new(""))
-                            .to_str()
-                            .unwrap(),
-                    )
-                });
-                if let Some(target) = target {
-                    println!(
-                        "about to insert pid {} and name {} with command {:?}",
-                        pid,
-                        proc.name(),
-                        proc.cmd()
-                    );
+        self.update_process_tree(system.processes());
 
-                    self.add_new_process(
-                        *pid,
-                        proc,
-                        system,
-                        event_logger,
-                        Some(&target.clone()),
-                        file_watcher,
-                    )?;
-                }
-            }
+        let new_tools: Vec<(Pid, usize)> = self
+            .process_tree
+            .iter()
+            .filter(|(pid, _)| !self.seen.contains_key(pid))
+            .filter_map(|(pid, node)| Some((*pid, *node.matched_targets.first()?)))
+            .collect();
+        for (pid, target) in new_tools {
+            // only the process list is refreshed for processes that aren't tools yet
+            self.refresh_tool(system, pid);
+            let Some(proc) = system.process(pid) else {
+                continue;
+            };
+            println!(
+                "about to insert pid {} and name {} with command {:?}",
+                pid,
+                proc.name(),
+                proc.cmd()
+            );
+
+            let target = self.targets[target].clone();
+            self.add_new_process(pid, proc, system, event_logger, Some(&target), file_watcher)?;
         }
 
-        self.parse_process_tree(
-            system,
-            self.targets
-                .iter()
-                .filter(|target| target.should_be_merged_with_parents())
-                .cloned()
-                .collect(),
-            event_logger,
-            file_watcher,
-        )?;
+        self.parse_process_tree(system, event_logger, file_watcher)?;
+        self.sample_untracked(system);
 
         Ok(())
     }
@@ -140,6 +334,41 @@ impl ProcessWatcher {
         event_logger: &mut EventRecorder,
         process_metrics_send_interval: Duration,
     ) -> Result<()> {
+        // sampled as soon as a tool is seen, so short tools still get their summary in `CompletedProcess`,
+        // then every `TOOL_SAMPLE_INTERVAL`. The proc connector's exit event carries the final usage
+        let now = Instant::now();
+        let samples: Vec<(Pid, Vec<TreeMember>, Vec<open_files::OpenFile>)> = self
+            .seen
+            .iter()
+            .filter(|(_, proc)| {
+                proc.last_sample.map_or(true, |last_sample| {
+                    now - last_sample >= TOOL_SAMPLE_INTERVAL
+                })
+            })
+            .map(|(pid, _)| (*pid, self.sample_tool(*pid), self.sample_open_files(*pid)))
+            .collect();
+        for (pid, tree, files) in samples {
+            if let Some(proc) = self.seen.get_mut(&pid) {
+                proc.last_sample = Some(now);
+                if let Some(((_, _, root), descendants)) = tree.split_first() {
+                    let mut sample = if self.aggregate_descendants {
+                        let descendants: Vec<ProcessSample> =
+                            descendants.iter().map(|(_, _, sample)| *sample).collect();
+                        root.with_descendants(&descendants)
+                    } else {
+                        *root
+                    };
+                    // the live tree's sum leaves out descendants that exited
+                    (sample.read_bytes, sample.written_bytes) = proc.io.update(&tree);
+                    proc.usage.update(&sample);
+                    if let Some(cpu_time) = sample.cpu_time_sec() {
+                        proc.cpu.update(cpu_time, now);
+                    }
+                }
+                proc.files.update(&files);
+            }
+        }
+
         for (pid, proc) in system.processes().iter() {
             if let Some(p) = self.seen.get(pid) {
                 if !p.just_started {
@@ -148,7 +377,7 @@ impl ProcessWatcher {
                             self.seen.get_mut(pid).unwrap().last_update =
                                 ProcLastUpdate::RefreshesRemaining(refresh_count - 1);
                         } else {
-                            self.add_process_metrics(proc, event_logger, None)?;
+                            self.add_process_metrics(proc, system, event_logger)?;
                             self.seen.get_mut(pid).unwrap().last_update =
                                 ProcLastUpdate::Some(Utc::now());
                         }
@@ -156,7 +385,7 @@ impl ProcessWatcher {
                     }
                     if let ProcLastUpdate::Some(last_update) = p.last_update {
                         if last_update + process_metrics_send_interval < Utc::now() {
-                            self.add_process_metrics(proc, event_logger, None)?;
+                            self.add_process_metrics(proc, system, event_logger)?;
                             self.seen.get_mut(pid).unwrap().last_update =
                                 ProcLastUpdate::Some(Utc::now());
                         }
@@ -182,7 +411,8 @@ impl ProcessWatcher {
         let mut to_remove = vec![];
         for (pid, proc) in self.seen.iter() {
             if !system.processes().contains_key(pid) {
-                self.log_completed_process(pid, proc, event_logger)?;
+                // the exit status of processes that aren't ours can't be polled
+                self.log_completed_process(pid, proc, Utc::now(), None, event_logger)?;
                 to_remove.push(*pid);
             }
         }
@@ -194,32 +424,182 @@ impl ProcessWatcher {
         Ok(())
     }
 
-    pub fn build_process_trees(&mut self, system_processes: &HashMap<Pid, Process>) {
-        let mut nodes: HashMap<Pid, ProcessTreeNode> = HashMap::new();
+    /// Hash of what targets are matched against
+    /// sysinfo keeps the name a process started with, it's left out
+    fn fingerprint(proc: &Process) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        proc.start_time().hash(&mut hasher);
+        proc.cmd().hash(&mut hasher);
+        proc.exe().hash(&mut hasher);
+        hasher.finish()
+    }
+
+    fn match_targets(
+        &self,
+        proc: &Process,
+        system_processes: &HashMap<Pid, Process>,
+    ) -> Vec<usize> {
+        let info = process_info(proc);
+        self.targets
+            .iter()
+            .enumerate()
+            .filter(|(_, target)| target.matches_process(&info, system_processes))
+            .map(|(index, _)| index)
+            .collect()
+    }
+
+    fn unlink_child(&mut self, parent: Option<Pid>, child: Pid) {
+        if let Some(parent) = parent.and_then(|parent| self.process_tree.get_mut(&parent)) {
+            parent.children.retain(|pid| *pid != child);
+        }
+    }
+
+    /// Applies what changed since the last poll to the process tree.
+    /// Targets are only matched against processes that are new, or tools and their descendants that exec'd.
+    /// The rest keep their matches, their exec's are reported by the proc connector
+    pub fn update_process_tree(&mut self, system_processes: &HashMap<Pid, Process>) {
+        let exited: Vec<Pid> = self
+            .process_tree
+            .keys()
+            .filter(|pid| !system_processes.contains_key(pid))
+            .copied()
+            .collect();
+        for pid in exited {
+            if let Some(node) = self.process_tree.remove(&pid) {
+                self.unlink_child(node.parent_id, pid);
+            }
+        }
 
+        // linked once every new process is in, a parent can come after its children
+        let mut to_link = vec![];
         for (pid, proc) in system_processes {
-            let properties = Self::gather_process_data(pid, proc, None);
-            let node = ProcessTreeNode {
-                properties,
-                children: vec![],
-                parent_id: proc.parent(),
-                start_time: DateTime::from_timestamp(proc.start_time() as i64, 0).unwrap(),
+            let fingerprint = Self::fingerprint(proc);
+            let parent_id = proc.parent();
+
+            let previous_parent = match self.process_tree.get_mut(pid) {
+                Some(node) if node.fingerprint == fingerprint => {
+                    if node.parent_id == parent_id {
+                        continue;
+                    }
+                    // orphans are adopted by init or a subreaper
+                    std::mem::replace(&mut node.parent_id, parent_id)
+                }
+                _ => {
+                    let node = ProcessTreeNode {
+                        children: vec![],
+                        parent_id,
+                        start_time: DateTime::from_timestamp(proc.start_time() as i64, 0).unwrap(),
+                        fingerprint,
+                        matched_targets: self.match_targets(proc, system_processes),
+                    };
+                    match self.process_tree.insert(*pid, node) {
+                        // exec'd or the pid was reused, live children are still its children
+                        Some(previous) => {
+                            self.process_tree.get_mut(pid).unwrap().children = previous.children;
+                            previous.parent_id
+                        }
+                        None => None,
+                    }
+                }
             };
+            self.unlink_child(previous_parent, *pid);
+            to_link.push((*pid, parent_id));
+        }
 
-            nodes.insert(*pid, node);
+        for (pid, parent_id) in to_link {
+            if let Some(parent) = parent_id.and_then(|parent| self.process_tree.get_mut(&parent)) {
+                if !parent.children.contains(&pid) {
+                    parent.children.push(pid);
+                }
+            }
         }
+    }
 
-        for (pid, proc) in system_processes {
-            let parent = proc.parent();
-            if let Some(parent) = parent {
-                let node = nodes.get(pid).unwrap().clone();
-                if let Some(parent_node) = nodes.get_mut(&parent) {
-                    parent_node.children.push(node.clone());
+    /// The tool a process belongs to, itself or, when aggregating descendants, its closest tool ancestor
+    fn tool_of(&self, pid: Pid) -> Option<Pid> {
+        let mut current = pid;
+        // bounded, a reused pid can make the parent links loop
+        for _ in 0..=self.process_tree.len() {
+            if self.seen.contains_key(&current) {
+                return Some(current);
+            }
+            if !self.aggregate_descendants {
+                return None;
+            }
+            current = self.process_tree.get(&current)?.parent_id?;
+        }
+        None
+    }
+
+    /// Descendants whose metrics count towards the tool `pid`.
+    /// Descendants that are tools themselves are skipped with their subtree, they report their own metrics
+    fn tool_descendants(&self, pid: Pid) -> Vec<Pid> {
+        if !self.aggregate_descendants {
+            return vec![];
+        }
+
+        let mut descendants = vec![];
+        let mut visited = HashSet::from([pid]);
+        let mut to_visit = vec![pid];
+        while let Some(parent) = to_visit.pop() {
+            let children = self.process_tree.get(&parent).map(|node| &node.children);
+            for child in children.into_iter().flatten() {
+                if self.seen.contains_key(child) || !visited.insert(*child) {
+                    continue;
                 }
+                descendants.push(*child);
+                to_visit.push(*child);
             }
         }
+        descendants
+    }
+
+    /// Reads the usage counters of a tool, then of its descendants when aggregating.
+    /// Empty once the tool is gone
+    fn sample_tool(&self, pid: Pid) -> Vec<TreeMember> {
+        let Some(sample) = ProcessSample::read(pid.as_u32()) else {
+            return vec![];
+        };
+        let descendants = self
+            .tool_descendants(pid)
+            .into_iter()
+            .filter_map(|descendant| {
+                let parent = self
+                    .process_tree
+                    .get(&descendant)
+                    .and_then(|node| node.parent_id)
+                    .map(|parent| parent.as_u32());
+                let sample = ProcessSample::read(descendant.as_u32())?;
+                Some((descendant.as_u32(), parent, sample))
+            });
+        std::iter::once((pid.as_u32(), None, sample))
+            .chain(descendants)
+            .collect()
+    }
 
-        self.process_tree = nodes
+    /// Files open in the tool and the descendants it's aggregated with
+    fn sample_open_files(&self, pid: Pid) -> Vec<open_files::OpenFile> {
+        std::iter::once(pid)
+            .chain(self.tool_descendants(pid))
+            .flat_map(|pid| open_files::read_open_files(pid.as_u32()))
+            .collect()
+    }
+
+    /// Adds the memory and disk usage of `descendants` to a tool's properties,
+    /// the tool's cpu utilization is measured over its subtree already
+    pub fn add_descendant_metrics<'a>(
+        properties: &mut ProcessProperties,
+        descendants: impl IntoIterator<Item = &'a Process>,
+    ) {
+        for proc in descendants {
+            let disk_usage = proc.disk_usage();
+            properties.process_memory_usage += proc.memory();
+            properties.process_memory_virtual += proc.virtual_memory();
+            properties.process_disk_usage_read_total += disk_usage.total_read_bytes;
+            properties.process_disk_usage_write_total += disk_usage.total_written_bytes;
+            properties.process_disk_usage_read_last_interval += disk_usage.read_bytes;
+            properties.process_disk_usage_write_last_interval += disk_usage.written_bytes;
+        }
     }
 
     pub fn get_parent_processes(
@@ -231,24 +611,12 @@ impl ProcessWatcher {
         let mut result = vec![];
 
         for process in valid_processes {
-            let mut parent = *process;
-            let mut last_valid_parent = *process;
-
-            while let Some(parent_node) = map.get(&parent) {
-                if let Some(parent_id) = parent_node.parent_id {
-                    parent = parent_id;
-                    if !valid_processes.contains(&parent) {
-                        if !force_ancestor_to_match {
-                            last_valid_parent = parent;
-                        }
-                        break;
-                    }
-                    last_valid_parent = parent;
-                } else {
-                    // last_valid_parent = parent;
-                    break;
-                }
-            }
+            let last_valid_parent = merged_ancestor(
+                *process,
+                |pid| map.get(&pid).and_then(|node| node.parent_id),
+                |pid| valid_processes.contains(&pid),
+                force_ancestor_to_match,
+            );
 
             if !result.contains(&last_valid_parent) {
                 result.push(last_valid_parent);
@@ -258,53 +626,53 @@ impl ProcessWatcher {
         result
     }
 
+    /// Starts tracking the ancestors of processes matching `merge_with_parents` targets
     pub fn parse_process_tree(
         &mut self,
-        system: &System,
-        targets: Vec<Target>,
+        system: &mut System,
         event_logger: &mut EventRecorder,
         file_watcher: &FileWatcher,
     ) -> Result<()> {
-        self.build_process_trees(system.processes());
-        let nodes: &HashMap<Pid, ProcessTreeNode> = &self.process_tree;
+        // ordered by target, the first target to claim a process names it
+        let mut matching: BTreeMap<usize, Vec<Pid>> = BTreeMap::new();
+        for (pid, node) in &self.process_tree {
+            for target in &node.matched_targets {
+                matching.entry(*target).or_default().push(*pid);
+            }
+        }
 
         let mut processes_to_gather = vec![];
 
-        for target in &targets {
-            let mut valid_processes = vec![];
-
-            for (pid, node) in nodes {
-                if target.matches(
-                    &node.properties.tool_name,
-                    &node.properties.tool_cmd,
-                    &node.properties.tool_binary_path,
-                ) {
-                    valid_processes.push(*pid);
-                }
+        for (index, valid_processes) in matching {
+            let target = &self.targets[index];
+            if !target.should_be_merged_with_parents() {
+                continue;
             }
 
             let parents = self.get_parent_processes(
-                nodes,
+                &self.process_tree,
                 &valid_processes,
                 target.should_force_ancestor_to_match(),
             );
 
             for parent in parents {
-                if !processes_to_gather.contains(&(parent, target)) {
-                    processes_to_gather.push((parent, target));
+                if !processes_to_gather.contains(&(parent, index)) {
+                    processes_to_gather.push((parent, index));
                 }
             }
         }
 
-        for (pid, target) in processes_to_gather {
+        for (pid, index) in processes_to_gather {
             if !self.seen.contains_key(&pid) {
+                self.refresh_tool(system, pid);
                 let process = system.process(pid);
                 if process.is_none() {
                     eprintln!("[{}] Process({}) wasn't found", Utc::now(), pid);
                     continue;
                 }
                 let proc = process.unwrap();
-                self.add_new_process(pid, proc, system, event_logger, Some(target), file_watcher)?;
+                let target = self.targets[index].clone();
+                self.add_new_process(pid, proc, system, event_logger, Some(&target), file_watcher)?;
             }
         }
         Ok(())
@@ -340,6 +708,8 @@ impl ProcessWatcher {
             process_memory_virtual: proc.virtual_memory(),
             process_status: process_status_to_string(&proc.status()),
             input_files: None,
+            cgroup: None,
+            container: None,
         }
     }
 
@@ -363,12 +733,7 @@ impl ProcessWatcher {
             .seen
             .entry(short_lived_process.properties.tool_pid.parse().unwrap())
         {
-            v.insert(Proc {
-                name: short_lived_process.command,
-                start_time: Utc::now(),
-                last_update: ProcLastUpdate::RefreshesRemaining(2),
-                just_started: true,
-            });
+            v.insert(Proc::started(short_lived_process.command, Utc::now()));
         }
 
         Ok(())
@@ -403,6 +768,8 @@ impl ProcessWatcher {
                     process_disk_usage_write_total: 0,
                     process_status: "Unknown".to_string(),
                     input_files: None,
+                    cgroup: None,
+                    container: None,
                 },
             }
         }
@@ -417,23 +784,13 @@ impl ProcessWatcher {
         target: Option<&Target>,
         file_watcher: &FileWatcher,
     ) -> Result<()> {
-        self.seen.insert(
-            pid,
-            Proc {
-                name: proc.name().to_string(),
-                start_time: Utc::now(),
-                last_update: ProcLastUpdate::RefreshesRemaining(2),
-                just_started: true,
-            },
-        );
-
         let Some(p) = system.process(pid) else {
+            self.seen
+                .insert(pid, Proc::started(proc.name().to_string(), Utc::now()));
             eprintln!("[{}] Process({}) wasn't found", Utc::now(), proc.name());
             return Ok(());
         };
 
-        let start_time = Utc::now();
-
         let display_name = if let Some(target) = target {
             let name = target
                 .get_display_name_object()
@@ -444,9 +801,56 @@ impl ProcessWatcher {
             proc.name().to_owned()
         };
 
-        let mut properties = Self::gather_process_data(&pid, p, Some(display_name.clone()));
+        let mut properties = Self::gather_process_data(&pid, p, Some(display_name));
+        let cgroup_path = cgroup::cgroup_of(pid.as_u32());
+        properties.cgroup = cgroup_path.as_deref().and_then(cgroup::read_cgroup);
+        properties.container = self
+            .containers
+            .resolve(pid.as_u32(), cgroup_path.as_deref());
+        let descendants = self.tool_descendants(pid);
+        Self::add_descendant_metrics(
+            &mut properties,
+            descendants.iter().filter_map(|pid| system.process(*pid)),
+        );
+
+        self.record_tool_execution(
+            pid,
+            proc.name().to_string(),
+            properties,
+            p.cmd(),
+            cgroup_path,
+            Utc::now(),
+            event_logger,
+            file_watcher,
+        );
+
+        Ok(())
+    }
 
-        let cmd_arguments = p.cmd();
+    /// Starts tracking a matched tool and records its `ToolExecution` event
+    #[allow(clippy::too_many_arguments)]
+    fn record_tool_execution(
+        &mut self,
+        pid: Pid,
+        name: String,
+        mut properties: ProcessProperties,
+        cmd_arguments: &[String],
+        cgroup: Option<String>,
+        start_time: DateTime<Utc>,
+        event_logger: &mut EventRecorder,
+        file_watcher: &FileWatcher,
+    ) {
+        if let Some(cgroup) = &cgroup {
+            // a tool killed before the collector's first read of its cgroup counts from here.
+            // No count to read is a cgroup new with the tool, none of its kills were seen yet
+            self.oom_kill_baselines
+                .entry(cgroup.clone())
+                .or_insert_with(|| cgroup::read_oom_kills(cgroup).unwrap_or_default());
+        }
+        let mut proc = Proc::started(name, start_time);
+        proc.cgroup = cgroup;
+        proc.container = properties.container.clone();
+        self.seen.insert(pid, proc);
 
         let mut input_files = vec![];
 
@@ -482,45 +886,155 @@ impl ProcessWatcher {
 
         properties.input_files = Some(input_files);
 
+        let display_name = properties.tool_name.clone();
         event_logger.record_event(
             EventType::ToolExecution,
             format!("[{}] Tool process: {}", start_time, &display_name),
             Some(EventAttributes::Process(properties)),
-            None,
+            Some(start_time),
         );
 
         self.log_datasets_in_process(event_logger, cmd_arguments);
+    }
+
+    /// Records the tools that started and exited since the last poll, as reported by the proc connector.
+    /// Tools that are gone by the time `sysinfo` refreshes only show up here, their ancestors are polled ones
+    pub fn handle_proc_events(
+        &mut self,
+        events: Vec<ProcEvent>,
+        system_processes: &HashMap<Pid, Process>,
+        event_logger: &mut EventRecorder,
+        file_watcher: &FileWatcher,
+    ) -> Result<()> {
+        for event in events {
+            match event {
+                ProcEvent::Exec(snapshot) => {
+                    let pid = Pid::from_u32(snapshot.pid);
+                    if self.seen.contains_key(&pid) {
+                        continue;
+                    }
+
+                    let info = ProcessInfo::from_argv(&snapshot.name, &snapshot.cmd, &snapshot.exe)
+                        .set_pid(snapshot.pid, Some(snapshot.parent_pid));
+                    let Some(target) = self
+                        .targets
+                        .iter()
+                        .find(|target| target.matches_process(&info, system_processes))
+                    else {
+                        continue;
+                    };
+
+                    let display_name = target
+                        .get_display_name_object()
+                        .get_display_name(&snapshot.name, &snapshot.cmd);
+                    let properties = ProcessProperties {
+                        tool_name: display_name,
+                        tool_pid: snapshot.pid.to_string(),
+                        tool_parent_pid: snapshot.parent_pid.to_string(),
+                        tool_binary_path: snapshot.exe.clone(),
+                        tool_cmd: info.command.clone(),
+                        start_timestamp: snapshot.timestamp.to_rfc3339(),
+                        process_cpu_utilization: 0.0,
+                        process_run_time: 0,
+                        process_disk_usage_read_total: 0,
+                        process_disk_usage_write_total: 0,
+                        process_disk_usage_read_last_interval: 0,
+                        process_disk_usage_write_last_interval: 0,
+                        process_memory_usage: 0,
+                        process_memory_virtual: 0,
+                        process_status: "Run".to_string(),
+                        input_files: None,
+                        cgroup: snapshot.cgroup.as_deref().and_then(cgroup::read_cgroup),
+                        container: self
+                            .containers
+                            .resolve(snapshot.pid, snapshot.cgroup.as_deref()),
+                    };
+
+                    self.record_tool_execution(
+                        pid,
+                        snapshot.name,
+                        properties,
+                        &snapshot.cmd,
+                        snapshot.cgroup,
+                        snapshot.timestamp,
+                        event_logger,
+                        file_watcher,
+                    );
+                }
+                ProcEvent::Exit {
+                    pid,
+                    exit,
+                    usage,
+                    timestamp,
+                } => {
+                    let pid = Pid::from_u32(pid);
+                    if let Some(mut proc) = self.seen.remove(&pid) {
+                        if let Some(usage) = usage {
+                            // the descendants' share is in the children cpu times once reaped
+                            let usage = if self.aggregate_descendants {
+                                usage.with_descendants(&[])
+                            } else {
+                                usage
+                            };
+                            proc.usage.update(&usage);
+                        }
+                        self.log_completed_process(
+                            &pid,
+                            &proc,
+                            timestamp,
+                            Some(exit),
+                            event_logger,
+                        )?;
+                    }
+                }
+                ProcEvent::Open { pid, mut file } => {
+                    let Some(tool) = self.tool_of(Pid::from_u32(pid)) else {
+                        continue;
+                    };
+                    let Some(size) = open_files::data_file_size(&file.path) else {
+                        continue;
+                    };
+                    file.size = Some(size);
+                    if let Some(proc) = self.seen.get_mut(&tool) {
+                        proc.files.update(&[file]);
+                    }
+                }
+            }
+        }
 
         Ok(())
     }
 
     fn add_process_metrics(
-        &mut self,
+        &self,
         proc: &Process,
+        system: &System,
         event_logger: &mut EventRecorder,
-        target: Option<&Target>,
     ) -> Result<()> {
         let pid = proc.pid();
         let start_time = Utc::now();
+        let display_name = proc.name().to_owned();
 
-        let display_name = if let Some(target) = target {
-            target
-                .get_display_name_object()
-                .get_display_name(proc.name(), proc.cmd())
-        } else {
-            proc.name().to_owned()
-        };
-
-        let properties = EventAttributes::Process(Self::gather_process_data(
-            &pid,
-            proc,
-            Some(display_name.clone()),
-        ));
+        let mut properties = Self::gather_process_data(&pid, proc, Some(display_name.clone()));
+        Self::add_descendant_metrics(
+            &mut properties,
+            self.tool_descendants(pid)
+                .iter()
+                .filter_map(|pid| system.process(*pid)),
+        );
+        if let Some(tool) = self.seen.get(&pid) {
+            properties.cgroup = tool.cgroup.as_deref().and_then(cgroup::read_cgroup);
+            properties.container = tool
+                .container
+                .clone()
+                .map(|container| self.containers.latest(container));
+            properties.process_cpu_utilization = tool.cpu.utilization().unwrap_or_default();
+        }
 
         event_logger.record_event(
             EventType::ToolMetricEvent,
             format!("[{}] Tool metric event: {}", start_time, &display_name),
-            Some(properties),
+            Some(EventAttributes::Process(properties)),
             None,
         );
 
@@ -600,22 +1114,34 @@ impl ProcessWatcher {
         &self,
         pid: &Pid,
         proc: &Proc,
+        end_time: DateTime<Utc>,
+        exit: Option<ProcessExit>,
         event_logger: &mut EventRecorder,
     ) -> Result<()> {
         // NOTE: to avoid handling casting from u128 to u64, moving to as_secs from as_millis
-        let duration_sec = (Utc::now() - proc.start_time).to_std()?.as_secs();
+        let duration_sec = (end_time - proc.start_time)
+            .to_std()
+            .unwrap_or_default()
+            .as_secs();
 
-        let properties = CompletedProcess {
+        let mut properties = CompletedProcess {
             tool_name: proc.name.clone(),
             tool_pid: pid.to_string(),
             duration_sec,
+            ..Default::default()
         };
+        if let Some(exit) = exit {
+            properties.set_exit(exit);
+        }
+        proc.usage.fill(&mut properties);
+        proc.files.fill(&mut properties);
 
+        let outcome = exit.unwrap_or_default().describe();
         event_logger.record_event(
             EventType::FinishedToolExecution,
-            format!("[{}] {} exited", Utc::now(), &proc.name),
+            format!("[{}] {} {}", end_time, &proc.name, outcome),
             Some(EventAttributes::CompletedProcess(properties)),
-            None,
+            Some(end_time),
         );
 
         Ok(())
@@ -642,13 +1168,35 @@ impl ProcessWatcher {
         );
     }
 
-    pub fn reload_targets(&mut self, targets: Vec<Target>) {
+    /// Re-matches the cached process tree against the new targets.
+    /// Tools already tracked keep being tracked until they exit, so their executions aren't cut short or recorded twice
+    pub fn reload_targets(
+        &mut self,
+        targets: Vec<Target>,
+        system_processes: &HashMap<Pid, Process>,
+    ) {
         if targets == self.targets {
             return;
         }
 
         self.targets = targets;
-        self.seen.clear();
+        // cached matches point into the old targets
+        let matches: Vec<(Pid, Vec<usize>)> = self
+            .process_tree
+            .keys()
+            .map(|pid| {
+                let matched = system_processes
+                    .get(pid)
+                    .map(|proc| self.match_targets(proc, system_processes))
+                    .unwrap_or_default();
+                (*pid, matched)
+            })
+            .collect();
+        for (pid, matched) in matches {
+            if let Some(node) = self.process_tree.get_mut(&pid) {
+                node.matched_targets = matched;
+            }
+        }
     }
 
     pub fn is_empty(&self) -> bool {
@@ -667,11 +1215,44 @@ impl ProcessWatcher {
     pub fn preview_targets_count(&self) -> usize {
         self.seen.len()
     }
+
+    pub fn tool_pids(&self) -> impl Iterator<Item = u32> + '_ {
+        self.seen.keys().map(|pid| pid.as_u32())
+    }
+
+    /// `oom_kill` counts of the cgroups tools started in since the last call
+    pub fn take_oom_kill_baselines(&mut self) -> HashMap<String, u64> {
+        std::mem::take(&mut self.oom_kill_baselines)
+    }
+
+    /// cgroups the running tools were started in
+    pub fn tool_cgroups(&self) -> HashSet<String> {
+        self.seen
+            .values()
+            .filter_map(|proc| proc.cgroup.clone())
+            .collect()
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::config_manager::target_process::target_matching::TargetMatch;
+    use crate::extracts::open_files::{AccessMode, OpenFile};
+    use crate::extracts::proc_connector::ExecSnapshot;
+    use crate::types::event::attributes::process::FileAccess;
+    use chrono::TimeDelta;
+    use std::io::Write;
+
+    fn tree_node(parent: usize) -> ProcessTreeNode {
+        ProcessTreeNode {
+            children: vec![],
+            parent_id: Some(parent.into()),
+            start_time: Utc::now(),
+            fingerprint: 0,
+            matched_targets: vec![],
+        }
+    }
 
     #[test]
     fn test_get_parent_processes() {
@@ -690,33 +1271,7 @@ mod tests {
         let mut nodes: HashMap<Pid, ProcessTreeNode> = HashMap::new();
 
         for (parent, child) in dataset {
-            let properties = ProcessProperties {
-                tool_name: "test".to_string(),
-                tool_pid: child.to_string(),
-                tool_parent_pid: parent.to_string(),
-                tool_binary_path: "test".to_string(),
-                tool_cmd: "test".to_string(),
-                start_timestamp: "test".to_string(),
-                process_cpu_utilization: 0.0,
-                process_memory_usage: 0,
-                process_memory_virtual: 0,
-                process_run_time: 0,
-                process_disk_usage_read_last_interval: 0,
-                process_disk_usage_write_last_interval: 0,
-                process_disk_usage_read_total: 0,
-                process_disk_usage_write_total: 0,
-                process_status: "test".to_string(),
-                input_files: None,
-            };
-
-            let node = ProcessTreeNode {
-                properties,
-                children: vec![],
-                parent_id: Some(parent.into()),
-                start_time: Utc::now(),
-            };
-
-            nodes.insert(child.into(), node);
+            nodes.insert(child.into(), tree_node(parent));
         }
 
         let watcher = ProcessWatcher::new(vec![]);
@@ -742,8 +1297,123 @@ mod tests {
         let mut process_watcher = ProcessWatcher::new(vec![]);
         let system = System::new_all();
 
-        process_watcher.build_process_trees(system.processes());
+        process_watcher.update_process_tree(system.processes());
+
+        let own_pid = Pid::from_u32(std::process::id());
+        let own = &process_watcher.process_tree[&own_pid];
+        let parent = own.parent_id.unwrap();
+        assert!(process_watcher.process_tree[&parent]
+            .children
+            .contains(&own_pid));
+
+        Ok(())
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_tool_exec_changes_the_fingerprint() -> Result<()> {
+        let mut child = std::process::Command::new("sh")
+            .args(["-c", "read line; exec sleep 30"])
+            .stdin(std::process::Stdio::piped())
+            .spawn()?;
+        let pid = Pid::from_u32(child.id());
+        let mut system = System::new();
+        system.refresh_process_specifics(pid, ProcessWatcher::tool_refresh_kind());
+        let before = ProcessWatcher::fingerprint(system.process(pid).unwrap());
+
+        child.stdin.take().unwrap().write_all(b"\n")?;
+        for _ in 0..50 {
+            std::thread::sleep(Duration::from_millis(20));
+            system.refresh_process_specifics(pid, ProcessWatcher::tool_refresh_kind());
+            if system.process(pid).unwrap().cmd() != ["sh", "-c", "read line; exec sleep 30"] {
+                break;
+            }
+        }
+        let process = system.process(pid).unwrap();
+        assert_eq!(process.cmd(), ["sleep", "30"]);
+        assert_ne!(ProcessWatcher::fingerprint(process), before);
 
+        child.kill()?;
+        child.wait()?;
+        Ok(())
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_process_tree_is_updated_incrementally() -> Result<()> {
+        let mut process_watcher = ProcessWatcher::new(vec![Target::new(TargetMatch::ProcessName(
+            "sleep".to_string(),
+        ))]);
+        let mut system = System::new_all();
+        process_watcher.update_process_tree(system.processes());
+        let own_pid = Pid::from_u32(std::process::id());
+
+        let mut child = std::process::Command::new("sleep").arg("30").spawn()?;
+        let child_pid = Pid::from_u32(child.id());
+        system.refresh_processes();
+        process_watcher.update_process_tree(system.processes());
+
+        assert!(process_watcher.process_tree[&own_pid]
+            .children
+            .contains(&child_pid));
+        let node = &process_watcher.process_tree[&child_pid];
+        assert_eq!(node.parent_id, Some(own_pid));
+        assert_eq!(node.matched_targets, vec![0]);
+
+        // unchanged processes keep their cached matches
+        process_watcher
+            .process_tree
+            .get_mut(&child_pid)
+            .unwrap()
+            .matched_targets
+            .clear();
+        system.refresh_processes();
+        process_watcher.update_process_tree(system.processes());
+        assert!(process_watcher.process_tree[&child_pid]
+            .matched_targets
+            .is_empty());
+
+        child.kill()?;
+        child.wait()?;
+        system.refresh_processes();
+        process_watcher.update_process_tree(system.processes());
+        assert!(!process_watcher.process_tree.contains_key(&child_pid));
+        assert!(!process_watcher.process_tree[&own_pid]
+            .children
+            .contains(&child_pid));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_reload_targets_keeps_tracked_tools() -> Result<()> {
+        let sleep = Target::new(TargetMatch::ProcessName("sleep".to_string()));
+        let mut process_watcher = ProcessWatcher::new(vec![sleep.clone()]);
+        let mut child = std::process::Command::new("sleep").arg("30").spawn()?;
+        let child_pid = Pid::from_u32(child.id());
+        let mut system = System::new_all();
+        system.refresh_processes();
+        process_watcher.update_process_tree(system.processes());
+        process_watcher
+            .seen
+            .insert(child_pid, Proc::started("sleep".to_string(), Utc::now()));
+
+        let cat = Target::new(TargetMatch::ProcessName("cat".to_string()));
+        process_watcher.reload_targets(vec![cat, sleep], system.processes());
+        assert!(process_watcher.seen.contains_key(&child_pid));
+        assert_eq!(
+            process_watcher.process_tree[&child_pid].matched_targets,
+            vec![1]
+        );
+
+        process_watcher.reload_targets(vec![], system.processes());
+        assert!(process_watcher.seen.contains_key(&child_pid));
+        assert!(process_watcher.process_tree[&child_pid]
+            .matched_targets
+            .is_empty());
+
+        child.kill()?;
+        child.wait()?;
         Ok(())
     }
 
@@ -769,4 +1439,180 @@ mod tests {
         process_watcher.log_datasets_in_process(&mut events_logger, &command);
         assert_eq!(process_watcher.datasamples_tracker.len(), 4);
     }
+
+    #[test]
+    fn test_handle_proc_events_records_short_lived_tool() -> Result<()> {
+        let mut process_watcher = ProcessWatcher::new(vec![Target::new(TargetMatch::ProcessName(
+            "samtools".to_string(),
+        ))]);
+        let mut event_logger = EventRecorder::default();
+        let start = Utc::now();
+
+        let exec = |pid: u32, name: &str| {
+            ProcEvent::Exec(ExecSnapshot {
+                pid,
+                parent_pid: 1,
+                name: name.to_string(),
+                cmd: vec![name.to_string(), "view".to_string()],
+                exe: format!("/usr/bin/{name}"),
+                cgroup: None,
+                timestamp: start,
+            })
+        };
+
+        // both events arrive within the same poll, the process is already gone
+        process_watcher.handle_proc_events(
+            vec![
+                exec(100, "samtools"),
+                exec(101, "ls"),
+                ProcEvent::Exit {
+                    pid: 100,
+                    // SIGSEGV with a core dump
+                    exit: ProcessExit::from_wait_status(11 | 0x80),
+                    usage: Some(ProcessSample {
+                        cpu_user_time_sec: Some(1.5),
+                        read_bytes: Some(4096),
+                        ..Default::default()
+                    }),
+                    timestamp: start + TimeDelta::seconds(2),
+                },
+                ProcEvent::Exit {
+                    pid: 101,
+                    exit: ProcessExit::from_wait_status(0),
+                    usage: None,
+                    timestamp: start,
+                },
+            ],
+            &HashMap::new(),
+            &mut event_logger,
+            &FileWatcher::new(),
+        )?;
+
+        let statuses: Vec<&str> = event_logger
+            .get_events()
+            .iter()
+            .map(|event| event.process_status.as_str())
+            .collect();
+        assert_eq!(
+            statuses,
+            vec![
+                EventType::ToolExecution.as_str(),
+                EventType::DataSamplesEvent.as_str(),
+                EventType::FinishedToolExecution.as_str()
+            ]
+        );
+
+        let Some(EventAttributes::CompletedProcess(completed)) =
+            &event_logger.get_events()[2].attributes
+        else {
+            panic!("expected a completed process");
+        };
+        assert_eq!(completed.tool_pid, "100");
+        assert_eq!(completed.duration_sec, 2);
+        assert_eq!(completed.exit_code, None);
+        assert_eq!(completed.signal, Some(11));
+        assert!(completed.core_dumped);
+        assert!(completed.failed);
+        assert_eq!(completed.cpu_user_time_sec, Some(1.5));
+        assert_eq!(completed.disk_read_total, Some(4096));
+        assert_eq!(completed.peak_memory_usage, None);
+        assert!(event_logger.get_events()[2]
+            .message
+            .ends_with("samtools was killed by signal 11 (core dumped)"));
+        assert!(process_watcher.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_tool_descendants_skip_tracked_subtrees() {
+        let mut process_watcher = ProcessWatcher::new(vec![]);
+        // 1 (tool) -> 2 -> 3, 1 -> 4 (tool) -> 5, 6 is unrelated
+        for (pid, parent) in [(1, 0), (2, 1), (3, 2), (4, 1), (5, 4), (6, 0)] {
+            process_watcher
+                .process_tree
+                .insert(Pid::from(pid), tree_node(parent));
+            if let Some(parent) = process_watcher.process_tree.get_mut(&Pid::from(parent)) {
+                parent.children.push(Pid::from(pid));
+            }
+        }
+        for pid in [1, 4] {
+            process_watcher.seen.insert(
+                Pid::from(pid),
+                Proc::started("tool".to_string(), Utc::now()),
+            );
+        }
+
+        let mut descendants = process_watcher.tool_descendants(Pid::from(1));
+        descendants.sort();
+        assert_eq!(descendants, vec![Pid::from(2), Pid::from(3)]);
+        assert_eq!(
+            process_watcher.tool_descendants(Pid::from(4)),
+            vec![Pid::from(5)]
+        );
+        assert_eq!(process_watcher.tool_of(Pid::from(3)), Some(Pid::from(1)));
+        assert_eq!(process_watcher.tool_of(Pid::from(5)), Some(Pid::from(4)));
+        assert_eq!(process_watcher.tool_of(Pid::from(6)), None);
+
+        process_watcher.set_aggregate_descendants(false);
+        assert!(process_watcher.tool_descendants(Pid::from(1)).is_empty());
+        assert_eq!(process_watcher.tool_of(Pid::from(3)), None);
+        assert_eq!(process_watcher.tool_of(Pid::from(4)), Some(Pid::from(4)));
+    }
+
+    #[test]
+    fn test_open_events_are_recorded_as_tool_files() -> Result<()> {
+        let mut process_watcher = ProcessWatcher::new(vec![]);
+        process_watcher
+            .seen
+            .insert(Pid::from(100), Proc::started("bwa".to_string(), Utc::now()));
+        let mut event_logger = EventRecorder::default();
+
+        let dir = tempfile::tempdir()?;
+        let output = dir.path().join("aligned.sam");
+        std::fs::write(&output, b"@HD\n")?;
+        let open = |pid: u32, path: &str| ProcEvent::Open {
+            pid,
+            file: OpenFile {
+                path: path.to_string(),
+                mode: AccessMode::Write,
+                offset: 0,
+                size: None,
+            },
+        };
+
+        process_watcher.handle_proc_events(
+            vec![
+                open(100, output.to_str().unwrap()),
+                open(100, dir.path().to_str().unwrap()),
+                open(101, output.to_str().unwrap()),
+                ProcEvent::Exit {
+                    pid: 100,
+                    exit: ProcessExit::from_wait_status(0),
+                    usage: None,
+                    timestamp: Utc::now(),
+                },
+            ],
+            &HashMap::new(),
+            &mut event_logger,
+            &FileWatcher::new(),
+        )?;
+
+        let Some(EventAttributes::CompletedProcess(completed)) =
+            &event_logger.get_events()[0].attributes
+        else {
+            panic!("expected a completed process");
+        };
+        assert_eq!(
+            completed.files_written,
+            vec![FileAccess {
+                file_path: output.to_str().unwrap().to_string(),
+                file_size: Some(4),
+                max_offset: 0,
+            }]
+        );
+        assert!(completed.files_read.is_empty());
+
+        Ok(())
+    }
 }
@@ -15,7 +15,6 @@ pub mod utils;
 
 use anyhow::{Context, Ok, Result};
 use daemonize::Daemonize;
-use exporters::db::AuroraClient;
 use tracing_appender::rolling::{RollingFileAppender, Rotation};
 use tracing_subscriber::{
     fmt::{self, time::SystemTime},
@@ -37,6 +36,7 @@ const STDERR_FILE: &str = "/tmp/tracerd.err";
 const LOG_FILE: &str = "/tmp/daemon.log";
 const SOCKET_PATH: &str = "/tmp/tracerd.sock";
 const FILE_CACHE_DIR: &str = "/tmp/tracerd_cache";
+const SPOOL_DIR: &str = "/tmp/tracerd_spool";
 
 const SYSLOG_FILE: &str = "/var/log/syslog";
 
@@ -75,15 +75,16 @@ pub async fn run(
     // Set up logging first
     setup_logging()?;
 
-    let raw_config = ConfigManager::load_config();
+    let mut raw_config = ConfigManager::load_config();
+    raw_config.offline |= cli_config_args.offline;
 
-    // create the conn pool to aurora
-    let db_client = Arc::new(AuroraClient::new(&raw_config, None).await);
+    // create the enabled event sinks, including the conn pool to aurora
+    let exporter = Arc::new(exporters::build_exporters(&raw_config).await?);
 
     let client = TracerClient::new(
         raw_config.clone(),
         workflow_directory_path,
-        db_client,
+        exporter,
         cli_config_args,
     )
     .await
@@ -126,9 +127,12 @@ fn setup_logging() -> Result<()> {
 }
 
 pub async fn monitor_processes_with_tracer_client(tracer_client: &mut TracerClient) -> Result<()> {
+    tracer_client.poll_target_packs();
+    tracer_client.poll_proc_events()?;
     tracer_client.remove_completed_processes().await?;
     tracer_client.poll_processes()?;
     // tracer_client.run_cleanup().await?;
+    tracer_client.poll_run_root_pid().await?;
     tracer_client.poll_process_metrics().await?;
     tracer_client.poll_syslog().await?;
     tracer_client.poll_stdout_stderr().await?;
@@ -168,7 +172,11 @@ mod tests {
 
         setup_env_vars(region);
 
-        let aurora_client = Arc::new(AuroraClient::new(&config, None).await);
+        let aurora_client = Arc::new(
+            AuroraClient::new(&config, None)
+                .await
+                .expect("Failed to create aurora client"),
+        );
 
         let mut tracer_client = TracerClient::new(
             config,
@@ -1,20 +1,23 @@
 // src/tracer_client.rs
 use crate::cloud_providers::aws::PricingClient;
-use crate::config_manager::{self, Config};
+use crate::config_manager::target_process::target_packs::TargetPacksWatcher;
+use crate::config_manager::{self, Config, ConfigManager};
 use crate::events::{
     recorder::{EventRecorder, EventType},
     send_start_run_event,
 };
-use crate::exporters::db::AuroraClient;
+use crate::exporters::{resolve_export_dir, EventExporter};
 use crate::extracts::{
+    discovery,
     file_watcher::FileWatcher,
     metrics::SystemMetricsCollector,
+    proc_connector::{self, EventFilter, ProcEventReceiver},
     process_watcher::{ProcessWatcher, ShortLivedProcessLog},
     stdout::StdoutWatcher,
     syslog::{run_syslog_lines_read_thread, SyslogWatcher},
 };
 use crate::types::cli::TracerCliInitArgs;
-use crate::types::event::attributes::EventAttributes;
+use crate::types::event::attributes::{run::RunCompletion, EventAttributes};
 use crate::{monitor_processes_with_tracer_client, DEFAULT_SERVICE_URL, FILE_CACHE_DIR};
 use crate::{SOCKET_PATH, SYSLOG_FILE};
 use anyhow::{Context, Result};
@@ -23,7 +26,7 @@ use std::borrow::BorrowMut;
 use std::ops::Sub;
 use std::sync::Arc;
 use std::time::{Duration, Instant};
-use sysinfo::{Pid, System};
+use sysinfo::{Pid, ProcessRefreshKind, System, UpdateKind};
 use tokio::sync::{Mutex, RwLock};
 
 use crate::daemon_communication::server::run_server;
@@ -48,10 +51,14 @@ pub struct RunMetadata {
     pub pipeline_name: String,
     pub parent_pid: Option<Pid>,
     pub start_time: DateTime<Utc>,
+    /// When the root process was first seen gone
+    pub root_exited_at: Option<Instant>,
 }
 
 const RUN_COMPLICATED_PROCESS_IDENTIFICATION: bool = false;
 const WAIT_FOR_PROCESS_BEFORE_NEW_RUN: bool = false;
+/// Leaves `tracer run` time to end the run itself with the command's exit status
+const RUN_ROOT_EXIT_GRACE: Duration = Duration::from_secs(5);
 
 pub type LinesBufferArc = Arc<RwLock<Vec<String>>>;
 
@@ -64,6 +71,9 @@ pub struct TracerClient {
     last_file_size_change_time_delta: TimeDelta,
     pub logs: EventRecorder,
     pub process_watcher: ProcessWatcher,
+    // None when the proc connector is unavailable, tools are then only found by polling
+    proc_events: Option<ProcEventReceiver>,
+    target_packs: TargetPacksWatcher,
     syslog_watcher: SyslogWatcher,
     stdout_watcher: StdoutWatcher,
     metrics_collector: SystemMetricsCollector,
@@ -74,9 +84,9 @@ pub struct TracerClient {
     syslog_lines_buffer: LinesBufferArc,
     stdout_lines_buffer: LinesBufferArc,
     stderr_lines_buffer: LinesBufferArc,
-    pub db_client: Arc<AuroraClient>,
+    pub exporter: Arc<dyn EventExporter>,
     pipeline_name: String,
-    pub pricing_client: PricingClient,
+    pub pricing_client: Option<PricingClient>,
     initialization_id: Option<String>,
     config: Config,
     tags: Vec<String>,
@@ -86,17 +96,36 @@ impl TracerClient {
     pub async fn new(
         config: Config,
         workflow_directory: String,
-        db_client: Arc<AuroraClient>,
+        exporter: Arc<dyn EventExporter>,
         cli_args: TracerCliInitArgs,
     ) -> Result<TracerClient> {
         println!("Initializing TracerClient with API Key: {}", config.api_key);
 
-        let pricing_client = PricingClient::new(config.aws_init_type.clone(), "us-east-1").await;
+        let pricing_client = if config.offline {
+            None
+        } else {
+            match PricingClient::new(config.aws_init_type.clone(), "us-east-1").await {
+                Ok(client) => Some(client),
+                Err(err) => {
+                    tracing::warn!("Failed to initialize pricing client: {:?}", err);
+                    println!(
+                        "Warning: AWS credentials unavailable, EC2 cost analysis disabled: {err}"
+                    );
+                    None
+                }
+            }
+        };
 
         let file_watcher = FileWatcher::new();
 
         file_watcher.prepare_cache_directory(FILE_CACHE_DIR)?;
 
+        let targets = ConfigManager::load_targets(&config);
+        let proc_events = Self::subscribe_proc_events(EventFilter::new(targets.clone()));
+
+        let mut process_watcher = ProcessWatcher::new(targets);
+        process_watcher.set_aggregate_descendants(config.aggregate_process_tree_metrics);
+
         Ok(TracerClient {
             // fixed values
             interval: Duration::from_millis(config.process_polling_interval_ms),
@@ -120,10 +149,12 @@ impl TracerClient {
             syslog_lines_buffer: Arc::new(RwLock::new(Vec::new())),
             stdout_lines_buffer: Arc::new(RwLock::new(Vec::new())),
             stderr_lines_buffer: Arc::new(RwLock::new(Vec::new())),
-            process_watcher: ProcessWatcher::new(config.targets.clone()),
+            process_watcher,
+            proc_events,
+            target_packs: TargetPacksWatcher::new(ConfigManager::get_target_packs_path()),
             metrics_collector: SystemMetricsCollector::new(),
             nextflow_log_watcher: NextflowLogWatcher::new(),
-            db_client,
+            exporter,
             pipeline_name: cli_args.pipeline_name,
             pricing_client,
             initialization_id: cli_args.run_id,
@@ -134,7 +165,9 @@ impl TracerClient {
 
     pub fn reload_config_file(&mut self, config: &Config) {
         self.interval = Duration::from_millis(config.process_polling_interval_ms);
-        self.process_watcher.reload_targets(config.targets.clone());
+        self.reload_targets(config);
+        self.process_watcher
+            .set_aggregate_descendants(config.aggregate_process_tree_metrics);
         self.config = config.clone()
     }
 
@@ -183,11 +216,22 @@ impl TracerClient {
         );
 
         if self.last_sent.is_none() || Instant::now() - self.last_sent.unwrap() >= self.interval {
+            self.system.refresh_cpu_usage();
+            self.system.refresh_memory();
             self.metrics_collector
-                .collect_metrics(&mut self.system, &mut self.logs)
+                .seed_oom_kills(self.process_watcher.take_oom_kill_baselines());
+            self.metrics_collector
+                .collect_metrics(
+                    &mut self.system,
+                    &self.process_watcher.tool_cgroups(),
+                    &mut self.logs,
+                )
                 .context("Failed to collect metrics")?;
 
-            self.db_client
+            // failed sinks spool the batch themselves, a lost batch shouldn't stop the run.
+            // It isn't kept for the next flush, the sinks that received it would get it twice
+            if let Err(err) = self
+                .exporter
                 .batch_insert_events(
                     run_name,
                     run_id,
@@ -195,7 +239,18 @@ impl TracerClient {
                     self.logs.get_events(),
                 )
                 .await
-                .map_err(|err| anyhow::anyhow!("Error submitting batch events {:?}", err))?;
+            {
+                tracing::error!(
+                    "Error submitting batch events, {} events were dropped by the failed sinks{}: {:?}",
+                    self.logs.len(),
+                    if self.config.spool.enabled {
+                        ""
+                    } else {
+                        ", enable [spool] to keep them"
+                    },
+                    err
+                );
+            }
 
             self.last_sent = Some(Instant::now());
             self.logs.clear();
@@ -206,6 +261,37 @@ impl TracerClient {
         }
     }
 
+    /// Marks `pid` as the process the current run belongs to, e.g. the command spawned by `tracer run`
+    pub fn set_run_root_pid(&mut self, pid: u32) {
+        if let Some(run) = self.current_run.as_mut() {
+            run.parent_pid = Some(Pid::from_u32(pid));
+            run.root_exited_at = None;
+        }
+    }
+
+    /// Ends the run once its root process is gone, e.g. when `tracer run` was killed before it could
+    pub async fn poll_run_root_pid(&mut self) -> Result<()> {
+        let Some(run) = self.current_run.as_mut() else {
+            return Ok(());
+        };
+        let Some(root_pid) = run.parent_pid else {
+            return Ok(());
+        };
+        if self
+            .process_watcher
+            .is_process_alive(&self.system, root_pid)
+        {
+            return Ok(());
+        }
+        let exited_at = *run.root_exited_at.get_or_insert_with(Instant::now);
+        if exited_at.elapsed() < RUN_ROOT_EXIT_GRACE {
+            return Ok(());
+        }
+
+        self.end_run("Run ended due to parent process termination", None)
+            .await
+    }
+
     pub fn get_run_metadata(&self) -> Option<RunMetadata> {
         self.current_run.clone()
     }
@@ -216,28 +302,16 @@ impl TracerClient {
                 return Ok(());
             }
             if run.last_interaction.elapsed() > self.last_interaction_new_run_duration {
-                self.logs.record_event(
-                    EventType::FinishedRun,
-                    "Run ended due to inactivity".to_string(),
-                    None,
-                    None,
-                );
-                self.current_run = None;
+                self.end_run("Run ended due to inactivity", None).await?;
             } else if run.parent_pid.is_none() && !self.process_watcher.is_empty() {
                 run.parent_pid = self.process_watcher.get_parent_pid(Some(run.start_time));
-            } else if run.parent_pid.is_some() {
-                let parent_pid = run.parent_pid.unwrap();
+            } else if let Some(parent_pid) = run.parent_pid {
                 if !self
                     .process_watcher
                     .is_process_alive(&self.system, parent_pid)
                 {
-                    self.logs.record_event(
-                        EventType::FinishedRun,
-                        "Run ended due to parent process termination".to_string(),
-                        None,
-                        None,
-                    );
-                    self.current_run = None;
+                    self.end_run("Run ended due to parent process termination", None)
+                        .await?;
                 }
             }
         } else if !WAIT_FOR_PROCESS_BEFORE_NEW_RUN || !self.process_watcher.is_empty() {
@@ -256,14 +330,16 @@ impl TracerClient {
         let result = send_start_run_event(
             &self.system,
             &self.pipeline_name,
-            &self.pricing_client,
+            self.pricing_client.as_ref(),
             &self.initialization_id,
+            self.config.offline,
         )
         .await?;
 
         self.current_run = Some(RunMetadata {
             last_interaction: Instant::now(),
             parent_pid: None,
+            root_exited_at: None,
             start_time: timestamp.unwrap_or_else(Utc::now),
             name: result.run_name.clone(),
             id: result.run_id.clone(),
@@ -283,23 +359,78 @@ impl TracerClient {
             Some(EventAttributes::SystemProperties(result.system_properties)),
             timestamp,
         );
+        self.process_watcher
+            .start_discovery(&self.config.discovery, timestamp.unwrap_or_else(Utc::now));
 
         Ok(())
     }
 
+    /// Ends discovery mode, the heavy untracked processes get an event each and a suggested target pack
+    fn report_discovery(
+        process_watcher: &mut ProcessWatcher,
+        logs: &mut EventRecorder,
+        config: &Config,
+        run_name: &str,
+    ) {
+        let heavy = process_watcher.finish_discovery();
+        if heavy.is_empty() {
+            return;
+        }
+        match resolve_export_dir(config.discovery.directory.as_deref())
+            .and_then(|dir| discovery::write_suggested_target_pack(&dir, run_name, &heavy))
+        {
+            Ok(path) => tracing::info!(
+                "{} untracked processes were heavy, suggested targets written to {:?}",
+                heavy.len(),
+                path
+            ),
+            Err(err) => tracing::warn!("Failed to write suggested targets: {:?}", err),
+        }
+        for untracked in heavy {
+            logs.record_event(
+                EventType::UntrackedHeavyProcess,
+                format!(
+                    "[discovery] {} is not tracked by any target",
+                    untracked.binary_path
+                ),
+                Some(EventAttributes::UntrackedHeavyProcess(untracked)),
+                None,
+            );
+        }
+    }
+
     pub async fn stop_run(&mut self) -> Result<()> {
-        if self.current_run.is_some() {
+        self.stop_run_with_completion(None).await
+    }
+
+    /// Ends the current run, `completion` describes how the command wrapped by `tracer run` exited
+    pub async fn stop_run_with_completion(
+        &mut self,
+        completion: Option<RunCompletion>,
+    ) -> Result<()> {
+        self.end_run("[CLI] Finishing pipeline run", completion)
+            .await
+    }
+
+    async fn end_run(&mut self, message: &str, completion: Option<RunCompletion>) -> Result<()> {
+        if let Some(run) = &self.current_run {
+            Self::report_discovery(
+                &mut self.process_watcher,
+                &mut self.logs,
+                &self.config,
+                &run.name,
+            );
             self.logs.record_event(
                 EventType::FinishedRun,
-                "[CLI] Finishing pipeline run".to_owned(),
-                None,
+                message.to_owned(),
+                completion.map(EventAttributes::RunCompletion),
                 Some(Utc::now()),
             );
             // clear events containing this run
             let run_metadata = self.current_run.as_ref().unwrap();
 
             if let Err(err) = self
-                .db_client
+                .exporter
                 .batch_insert_events(
                     &run_metadata.name,
                     &run_metadata.id,
@@ -323,6 +454,63 @@ impl TracerClient {
         Ok(())
     }
 
+    /// Subscribes to eBPF tracepoints when built with them, the proc connector otherwise, `None` leaves only polling
+    fn subscribe_proc_events(filter: EventFilter) -> Option<ProcEventReceiver> {
+        #[cfg(all(feature = "ebpf", target_os = "linux"))]
+        match crate::extracts::ebpf::subscribe(filter.clone()) {
+            Ok(receiver) => return Some(receiver),
+            Err(err) => tracing::warn!(
+                "eBPF tracing unavailable, falling back to the proc connector: {:?}",
+                err
+            ),
+        }
+
+        match proc_connector::subscribe(filter) {
+            Ok(receiver) => Some(receiver),
+            Err(err) => {
+                tracing::warn!(
+                    "Proc connector unavailable, falling back to polling processes: {:?}",
+                    err
+                );
+                None
+            }
+        }
+    }
+
+    /// Reloads the targets when a target pack was added, edited or removed
+    pub fn poll_target_packs(&mut self) {
+        if self.target_packs.changed() {
+            tracing::info!("Target packs changed, reloading targets");
+            self.reload_targets(&self.config.clone());
+        }
+    }
+
+    fn reload_targets(&mut self, config: &Config) {
+        let targets = ConfigManager::load_targets(config);
+        if let Some(proc_events) = &self.proc_events {
+            proc_events.filter().set_targets(targets.clone());
+        }
+        self.process_watcher
+            .reload_targets(targets, self.system.processes());
+    }
+
+    /// Handles the queued process events, before polling finds completed processes without knowing how they exited
+    pub fn poll_proc_events(&mut self) -> Result<()> {
+        if let Some(proc_events) = &self.proc_events {
+            // exits of the tools found by the last poll are reported from now on
+            proc_events
+                .filter()
+                .sync_tracked(self.process_watcher.tool_pids());
+            self.process_watcher.handle_proc_events(
+                proc_events.drain(),
+                self.system.processes(),
+                &mut self.logs,
+                &self.file_watcher,
+            )?;
+        }
+        Ok(())
+    }
+
     /// These functions require logs and the system
     pub fn poll_processes(&mut self) -> Result<()> {
         self.process_watcher.poll_processes(
@@ -353,6 +541,9 @@ impl TracerClient {
     }
 
     pub async fn poll_files(&mut self) -> Result<()> {
+        if self.config.offline {
+            return Ok(());
+        }
         self.file_watcher
             .poll_files(
                 DEFAULT_SERVICE_URL,
@@ -378,6 +569,13 @@ impl TracerClient {
     pub async fn poll_stdout_stderr(&mut self) -> Result<()> {
         let (stdout_lines_buffer, stderr_lines_buffer) = self.get_stdout_stderr_lines_buffer();
 
+        if self.config.offline {
+            // nowhere to send the captured lines to, drop them instead of buffering forever
+            stdout_lines_buffer.write().await.clear();
+            stderr_lines_buffer.write().await.clear();
+            return Ok(());
+        }
+
         self.stdout_watcher
             .poll_stdout(
                 DEFAULT_SERVICE_URL,
@@ -403,8 +601,17 @@ impl TracerClient {
             .await
     }
 
+    /// Refreshes the process list and the tools' usage on every poll.
+    /// Reading every process' memory and io, and host cpus and disks, is left to the metrics cadence
     pub fn refresh_sysinfo(&mut self) {
-        self.system.refresh_all();
+        // cmd and exe are read once per process, they're what targets are matched against.
+        // Tools and their descendants re-read them in `refresh_tools`
+        self.system.refresh_processes_specifics(
+            ProcessRefreshKind::new()
+                .with_cmd(UpdateKind::OnlyIfNotSet)
+                .with_exe(UpdateKind::OnlyIfNotSet),
+        );
+        self.process_watcher.refresh_tools(&mut self.system);
     }
 
     pub fn reset_just_started_process_flag(&mut self) {
@@ -431,12 +638,19 @@ impl TracerClient {
 
         let cancellation_token = CancellationToken::new();
 
-        tokio::spawn(run_server(
-            tracer_client.clone(),
-            SOCKET_PATH,
-            cancellation_token.clone(),
-            config.clone(),
-        ));
+        tokio::spawn({
+            let tracer_client = tracer_client.clone();
+            let cancellation_token = cancellation_token.clone();
+            let config = config.clone();
+            async move {
+                if let Err(err) =
+                    run_server(tracer_client, SOCKET_PATH, cancellation_token, config).await
+                {
+                    tracing::error!("Socket server stopped: {:?}", err);
+                    eprintln!("Socket server stopped: {:?}", err);
+                }
+            }
+        });
 
         let syslog_lines_task = tokio::spawn(run_syslog_lines_read_thread(
             SYSLOG_FILE,
@@ -486,19 +700,39 @@ impl TracerClient {
         syslog_lines_task.abort();
         stdout_lines_task.abort();
 
-        // close the connection pool to aurora
+        // flush the exporters and close the connection pool to aurora
         let guard = tracer_client.lock().await;
-        let _ = guard.db_client.close().await;
+        let _ = guard.exporter.close().await;
 
         Ok(())
     }
 }
 
+/// A client that exports to files under `work_dir` and spools there, nothing leaves the machine
+#[cfg(test)]
+pub(crate) async fn offline_tracer_client(work_dir: &str) -> TracerClient {
+    let mut config = ConfigManager::load_default_config();
+    config.offline = true;
+    config.exporters.local_file.directory = Some(format!("{work_dir}/exports"));
+    config.spool.directory = Some(format!("{work_dir}/spool"));
+
+    let exporter = Arc::new(crate::exporters::build_exporters(&config).await.unwrap());
+    TracerClient::new(
+        config,
+        work_dir.to_string(),
+        exporter,
+        TracerCliInitArgs::default(),
+    )
+    .await
+    .unwrap()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::config_manager::ConfigManager;
     use crate::events::recorder::EventType;
+    use crate::exporters::db::AuroraClient;
     use anyhow::Result;
     use serde_json::Value;
     use sqlx::types::Json;
@@ -514,13 +748,18 @@ mod tests {
         let work_dir = temp_dir.path().to_str().unwrap();
 
         // Create an instance of AuroraClient
-        let db_client = Arc::new(AuroraClient::new(&config, Some(1)).await);
+        let db_client = Arc::new(
+            AuroraClient::new(&config, Some(1))
+                .await
+                .expect("Failed to create aurora client"),
+        );
 
         let cli_config = TracerCliInitArgs::default();
 
-        let mut client = TracerClient::new(config, work_dir.to_string(), db_client, cli_config)
-            .await
-            .expect("Failed to create tracer client");
+        let mut client =
+            TracerClient::new(config, work_dir.to_string(), db_client.clone(), cli_config)
+                .await
+                .expect("Failed to create tracer client");
 
         client
             .start_new_run(None)
@@ -546,7 +785,7 @@ mod tests {
         // Prepare the SQL query
         let query = "SELECT data, run_name FROM batch_jobs_logs WHERE run_name = $1";
 
-        let db_client = client.db_client.get_pool();
+        let db_client = db_client.get_pool();
 
         // Verify the row was inserted into the database
         let result: (Json<Value>, String) = sqlx::query_as(query)
@@ -571,7 +810,11 @@ mod tests {
         let job_id = "job-1234";
 
         // Create an instance of AuroraClient
-        let db_client = Arc::new(AuroraClient::new(&config, Some(1)).await);
+        let db_client = Arc::new(
+            AuroraClient::new(&config, Some(1))
+                .await
+                .expect("Failed to create aurora client"),
+        );
 
         let tags = vec!["Hello".to_string(), "Test".to_string()];
 
@@ -579,6 +822,7 @@ mod tests {
             pipeline_name: "Test Pipeline".to_string(),
             run_id: None,
             tags: tags.clone(),
+            offline: false,
         };
 
         let mut client = TracerClient::new(config, work_dir.to_string(), db_client, cli_config)
@@ -606,4 +850,93 @@ mod tests {
 
         assert_eq!(client.tags, tags);
     }
+
+    #[tokio::test]
+    async fn test_offline_run_writes_events_locally() -> Result<()> {
+        let temp_dir = tempdir().expect("cant create temp dir");
+        let work_dir = temp_dir.path().to_str().unwrap();
+        let export_dir = temp_dir.path().join("exports");
+        let mut client = offline_tracer_client(work_dir).await;
+
+        client.start_new_run(None).await?;
+        let run_id = client.current_run.clone().unwrap().id;
+
+        client.submit_batched_data().await?;
+
+        let exported = std::fs::read_to_string(export_dir.join(format!("{run_id}.jsonl")))?;
+        assert!(exported.contains("new_run"));
+
+        client.set_run_root_pid(4242);
+        assert_eq!(
+            client.current_run.as_ref().unwrap().parent_pid,
+            Some(Pid::from_u32(4242))
+        );
+
+        client
+            .stop_run_with_completion(Some(RunCompletion {
+                command: "nextflow run main.nf".to_string(),
+                exit_code: Some(3),
+                signal: None,
+            }))
+            .await?;
+        assert!(client.current_run.is_none());
+
+        let exported = std::fs::read_to_string(export_dir.join(format!("{run_id}.jsonl")))?;
+        let finished_run: Value = serde_json::from_str(exported.lines().last().unwrap())?;
+        assert_eq!(finished_run["process_status"], "finished_run");
+        assert_eq!(finished_run["attributes"]["run_completion"]["exit_code"], 3);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_run_ends_when_root_process_exits() -> Result<()> {
+        let temp_dir = tempdir().expect("cant create temp dir");
+        let work_dir = temp_dir.path().to_str().unwrap();
+        let export_dir = temp_dir.path().join("exports");
+
+        let mut config = ConfigManager::load_default_config();
+        config.offline = true;
+        config.exporters.local_file.directory = Some(export_dir.to_str().unwrap().to_string());
+
+        let exporter = Arc::new(crate::exporters::build_exporters(&config).await?);
+        let mut client = TracerClient::new(
+            config,
+            work_dir.to_string(),
+            exporter,
+            TracerCliInitArgs::default(),
+        )
+        .await?;
+
+        client.start_new_run(None).await?;
+        let run_id = client.current_run.clone().unwrap().id;
+
+        let mut root = std::process::Command::new("sleep").arg("30").spawn()?;
+        client.set_run_root_pid(root.id());
+        client.refresh_sysinfo();
+        client.poll_run_root_pid().await?;
+        assert!(client.current_run.is_some());
+
+        root.kill()?;
+        root.wait()?;
+        client.refresh_sysinfo();
+        client.poll_run_root_pid().await?;
+        // `tracer run` gets to end the run itself first
+        assert!(client.current_run.is_some());
+
+        client.current_run.as_mut().unwrap().root_exited_at =
+            Some(Instant::now() - RUN_ROOT_EXIT_GRACE);
+        client.poll_run_root_pid().await?;
+        assert!(client.current_run.is_none());
+
+        let exported = std::fs::read_to_string(export_dir.join(format!("{run_id}.jsonl")))?;
+        let finished_run: Value = serde_json::from_str(exported.lines().last().unwrap())?;
+        assert_eq!(finished_run["process_status"], "finished_run");
+        assert_eq!(
+            finished_run["message"],
+            "Run ended due to parent process termination"
+        );
+
+        Ok(())
+    }
 }
@@ -1,4 +1,4 @@
-use clap::Args;
+use clap::{Args, Subcommand};
 
 #[derive(Default, Args, Debug, Clone)]
 pub struct TracerCliInitArgs {
@@ -14,4 +14,47 @@ pub struct TracerCliInitArgs {
     /// attribution: used to assign tags to a certain pipeline
     #[clap(long, value_delimiter = ',')]
     pub tags: Vec<String>,
+
+    /// offline mode: skip AWS and database lookups and only write events to local files
+    #[clap(long)]
+    pub offline: bool,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct TracerCliRunArgs {
+    /// used to start the daemon when it isn't running yet
+    #[clap(flatten)]
+    pub init: TracerCliInitArgs,
+
+    /// end the run already in progress on the daemon instead of refusing to start
+    #[clap(long)]
+    pub end_active_run: bool,
+
+    /// the command to trace, given after `--`
+    #[clap(last = true, required = true)]
+    pub command: Vec<String>,
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum TracerCliTargetsCommand {
+    /// List the effective targets, built-in or from tracer.toml, in the order they're tried
+    List,
+
+    /// Explain which target a process would match, and what excluded it otherwise
+    Test {
+        /// process name, as in /proc/<pid>/comm
+        #[clap(long)]
+        name: String,
+
+        /// full command line
+        #[clap(long, default_value = "")]
+        cmd: String,
+
+        /// path of the executable
+        #[clap(long, default_value = "")]
+        exe: String,
+    },
+
+    /// Explain the targets matched by every process running on the host
+    Scan,
 }
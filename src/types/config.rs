@@ -1,6 +1,180 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
 #[derive(Clone, Debug)]
 pub enum AwsConfig {
     Profile(String),
     RoleArn(String),
     Env,
 }
+
+/// Settings for every event sink the daemon can export batches to.
+/// Each sink is enabled independently under `[exporters.<sink>]` in tracer.toml
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(default)]
+pub struct ExportersConfig {
+    pub aurora: AuroraExporterConfig,
+    pub local_file: LocalFileExporterConfig,
+    pub parquet: ParquetExporterConfig,
+    pub prometheus: PrometheusExporterConfig,
+    pub otlp: OtlpExporterConfig,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct AuroraExporterConfig {
+    pub enabled: bool,
+    /// Max connections in the postgres pool
+    pub pool_size: Option<u32>,
+}
+
+impl Default for AuroraExporterConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            pool_size: None,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(default)]
+pub struct LocalFileExporterConfig {
+    pub enabled: bool,
+    /// Directory the JSON lines files are written to, defaults to the tracer exports dir
+    pub directory: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(default)]
+pub struct ParquetExporterConfig {
+    pub enabled: bool,
+    /// Directory the parquet files are written to, defaults to the tracer exports dir
+    pub directory: Option<String>,
+    pub rotation: ParquetRotation,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ParquetRotation {
+    /// A single file per run, finalized when the run finishes
+    #[default]
+    PerRun,
+    /// A new file for every submitted batch
+    PerBatch,
+}
+
+/// Serves the latest host and tool gauges on `/metrics` for Prometheus to scrape
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct PrometheusExporterConfig {
+    pub enabled: bool,
+    pub listen_address: String,
+}
+
+impl Default for PrometheusExporterConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            listen_address: "127.0.0.1:9464".to_string(),
+        }
+    }
+}
+
+/// Exports runs as OTLP traces, with one span per tool process
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct OtlpExporterConfig {
+    pub enabled: bool,
+    /// OTLP/HTTP traces endpoint of the collector
+    pub endpoint: String,
+    /// Extra headers sent with every export, e.g. for authentication
+    pub headers: HashMap<String, String>,
+    /// `service.name` resource attribute of the exported spans
+    pub service_name: String,
+    pub timeout_ms: u64,
+}
+
+impl Default for OtlpExporterConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            endpoint: "http://localhost:4318/v1/traces".to_string(),
+            headers: HashMap::new(),
+            service_name: "tracer".to_string(),
+            timeout_ms: 10_000,
+        }
+    }
+}
+
+/// Write-ahead spool keeping batches a sink failed to receive, see `exporters::SpoolingExporter`
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct SpoolConfig {
+    pub enabled: bool,
+    /// Defaults to a directory next to the daemon file cache
+    pub directory: Option<String>,
+    /// Oldest batches are dropped once a sink's spool grows past this size
+    pub max_size_mb: u64,
+    pub initial_backoff_ms: u64,
+    pub max_backoff_ms: u64,
+    /// Failed replays before a batch is dead-lettered, so it stops holding up the ones behind it
+    pub max_attempts: u32,
+}
+
+impl Default for SpoolConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            directory: None,
+            max_size_mb: 512,
+            initial_backoff_ms: 10_000,
+            max_backoff_ms: 5 * 60 * 1000,
+            max_attempts: 20,
+        }
+    }
+}
+
+/// Where `tracer alert` messages are routed to
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct NotificationsConfig {
+    /// Incoming webhook (e.g. Slack or Teams) alerts are posted to as JSON
+    pub webhook_url: Option<String>,
+    pub timeout_ms: u64,
+}
+
+impl Default for NotificationsConfig {
+    fn default() -> Self {
+        Self {
+            webhook_url: None,
+            timeout_ms: 5_000,
+        }
+    }
+}
+
+/// Learning mode, reports heavy processes no target matched when a run ends
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct DiscoveryConfig {
+    pub enabled: bool,
+    /// Processes of the same binary and first argument are reported once they reach any threshold
+    pub min_cpu_time_sec: f64,
+    pub min_memory_mb: u64,
+    pub min_duration_sec: u64,
+    /// Where suggested target packs are written, defaults to the tracer exports dir
+    pub directory: Option<String>,
+}
+
+impl Default for DiscoveryConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            min_cpu_time_sec: 600.0,
+            min_memory_mb: 2048,
+            min_duration_sec: 30 * 60,
+            directory: None,
+        }
+    }
+}
@@ -0,0 +1,28 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(clap::ValueEnum, Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum LogSeverity {
+    Debug,
+    #[default]
+    Info,
+    Warning,
+    Error,
+}
+
+impl LogSeverity {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LogSeverity::Debug => "debug",
+            LogSeverity::Info => "info",
+            LogSeverity::Warning => "warning",
+            LogSeverity::Error => "error",
+        }
+    }
+}
+
+/// Attributes of a message sent with `tracer log`
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct LogProperties {
+    pub severity: LogSeverity,
+}
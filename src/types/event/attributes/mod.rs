@@ -1,9 +1,13 @@
 use crate::types::event::attributes::system_metrics::NextflowLog;
-use process::{CompletedProcess, DataSetsProcessed, ProcessProperties};
+use log::LogProperties;
+use process::{CompletedProcess, DataSetsProcessed, ProcessProperties, UntrackedHeavyProcess};
+use run::RunCompletion;
 use syslog::SyslogProperties;
-use system_metrics::{SystemMetric, SystemProperties};
+use system_metrics::{CgroupStatistic, SystemMetric, SystemProperties};
 
+pub mod log;
 pub mod process;
+pub mod run;
 pub mod syslog;
 pub mod system_metrics;
 
@@ -17,6 +21,10 @@ pub enum EventAttributes {
     SystemProperties(SystemProperties),
     ProcessDatasetStats(DataSetsProcessed),
     NextflowLog(NextflowLog),
+    Log(LogProperties),
+    RunCompletion(RunCompletion),
+    Cgroup(CgroupStatistic),
+    UntrackedHeavyProcess(UntrackedHeavyProcess),
     // TODO: take out when done with demo
     Other(serde_json::Value),
 }
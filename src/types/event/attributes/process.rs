@@ -1,5 +1,7 @@
 use serde::{Deserialize, Serialize};
 
+use super::system_metrics::CgroupStatistic;
+
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct InputFile {
     pub file_name: String,
@@ -9,7 +11,7 @@ pub struct InputFile {
     pub file_updated_at_timestamp: String,
 }
 
-#[derive(Serialize, Deserialize, Clone, Debug)]
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
 pub struct ProcessProperties {
     pub tool_name: String,
     pub tool_pid: String,
@@ -27,13 +29,142 @@ pub struct ProcessProperties {
     pub process_disk_usage_write_total: u64,
     pub process_status: String,
     pub input_files: Option<Vec<InputFile>>,
+    /// Accounting of the cgroup the tool runs in, containers and scheduler jobs get their own
+    #[serde(default)]
+    pub cgroup: Option<CgroupStatistic>,
+    #[serde(default)]
+    pub container: Option<ContainerInfo>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum ContainerRuntime {
+    Docker,
+    Podman,
+    Containerd,
+    Apptainer,
+}
+
+impl ContainerRuntime {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ContainerRuntime::Docker => "docker",
+            ContainerRuntime::Podman => "podman",
+            ContainerRuntime::Containerd => "containerd",
+            ContainerRuntime::Apptainer => "apptainer",
+        }
+    }
+}
+
+/// The container a tool runs in, image fields are None when the runtime couldn't be asked
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ContainerInfo {
+    pub runtime: ContainerRuntime,
+    /// Full container id, None for Apptainer which has no container ids
+    pub container_id: Option<String>,
+    /// Image reference the container was started from, the image file for Apptainer
+    pub image_name: Option<String>,
+    /// `sha256:` repo digest of the image, its image id when it was never pulled from a registry
+    pub image_digest: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct CompletedProcess {
     pub tool_name: String,
     pub tool_pid: String,
     pub duration_sec: u64,
+    /// None when the process was killed by a signal or its exit status couldn't be collected,
+    /// which is the case when processes are only polled
+    #[serde(default)]
+    pub exit_code: Option<i32>,
+    #[serde(default)]
+    pub signal: Option<i32>,
+    #[serde(default)]
+    pub core_dumped: bool,
+    /// Exited non-zero or was killed by a signal
+    #[serde(default)]
+    pub failed: bool,
+    /// Peak resident memory in bytes, None when the process exited before it was sampled
+    #[serde(default)]
+    pub peak_memory_usage: Option<u64>,
+    #[serde(default)]
+    pub peak_memory_virtual: Option<u64>,
+    #[serde(default)]
+    pub cpu_user_time_sec: Option<f64>,
+    #[serde(default)]
+    pub cpu_system_time_sec: Option<f64>,
+    #[serde(default)]
+    pub disk_read_total: Option<u64>,
+    #[serde(default)]
+    pub disk_write_total: Option<u64>,
+    #[serde(default)]
+    pub max_threads: Option<u64>,
+    /// Files the tool had open for reading or writing, from sampling its file descriptors
+    #[serde(default)]
+    pub files_read: Vec<FileAccess>,
+    #[serde(default)]
+    pub files_written: Vec<FileAccess>,
+}
+
+/// A file a tool had open while it ran
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct FileAccess {
+    pub file_path: String,
+    /// Size when the file was last seen open
+    pub file_size: Option<u64>,
+    /// Furthest offset the tool was seen at, how much of the file it got through
+    pub max_offset: u64,
+}
+
+impl CompletedProcess {
+    pub fn set_exit(&mut self, exit: ProcessExit) {
+        self.exit_code = exit.exit_code;
+        self.signal = exit.signal;
+        self.core_dumped = exit.core_dumped;
+        self.failed = exit.failed();
+    }
+}
+
+/// How a process terminated, decoded from its wait status
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ProcessExit {
+    pub exit_code: Option<i32>,
+    pub signal: Option<i32>,
+    pub core_dumped: bool,
+}
+
+impl ProcessExit {
+    /// Decodes a status as returned by `wait(2)`, see `WIFEXITED` and `WIFSIGNALED`
+    pub fn from_wait_status(status: u32) -> Self {
+        let signal = (status & 0x7f) as i32;
+        if signal == 0 {
+            ProcessExit {
+                exit_code: Some(((status >> 8) & 0xff) as i32),
+                ..Default::default()
+            }
+        } else {
+            ProcessExit {
+                signal: Some(signal),
+                core_dumped: status & 0x80 != 0,
+                ..Default::default()
+            }
+        }
+    }
+
+    pub fn failed(&self) -> bool {
+        self.signal.is_some() || self.exit_code.is_some_and(|code| code != 0)
+    }
+
+    pub fn describe(&self) -> String {
+        match (self.exit_code, self.signal) {
+            (Some(code), _) => format!("exited with code {code}"),
+            (None, Some(signal)) if self.core_dumped => {
+                format!("was killed by signal {signal} (core dumped)")
+            }
+            (None, Some(signal)) => format!("was killed by signal {signal}"),
+            (None, None) => "exited".to_string(),
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -41,3 +172,19 @@ pub struct DataSetsProcessed {
     pub datasets: String,
     pub total: u64,
 }
+
+/// Processes no target matched that went over a discovery threshold, grouped by binary and first argument
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct UntrackedHeavyProcess {
+    pub binary_path: String,
+    /// First argument that isn't an option, tells scripts of the same interpreter apart
+    pub first_argument: Option<String>,
+    pub process_name: String,
+    /// Command of the process that used the most cpu time
+    pub example_cmd: String,
+    pub process_count: u64,
+    /// Summed over the processes
+    pub cpu_time_sec: f64,
+    pub peak_memory_usage: u64,
+    pub longest_duration_sec: u64,
+}
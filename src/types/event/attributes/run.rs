@@ -0,0 +1,10 @@
+use serde::{Deserialize, Serialize};
+
+/// How the command wrapped by `tracer run` exited
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct RunCompletion {
+    pub command: String,
+    /// None when the command was killed by a signal
+    pub exit_code: Option<i32>,
+    pub signal: Option<i32>,
+}
@@ -10,6 +10,28 @@ pub struct DiskStatistic {
     pub disk_utilization: f64,
 }
 
+/// Accounting of a cgroup v2, fields are None when the controller isn't enabled for the cgroup
+#[derive(Clone, Debug, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct CgroupStatistic {
+    pub cgroup_path: String,
+    pub memory_current: Option<u64>,
+    pub memory_peak: Option<u64>,
+    /// Lowest `memory.max` of the cgroup and its ancestors, None when unlimited
+    pub memory_limit: Option<u64>,
+    pub cpu_usage_usec: Option<u64>,
+    pub cpu_user_usec: Option<u64>,
+    pub cpu_system_usec: Option<u64>,
+    pub cpu_throttled_usec: Option<u64>,
+    /// Lowest `cpu.max` quota of the cgroup and its ancestors in cores, None when unlimited
+    pub cpu_limit_cores: Option<f64>,
+    pub io_read_bytes: Option<u64>,
+    pub io_write_bytes: Option<u64>,
+    /// Times the cgroup hit its memory limit and the OOM killer ran
+    pub oom_events: Option<u64>,
+    /// Processes of the cgroup killed by the OOM killer
+    pub oom_kills: Option<u64>,
+}
+
 #[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
 pub struct SystemMetric {
     pub events_name: String,
@@ -21,6 +43,14 @@ pub struct SystemMetric {
     pub system_memory_swap_used: u64,
     pub system_cpu_utilization: f32,
     pub system_disk_io: HashMap<String, DiskStatistic>,
+    /// Cgroups of the tracked tools by path
+    #[serde(default)]
+    pub system_cgroups: HashMap<String, CgroupStatistic>,
+    /// Overhead of the tracer daemon itself, cpu in percent of one core since the previous metric
+    #[serde(default)]
+    pub tracer_cpu_utilization: Option<f32>,
+    #[serde(default)]
+    pub tracer_memory_usage: Option<u64>,
 }
 
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
@@ -1,4 +1,5 @@
 pub mod debug_log;
 pub mod http_client;
+pub mod notifications;
 pub mod tracing;
 pub mod upload;
@@ -0,0 +1,115 @@
+use std::time::Duration;
+
+use anyhow::{bail, Context, Result};
+use reqwest::Client;
+use serde_json::json;
+
+use crate::types::config::NotificationsConfig;
+use crate::types::event::Event;
+
+fn alert_text(alert: &Event) -> String {
+    format!(
+        "[tracer] Alert in pipeline {} (run {}): {}",
+        alert.pipeline_name.as_deref().unwrap_or("unknown"),
+        alert.run_name.as_deref().unwrap_or("none"),
+        alert.message
+    )
+}
+
+/// Posts an alert to the configured webhook, returns false when no channel is configured
+pub async fn send_alert_notification(config: &NotificationsConfig, alert: &Event) -> Result<bool> {
+    let Some(webhook_url) = &config.webhook_url else {
+        return Ok(false);
+    };
+
+    // `text` is what chat webhooks display, the full event is kept for other receivers
+    let body = json!({
+        "text": alert_text(alert),
+        "event": alert,
+    });
+
+    let response = Client::new()
+        .post(webhook_url)
+        .timeout(Duration::from_millis(config.timeout_ms))
+        .json(&body)
+        .send()
+        .await
+        .context("Failed to send alert notification")?;
+
+    let status = response.status();
+    if !status.is_success() {
+        let text = response.text().await.unwrap_or_default();
+        bail!("Alert notification rejected with status {status}: {text}");
+    }
+
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events::recorder::{EventRecorder, EventType};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    fn alert() -> Event {
+        let mut recorder = EventRecorder::new(Some("pipeline".to_string()), None, None);
+        recorder.record_event(EventType::Alert, "disk full".to_string(), None, None);
+        recorder.get_events()[0].clone()
+    }
+
+    #[tokio::test]
+    async fn test_alert_without_channel_is_not_sent() {
+        let sent = send_alert_notification(&NotificationsConfig::default(), &alert())
+            .await
+            .unwrap();
+        assert!(!sent);
+    }
+
+    #[tokio::test]
+    async fn test_alert_is_posted_to_webhook() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let config = NotificationsConfig {
+            webhook_url: Some(format!("http://{}/hook", listener.local_addr().unwrap())),
+            ..Default::default()
+        };
+
+        let server = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut request = Vec::new();
+            let mut buffer = [0u8; 1024];
+            // read until the whole body announced in the headers has arrived
+            loop {
+                let n = stream.read(&mut buffer).await.unwrap();
+                request.extend_from_slice(&buffer[..n]);
+                let text = String::from_utf8_lossy(&request);
+                if let Some((head, body)) = text.split_once("\r\n\r\n") {
+                    let content_length = head
+                        .lines()
+                        .find_map(|line| {
+                            line.to_lowercase()
+                                .strip_prefix("content-length: ")
+                                .map(String::from)
+                        })
+                        .and_then(|length| length.trim().parse::<usize>().ok())
+                        .unwrap_or(0);
+                    if body.len() >= content_length {
+                        break;
+                    }
+                }
+            }
+            stream
+                .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n")
+                .await
+                .unwrap();
+            String::from_utf8(request).unwrap()
+        });
+
+        let sent = send_alert_notification(&config, &alert()).await.unwrap();
+        let request = server.await.unwrap();
+
+        assert!(sent);
+        assert!(request.starts_with("POST /hook"));
+        assert!(request.contains("[tracer] Alert in pipeline pipeline (run none): disk full"));
+    }
+}
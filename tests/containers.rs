@@ -0,0 +1,84 @@
+use bollard::container::{
+    Config, CreateContainerOptions, RemoveContainerOptions, StartContainerOptions,
+};
+use bollard::image::CreateImageOptions;
+use bollard::Docker;
+use futures::StreamExt;
+use tracer::extracts::{cgroup, containers::ContainerResolver};
+use tracer::types::event::attributes::process::ContainerRuntime;
+
+const IMAGE: &str = "alpine:3.20";
+
+/// Runs against the local Docker daemon, skipped when there's none
+#[tokio::test]
+async fn test_resolve_docker_container() {
+    let Ok(docker) = Docker::connect_with_local_defaults() else {
+        println!("Docker isn't available, skipping");
+        return;
+    };
+    if docker.ping().await.is_err() {
+        println!("Docker isn't running, skipping");
+        return;
+    }
+
+    let mut pull = docker.create_image(
+        Some(CreateImageOptions {
+            from_image: IMAGE,
+            ..Default::default()
+        }),
+        None,
+        None,
+    );
+    while let Some(progress) = pull.next().await {
+        if let Err(err) = progress {
+            println!("Failed to pull {IMAGE}, skipping: {err}");
+            return;
+        }
+    }
+
+    let container = docker
+        .create_container(
+            None::<CreateContainerOptions<String>>,
+            Config {
+                image: Some(IMAGE),
+                cmd: Some(vec!["sleep", "30"]),
+                ..Default::default()
+            },
+        )
+        .await
+        .expect("Failed to create container");
+    docker
+        .start_container(&container.id, None::<StartContainerOptions<String>>)
+        .await
+        .expect("Failed to start container");
+
+    let inspect = docker.inspect_container(&container.id, None).await.unwrap();
+    let pid = inspect.state.and_then(|state| state.pid).unwrap() as u32;
+    let image_id = inspect.image.unwrap();
+
+    let resolved = tokio::task::spawn_blocking(move || {
+        let cgroup = cgroup::cgroup_of(pid);
+        ContainerResolver::new().resolve(pid, cgroup.as_deref())
+    })
+    .await
+    .unwrap();
+
+    docker
+        .remove_container(
+            &container.id,
+            Some(RemoveContainerOptions {
+                force: true,
+                ..Default::default()
+            }),
+        )
+        .await
+        .unwrap();
+
+    let resolved = resolved.expect("container not found from the process' cgroup");
+    assert_eq!(resolved.runtime, ContainerRuntime::Docker);
+    assert_eq!(resolved.container_id, Some(container.id));
+    assert_eq!(resolved.image_name.as_deref(), Some(IMAGE));
+    let digest = resolved.image_digest.unwrap();
+    assert!(digest.starts_with("sha256:"));
+    assert_ne!(digest, image_id, "a pulled image has a repo digest");
+}
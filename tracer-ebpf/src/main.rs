@@ -0,0 +1,264 @@
+// tracer-ebpf/src/main.rs
+//! Tracepoints streaming exec, openat and exit events to the tracer daemon over a ring buffer.
+//! Loaded by `src/extracts/ebpf/mod.rs` when the daemon is built with the `ebpf` feature
+#![no_std]
+#![no_main]
+
+use aya_ebpf::{
+    helpers::{
+        bpf_get_current_comm, bpf_get_current_pid_tgid, bpf_get_current_task,
+        bpf_probe_read_kernel, bpf_probe_read_kernel_str_bytes, bpf_probe_read_user_str_bytes,
+    },
+    macros::{map, tracepoint},
+    maps::{HashMap, RingBuf},
+    programs::TracePointContext,
+    EbpfContext,
+};
+
+// kept in sync with `RawEvent` in src/extracts/ebpf/mod.rs
+const EVENT_EXEC: u32 = 1;
+const EVENT_OPEN: u32 = 2;
+const EVENT_EXIT: u32 = 3;
+const PATH_LEN: usize = 256;
+/// Tools and the processes they started
+const MAX_TRACKED_PIDS: u32 = 16384;
+
+#[repr(C)]
+pub struct RawEvent {
+    pub kind: u32,
+    /// Thread group id, the pid seen from userspace
+    pub pid: u32,
+    /// Open flags of `openat`
+    pub flags: u32,
+    pub path_len: u32,
+    /// Set when the fields below were read from the task, they're zero otherwise
+    pub task_read: u32,
+    pub parent_pid: u32,
+    /// Wait status, of exit events
+    pub exit_code: u32,
+    /// Directory fd of `openat`, relative paths are relative to it
+    pub dirfd: i32,
+    /// Cpu times in nanoseconds and peak RSS in pages, of the whole process at exit
+    pub utime_ns: u64,
+    pub stime_ns: u64,
+    pub children_utime_ns: u64,
+    pub children_stime_ns: u64,
+    pub maxrss_pages: u64,
+    pub comm: [u8; 16],
+    pub path: [u8; PATH_LEN],
+}
+
+/// Byte offsets in the kernel's structs, found in its BTF by the loader.
+/// Kept in sync with `TaskOffsets` in src/extracts/ebpf/btf.rs
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct TaskOffsets {
+    pub exit_code: u32,
+    pub tgid: u32,
+    pub real_parent: u32,
+    pub utime: u32,
+    pub stime: u32,
+    pub signal: u32,
+    pub signal_utime: u32,
+    pub signal_stime: u32,
+    pub signal_cutime: u32,
+    pub signal_cstime: u32,
+    pub signal_maxrss: u32,
+    pub resolved: u32,
+}
+
+#[no_mangle]
+static TASK_OFFSETS: TaskOffsets = TaskOffsets {
+    exit_code: 0,
+    tgid: 0,
+    real_parent: 0,
+    utime: 0,
+    stime: 0,
+    signal: 0,
+    signal_utime: 0,
+    signal_stime: 0,
+    signal_cutime: 0,
+    signal_cstime: 0,
+    signal_maxrss: 0,
+    resolved: 0,
+};
+
+// offsets from /sys/kernel/tracing/events/<category>/<name>/format
+/// `__data_loc char[] filename` of sched/sched_process_exec
+const EXEC_FILENAME_LOC: usize = 8;
+/// `int dfd` of syscalls/sys_enter_openat, syscall arguments take 8 bytes each
+const OPENAT_DFD: usize = 16;
+/// `const char * filename` of syscalls/sys_enter_openat
+const OPENAT_FILENAME: usize = 24;
+/// `int flags` of syscalls/sys_enter_openat
+const OPENAT_FLAGS: usize = 32;
+
+#[map]
+static EVENTS: RingBuf = RingBuf::with_byte_size(1 << 24, 0);
+
+/// Processes whose opens are submitted. Userspace adds the tools it tracks,
+/// their children are added here and every process is removed when it exits
+#[map]
+static TRACKED_PIDS: HashMap<u32, u8> = HashMap::with_max_entries(MAX_TRACKED_PIDS, 0);
+
+/// Thread group id and thread id of the current task
+fn current_ids() -> (u32, u32) {
+    let pid_tgid = bpf_get_current_pid_tgid();
+    ((pid_tgid >> 32) as u32, pid_tgid as u32)
+}
+
+#[inline(always)]
+unsafe fn read_field<T>(base: *const u8, offset: u32) -> Option<T> {
+    bpf_probe_read_kernel(base.add(offset as usize) as *const T).ok()
+}
+
+/// Thread group id of the current task's parent, `None` without the loader's offsets
+#[inline(always)]
+unsafe fn parent_tgid() -> Option<u32> {
+    // read through a volatile so the loader's value isn't folded away
+    let offsets = core::ptr::read_volatile(&TASK_OFFSETS);
+    if offsets.resolved == 0 {
+        return None;
+    }
+    let task = bpf_get_current_task() as *const u8;
+    let parent = read_field::<*const u8>(task, offsets.real_parent)?;
+    Some(read_field::<i32>(parent, offsets.tgid)? as u32)
+}
+
+fn contains_pid(tgid: u32) -> bool {
+    // SAFETY: the value is only checked for presence, it's never read
+    unsafe { TRACKED_PIDS.get(&tgid) }.is_some()
+}
+
+/// Whether the process is tracked, a child of a tracked process is tracked from its first event on
+#[inline(always)]
+fn is_tracked(tgid: u32) -> bool {
+    if contains_pid(tgid) {
+        return true;
+    }
+    // SAFETY: the offsets are the kernel's own from its BTF, the helpers check the reads
+    match unsafe { parent_tgid() } {
+        // a full map leaves the child untracked
+        Some(parent) if contains_pid(parent) => TRACKED_PIDS.insert(&tgid, &1, 0).is_ok(),
+        _ => false,
+    }
+}
+
+/// The parent of exec'ing processes, the status and usage of exiting ones.
+/// Read here because the process is usually reaped before userspace gets the event
+#[inline(always)]
+unsafe fn read_task(event: *mut RawEvent, kind: u32) -> Option<()> {
+    if kind == EVENT_OPEN {
+        return None;
+    }
+    (*event).parent_pid = parent_tgid()?;
+    let offsets = core::ptr::read_volatile(&TASK_OFFSETS);
+    let task = bpf_get_current_task() as *const u8;
+
+    if kind == EVENT_EXIT {
+        (*event).exit_code = read_field::<i32>(task, offsets.exit_code)? as u32;
+        // exited threads add their times to the signal struct, the exiting leader has its own
+        let signal = read_field::<*const u8>(task, offsets.signal)?;
+        (*event).utime_ns = read_field::<u64>(task, offsets.utime)?
+            + read_field::<u64>(signal, offsets.signal_utime)?;
+        (*event).stime_ns = read_field::<u64>(task, offsets.stime)?
+            + read_field::<u64>(signal, offsets.signal_stime)?;
+        (*event).children_utime_ns = read_field::<u64>(signal, offsets.signal_cutime)?;
+        (*event).children_stime_ns = read_field::<u64>(signal, offsets.signal_cstime)?;
+        (*event).maxrss_pages = read_field::<u64>(signal, offsets.signal_maxrss)?;
+    }
+    (*event).task_read = 1;
+    Some(())
+}
+
+#[inline(always)]
+fn submit(kind: u32, flags: u32, dirfd: i32, read_path: impl FnOnce(&mut [u8; PATH_LEN]) -> usize) {
+    // dropped when the ring is full, userspace still finds tools in its /proc scan
+    let Some(mut entry) = EVENTS.reserve::<RawEvent>(0) else {
+        return;
+    };
+    let event = entry.as_mut_ptr();
+    // SAFETY: event is the entry reserved above, it's only submitted once every field is written
+    unsafe {
+        // reserved memory isn't zeroed, the task's fields are only set when they can be read
+        (*event).task_read = 0;
+        (*event).parent_pid = 0;
+        (*event).exit_code = 0;
+        (*event).dirfd = dirfd;
+        (*event).utime_ns = 0;
+        (*event).stime_ns = 0;
+        (*event).children_utime_ns = 0;
+        (*event).children_stime_ns = 0;
+        (*event).maxrss_pages = 0;
+        (*event).kind = kind;
+        (*event).pid = current_ids().0;
+        (*event).flags = flags;
+        (*event).comm = bpf_get_current_comm().unwrap_or_default();
+        (*event).path_len = read_path(&mut (*event).path) as u32;
+        read_task(event, kind);
+    }
+    entry.submit(0);
+}
+
+/// Successful `execve` and `execveat`, the filename is the binary that was exec'd
+#[tracepoint]
+pub fn tracer_exec(ctx: TracePointContext) -> u32 {
+    // every exec is submitted, userspace matches them against the targets.
+    // A child exec'ing is tracked here in case its parent exits before the child opens a file
+    is_tracked(current_ids().0);
+    // SAFETY: the offsets are the tracepoint's format, the helpers check the kernel reads
+    submit(EVENT_EXEC, 0, 0, |path| unsafe {
+        let Ok(loc) = ctx.read_at::<u32>(EXEC_FILENAME_LOC) else {
+            return 0;
+        };
+        // the low 16 bits are the offset of the string from the start of the record
+        let filename = (ctx.as_ptr() as *const u8).add((loc & 0xffff) as usize);
+        bpf_probe_read_kernel_str_bytes(filename, path).map_or(0, |read| read.len())
+    });
+    0
+}
+
+#[tracepoint]
+pub fn tracer_openat(ctx: TracePointContext) -> u32 {
+    // every process on the host opens files, only the tools' opens are submitted
+    if !is_tracked(current_ids().0) {
+        return 0;
+    }
+    // SAFETY: the offsets are the tracepoint's format, the helpers check the reads
+    let Ok(flags) = (unsafe { ctx.read_at::<u64>(OPENAT_FLAGS) }) else {
+        return 0;
+    };
+    let Ok(dirfd) = (unsafe { ctx.read_at::<u64>(OPENAT_DFD) }) else {
+        return 0;
+    };
+    submit(EVENT_OPEN, flags as u32, dirfd as i32, |path| unsafe {
+        let Ok(filename) = ctx.read_at::<*const u8>(OPENAT_FILENAME) else {
+            return 0;
+        };
+        bpf_probe_read_user_str_bytes(filename, path).map_or(0, |read| read.len())
+    });
+    0
+}
+
+#[tracepoint]
+pub fn tracer_exit(_ctx: TracePointContext) -> u32 {
+    let (tgid, tid) = current_ids();
+    // every thread exits through here, the process ends with its group leader
+    if tgid == tid {
+        // a pid that's reused isn't tracked. Exits of untracked processes are still submitted,
+        // a tool userspace only just matched can exit before it's added
+        let _ = TRACKED_PIDS.remove(&tgid);
+        submit(EVENT_EXIT, 0, 0, |_| 0);
+    }
+    0
+}
+
+#[cfg(not(test))]
+#[panic_handler]
+fn panic(_info: &core::panic::PanicInfo) -> ! {
+    loop {}
+}
+
+#[link_section = "license"]
+#[no_mangle]
+static LICENSE: [u8; 4] = *b"GPL\0";